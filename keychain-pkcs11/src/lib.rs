@@ -0,0 +1,71 @@
+//! PKCS#11 module which exposes keys stored in a keychain, e.g. Secure
+//! Enclave keys, to applications which support PKCS#11 modules such as
+//! OpenSSL, OpenSSH (via `PKCS11Provider`), Firefox, and Java.
+//!
+//! The module serves every key in the default keychain on a token labeled
+//! `keychain-services`. If the `KEYCHAIN_PKCS11_FILE` environment variable
+//! is set, it instead serves the keys in the `FileBackend` keychain at that
+//! path, which is opened with the PIN the user logs in to the token with.
+//!
+//! ```text
+//! $ pkcs11-tool --module libkeychain_pkcs11.so --list-objects
+//! ```
+
+#![allow(non_snake_case)]
+#![deny(warnings, missing_docs, unused_import_braces, unused_qualifications)]
+
+#[cfg(unix)]
+use keychain_services::{item::Query, FileBackend, Keychain, Pkcs11Provider};
+#[cfg(unix)]
+use std::{env, ffi::c_void, os::raw::c_ulong, path::PathBuf, sync::OnceLock};
+
+/// Environment variable containing the path of a `FileBackend` keychain
+#[cfg(unix)]
+const FILE_VAR: &str = "KEYCHAIN_PKCS11_FILE";
+
+/// Return value of `C_GetFunctionList` on success
+#[cfg(unix)]
+const CKR_OK: c_ulong = 0x0000;
+
+/// Return value of `C_GetFunctionList` if the provider couldn't be created
+#[cfg(unix)]
+const CKR_GENERAL_ERROR: c_ulong = 0x0005;
+
+/// Get the module's PKCS#11 function list, which is the entry point
+/// applications use to load the module.
+///
+/// # Safety
+///
+/// `ppFunctionList` must be null or valid for writes.
+#[cfg(unix)]
+#[no_mangle]
+pub unsafe extern "C" fn C_GetFunctionList(ppFunctionList: *mut *const c_void) -> c_ulong {
+    static INSTALL: OnceLock<c_ulong> = OnceLock::new();
+
+    let rv = *INSTALL.get_or_init(|| {
+        let provider = match env::var_os(FILE_VAR) {
+            Some(path) => {
+                let path = PathBuf::from(path);
+
+                Pkcs11Provider::with_login(Query::new(), move |pin| {
+                    FileBackend::open(&path, pin).map(Keychain::new)
+                })
+            }
+            None => match Keychain::find_default() {
+                Ok(keychain) => Pkcs11Provider::new(keychain, Query::new()),
+                Err(_) => return CKR_GENERAL_ERROR,
+            },
+        };
+
+        match provider.install() {
+            Ok(()) => CKR_OK,
+            Err(_) => CKR_GENERAL_ERROR,
+        }
+    });
+
+    if rv != CKR_OK {
+        return rv;
+    }
+
+    keychain_services::pkcs11::C_GetFunctionList(ppFunctionList)
+}
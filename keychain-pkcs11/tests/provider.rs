@@ -0,0 +1,286 @@
+//! Exercise the PKCS#11 module by loading it with the `Pkcs11Backend`
+
+#![cfg(target_os = "linux")]
+
+use keychain_services::*;
+use std::{env, fs, path::PathBuf, process::Command, thread};
+
+const TEST_MESSAGE: &[u8] = b"Embrace the chaos, it is the only way to find order";
+
+/// Path of the PKCS#11 module, which is built next to this test
+fn module_path() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    let path = exe.parent().unwrap().join("libkeychain_pkcs11.so");
+
+    assert!(path.exists(), "{} not built", path.display());
+    path
+}
+
+#[test]
+fn provider() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.keychain");
+    let kdf = Pbes2Kdf::Scrypt {
+        log_n: 10,
+        r: 8,
+        p: 1,
+    };
+
+    let keychain = Keychain::new(FileBackend::create_with_kdf(&path, "1234", kdf).unwrap());
+    let ec_keypair = keychain
+        .create_key_pair(
+            KeyPairGenerateParams::new(AttrKeyType::EcSecPrimeRandom, 256)
+                .label("ec key")
+                .application_tag("com.example.ec")
+                .permanent(true),
+        )
+        .unwrap();
+
+    // The module opens the file keychain using the PIN
+    env::set_var("KEYCHAIN_PKCS11_FILE", &path);
+    let module = module_path();
+
+    let err = Pkcs11Backend::open(&module, "keychain-services", Some("0000"))
+        .err()
+        .unwrap();
+    assert_eq!(*err.kind(), ErrorKind::AuthFailed);
+
+    let token =
+        Keychain::new(Pkcs11Backend::open(&module, "keychain-services", Some("1234")).unwrap());
+
+    // Keys in the keychain are found by label and tag
+    let ec_key = token
+        .find_key(
+            item::Query::new()
+                .label("ec key")
+                .key_class(AttrKeyClass::Private),
+        )
+        .unwrap();
+    assert_eq!(
+        ec_key.application_tag().unwrap().as_bytes(),
+        b"com.example.ec"
+    );
+    assert_eq!(
+        ec_key.application_label(),
+        ec_keypair.public_key.application_label()
+    );
+
+    for alg in &[
+        KeyAlgorithm::ECDSASignatureMessageX962SHA256,
+        KeyAlgorithm::ECDSASignatureMessageX962SHA384,
+    ] {
+        let signature = ec_key.sign(*alg, TEST_MESSAGE).unwrap();
+        assert!(ec_keypair
+            .public_key
+            .verify(TEST_MESSAGE, &signature)
+            .unwrap());
+    }
+
+    // Operations from several threads share the backend's session
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            let ec_key = ec_key.clone();
+            thread::spawn(move || {
+                (0..8)
+                    .map(|_| {
+                        ec_key
+                            .sign(KeyAlgorithm::ECDSASignatureMessageX962SHA256, TEST_MESSAGE)
+                            .unwrap()
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        for signature in thread.join().unwrap() {
+            assert!(ec_keypair
+                .public_key
+                .verify(TEST_MESSAGE, &signature)
+                .unwrap());
+        }
+    }
+
+    // Key pairs generated on the token are added to the keychain
+    let rsa_keypair = token
+        .create_key_pair(
+            KeyPairGenerateParams::new(AttrKeyType::Rsa, 2048)
+                .label("rsa key")
+                .permanent(true),
+        )
+        .unwrap();
+
+    for alg in &[
+        KeyAlgorithm::RSAEncryptionPKCS1,
+        KeyAlgorithm::RSAEncryptionOAEPSHA256,
+        KeyAlgorithm::RSAEncryptionOAEPSHA256AESGCM,
+    ] {
+        let ciphertext = rsa_keypair.public_key.encrypt(*alg, TEST_MESSAGE).unwrap();
+        let plaintext = rsa_keypair.private_key.decrypt(ciphertext).unwrap();
+        assert_eq!(plaintext, TEST_MESSAGE);
+    }
+
+    for alg in &[
+        KeyAlgorithm::RSASignatureMessagePKCS1v15SHA256,
+        KeyAlgorithm::RSASignatureMessagePSSSHA256,
+    ] {
+        let signature = rsa_keypair.private_key.sign(*alg, TEST_MESSAGE).unwrap();
+        assert!(rsa_keypair
+            .public_key
+            .verify(TEST_MESSAGE, &signature)
+            .unwrap());
+    }
+
+    // Session key pairs only exist on the token
+    let session_keypair = token
+        .generate_key_pair(KeyPairGenerateParams::new(
+            AttrKeyType::EcSecPrimeRandom,
+            384,
+        ))
+        .unwrap();
+    let signature = session_keypair
+        .private_key
+        .sign(KeyAlgorithm::ECDSASignatureMessageX962SHA384, TEST_MESSAGE)
+        .unwrap();
+    assert!(session_keypair
+        .public_key
+        .verify(TEST_MESSAGE, &signature)
+        .unwrap());
+
+    // Keys deleted from the token are deleted from the keychain
+    let ec_public_key = token
+        .find_key(
+            item::Query::new()
+                .label("ec key")
+                .key_class(AttrKeyClass::Public),
+        )
+        .unwrap();
+    ec_public_key.delete().unwrap();
+    ec_key.delete().unwrap();
+
+    let keychain = Keychain::new(FileBackend::open(&path, "1234").unwrap());
+    let keys = keychain
+        .find_keys(item::Query::new().key_class(AttrKeyClass::Private))
+        .unwrap();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].label().unwrap().as_str(), "rsa key");
+    assert_eq!(
+        keys[0].application_label(),
+        rsa_keypair.private_key.application_label()
+    );
+}
+
+/// Run `pkcs11-tool` (from OpenSC) with the module, returning its output
+fn pkcs11_tool(keychain: &std::path::Path, args: &[&str]) -> String {
+    let output = Command::new("pkcs11-tool")
+        .arg("--module")
+        .arg(module_path())
+        .args(["--login", "--pin", "1234"])
+        .args(args)
+        .env("KEYCHAIN_PKCS11_FILE", keychain)
+        .output()
+        .expect("couldn't run pkcs11-tool");
+
+    assert!(
+        output.status.success(),
+        "pkcs11-tool {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Use the module from OpenSC's `pkcs11-tool`
+#[test]
+#[ignore = "requires pkcs11-tool"]
+fn pkcs11_tool_interop() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.keychain");
+    let kdf = Pbes2Kdf::Scrypt {
+        log_n: 10,
+        r: 8,
+        p: 1,
+    };
+
+    let keychain = Keychain::new(FileBackend::create_with_kdf(&path, "1234", kdf).unwrap());
+    let ec_keypair = keychain
+        .create_key_pair(
+            KeyPairGenerateParams::new(AttrKeyType::EcSecPrimeRandom, 256)
+                .label("ec key")
+                .permanent(true),
+        )
+        .unwrap();
+    let rsa_keypair = keychain
+        .create_key_pair(
+            KeyPairGenerateParams::new(AttrKeyType::Rsa, 2048)
+                .label("rsa key")
+                .permanent(true),
+        )
+        .unwrap();
+
+    let objects = pkcs11_tool(&path, &["--list-objects"]);
+    assert!(objects.contains("ec key"), "{}", objects);
+    assert!(objects.contains("rsa key"), "{}", objects);
+
+    // ECDSA signatures are written in the fixed-size `r || s` form
+    let message = dir.path().join("message");
+    let signature = dir.path().join("signature");
+    fs::write(&message, TEST_MESSAGE).unwrap();
+    pkcs11_tool(
+        &path,
+        &[
+            "--sign",
+            "--mechanism",
+            "ECDSA-SHA256",
+            "--label",
+            "ec key",
+            "--input-file",
+            message.to_str().unwrap(),
+            "--output-file",
+            signature.to_str().unwrap(),
+        ],
+    );
+
+    let signature = Signature::from_fixed(
+        KeyAlgorithm::ECDSASignatureMessageX962SHA256,
+        EcCurve::P256,
+        &fs::read(&signature).unwrap(),
+    )
+    .unwrap();
+    assert!(ec_keypair
+        .public_key
+        .verify(TEST_MESSAGE, &signature)
+        .unwrap());
+
+    let ciphertext = dir.path().join("ciphertext");
+    let plaintext = dir.path().join("plaintext");
+    fs::write(
+        &ciphertext,
+        rsa_keypair
+            .public_key
+            .encrypt(KeyAlgorithm::RSAEncryptionOAEPSHA256, TEST_MESSAGE)
+            .unwrap()
+            .as_bytes(),
+    )
+    .unwrap();
+    pkcs11_tool(
+        &path,
+        &[
+            "--decrypt",
+            "--mechanism",
+            "RSA-PKCS-OAEP",
+            "--hash-algorithm",
+            "SHA256",
+            "--mgf",
+            "MGF1-SHA256",
+            "--label",
+            "rsa key",
+            "--input-file",
+            ciphertext.to_str().unwrap(),
+            "--output-file",
+            plaintext.to_str().unwrap(),
+        ],
+    );
+    assert_eq!(fs::read(&plaintext).unwrap(), TEST_MESSAGE);
+}
@@ -1,15 +1,23 @@
 //! Keychain item access control types: ACLs and policies around usage of
 //! private keys stored in the keychain.
 
-use crate::{attr::AttrAccessible, error::Error, ffi::*};
+#[cfg(target_os = "macos")]
+use crate::ffi::*;
+use crate::{attr::AttrAccessible, error::Error};
+#[cfg(target_os = "macos")]
 use core_foundation::{
-    base::{kCFAllocatorDefault, CFOptionFlags, TCFType},
+    base::{kCFAllocatorDefault, CFType, TCFType},
     error::CFErrorRef,
 };
-use std::{
-    fmt::{self, Debug},
-    ptr,
-};
+#[cfg(target_os = "macos")]
+use std::ptr;
+
+#[cfg(target_os = "macos")]
+use core_foundation::base::CFOptionFlags;
+
+/// Bitflags passed to `SecAccessControlCreateWithFlags`.
+#[cfg(not(target_os = "macos"))]
+type CFOptionFlags = std::os::raw::c_ulong;
 
 /// Marker trait for types which can be used as `AccessControlFlags`.
 pub trait AccessControlFlag: Copy + Clone + Sized + Into<CFOptionFlags> {}
@@ -145,7 +153,7 @@ impl AccessControlFlags {
 }
 
 /// Shorthand syntax for when flags are all of the same type
-impl<'a, F> From<&'a [F]> for AccessControlFlags
+impl<F> From<&[F]> for AccessControlFlags
 where
     F: AccessControlFlag,
 {
@@ -160,17 +168,19 @@ where
     }
 }
 
-declare_TCFType! {
-    /// Access control policy (a.k.a. ACL) for a keychain item, combining both a
-    /// set of `AccessControlFlags` and a `AttrAccessible` restriction.
-    ///
-    /// Wrapper for the `SecAccessControl`/`SecAccessControlRef` types:
-    /// <https://developer.apple.com/documentation/security/secaccesscontrolref>
-    AccessControl, AccessControlRef
+/// Access control policy (a.k.a. ACL) for a keychain item, combining both a
+/// set of `AccessControlFlags` and a `AttrAccessible` restriction.
+///
+/// Wrapper for the `SecAccessControl`/`SecAccessControlRef` types:
+/// <https://developer.apple.com/documentation/security/secaccesscontrolref>
+#[derive(Clone, Debug)]
+pub struct AccessControl {
+    protection: AttrAccessible,
+    flags: AccessControlFlags,
+    #[cfg(target_os = "macos")]
+    sec_access_control: SecAccessControl,
 }
 
-impl_TCFType!(AccessControl, AccessControlRef, SecAccessControlGetTypeID);
-
 impl AccessControl {
     /// Create a new `AccessControl` policy/ACL.
     ///
@@ -179,6 +189,39 @@ impl AccessControl {
     pub fn create_with_flags(
         protection: AttrAccessible,
         flags: AccessControlFlags,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            protection,
+            flags,
+            #[cfg(target_os = "macos")]
+            sec_access_control: SecAccessControl::create_with_flags(protection, flags)?,
+        })
+    }
+
+    /// Get the accessibility restriction for this policy
+    pub fn protection(&self) -> AttrAccessible {
+        self.protection
+    }
+
+    /// Get the `AccessControlFlags` for this policy
+    pub fn flags(&self) -> AccessControlFlags {
+        self.flags
+    }
+
+    /// Get a `CFType` object representing this policy
+    #[cfg(target_os = "macos")]
+    pub(crate) fn as_CFType(&self) -> CFType {
+        self.sec_access_control.as_CFType()
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl SecAccessControl {
+    /// Wrapper for the `SecAccessControlCreateWithFlags()` function:
+    /// <https://developer.apple.com/documentation/security/1394452-secaccesscontrolcreatewithflags>
+    fn create_with_flags(
+        protection: AttrAccessible,
+        flags: AccessControlFlags,
     ) -> Result<Self, Error> {
         let mut error: CFErrorRef = ptr::null_mut();
 
@@ -198,10 +241,3 @@ impl AccessControl {
         }
     }
 }
-
-impl Debug for AccessControl {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        // TODO: display more information about `AccessControl`s
-        write!(f, "SecAccessControl {{ ... }}")
-    }
-}
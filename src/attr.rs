@@ -1,15 +1,21 @@
 //! Keychain item attributes (i.e. `SecAttr*`)
 
+#[cfg(target_os = "macos")]
 use crate::ffi::*;
+#[cfg(target_os = "macos")]
 use core_foundation::{
     base::{CFType, TCFType, ToVoid},
-    data::CFData,
     string::{CFString, CFStringRef},
 };
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "macos")]
+use std::ffi::c_void;
 use std::{
-    ffi::c_void,
+    collections::{btree_map, BTreeMap},
     fmt::{self, Debug, Display},
+    iter::FromIterator,
     str::{self, Utf8Error},
+    time::SystemTime,
 };
 
 /// Trait implemented by all `Attr*` types to simplify adding them to
@@ -18,14 +24,14 @@ pub(crate) trait TAttr {
     /// Get the `AttrKind` for this attribute.
     fn kind(&self) -> AttrKind;
 
-    /// Get a `CFType` object representing this attribute.
-    fn as_CFType(&self) -> CFType;
+    /// Get an `AttrValue` representing this attribute.
+    fn as_value(&self) -> AttrValue;
 }
 
 /// Enum of attribute types passed in parameter dictionaries. This wraps up
 /// access to framework constants which would otherwise be unsafe.
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub(crate) enum AttrKind {
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum AttrKind {
     /// Wrapper for the `kSecAttrAccessControl` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattraccesscontrol>
     AccessControl,
@@ -46,6 +52,10 @@ pub(crate) enum AttrKind {
     /// <https://developer.apple.com/documentation/security/ksecattrapplicationtag>
     ApplicationTag,
 
+    /// Wrapper for the `kSecAttrCreationDate` attribute key. See:
+    /// <https://developer.apple.com/documentation/security/ksecattrcreationdate>
+    CreationDate,
+
     /// Wrapper for the `kSecKeyDerive` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrcanderive>
     Derive,
@@ -78,14 +88,26 @@ pub(crate) enum AttrKind {
     /// <https://developer.apple.com/documentation/security/ksecattrlabel>
     Label,
 
+    /// Wrapper for the `kSecAttrModificationDate` attribute key. See:
+    /// <https://developer.apple.com/documentation/security/ksecattrmodificationdate>
+    ModificationDate,
+
     /// Wrapper for the `kSecAttrIsPermanent` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrispermanent>
     Permanent,
 
+    /// Wrapper for the `kSecPrivateKeyAttrs` key generation parameter. See:
+    /// <https://developer.apple.com/documentation/security/ksecprivatekeyattrs>
+    PrivateKeyAttrs,
+
     /// Wrapper for the `kSecAttrProtocol` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrprotocol>
     Protocol,
 
+    /// Wrapper for the `kSecPublicKeyAttrs` key generation parameter. See:
+    /// <https://developer.apple.com/documentation/security/ksecpublickeyattrs>
+    PublicKeyAttrs,
+
     /// Wrapper for `kSecKeySensitive` attribute key. See
     /// <https://developer.apple.com/documentation/security/ksecattrissensitive>
     Sensitive,
@@ -124,7 +146,41 @@ pub(crate) enum AttrKind {
 }
 
 impl AttrKind {
+    /// All attribute kinds known to this crate
+    #[cfg(target_os = "macos")]
+    pub(crate) const ALL: &'static [AttrKind] = &[
+        AttrKind::AccessControl,
+        AttrKind::Accessible,
+        AttrKind::Account,
+        AttrKind::ApplicationLabel,
+        AttrKind::ApplicationTag,
+        AttrKind::CreationDate,
+        AttrKind::Derive,
+        AttrKind::Decrypt,
+        AttrKind::Encrypt,
+        AttrKind::Extractable,
+        AttrKind::KeyClass,
+        AttrKind::KeySizeInBits,
+        AttrKind::KeyType,
+        AttrKind::Label,
+        AttrKind::ModificationDate,
+        AttrKind::Permanent,
+        AttrKind::PrivateKeyAttrs,
+        AttrKind::Protocol,
+        AttrKind::PublicKeyAttrs,
+        AttrKind::Sensitive,
+        AttrKind::Server,
+        AttrKind::Service,
+        AttrKind::Sign,
+        AttrKind::Synchronizable,
+        AttrKind::TokenId,
+        AttrKind::Unwrap,
+        AttrKind::Verify,
+        AttrKind::Wrap,
+    ];
+
     /// Attempt to look up an attribute kind by its `SecKeychainAttrType`.
+    #[cfg(target_os = "macos")]
     // TODO: cache `SecKeychainAttrTypes`? e.g. as `lazy_static`
     pub(crate) fn from_tag(tag: SecKeychainAttrType) -> Option<Self> {
         let result = unsafe {
@@ -138,6 +194,8 @@ impl AttrKind {
                 AttrKind::ApplicationLabel
             } else if tag == SecKeychainAttrType::from(kSecAttrApplicationTag) {
                 AttrKind::ApplicationTag
+            } else if tag == SecKeychainAttrType::from(kSecAttrCreationDate) {
+                AttrKind::CreationDate
             } else if tag == SecKeychainAttrType::from(kSecAttrKeyClass) {
                 AttrKind::KeyClass
             } else if tag == SecKeychainAttrType::from(kSecAttrKeySizeInBits) {
@@ -148,6 +206,8 @@ impl AttrKind {
                 AttrKind::Permanent
             } else if tag == SecKeychainAttrType::from(kSecAttrLabel) {
                 AttrKind::Label
+            } else if tag == SecKeychainAttrType::from(kSecAttrModificationDate) {
+                AttrKind::ModificationDate
             } else if tag == SecKeychainAttrType::from(kSecAttrProtocol) {
                 AttrKind::Protocol
             } else if tag == SecKeychainAttrType::from(kSecAttrServer) {
@@ -185,12 +245,14 @@ impl AttrKind {
     }
 }
 
+#[cfg(target_os = "macos")]
 impl From<SecKeychainAttrType> for AttrKind {
     fn from(tag: SecKeychainAttrType) -> Self {
         Self::from_tag(tag).unwrap_or_else(|| panic!("invalid SecKeychainAttrType tag: {:?}", tag))
     }
 }
 
+#[cfg(target_os = "macos")]
 impl From<AttrKind> for CFStringRef {
     fn from(attr: AttrKind) -> CFStringRef {
         unsafe {
@@ -200,6 +262,7 @@ impl From<AttrKind> for CFStringRef {
                 AttrKind::Account => kSecAttrAccount,
                 AttrKind::ApplicationLabel => kSecAttrApplicationLabel,
                 AttrKind::ApplicationTag => kSecAttrApplicationTag,
+                AttrKind::CreationDate => kSecAttrCreationDate,
                 AttrKind::Derive => kSecAttrCanDerive,
                 AttrKind::Decrypt => kSecAttrCanDecrypt,
                 AttrKind::Encrypt => kSecAttrCanEncrypt,
@@ -207,7 +270,10 @@ impl From<AttrKind> for CFStringRef {
                 AttrKind::KeyClass => kSecAttrKeyClass,
                 AttrKind::KeySizeInBits => kSecAttrKeySizeInBits,
                 AttrKind::KeyType => kSecAttrKeyType,
+                AttrKind::ModificationDate => kSecAttrModificationDate,
                 AttrKind::Permanent => kSecAttrIsPermanent,
+                AttrKind::PrivateKeyAttrs => kSecPrivateKeyAttrs,
+                AttrKind::PublicKeyAttrs => kSecPublicKeyAttrs,
                 AttrKind::Sensitive => kSecAttrIsSensitive,
                 AttrKind::Sign => kSecAttrCanSign,
                 AttrKind::Verify => kSecAttrCanVerify,
@@ -224,6 +290,7 @@ impl From<AttrKind> for CFStringRef {
     }
 }
 
+#[cfg(target_os = "macos")]
 unsafe impl ToVoid<CFType> for AttrKind {
     fn to_void(&self) -> *const c_void {
         CFStringRef::from(*self).to_void()
@@ -271,8 +338,23 @@ pub enum AttrAccessible {
 }
 
 impl AttrAccessible {
+    /// Get the string value Keychain Services uses to represent this
+    /// accessibility restriction.
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            AttrAccessible::WhenPasscodeSetThisDeviceOnly => "akpu",
+            AttrAccessible::WhenUnlockedThisDeviceOnly => "aku",
+            AttrAccessible::WhenUnlocked => "ak",
+            AttrAccessible::AfterFirstUnlockThisDeviceOnly => "cku",
+            AttrAccessible::AfterFirstUnlock => "ck",
+            AttrAccessible::AlwaysThisDeviceOnly => "dku",
+            AttrAccessible::Always => "dk",
+        }
+    }
+
     /// Get pointer to an accessibility value to associate with the
     /// `kSecAttrAccessible` key for a keychain item
+    #[cfg(target_os = "macos")]
     pub fn as_CFString(self) -> CFString {
         unsafe {
             CFString::wrap_under_get_rule(match self {
@@ -299,8 +381,8 @@ impl TAttr for AttrAccessible {
         AttrKind::Accessible
     }
 
-    fn as_CFType(&self) -> CFType {
-        self.as_CFString().as_CFType()
+    fn as_value(&self) -> AttrValue {
+        AttrValue::String(self.as_str().to_owned())
     }
 }
 
@@ -314,12 +396,12 @@ impl TAttr for AttrAccessible {
 /// Wrapper for the `kSecAttrApplicationLabel` attribute key. See:
 /// <https://developer.apple.com/documentation/security/ksecattrapplicationlabel>
 #[derive(Clone, Eq, PartialEq)]
-pub struct AttrApplicationLabel(pub(crate) CFData);
+pub struct AttrApplicationLabel(pub(crate) Vec<u8>);
 
 impl AttrApplicationLabel {
     /// Create a new application label from a byte slice
     pub fn new(bytes: &[u8]) -> Self {
-        AttrApplicationLabel(CFData::from_buffer(bytes))
+        AttrApplicationLabel(bytes.into())
     }
 
     /// Borrow this value as a byte slice
@@ -341,7 +423,7 @@ impl Debug for AttrApplicationLabel {
     }
 }
 
-impl<'a> From<&'a [u8]> for AttrApplicationLabel {
+impl From<&[u8]> for AttrApplicationLabel {
     fn from(bytes: &[u8]) -> Self {
         Self::new(bytes)
     }
@@ -352,8 +434,8 @@ impl TAttr for AttrApplicationLabel {
         AttrKind::ApplicationLabel
     }
 
-    fn as_CFType(&self) -> CFType {
-        self.0.as_CFType()
+    fn as_value(&self) -> AttrValue {
+        AttrValue::Data(self.0.clone())
     }
 }
 
@@ -367,12 +449,12 @@ impl TAttr for AttrApplicationLabel {
 /// Wrapper for the `kSecAttrApplicationTag` attribute key. See:
 /// <https://developer.apple.com/documentation/security/ksecattrapplicationtag>
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct AttrApplicationTag(pub(crate) CFData);
+pub struct AttrApplicationTag(pub(crate) Vec<u8>);
 
 impl AttrApplicationTag {
     /// Create a new application tag from a byte slice
     pub fn new(bytes: &[u8]) -> Self {
-        AttrApplicationTag(CFData::from_buffer(bytes))
+        AttrApplicationTag(bytes.into())
     }
 
     /// Borrow the tag data as a byte slice
@@ -398,13 +480,13 @@ impl Display for AttrApplicationTag {
     }
 }
 
-impl<'a> From<&'a [u8]> for AttrApplicationTag {
+impl From<&[u8]> for AttrApplicationTag {
     fn from(bytes: &[u8]) -> Self {
         Self::new(bytes)
     }
 }
 
-impl<'a> From<&'a str> for AttrApplicationTag {
+impl From<&str> for AttrApplicationTag {
     fn from(string: &str) -> Self {
         Self::new(string.as_bytes())
     }
@@ -415,8 +497,8 @@ impl TAttr for AttrApplicationTag {
         AttrKind::ApplicationTag
     }
 
-    fn as_CFType(&self) -> CFType {
-        self.0.as_CFType()
+    fn as_value(&self) -> AttrValue {
+        AttrValue::Data(self.0.clone())
     }
 }
 
@@ -425,12 +507,17 @@ impl TAttr for AttrApplicationTag {
 /// Wrapper for the `kSecAttrLabel` attribute key. See:
 /// <https://developer.apple.com/documentation/security/ksecattrlabel>
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct AttrLabel(pub(crate) CFString);
+pub struct AttrLabel(pub(crate) String);
 
 impl AttrLabel {
     /// Create a new label from a `&str`
     pub fn new(label: &str) -> Self {
-        AttrLabel(CFString::new(label))
+        AttrLabel(label.to_owned())
+    }
+
+    /// Borrow the label as a `str`
+    pub fn as_str(&self) -> &str {
+        &self.0
     }
 }
 
@@ -440,7 +527,7 @@ impl Display for AttrLabel {
     }
 }
 
-impl<'a> From<&'a str> for AttrLabel {
+impl From<&str> for AttrLabel {
     fn from(label: &str) -> Self {
         Self::new(label)
     }
@@ -451,8 +538,8 @@ impl TAttr for AttrLabel {
         AttrKind::Label
     }
 
-    fn as_CFType(&self) -> CFType {
-        self.0.as_CFType()
+    fn as_value(&self) -> AttrValue {
+        AttrValue::String(self.0.clone())
     }
 }
 
@@ -484,8 +571,30 @@ pub enum AttrKeyClass {
 }
 
 impl AttrKeyClass {
+    /// Get the string value Keychain Services uses to represent this key class.
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            AttrKeyClass::Public => "0",
+            AttrKeyClass::Private => "1",
+            AttrKeyClass::Symmetric => "2",
+        }
+    }
+
+    /// Parse a key class from its `AttrValue` representation.
+    pub(crate) fn from_value(value: &AttrValue) -> Option<Self> {
+        [
+            AttrKeyClass::Public,
+            AttrKeyClass::Private,
+            AttrKeyClass::Symmetric,
+        ]
+        .iter()
+        .find(|class| value.matches_str(class.as_str()))
+        .cloned()
+    }
+
     /// Get `CFString` containing the `kSecAttrKeyClass` dictionary value for
     /// this particular `SecAttrKeyClass`.
+    #[cfg(target_os = "macos")]
     pub fn as_CFString(self) -> CFString {
         unsafe {
             CFString::wrap_under_get_rule(match self {
@@ -502,11 +611,12 @@ impl TAttr for AttrKeyClass {
         AttrKind::KeyClass
     }
 
-    fn as_CFType(&self) -> CFType {
-        self.as_CFString().as_CFType()
+    fn as_value(&self) -> AttrValue {
+        AttrValue::String(self.as_str().to_owned())
     }
 }
 
+#[cfg(target_os = "macos")]
 impl From<CFStringRef> for AttrKeyClass {
     fn from(string_ref: CFStringRef) -> AttrKeyClass {
         unsafe {
@@ -521,6 +631,7 @@ impl From<CFStringRef> for AttrKeyClass {
     }
 }
 
+#[cfg(target_os = "macos")]
 impl<'a> From<&'a CFString> for AttrKeyClass {
     fn from(string: &'a CFString) -> AttrKeyClass {
         unsafe {
@@ -563,8 +674,30 @@ pub enum AttrKeyType {
 }
 
 impl AttrKeyType {
+    /// Get the string value Keychain Services uses to represent this key type.
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            AttrKeyType::Aes => "2147483649",
+            AttrKeyType::Rsa => "42",
+            AttrKeyType::EcSecPrimeRandom => "73",
+        }
+    }
+
+    /// Parse a key type from its `AttrValue` representation.
+    pub(crate) fn from_value(value: &AttrValue) -> Option<Self> {
+        [
+            AttrKeyType::Aes,
+            AttrKeyType::Rsa,
+            AttrKeyType::EcSecPrimeRandom,
+        ]
+        .iter()
+        .find(|key_type| value.matches_str(key_type.as_str()))
+        .cloned()
+    }
+
     /// Get `CFString` containing the `kSecAttrKeyType` dictionary value for
     /// this particular `SecAttrKeyType`.
+    #[cfg(target_os = "macos")]
     pub fn as_CFString(self) -> CFString {
         unsafe {
             CFString::wrap_under_get_rule(match self {
@@ -581,11 +714,12 @@ impl TAttr for AttrKeyType {
         AttrKind::KeyType
     }
 
-    fn as_CFType(&self) -> CFType {
-        self.as_CFString().as_CFType()
+    fn as_value(&self) -> AttrValue {
+        AttrValue::String(self.as_str().to_owned())
     }
 }
 
+#[cfg(target_os = "macos")]
 impl From<CFStringRef> for AttrKeyType {
     fn from(string_ref: CFStringRef) -> AttrKeyType {
         unsafe {
@@ -600,6 +734,7 @@ impl From<CFStringRef> for AttrKeyType {
     }
 }
 
+#[cfg(target_os = "macos")]
 impl<'a> From<&'a CFString> for AttrKeyType {
     fn from(string: &'a CFString) -> AttrKeyType {
         unsafe {
@@ -716,8 +851,47 @@ pub enum AttrProtocol {
 }
 
 impl AttrProtocol {
+    /// Get the four character code Keychain Services uses to represent this
+    /// protocol.
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            AttrProtocol::FTP => "ftp ",
+            AttrProtocol::FTPAccount => "ftpa",
+            AttrProtocol::HTTP => "http",
+            AttrProtocol::IRC => "irc ",
+            AttrProtocol::NNTP => "nntp",
+            AttrProtocol::POP3 => "pop3",
+            AttrProtocol::SMTP => "smtp",
+            AttrProtocol::SOCKS => "sox ",
+            AttrProtocol::IMAP => "imap",
+            AttrProtocol::LDAP => "ldap",
+            AttrProtocol::AppleTalk => "atlk",
+            AttrProtocol::AFP => "afp ",
+            AttrProtocol::Telnet => "teln",
+            AttrProtocol::SSH => "ssh ",
+            AttrProtocol::FTPS => "ftps",
+            AttrProtocol::HTTPS => "htps",
+            AttrProtocol::HTTPProxy => "htpx",
+            AttrProtocol::HTTPSProxy => "htsx",
+            AttrProtocol::FTPProxy => "ftpx",
+            AttrProtocol::SMB => "smb ",
+            AttrProtocol::RTSP => "rtsp",
+            AttrProtocol::RTSPProxy => "rtsx",
+            AttrProtocol::DAAP => "daap",
+            AttrProtocol::EPPC => "eppc",
+            AttrProtocol::IPP => "ipp ",
+            AttrProtocol::NNTPS => "ntps",
+            AttrProtocol::LDAPS => "ldps",
+            AttrProtocol::TelnetS => "tels",
+            AttrProtocol::IMAPS => "imps",
+            AttrProtocol::IRCS => "ircs",
+            AttrProtocol::POP3S => "pops",
+        }
+    }
+
     /// Get `CFString` containing the `kSecAttrProtocol` dictionary value for
     /// this particular `SecAttrProtocol`.
+    #[cfg(target_os = "macos")]
     pub fn as_CFString(self) -> CFString {
         unsafe {
             CFString::wrap_under_get_rule(match self {
@@ -762,13 +936,13 @@ impl TAttr for AttrProtocol {
         AttrKind::Protocol
     }
 
-    fn as_CFType(&self) -> CFType {
-        self.as_CFString().as_CFType()
+    fn as_value(&self) -> AttrValue {
+        AttrValue::String(self.as_str().to_owned())
     }
 }
 
 /// Identifiers for external storage tokens for cryptographic keys
-/// (i.e. Secure Enclave).
+/// (i.e. Secure Enclave, or a PKCS#11 token).
 ///
 /// Wrapper for the `kSecAttrTokenID` attribute key. See:
 /// <https://developer.apple.com/documentation/security/ksecattrtokenid>
@@ -779,16 +953,33 @@ pub enum AttrTokenId {
     /// Wrapper for the `kSecAttrTokenIDSecureEnclave` attribute value. See:
     /// <https://developer.apple.com/documentation/security/ksecattrtokenidsecureenclave>
     SecureEnclave,
+
+    /// Hardware token (e.g. an HSM or smart card) accessed through a
+    /// PKCS#11 module using `Pkcs11Backend`.
+    ///
+    /// This is not a Keychain Services token ID, and is only meaningful to
+    /// the PKCS#11 backend.
+    Pkcs11,
 }
 
 impl AttrTokenId {
+    /// Get the string value Keychain Services uses to represent this token.
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            AttrTokenId::SecureEnclave => "com.apple.setoken",
+            AttrTokenId::Pkcs11 => "pkcs11",
+        }
+    }
+
     /// Get `CFString` containing the `kSecAttrTokenID` dictionary value for
     /// this particular `SecAttrTokenId`.
+    #[cfg(target_os = "macos")]
     pub fn as_CFString(self) -> CFString {
-        unsafe {
-            CFString::wrap_under_get_rule(match self {
-                AttrTokenId::SecureEnclave => kSecAttrTokenIDSecureEnclave,
-            })
+        match self {
+            AttrTokenId::SecureEnclave => unsafe {
+                CFString::wrap_under_get_rule(kSecAttrTokenIDSecureEnclave)
+            },
+            AttrTokenId::Pkcs11 => CFString::from_static_string(self.as_str()),
         }
     }
 }
@@ -798,8 +989,8 @@ impl TAttr for AttrTokenId {
         AttrKind::TokenId
     }
 
-    fn as_CFType(&self) -> CFType {
-        self.as_CFString().as_CFType()
+    fn as_value(&self) -> AttrValue {
+        AttrValue::String(self.as_str().to_owned())
     }
 }
 
@@ -870,6 +1061,7 @@ pub enum KeyAttr {
 impl KeyAttr {
     /// Get `CFString` containing the `kSecKeyAttr` dictionary value for
     /// this particular `SecKeyAttr`.
+    #[cfg(target_os = "macos")]
     pub fn as_CFString(self) -> CFString {
         unsafe {
             CFString::wrap_under_get_rule(match self {
@@ -896,3 +1088,272 @@ impl KeyAttr {
         }
     }
 }
+
+/// Values of keychain item attributes, in a platform-independent form
+/// (i.e. without reference to Core Foundation types).
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum AttrValue {
+    /// Boolean flags, e.g. `kSecAttrIsPermanent` (`CFBoolean`).
+    Bool(bool),
+
+    /// Binary data, e.g. `kSecAttrApplicationTag` (`CFData`).
+    Data(Vec<u8>),
+
+    /// Timestamps, e.g. `kSecAttrCreationDate` (`CFDate`).
+    Date(SystemTime),
+
+    /// Nested attribute dictionaries, e.g. `kSecPrivateKeyAttrs`
+    /// (`CFDictionary`).
+    Dictionary(AttributeMap),
+
+    /// Integers, e.g. `kSecAttrKeySizeInBits` (`CFNumber`).
+    Integer(i64),
+
+    /// Strings, e.g. `kSecAttrLabel` or enumerated values like
+    /// `kSecAttrKeyType` (`CFString`).
+    String(String),
+}
+
+impl AttrValue {
+    /// Get this value as a `bool`, if it is one
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            AttrValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Get this value as a byte slice, if it is binary data
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            AttrValue::Data(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Get this value as a `SystemTime`, if it is a date
+    pub fn as_date(&self) -> Option<SystemTime> {
+        match self {
+            AttrValue::Date(time) => Some(*time),
+            _ => None,
+        }
+    }
+
+    /// Get this value as an `AttributeMap`, if it is a nested dictionary
+    pub fn as_dictionary(&self) -> Option<&AttributeMap> {
+        match self {
+            AttrValue::Dictionary(attrs) => Some(attrs),
+            _ => None,
+        }
+    }
+
+    /// Get this value as an `i64`, if it is an integer
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            AttrValue::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Get this value as a `str`, if it is a string
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            AttrValue::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// Does this value represent the given string? Keychain Services encodes
+    /// enumerated values like `kSecAttrKeyType` as numeric strings, which
+    /// may also come back as integers.
+    pub(crate) fn matches_str(&self, string: &str) -> bool {
+        match self {
+            AttrValue::String(s) => s == string,
+            AttrValue::Integer(n) => n.to_string() == string,
+            _ => false,
+        }
+    }
+}
+
+impl From<bool> for AttrValue {
+    fn from(value: bool) -> AttrValue {
+        AttrValue::Bool(value)
+    }
+}
+
+impl From<&[u8]> for AttrValue {
+    fn from(bytes: &[u8]) -> AttrValue {
+        AttrValue::Data(bytes.into())
+    }
+}
+
+impl From<Vec<u8>> for AttrValue {
+    fn from(bytes: Vec<u8>) -> AttrValue {
+        AttrValue::Data(bytes)
+    }
+}
+
+impl From<SystemTime> for AttrValue {
+    fn from(time: SystemTime) -> AttrValue {
+        AttrValue::Date(time)
+    }
+}
+
+impl From<AttributeMap> for AttrValue {
+    fn from(attrs: AttributeMap) -> AttrValue {
+        AttrValue::Dictionary(attrs)
+    }
+}
+
+impl From<i64> for AttrValue {
+    fn from(value: i64) -> AttrValue {
+        AttrValue::Integer(value)
+    }
+}
+
+impl From<&str> for AttrValue {
+    fn from(value: &str) -> AttrValue {
+        AttrValue::String(value.to_owned())
+    }
+}
+
+impl From<String> for AttrValue {
+    fn from(value: String) -> AttrValue {
+        AttrValue::String(value)
+    }
+}
+
+/// Set of keychain item attributes, i.e. a platform-independent form of the
+/// attribute dictionaries passed to and returned from Keychain Services.
+///
+/// These are converted to/from `CFDictionary` only when calling into the
+/// Security Framework, so they can be constructed, inspected, compared and
+/// serialized on any platform.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct AttributeMap(BTreeMap<AttrKind, AttrValue>);
+
+impl AttributeMap {
+    /// Create a new, empty attribute map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of attributes in this map
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Is this attribute map empty?
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Does this map contain a value for the given attribute?
+    pub fn contains(&self, kind: AttrKind) -> bool {
+        self.0.contains_key(&kind)
+    }
+
+    /// Get the value of an attribute, if present
+    pub fn get(&self, kind: AttrKind) -> Option<&AttrValue> {
+        self.0.get(&kind)
+    }
+
+    /// Set an attribute to the given value, returning the previous value
+    pub fn insert<V: Into<AttrValue>>(&mut self, kind: AttrKind, value: V) -> Option<AttrValue> {
+        self.0.insert(kind, value.into())
+    }
+
+    /// Remove an attribute, returning its previous value
+    pub fn remove(&mut self, kind: AttrKind) -> Option<AttrValue> {
+        self.0.remove(&kind)
+    }
+
+    /// Iterate over the attributes in this map (ordered by `AttrKind`)
+    pub fn iter(&self) -> btree_map::Iter<'_, AttrKind, AttrValue> {
+        self.0.iter()
+    }
+
+    /// Compute the attributes which differ between this map and `other`,
+    /// ordered by `AttrKind`
+    pub fn diff<'a>(&'a self, other: &'a AttributeMap) -> Vec<(AttrKind, AttrChange<'a>)> {
+        let mut kinds: Vec<AttrKind> = self.0.keys().chain(other.0.keys()).cloned().collect();
+        kinds.sort();
+        kinds.dedup();
+
+        kinds
+            .into_iter()
+            .filter_map(|kind| {
+                let change = match (self.get(kind), other.get(kind)) {
+                    (Some(old), Some(new)) if old != new => AttrChange::Changed { old, new },
+                    (Some(old), None) => AttrChange::Removed(old),
+                    (None, Some(new)) => AttrChange::Added(new),
+                    _ => return None,
+                };
+
+                Some((kind, change))
+            })
+            .collect()
+    }
+
+    /// Set an attribute (i.e. `TAttr`)
+    pub(crate) fn insert_attr(&mut self, attr: &dyn TAttr) {
+        self.0.insert(attr.kind(), attr.as_value());
+    }
+
+    /// Get a byte slice attribute
+    pub(crate) fn get_data(&self, kind: AttrKind) -> Option<&[u8]> {
+        self.get(kind).and_then(AttrValue::as_bytes)
+    }
+
+    /// Get a string attribute
+    pub(crate) fn get_string(&self, kind: AttrKind) -> Option<&str> {
+        self.get(kind).and_then(AttrValue::as_str)
+    }
+}
+
+impl Extend<(AttrKind, AttrValue)> for AttributeMap {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (AttrKind, AttrValue)>,
+    {
+        self.0.extend(iter)
+    }
+}
+
+impl FromIterator<(AttrKind, AttrValue)> for AttributeMap {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (AttrKind, AttrValue)>,
+    {
+        AttributeMap(iter.into_iter().collect())
+    }
+}
+
+impl<'a> IntoIterator for &'a AttributeMap {
+    type Item = (&'a AttrKind, &'a AttrValue);
+    type IntoIter = btree_map::Iter<'a, AttrKind, AttrValue>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A change to a single attribute, as computed by `AttributeMap::diff`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AttrChange<'a> {
+    /// Attribute is only present in the second map
+    Added(&'a AttrValue),
+
+    /// Attribute is only present in the first map
+    Removed(&'a AttrValue),
+
+    /// Attribute is present in both maps with different values
+    Changed {
+        /// Value in the first map
+        old: &'a AttrValue,
+
+        /// Value in the second map
+        new: &'a AttrValue,
+    },
+}
@@ -0,0 +1,561 @@
+//! Encrypted file-backed keychain backend implemented in pure Rust.
+//!
+//! The whole item database is stored in a single file, encrypted with
+//! AES-256-GCM under a key derived from the keychain's password. The file
+//! consists of a header containing the KDF parameters, a random salt, and
+//! a random nonce (all of which are authenticated as AAD), followed by the
+//! encrypted database.
+
+use super::{memory::MemoryBackend, Handle, KeychainBackend};
+use crate::{
+    attr::AttributeMap,
+    encoding::Pbes2Kdf,
+    error::{Error, ErrorKind},
+    keychain::{
+        item::{Class, MatchLimit, Query},
+        key::{KeyAlgorithm, KeyOperation, KeyPairGenerateParams, RestoreKeyParams},
+    },
+};
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes256Gcm, KeyInit,
+};
+use base64ct::{Base64, Encoding};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::{
+    convert::TryInto,
+    fmt::{self, Debug},
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Magic bytes at the start of every keychain file
+const MAGIC: &[u8; 8] = b"RSKCHAIN";
+
+/// Version of the keychain file format
+const VERSION: u8 = 1;
+
+/// KDF identifier for PBKDF2-HMAC-SHA256
+const KDF_PBKDF2_SHA256: u8 = 1;
+
+/// KDF identifier for scrypt
+const KDF_SCRYPT: u8 = 2;
+
+/// Size of the random salt passed to the KDF
+const SALT_SIZE: usize = 16;
+
+/// Size of an AES-GCM nonce
+const NONCE_SIZE: usize = 12;
+
+/// Default KDF: scrypt with `N = 2^15`, `r = 8`, and `p = 1`
+const DEFAULT_KDF: Pbes2Kdf = Pbes2Kdf::Scrypt {
+    log_n: 15,
+    r: 8,
+    p: 1,
+};
+
+/// Keychain backend which stores items in a password-encrypted file and
+/// performs all cryptographic operations in software.
+///
+/// Items are kept in memory once the file is opened, and every change is
+/// written back to the file atomically (by writing a temporary file and
+/// renaming it over the original), so a crash never leaves a partially
+/// written keychain behind. The file isn't locked, so it shouldn't be
+/// modified by several processes at the same time.
+pub struct FileBackend {
+    /// Location of the keychain file
+    path: PathBuf,
+
+    /// Items in the keychain
+    items: MemoryBackend,
+
+    /// Encryption parameters, which are also locked while changes are
+    /// written to the file
+    state: Mutex<State>,
+}
+
+/// Encryption parameters of an open keychain file
+struct State {
+    /// KDF used to derive the encryption key from the password
+    kdf: Pbes2Kdf,
+
+    /// Salt passed to the KDF
+    salt: [u8; SALT_SIZE],
+
+    /// Encryption key derived from the password
+    key: Zeroizing<[u8; 32]>,
+
+    /// Has the keychain been deleted?
+    deleted: bool,
+}
+
+impl FileBackend {
+    /// Create a new, empty keychain file at the given path, encrypted under
+    /// a key derived from the given password using scrypt.
+    ///
+    /// Returns an error with a kind of `ErrorKind::DuplicateKeychain` if a
+    /// file already exists at the given path.
+    pub fn create<P: AsRef<[u8]>>(path: &Path, password: P) -> Result<Self, Error> {
+        Self::create_with_kdf(path, password, DEFAULT_KDF)
+    }
+
+    /// Create a new, empty keychain file using the given KDF to derive the
+    /// encryption key from the password
+    pub fn create_with_kdf<P: AsRef<[u8]>>(
+        path: &Path,
+        password: P,
+        kdf: Pbes2Kdf,
+    ) -> Result<Self, Error> {
+        let mut salt = [0u8; SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        let key = kdf.derive_key(password.as_ref(), &salt)?;
+
+        // Claim the path with an empty file before saving over it, so that
+        // an existing keychain is never replaced
+        create_new(path).map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => Error::new(
+                ErrorKind::DuplicateKeychain,
+                &format!("keychain already exists: {}", path.display()),
+            ),
+            _ => e.into(),
+        })?;
+
+        let backend = Self {
+            path: path.to_owned(),
+            items: MemoryBackend::new(),
+            state: Mutex::new(State {
+                kdf,
+                salt,
+                key,
+                deleted: false,
+            }),
+        };
+
+        if let Err(e) = backend.save(&backend.state()) {
+            let _ = fs::remove_file(path);
+            return Err(e);
+        }
+
+        Ok(backend)
+    }
+
+    /// Open an existing keychain file using its password.
+    ///
+    /// Returns an error with a kind of `ErrorKind::AuthFailed` if the
+    /// password is incorrect (or the file has been tampered with), or
+    /// `ErrorKind::NoSuchKeychain` if the file doesn't exist.
+    pub fn open<P: AsRef<[u8]>>(path: &Path, password: P) -> Result<Self, Error> {
+        let contents = fs::read(path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => no_such_keychain(path),
+            _ => e.into(),
+        })?;
+
+        let (kdf, salt, header_len) = parse_header(&contents)?;
+        let key = kdf.derive_key(password.as_ref(), &salt)?;
+
+        let (aad, ciphertext) = contents.split_at(header_len);
+        let nonce: [u8; NONCE_SIZE] = aad[header_len - NONCE_SIZE..].try_into().unwrap();
+
+        let plaintext = Zeroizing::new(
+            Aes256Gcm::new(&(*key).into())
+                .decrypt(
+                    &nonce.into(),
+                    Payload {
+                        msg: ciphertext,
+                        aad,
+                    },
+                )
+                .map_err(|_| {
+                    Error::new(
+                        ErrorKind::AuthFailed,
+                        "couldn't decrypt keychain (wrong password?)",
+                    )
+                })?,
+        );
+
+        let stored_items: Vec<StoredItem> =
+            serde_json::from_slice(&plaintext).map_err(|e| invalid_keychain(&e))?;
+
+        let items = stored_items
+            .iter()
+            .map(|item| {
+                Base64::decode_vec(&item.data)
+                    .map(|data| (item.class, item.attrs.clone(), Zeroizing::new(data)))
+                    .map_err(|e| invalid_keychain(&e))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let backend = Self {
+            path: path.to_owned(),
+            items: MemoryBackend::new(),
+            state: Mutex::new(State {
+                kdf,
+                salt,
+                key,
+                deleted: false,
+            }),
+        };
+
+        backend.items.replace_items(&items)?;
+        Ok(backend)
+    }
+
+    /// Get the path to the keychain file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Lock the encryption parameters
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Apply a change to the items in this keychain and write them to the
+    /// file, undoing the change if the file couldn't be written
+    fn modify<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&MemoryBackend) -> Result<T, Error>,
+    {
+        let state = self.state();
+
+        if state.deleted {
+            return Err(no_such_keychain(&self.path));
+        }
+
+        let snapshot = self.items.export_items();
+        let result = f(&self.items)?;
+
+        if let Err(e) = self.save(&state) {
+            self.items.replace_items(&snapshot)?;
+            return Err(e);
+        }
+
+        Ok(result)
+    }
+
+    /// Encrypt the items in this keychain and atomically replace the file
+    fn save(&self, state: &State) -> Result<(), Error> {
+        let stored_items: Vec<_> = self
+            .items
+            .export_items()
+            .into_iter()
+            .map(|(class, attrs, data)| StoredItem {
+                class,
+                attrs,
+                data: Base64::encode_string(&data),
+            })
+            .collect();
+
+        let plaintext = Zeroizing::new(
+            serde_json::to_vec(&stored_items).expect("keychain serialization failed"),
+        );
+
+        let mut nonce = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+
+        let mut contents = encode_header(state.kdf, &state.salt);
+        contents.extend_from_slice(&nonce);
+
+        let ciphertext = Aes256Gcm::new(&(*state.key).into())
+            .encrypt(
+                &nonce.into(),
+                Payload {
+                    msg: &plaintext,
+                    aad: &contents,
+                },
+            )
+            .expect("AES-GCM encryption failed");
+
+        contents.extend_from_slice(&ciphertext);
+        write_atomically(&self.path, &contents)
+    }
+}
+
+impl Debug for FileBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "FileBackend {{ path: {:?}, items: {} }}",
+            self.path,
+            self.items.export_items().len()
+        )
+    }
+}
+
+impl KeychainBackend for FileBackend {
+    fn add(&self, class: Class, attrs: &AttributeMap, data: &[u8]) -> Result<Handle, Error> {
+        self.modify(|items| items.add(class, attrs, data))
+    }
+
+    fn copy_matching(
+        &self,
+        class: Class,
+        query: &Query,
+        limit: MatchLimit,
+    ) -> Result<Vec<Handle>, Error> {
+        self.items.copy_matching(class, query, limit)
+    }
+
+    fn update(&self, class: Class, query: &Query, attrs: &AttributeMap) -> Result<(), Error> {
+        self.modify(|items| items.update(class, query, attrs))
+    }
+
+    fn delete(&self, class: Class, query: &Query) -> Result<(), Error> {
+        self.modify(|items| items.delete(class, query))
+    }
+
+    fn delete_keychain(&self) -> Result<(), Error> {
+        let mut state = self.state();
+
+        if state.deleted {
+            return Err(no_such_keychain(&self.path));
+        }
+
+        fs::remove_file(&self.path)?;
+        self.items.delete_keychain()?;
+        state.deleted = true;
+        Ok(())
+    }
+
+    fn attributes(&self, handle: &Handle) -> Result<AttributeMap, Error> {
+        self.items.attributes(handle)
+    }
+
+    fn data(&self, handle: &Handle) -> Result<Vec<u8>, Error> {
+        self.items.data(handle)
+    }
+
+    fn create_key_pair(&self, params: &KeyPairGenerateParams) -> Result<(Handle, Handle), Error> {
+        self.modify(|items| items.create_key_pair(params))
+    }
+
+    fn restore_key(&self, params: &RestoreKeyParams) -> Result<Handle, Error> {
+        self.items.restore_key(params)
+    }
+
+    fn export_key(&self, key: &Handle) -> Result<Vec<u8>, Error> {
+        self.items.export_key(key)
+    }
+
+    fn copy_public_key(&self, key: &Handle) -> Result<Handle, Error> {
+        self.items.copy_public_key(key)
+    }
+
+    fn is_algorithm_supported(
+        &self,
+        key: &Handle,
+        operation: KeyOperation,
+        alg: KeyAlgorithm,
+    ) -> bool {
+        self.items.is_algorithm_supported(key, operation, alg)
+    }
+
+    fn sign(&self, key: &Handle, alg: KeyAlgorithm, data: &[u8]) -> Result<Vec<u8>, Error> {
+        self.items.sign(key, alg, data)
+    }
+
+    fn verify(
+        &self,
+        key: &Handle,
+        alg: KeyAlgorithm,
+        signed_data: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, Error> {
+        self.items.verify(key, alg, signed_data, signature)
+    }
+
+    fn key_exchange(
+        &self,
+        key: &Handle,
+        alg: KeyAlgorithm,
+        public_key: &Handle,
+        requested_size: usize,
+        shared_info: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        self.items
+            .key_exchange(key, alg, public_key, requested_size, shared_info)
+    }
+
+    fn encrypt(&self, key: &Handle, alg: KeyAlgorithm, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        self.items.encrypt(key, alg, plaintext)
+    }
+
+    fn decrypt(
+        &self,
+        key: &Handle,
+        alg: KeyAlgorithm,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        self.items.decrypt(key, alg, ciphertext)
+    }
+}
+
+/// Items as serialized in the encrypted database
+#[derive(Deserialize, Serialize)]
+struct StoredItem {
+    /// Class of this item
+    class: Class,
+
+    /// Attributes of this item
+    attrs: AttributeMap,
+
+    /// Base64-encoded secret data (e.g. password) or key external
+    /// representation
+    data: String,
+}
+
+impl Drop for StoredItem {
+    fn drop(&mut self) {
+        self.data.zeroize();
+    }
+}
+
+/// Serialize the file header, excluding the nonce
+fn encode_header(kdf: Pbes2Kdf, salt: &[u8]) -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.push(VERSION);
+
+    match kdf {
+        Pbes2Kdf::Pbkdf2Sha256 { iterations } => {
+            header.push(KDF_PBKDF2_SHA256);
+            header.extend_from_slice(&iterations.to_be_bytes());
+        }
+        Pbes2Kdf::Scrypt { log_n, r, p } => {
+            header.push(KDF_SCRYPT);
+            header.push(log_n);
+            header.extend_from_slice(&r.to_be_bytes());
+            header.extend_from_slice(&p.to_be_bytes());
+        }
+    }
+
+    header.extend_from_slice(salt);
+    header
+}
+
+/// Parse the file header, returning the KDF, salt, and the length of the
+/// header (including the nonce which ends it)
+fn parse_header(contents: &[u8]) -> Result<(Pbes2Kdf, [u8; SALT_SIZE], usize), Error> {
+    let truncated = || invalid_keychain(&"truncated header");
+
+    if !contents.starts_with(MAGIC) {
+        return Err(invalid_keychain(&"not a keychain file"));
+    }
+
+    let mut pos = MAGIC.len();
+    let mut take = |len: usize| {
+        let bytes = contents.get(pos..pos + len).ok_or_else(truncated)?;
+        pos += len;
+        Ok::<_, Error>(bytes)
+    };
+
+    let version = take(1)?[0];
+
+    if version != VERSION {
+        return Err(Error::new(
+            ErrorKind::Unimplemented,
+            &format!("unsupported keychain file version: {}", version),
+        ));
+    }
+
+    let kdf = match take(1)?[0] {
+        KDF_PBKDF2_SHA256 => Pbes2Kdf::Pbkdf2Sha256 {
+            iterations: u32::from_be_bytes(take(4)?.try_into().unwrap()),
+        },
+        KDF_SCRYPT => Pbes2Kdf::Scrypt {
+            log_n: take(1)?[0],
+            r: u32::from_be_bytes(take(4)?.try_into().unwrap()),
+            p: u32::from_be_bytes(take(4)?.try_into().unwrap()),
+        },
+        id => {
+            return Err(Error::new(
+                ErrorKind::Unimplemented,
+                &format!("unsupported keychain KDF: {}", id),
+            ))
+        }
+    };
+
+    let salt = take(SALT_SIZE)?.try_into().unwrap();
+    take(NONCE_SIZE)?;
+
+    Ok((kdf, salt, pos))
+}
+
+/// Replace the file at the given path with the given contents, such that
+/// the file contains either the old or new contents even if we crash
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::new(ErrorKind::Param, "keychain path is not a file"))?;
+
+    let mut tmp_name = file_name.to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let result = write_and_sync(&tmp_path, contents).and_then(|()| fs::rename(&tmp_path, path));
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+
+    // Sync the directory so the rename itself is durable
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Write the given contents to a new file readable only by its owner and
+/// flush them to disk
+fn write_and_sync(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// Create an empty file readable only by its owner, failing if the path
+/// already exists
+fn create_new(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    options.open(path)
+}
+
+/// Error for keychain files which don't exist (or have been deleted)
+fn no_such_keychain(path: &Path) -> Error {
+    Error::new(
+        ErrorKind::NoSuchKeychain,
+        &format!("no such keychain: {}", path.display()),
+    )
+}
+
+/// Error for malformed keychain files
+fn invalid_keychain<E: fmt::Display + ?Sized>(err: &E) -> Error {
+    Error::new(
+        ErrorKind::Decode,
+        &format!("invalid keychain file: {}", err),
+    )
+}
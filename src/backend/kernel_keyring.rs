@@ -0,0 +1,643 @@
+//! Keychain backend which stores passwords in the Linux kernel's key
+//! retention service, using the `add_key` and `keyctl` system calls.
+//!
+//! See `keyrings(7)`:
+//! <https://man7.org/linux/man-pages/man7/keyrings.7.html>
+
+use super::{memory::is_duplicate, Handle, KeychainBackend};
+use crate::{
+    attr::{AttrAccessible, AttrKind, AttrValue, AttributeMap},
+    error::{Error, ErrorKind},
+    keychain::{
+        item::{Class, MatchLimit, Query},
+        key::{KeyAlgorithm, KeyOperation, KeyPairGenerateParams, RestoreKeyParams},
+    },
+};
+use libc::{c_char, c_long, c_ulong};
+use std::{ffi::CString, io, ptr, time::Duration};
+use zeroize::Zeroizing;
+
+/// Serial numbers which identify keys and keyrings
+type KeySerial = i32;
+
+/// Process-specific keyring
+const KEY_SPEC_PROCESS_KEYRING: KeySerial = -2;
+
+/// Session-specific keyring
+const KEY_SPEC_SESSION_KEYRING: KeySerial = -3;
+
+/// UID-specific keyring
+const KEY_SPEC_USER_KEYRING: KeySerial = -4;
+
+/// Map a special keyring ID to an actual serial number
+const KEYCTL_GET_KEYRING_ID: c_ulong = 0;
+
+/// Revoke a key
+const KEYCTL_REVOKE: c_ulong = 3;
+
+/// Set the permissions mask of a key
+const KEYCTL_SETPERM: c_ulong = 5;
+
+/// Describe a key (i.e. get its type, ownership, permissions and description)
+const KEYCTL_DESCRIBE: c_ulong = 6;
+
+/// Read a key's payload (or the serial numbers of a keyring's keys)
+const KEYCTL_READ: c_ulong = 11;
+
+/// Set a timeout on a key
+const KEYCTL_SET_TIMEOUT: c_ulong = 15;
+
+/// Invalidate a key, unlinking it from all keyrings
+const KEYCTL_INVALIDATE: c_ulong = 21;
+
+/// Get the persistent keyring of a user
+const KEYCTL_GET_PERSISTENT: c_ulong = 22;
+
+/// Type of the keys passwords are stored as
+const KEY_TYPE: &[u8] = b"user\0";
+
+/// Prefix of the descriptions of keys stored by this backend
+const DESCRIPTION_PREFIX: &str = "keychain-services:generic:";
+
+/// Kernel keyrings which can hold the items of a `KernelKeyringBackend`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum KernelKeyring {
+    /// Session keyring, shared by processes in the same login session
+    /// (`KEY_SPEC_SESSION_KEYRING`)
+    Session,
+
+    /// User keyring, shared by all processes running as the current user
+    /// (`KEY_SPEC_USER_KEYRING`)
+    User,
+
+    /// Persistent keyring of the current user, which outlives login
+    /// sessions and is retained by the kernel until it expires (by default,
+    /// after three days of disuse). See `persistent-keyring(7)`.
+    Persistent,
+}
+
+/// Keychain backend which stores `GenericPassword` items as `user` keys in
+/// a Linux kernel keyring.
+///
+/// Keys are described by the service and account of their item (as
+/// `keychain-services:generic:<service>:<account>`, with `%` and `:`
+/// percent-encoded), and hold the password as their payload. Secrets are
+/// kept in kernel memory only and never written to disk, and are available
+/// once the user's keyring exists, i.e. items behave as if they were
+/// `AttrAccessible::AfterFirstUnlockThisDeviceOnly`.
+///
+/// Items can optionally be created with a timeout after which the kernel
+/// expires them, and a permissions mask (see `keyctl_setperm(3)`).
+///
+/// Cryptographic keys and other item classes are not supported.
+#[derive(Debug)]
+pub struct KernelKeyringBackend {
+    /// Serial number of the keyring items are stored in
+    keyring: KeySerial,
+
+    /// Timeout after which new items expire
+    timeout: Option<Duration>,
+
+    /// Permissions mask of new items
+    permissions: Option<u32>,
+}
+
+impl KernelKeyringBackend {
+    /// Store items in the given keyring
+    pub fn new(keyring: KernelKeyring) -> Result<Self, Error> {
+        let keyring = match keyring {
+            KernelKeyring::Session => keyctl(
+                KEYCTL_GET_KEYRING_ID,
+                &[KEY_SPEC_SESSION_KEYRING as c_ulong, 1],
+            )?,
+            KernelKeyring::User => keyctl(
+                KEYCTL_GET_KEYRING_ID,
+                &[KEY_SPEC_USER_KEYRING as c_ulong, 1],
+            )?,
+            KernelKeyring::Persistent => keyctl(
+                KEYCTL_GET_PERSISTENT,
+                &[-1i32 as c_ulong, KEY_SPEC_PROCESS_KEYRING as c_ulong],
+            )?,
+        };
+
+        Ok(Self {
+            keyring: keyring as KeySerial,
+            timeout: None,
+            permissions: None,
+        })
+    }
+
+    /// Expire items after the given timeout (rounded up to whole seconds)
+    /// once they've been added or updated
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the permissions mask of new items, e.g. `0x3f00_0000` to only
+    /// allow processes which possess the keyring to access them. See
+    /// `keyctl_setperm(3)` for the format of the mask.
+    pub fn permissions(mut self, permissions: u32) -> Self {
+        self.permissions = Some(permissions);
+        self
+    }
+
+    /// Revoke all items matching the given query. Unlike deleted items,
+    /// revoked keys remain in the keyring until the kernel garbage collects
+    /// them, but any attempt to use them (including via other keyrings
+    /// they've been linked into) fails.
+    pub fn revoke(&self, query: &Query) -> Result<(), Error> {
+        for (serial, _) in self.find(query, MatchLimit::All)? {
+            keyctl(KEYCTL_REVOKE, &[serial as c_ulong])?;
+        }
+
+        Ok(())
+    }
+
+    /// Find items which match a query, returning their serial numbers and
+    /// attributes. Returns an error with a kind of `ErrorKind::ItemNotFound`
+    /// if no items match.
+    fn find(
+        &self,
+        query: &Query,
+        limit: MatchLimit,
+    ) -> Result<Vec<(KeySerial, AttributeMap)>, Error> {
+        let items = self.items()?;
+
+        let results: Vec<_> = query
+            .select(items.iter().map(|(_, attrs)| attrs), limit)
+            .into_iter()
+            .map(|i| items[i].clone())
+            .collect();
+
+        if results.is_empty() {
+            Err(Error::new(
+                ErrorKind::ItemNotFound,
+                "The specified item could not be found in the keychain.",
+            ))
+        } else {
+            Ok(results)
+        }
+    }
+
+    /// Get the serial numbers and attributes of all items in the keyring,
+    /// skipping keys which weren't stored by this backend or are no longer
+    /// usable (i.e. revoked or expired)
+    fn items(&self) -> Result<Vec<(KeySerial, AttributeMap)>, Error> {
+        let contents = read(self.keyring)?;
+        let mut items = vec![];
+
+        for serial in contents.chunks_exact(4) {
+            let serial = KeySerial::from_ne_bytes([serial[0], serial[1], serial[2], serial[3]]);
+
+            let description = match describe(serial) {
+                Ok(description) => description,
+                Err(e) if *e.kind() == ErrorKind::ItemNotFound => continue,
+                Err(e) if *e.kind() == ErrorKind::AuthFailed => continue,
+                Err(e) => return Err(e),
+            };
+
+            if let Some(attrs) = parse_description(&description) {
+                items.push((serial, attrs));
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Add a key to the keyring, applying the configured timeout and
+    /// permissions
+    fn add_key(&self, attrs: &AttributeMap, data: &[u8]) -> Result<KeySerial, Error> {
+        let description = CString::new(encode_description(attrs)?)
+            .map_err(|e| Error::new(ErrorKind::Param, &e))?;
+
+        let serial = unsafe {
+            libc::syscall(
+                libc::SYS_add_key,
+                KEY_TYPE.as_ptr() as *const c_char,
+                description.as_ptr(),
+                data.as_ptr(),
+                data.len(),
+                self.keyring,
+            )
+        };
+
+        if serial < 0 {
+            return Err(keyctl_error(io::Error::last_os_error()));
+        }
+
+        let serial = serial as KeySerial;
+
+        if let Err(e) = self.configure(serial) {
+            let _ = keyctl(KEYCTL_INVALIDATE, &[serial as c_ulong]);
+            return Err(e);
+        }
+
+        Ok(serial)
+    }
+
+    /// Apply the configured timeout and permissions to a key
+    fn configure(&self, serial: KeySerial) -> Result<(), Error> {
+        if let Some(timeout) = self.timeout {
+            let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+            keyctl(
+                KEYCTL_SET_TIMEOUT,
+                &[serial as c_ulong, secs.max(1) as c_ulong],
+            )?;
+        }
+
+        if let Some(permissions) = self.permissions {
+            keyctl(KEYCTL_SETPERM, &[serial as c_ulong, permissions as c_ulong])?;
+        }
+
+        Ok(())
+    }
+}
+
+impl KeychainBackend for KernelKeyringBackend {
+    fn add(&self, class: Class, attrs: &AttributeMap, data: &[u8]) -> Result<Handle, Error> {
+        if class != Class::GenericPassword {
+            return Err(unsupported_class());
+        }
+
+        let existing = self.items()?;
+
+        if existing
+            .iter()
+            .any(|(_, other)| is_duplicate(class, attrs, other))
+        {
+            return Err(Error::new(
+                ErrorKind::DuplicateItem,
+                "The specified item already exists in the keychain.",
+            ));
+        }
+
+        let serial = self.add_key(attrs, data)?;
+        Ok(Handle::new(KernelKey(serial)))
+    }
+
+    fn copy_matching(
+        &self,
+        class: Class,
+        query: &Query,
+        limit: MatchLimit,
+    ) -> Result<Vec<Handle>, Error> {
+        if class != Class::GenericPassword {
+            return Err(unsupported_class());
+        }
+
+        Ok(self
+            .find(query, limit)?
+            .into_iter()
+            .map(|(serial, _)| Handle::new(KernelKey(serial)))
+            .collect())
+    }
+
+    fn update(&self, class: Class, query: &Query, attrs: &AttributeMap) -> Result<(), Error> {
+        if class != Class::GenericPassword {
+            return Err(unsupported_class());
+        }
+
+        let all_items = self.items()?;
+        let updates: Vec<_> = self
+            .find(query, MatchLimit::All)?
+            .into_iter()
+            .map(|(serial, existing)| {
+                let mut updated = existing.clone();
+                updated.extend(attrs.iter().map(|(k, v)| (*k, v.clone())));
+                (serial, existing, updated)
+            })
+            .collect();
+
+        // Ensure the update doesn't cause two items to share a primary key
+        for (serial, _, updated) in &updates {
+            for (other_serial, other) in &all_items {
+                let other = updates
+                    .iter()
+                    .find(|(s, _, _)| s == other_serial)
+                    .map(|(_, _, attrs)| attrs)
+                    .unwrap_or(other);
+
+                if other_serial != serial && is_duplicate(class, updated, other) {
+                    return Err(Error::new(
+                        ErrorKind::DuplicateItem,
+                        "The specified item already exists in the keychain.",
+                    ));
+                }
+            }
+        }
+
+        // Keys are identified by their description, so changing the service
+        // or account replaces them with a new key
+        for (serial, existing, updated) in updates {
+            if encode_description(&updated)? != encode_description(&existing)? {
+                let password = Zeroizing::new(read(serial)?);
+                self.add_key(&updated, &password)?;
+                keyctl(KEYCTL_INVALIDATE, &[serial as c_ulong])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delete(&self, class: Class, query: &Query) -> Result<(), Error> {
+        if class != Class::GenericPassword {
+            return Err(unsupported_class());
+        }
+
+        for (serial, _) in self.find(query, MatchLimit::All)? {
+            keyctl(KEYCTL_INVALIDATE, &[serial as c_ulong])?;
+        }
+
+        Ok(())
+    }
+
+    fn delete_keychain(&self) -> Result<(), Error> {
+        for (serial, _) in self.items()? {
+            keyctl(KEYCTL_INVALIDATE, &[serial as c_ulong])?;
+        }
+
+        Ok(())
+    }
+
+    fn attributes(&self, handle: &Handle) -> Result<AttributeMap, Error> {
+        parse_description(&describe(key(handle)?.0)?).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidItemRef,
+                "key was not stored by a KernelKeyringBackend",
+            )
+        })
+    }
+
+    fn data(&self, handle: &Handle) -> Result<Vec<u8>, Error> {
+        read(key(handle)?.0)
+    }
+
+    fn create_key_pair(&self, _params: &KeyPairGenerateParams) -> Result<(Handle, Handle), Error> {
+        Err(unsupported_class())
+    }
+
+    fn restore_key(&self, _params: &RestoreKeyParams) -> Result<Handle, Error> {
+        Err(unsupported_class())
+    }
+
+    fn export_key(&self, _key: &Handle) -> Result<Vec<u8>, Error> {
+        Err(unsupported_class())
+    }
+
+    fn copy_public_key(&self, _key: &Handle) -> Result<Handle, Error> {
+        Err(unsupported_class())
+    }
+
+    fn is_algorithm_supported(
+        &self,
+        _key: &Handle,
+        _operation: KeyOperation,
+        _alg: KeyAlgorithm,
+    ) -> bool {
+        false
+    }
+
+    fn sign(&self, _key: &Handle, _alg: KeyAlgorithm, _data: &[u8]) -> Result<Vec<u8>, Error> {
+        Err(unsupported_class())
+    }
+
+    fn verify(
+        &self,
+        _key: &Handle,
+        _alg: KeyAlgorithm,
+        _signed_data: &[u8],
+        _signature: &[u8],
+    ) -> Result<bool, Error> {
+        Err(unsupported_class())
+    }
+
+    fn key_exchange(
+        &self,
+        _key: &Handle,
+        _alg: KeyAlgorithm,
+        _public_key: &Handle,
+        _requested_size: usize,
+        _shared_info: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        Err(unsupported_class())
+    }
+
+    fn encrypt(
+        &self,
+        _key: &Handle,
+        _alg: KeyAlgorithm,
+        _plaintext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        Err(unsupported_class())
+    }
+
+    fn decrypt(
+        &self,
+        _key: &Handle,
+        _alg: KeyAlgorithm,
+        _ciphertext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        Err(unsupported_class())
+    }
+}
+
+/// Keys stored in a kernel keyring
+struct KernelKey(KeySerial);
+
+/// Get the key referenced by a handle
+fn key(handle: &Handle) -> Result<&KernelKey, Error> {
+    handle.downcast_ref::<KernelKey>().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidItemRef,
+            "handle does not belong to a KernelKeyringBackend",
+        )
+    })
+}
+
+/// Invoke the `keyctl` system call with the given arguments
+fn keyctl(operation: c_ulong, args: &[c_ulong]) -> Result<c_long, Error> {
+    let arg = |i: usize| args.get(i).cloned().unwrap_or(0);
+    let result =
+        unsafe { libc::syscall(libc::SYS_keyctl, operation, arg(0), arg(1), arg(2), arg(3)) };
+
+    if result < 0 {
+        Err(keyctl_error(io::Error::last_os_error()))
+    } else {
+        Ok(result)
+    }
+}
+
+/// Read the payload of a key, or the serial numbers of the keys in a
+/// keyring
+fn read(serial: KeySerial) -> Result<Vec<u8>, Error> {
+    read_buffer(KEYCTL_READ, serial)
+}
+
+/// Get the description of a key, i.e. `type;uid;gid;perm;description`
+fn describe(serial: KeySerial) -> Result<String, Error> {
+    let mut description = read_buffer(KEYCTL_DESCRIBE, serial)?;
+
+    // Remove the trailing NUL
+    description.pop();
+
+    String::from_utf8(description).map_err(|e| Error::new(ErrorKind::Decode, &e))
+}
+
+/// Invoke a `keyctl` operation which fills a buffer, retrying if the
+/// buffer was too small (e.g. because the key was updated concurrently)
+fn read_buffer(operation: c_ulong, serial: KeySerial) -> Result<Vec<u8>, Error> {
+    let mut len = keyctl(
+        operation,
+        &[serial as c_ulong, ptr::null_mut::<u8>() as c_ulong, 0],
+    )?;
+
+    loop {
+        let mut buffer = Zeroizing::new(vec![0u8; len as usize]);
+        let required = keyctl(
+            operation,
+            &[
+                serial as c_ulong,
+                buffer.as_mut_ptr() as c_ulong,
+                buffer.len() as c_ulong,
+            ],
+        )?;
+
+        if required <= len {
+            buffer.truncate(required as usize);
+            return Ok(buffer.to_vec());
+        }
+
+        len = required;
+    }
+}
+
+/// Encode the description of the key for a generic password with the given
+/// attributes, ensuring all of the attributes can be stored
+fn encode_description(attrs: &AttributeMap) -> Result<String, Error> {
+    let mut service = "";
+    let mut account = "";
+
+    for (kind, value) in attrs.iter() {
+        match (kind, value) {
+            (AttrKind::Service, AttrValue::String(value)) => service = value,
+            (AttrKind::Account, AttrValue::String(value)) => account = value,
+            (AttrKind::Accessible, AttrValue::String(value)) => check_accessible(value)?,
+            (AttrKind::Synchronizable, AttrValue::Bool(false)) => (),
+            (AttrKind::CreationDate, _) | (AttrKind::ModificationDate, _) => (),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::NoSuchAttr,
+                    &format!("attribute {:?} unsupported by kernel keyrings", kind),
+                ))
+            }
+        }
+    }
+
+    Ok(format!(
+        "{}{}:{}",
+        DESCRIPTION_PREFIX,
+        escape(service),
+        escape(account)
+    ))
+}
+
+/// Parse the attributes of a generic password from a key description (as
+/// returned by `KEYCTL_DESCRIBE`), if it was stored by this backend
+fn parse_description(description: &str) -> Option<AttributeMap> {
+    let mut fields = description.splitn(5, ';');
+
+    if fields.next()? != "user" {
+        return None;
+    }
+
+    let (service, account) = fields
+        .nth(3)?
+        .strip_prefix(DESCRIPTION_PREFIX)?
+        .split_once(':')?;
+
+    let mut attrs = AttributeMap::new();
+    attrs.insert(AttrKind::Service, unescape(service)?);
+    attrs.insert(AttrKind::Account, unescape(account)?);
+    attrs.insert_attr(&AttrAccessible::AfterFirstUnlockThisDeviceOnly);
+    Some(attrs)
+}
+
+/// Ensure the given `kSecAttrAccessible` value can be honored: kernel keys
+/// are usable whenever the keyring is, regardless of whether the device is
+/// locked
+fn check_accessible(value: &str) -> Result<(), Error> {
+    let supported = [
+        AttrAccessible::AfterFirstUnlockThisDeviceOnly,
+        AttrAccessible::AfterFirstUnlock,
+        AttrAccessible::AlwaysThisDeviceOnly,
+        AttrAccessible::Always,
+    ];
+
+    if supported
+        .iter()
+        .any(|accessible| accessible.as_str() == value)
+    {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::Unimplemented,
+            &format!("accessibility unsupported by kernel keyrings: {}", value),
+        ))
+    }
+}
+
+/// Percent-encode `%` and `:` in a description field
+fn escape(field: &str) -> String {
+    field.replace('%', "%25").replace(':', "%3A")
+}
+
+/// Decode a description field encoded with `escape`
+fn unescape(field: &str) -> Option<String> {
+    if field.contains(':') {
+        return None;
+    }
+
+    let mut result = String::with_capacity(field.len());
+    let mut rest = field;
+
+    while let Some(i) = rest.find('%') {
+        result.push_str(&rest[..i]);
+
+        match rest.get(i + 1..i + 3)? {
+            "25" => result.push('%'),
+            "3A" => result.push(':'),
+            _ => return None,
+        }
+
+        rest = &rest[i + 3..];
+    }
+
+    result.push_str(rest);
+    Some(result)
+}
+
+/// Error for item classes (and key operations) kernel keyrings can't store
+fn unsupported_class() -> Error {
+    Error::new(
+        ErrorKind::Unimplemented,
+        "kernel keyring backend only supports generic passwords",
+    )
+}
+
+/// Convert an error from a keyring system call into an `Error` with the
+/// closest `ErrorKind`
+fn keyctl_error(error: io::Error) -> Error {
+    let kind = match error.raw_os_error() {
+        Some(libc::ENOKEY) | Some(libc::EKEYEXPIRED) | Some(libc::EKEYREVOKED) => {
+            ErrorKind::ItemNotFound
+        }
+        Some(libc::EACCES) | Some(libc::EPERM) => ErrorKind::AuthFailed,
+        Some(libc::EDQUOT) => ErrorKind::DataTooLarge,
+        Some(libc::EINVAL) => ErrorKind::Param,
+        Some(libc::ENOSYS) => ErrorKind::NotAvailable,
+        _ => return error.into(),
+    };
+
+    Error::new(kind, &error)
+}
@@ -0,0 +1,502 @@
+//! In-memory keychain backend implemented in pure Rust.
+
+use super::{Handle, KeychainBackend};
+use crate::{
+    attr::{AttrKeyClass, AttrKeyType, AttrKind, AttrValue, AttributeMap},
+    error::{Error, ErrorKind},
+    keychain::{
+        item::{Class, MatchLimit, Query},
+        key::{KeyAlgorithm, KeyOperation, KeyPairGenerateParams, RestoreKeyParams},
+    },
+    software::{PrivateKey, SoftwareKey},
+};
+use std::{
+    fmt::{self, Debug},
+    sync::{Arc, Mutex, MutexGuard, RwLock},
+    time::SystemTime,
+};
+use zeroize::Zeroizing;
+
+/// Keychain backend which stores items in memory and performs all
+/// cryptographic operations in software.
+///
+/// Items are lost when the backend is dropped. This backend is useful for
+/// testing code which uses keychains on platforms without Keychain Services.
+#[derive(Default)]
+pub struct MemoryBackend {
+    items: Mutex<Vec<Arc<Entry>>>,
+}
+
+impl MemoryBackend {
+    /// Create a new, empty in-memory backend
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lock the item store
+    fn items(&self) -> MutexGuard<'_, Vec<Arc<Entry>>> {
+        self.items.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Add entries to the item store, ensuring none of them duplicate an
+    /// existing item
+    fn insert(&self, entries: &[&Arc<Entry>]) -> Result<(), Error> {
+        let mut items = self.items();
+        let now = SystemTime::now();
+
+        for entry in entries {
+            let attrs = entry.update_attributes(|attrs| {
+                attrs.insert(AttrKind::CreationDate, now);
+                attrs.insert(AttrKind::ModificationDate, now);
+            });
+
+            if items.iter().any(|item| {
+                item.class == entry.class && is_duplicate(entry.class, &item.attributes(), &attrs)
+            }) {
+                return Err(duplicate_item());
+            }
+        }
+
+        items.extend(entries.iter().map(|entry| Arc::clone(entry)));
+        Ok(())
+    }
+
+    /// Copy the class, attributes, and data (i.e. the external
+    /// representation for keys) of every item, e.g. to persist them
+    pub(super) fn export_items(&self) -> Vec<(Class, AttributeMap, Zeroizing<Vec<u8>>)> {
+        self.items()
+            .iter()
+            .map(|item| {
+                let data = match &item.key {
+                    Some(key) => key.to_external_representation(),
+                    None => item.data.clone(),
+                };
+
+                (item.class, item.attributes(), data)
+            })
+            .collect()
+    }
+
+    /// Replace all items with ones previously returned by `export_items`,
+    /// preserving their attributes (including creation and modification
+    /// dates)
+    pub(super) fn replace_items(
+        &self,
+        items: &[(Class, AttributeMap, Zeroizing<Vec<u8>>)],
+    ) -> Result<(), Error> {
+        let entries = items
+            .iter()
+            .map(|(class, attrs, data)| Entry::new(*class, attrs, data).map(Arc::new))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        *self.items() = entries;
+        Ok(())
+    }
+}
+
+impl Debug for MemoryBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MemoryBackend {{ items: {} }}", self.items().len())
+    }
+}
+
+impl KeychainBackend for MemoryBackend {
+    fn add(&self, class: Class, attrs: &AttributeMap, data: &[u8]) -> Result<Handle, Error> {
+        let entry = Arc::new(Entry::new(class, attrs, data)?);
+        self.insert(&[&entry])?;
+        Ok(Handle::new(entry))
+    }
+
+    fn copy_matching(
+        &self,
+        class: Class,
+        query: &Query,
+        limit: MatchLimit,
+    ) -> Result<Vec<Handle>, Error> {
+        let items: Vec<_> = self
+            .items()
+            .iter()
+            .filter(|item| item.class == class)
+            .cloned()
+            .collect();
+
+        let attrs: Vec<_> = items.iter().map(|item| item.attributes()).collect();
+
+        let results: Vec<_> = query
+            .select(&attrs, limit)
+            .into_iter()
+            .map(|i| Handle::new(items[i].clone()))
+            .collect();
+
+        if results.is_empty() {
+            Err(item_not_found())
+        } else {
+            Ok(results)
+        }
+    }
+
+    fn update(&self, class: Class, query: &Query, attrs: &AttributeMap) -> Result<(), Error> {
+        let items = self.items();
+        let mut updates = vec![];
+
+        for (i, item) in items.iter().enumerate() {
+            if item.matches(class, query) {
+                let mut updated = item.attributes();
+                updated.extend(attrs.iter().map(|(k, v)| (*k, v.clone())));
+                updated.insert(AttrKind::ModificationDate, SystemTime::now());
+                updates.push((i, updated));
+            }
+        }
+
+        if updates.is_empty() {
+            return Err(item_not_found());
+        }
+
+        // Ensure the update doesn't cause two items to share a primary key
+        for (i, updated) in &updates {
+            for (j, item) in items.iter().enumerate() {
+                let other = updates
+                    .iter()
+                    .find(|(k, _)| *k == j)
+                    .map(|(_, attrs)| attrs.clone())
+                    .unwrap_or_else(|| item.attributes());
+
+                if *i != j && item.class == class && is_duplicate(class, updated, &other) {
+                    return Err(duplicate_item());
+                }
+            }
+        }
+
+        for (i, updated) in updates {
+            items[i].update_attributes(|attrs| *attrs = updated);
+        }
+
+        Ok(())
+    }
+
+    fn delete(&self, class: Class, query: &Query) -> Result<(), Error> {
+        let mut items = self.items();
+        let count = items.len();
+        items.retain(|item| !item.matches(class, query));
+
+        if items.len() == count {
+            Err(item_not_found())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn delete_keychain(&self) -> Result<(), Error> {
+        self.items().clear();
+        Ok(())
+    }
+
+    fn attributes(&self, handle: &Handle) -> Result<AttributeMap, Error> {
+        Ok(entry(handle)?.attributes())
+    }
+
+    fn data(&self, handle: &Handle) -> Result<Vec<u8>, Error> {
+        let entry = entry(handle)?;
+
+        match &entry.key {
+            Some(key) => Ok(key.to_external_representation().to_vec()),
+            None => Ok(entry.data.to_vec()),
+        }
+    }
+
+    fn create_key_pair(&self, params: &KeyPairGenerateParams) -> Result<(Handle, Handle), Error> {
+        let private_key = PrivateKey::generate(params.key_type(), params.key_size())?;
+        let public_key = private_key.public_key();
+
+        let mut private_key_attrs = params.private_key_attributes().clone();
+
+        if let Some(access_control) = params.access_control_policy() {
+            private_key_attrs.insert_attr(&access_control.protection());
+        }
+
+        let public_key = Arc::new(Entry::key(
+            SoftwareKey::Public(public_key),
+            &params.public_key_attributes(),
+        ));
+        let private_key = Arc::new(Entry::key(
+            SoftwareKey::Private(private_key),
+            &private_key_attrs,
+        ));
+
+        // Like Keychain Services, store both keys of permanent key pairs
+        if private_key_attrs.get(AttrKind::Permanent) == Some(&AttrValue::Bool(true)) {
+            self.insert(&[&private_key, &public_key])?;
+        }
+
+        Ok((Handle::new(public_key), Handle::new(private_key)))
+    }
+
+    fn restore_key(&self, params: &RestoreKeyParams) -> Result<Handle, Error> {
+        let key = SoftwareKey::from_external_representation(
+            params.key_class,
+            params.key_type,
+            params.as_bytes(),
+        )?;
+
+        Ok(Handle::new(Arc::new(Entry::key(key, &AttributeMap::new()))))
+    }
+
+    fn export_key(&self, key: &Handle) -> Result<Vec<u8>, Error> {
+        // Like Keychain Services, refuse to export keys generated with
+        // `KeyPairGenerateParams::extractable(false)`
+        if entry(key)?.attributes().get(AttrKind::Extractable) == Some(&AttrValue::Bool(false)) {
+            return Err(Error::new(
+                ErrorKind::DataNotAvailable,
+                "key is not extractable",
+            ));
+        }
+
+        Ok(software_key(key)?.to_external_representation().to_vec())
+    }
+
+    fn copy_public_key(&self, key: &Handle) -> Result<Handle, Error> {
+        let public_key = SoftwareKey::Public(software_key(key)?.public_key());
+        Ok(Handle::new(Arc::new(Entry::key(
+            public_key,
+            &AttributeMap::new(),
+        ))))
+    }
+
+    fn is_algorithm_supported(
+        &self,
+        key: &Handle,
+        operation: KeyOperation,
+        alg: KeyAlgorithm,
+    ) -> bool {
+        software_key(key)
+            .map(|key| key.is_supported(operation, alg))
+            .unwrap_or(false)
+    }
+
+    fn sign(&self, key: &Handle, alg: KeyAlgorithm, data: &[u8]) -> Result<Vec<u8>, Error> {
+        match software_key(key)? {
+            SoftwareKey::Private(private_key) => private_key.sign(alg, data),
+            SoftwareKey::Public(_) => Err(wrong_key_class(KeyOperation::Sign)),
+        }
+    }
+
+    fn verify(
+        &self,
+        key: &Handle,
+        alg: KeyAlgorithm,
+        signed_data: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, Error> {
+        match software_key(key)? {
+            SoftwareKey::Public(public_key) => {
+                public_key.verify(alg, signed_data, signature)?;
+                Ok(true)
+            }
+            SoftwareKey::Private(_) => Err(wrong_key_class(KeyOperation::Verify)),
+        }
+    }
+
+    fn key_exchange(
+        &self,
+        key: &Handle,
+        alg: KeyAlgorithm,
+        public_key: &Handle,
+        requested_size: usize,
+        shared_info: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let private_key = match software_key(key)? {
+            SoftwareKey::Private(private_key) => private_key,
+            SoftwareKey::Public(_) => return Err(wrong_key_class(KeyOperation::KeyExchange)),
+        };
+
+        private_key
+            .key_exchange(
+                alg,
+                &software_key(public_key)?.public_key(),
+                requested_size,
+                shared_info,
+            )
+            .map(|shared_secret| shared_secret.to_vec())
+    }
+
+    fn encrypt(&self, key: &Handle, alg: KeyAlgorithm, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        match software_key(key)? {
+            SoftwareKey::Public(public_key) => public_key.encrypt(alg, plaintext),
+            SoftwareKey::Private(_) => Err(wrong_key_class(KeyOperation::Encrypt)),
+        }
+    }
+
+    fn decrypt(
+        &self,
+        key: &Handle,
+        alg: KeyAlgorithm,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        match software_key(key)? {
+            SoftwareKey::Private(private_key) => private_key.decrypt(alg, ciphertext),
+            SoftwareKey::Public(_) => Err(wrong_key_class(KeyOperation::Decrypt)),
+        }
+    }
+}
+
+/// Items stored in a `MemoryBackend`
+struct Entry {
+    /// Class of this item
+    class: Class,
+
+    /// Attributes of this item
+    attrs: RwLock<AttributeMap>,
+
+    /// Secret data (e.g. password) stored in this item
+    data: Zeroizing<Vec<u8>>,
+
+    /// Key material for `Class::Key` items
+    key: Option<SoftwareKey>,
+}
+
+impl Entry {
+    /// Create a new item of the given class. For `Class::Key` items, the
+    /// data is the key's external representation.
+    fn new(class: Class, attrs: &AttributeMap, data: &[u8]) -> Result<Self, Error> {
+        if class != Class::Key {
+            return Ok(Entry::item(class, attrs, data));
+        }
+
+        let key_class = attrs
+            .get(AttrKind::KeyClass)
+            .and_then(AttrKeyClass::from_value)
+            .ok_or_else(|| missing_attribute(AttrKind::KeyClass))?;
+
+        let key_type = attrs
+            .get(AttrKind::KeyType)
+            .and_then(AttrKeyType::from_value)
+            .ok_or_else(|| missing_attribute(AttrKind::KeyType))?;
+
+        let key = SoftwareKey::from_external_representation(key_class, key_type, data)?;
+        Ok(Entry::key(key, attrs))
+    }
+
+    /// Create a new non-key item
+    fn item(class: Class, attrs: &AttributeMap, data: &[u8]) -> Self {
+        Entry {
+            class,
+            attrs: RwLock::new(attrs.clone()),
+            data: Zeroizing::new(data.into()),
+            key: None,
+        }
+    }
+
+    /// Create a new key item, computing the attributes which are intrinsic
+    /// to the key (e.g. class, type, size, and application label)
+    fn key(key: SoftwareKey, attrs: &AttributeMap) -> Self {
+        let public_key = key.public_key();
+        let mut key_attrs = attrs.clone();
+        key_attrs.insert_attr(&key.key_class());
+        key_attrs.insert_attr(&public_key.key_type());
+        key_attrs.insert(
+            AttrKind::KeySizeInBits,
+            public_key.key_size_in_bits() as i64,
+        );
+        key_attrs.insert(
+            AttrKind::ApplicationLabel,
+            AttrValue::Data(public_key.application_label()),
+        );
+
+        Entry {
+            class: Class::Key,
+            attrs: RwLock::new(key_attrs),
+            data: Zeroizing::new(vec![]),
+            key: Some(key),
+        }
+    }
+
+    /// Get a copy of this item's attributes
+    fn attributes(&self) -> AttributeMap {
+        self.attrs.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Modify this item's attributes, returning a copy of the result
+    fn update_attributes<F>(&self, f: F) -> AttributeMap
+    where
+        F: FnOnce(&mut AttributeMap),
+    {
+        let mut attrs = self.attrs.write().unwrap_or_else(|e| e.into_inner());
+        f(&mut attrs);
+        attrs.clone()
+    }
+
+    /// Does this item match the given query?
+    fn matches(&self, class: Class, query: &Query) -> bool {
+        self.class == class && query.matches(&self.attributes())
+    }
+}
+
+/// Attributes which uniquely identify an item of a given class (i.e. the
+/// "primary key" Keychain Services uses to detect duplicate items)
+fn primary_key(class: Class) -> &'static [AttrKind] {
+    match class {
+        Class::GenericPassword => &[AttrKind::Account, AttrKind::Service],
+        Class::InternetPassword => &[AttrKind::Account, AttrKind::Server, AttrKind::Protocol],
+        Class::Key => &[
+            AttrKind::KeyClass,
+            AttrKind::ApplicationLabel,
+            AttrKind::ApplicationTag,
+            AttrKind::KeyType,
+            AttrKind::KeySizeInBits,
+        ],
+        Class::Certificate | Class::Identity => &[],
+    }
+}
+
+/// Would items with the given attributes be duplicates of each other?
+pub(super) fn is_duplicate(class: Class, a: &AttributeMap, b: &AttributeMap) -> bool {
+    let primary_key = primary_key(class);
+    !primary_key.is_empty() && primary_key.iter().all(|kind| a.get(*kind) == b.get(*kind))
+}
+
+/// Get the entry referenced by a handle
+fn entry(handle: &Handle) -> Result<&Arc<Entry>, Error> {
+    handle.downcast_ref::<Arc<Entry>>().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidItemRef,
+            "handle does not belong to a MemoryBackend",
+        )
+    })
+}
+
+/// Get the key referenced by a handle
+fn software_key(handle: &Handle) -> Result<&SoftwareKey, Error> {
+    entry(handle)?
+        .key
+        .as_ref()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidItemRef, "item is not a key"))
+}
+
+/// Error for when an item already exists
+fn duplicate_item() -> Error {
+    Error::new(
+        ErrorKind::DuplicateItem,
+        "The specified item already exists in the keychain.",
+    )
+}
+
+/// Error for when no items match a query
+fn item_not_found() -> Error {
+    Error::new(
+        ErrorKind::ItemNotFound,
+        "The specified item could not be found in the keychain.",
+    )
+}
+
+/// Error for when an item is missing a required attribute
+fn missing_attribute(kind: AttrKind) -> Error {
+    Error::new(ErrorKind::Param, &format!("missing attribute {:?}", kind))
+}
+
+/// Error for attempting an operation with the wrong class of key
+fn wrong_key_class(operation: KeyOperation) -> Error {
+    Error::new(
+        ErrorKind::Param,
+        &format!("{:?} operation not supported by this key", operation),
+    )
+}
@@ -0,0 +1,240 @@
+//! Pluggable storage and cryptography backends for keychains.
+//!
+//! The `KeychainBackend` trait abstracts over the operations this crate
+//! performs with Keychain Services (i.e. the `SecItem*` and `SecKey*`
+//! functions), allowing alternative implementations such as the in-memory
+//! `MemoryBackend` and the encrypted file-backed `FileBackend`, which work
+//! on any platform, and the Linux-only `SecretServiceBackend`,
+//! `KernelKeyringBackend`, and `Pkcs11Backend`.
+
+mod file;
+#[cfg(target_os = "linux")]
+mod kernel_keyring;
+mod memory;
+#[cfg(target_os = "linux")]
+mod pkcs11;
+#[cfg(target_os = "linux")]
+mod secret_service;
+#[cfg(target_os = "macos")]
+mod security_framework;
+
+#[cfg(target_os = "macos")]
+pub use self::security_framework::SecurityFrameworkBackend;
+pub use self::{file::FileBackend, memory::MemoryBackend};
+#[cfg(target_os = "linux")]
+pub use self::{
+    kernel_keyring::{KernelKeyring, KernelKeyringBackend},
+    pkcs11::Pkcs11Backend,
+    secret_service::SecretServiceBackend,
+};
+use crate::{
+    attr::AttributeMap,
+    error::Error,
+    keychain::{
+        item::{Class, MatchLimit, Query},
+        key::{KeyAlgorithm, KeyOperation, KeyPairGenerateParams, RestoreKeyParams},
+    },
+};
+use std::{
+    any::Any,
+    fmt::{self, Debug},
+    sync::{Arc, OnceLock},
+};
+
+/// Operations a keychain backend must support. Each method corresponds to
+/// a function in the Keychain Services API, whose semantics (including
+/// `ErrorKind`s for failures) backends are expected to follow.
+pub trait KeychainBackend: Debug + Send + Sync {
+    /// Add an item of the given class with the given attributes and data.
+    /// Returns an error with a kind of `ErrorKind::DuplicateItem` if an
+    /// item with the same primary key already exists.
+    ///
+    /// Equivalent to the `SecItemAdd` function. See:
+    /// <https://developer.apple.com/documentation/security/1401659-secitemadd>
+    fn add(&self, class: Class, attrs: &AttributeMap, data: &[u8]) -> Result<Handle, Error>;
+
+    /// Find items of the given class which match the given query. Returns
+    /// an error with a kind of `ErrorKind::ItemNotFound` if no items match.
+    ///
+    /// Equivalent to the `SecItemCopyMatching` function. See:
+    /// <https://developer.apple.com/documentation/security/1398306-secitemcopymatching>
+    fn copy_matching(
+        &self,
+        class: Class,
+        query: &Query,
+        limit: MatchLimit,
+    ) -> Result<Vec<Handle>, Error>;
+
+    /// Update the attributes of all items matching the given query.
+    ///
+    /// Equivalent to the `SecItemUpdate` function. See:
+    /// <https://developer.apple.com/documentation/security/1393617-secitemupdate>
+    fn update(&self, class: Class, query: &Query, attrs: &AttributeMap) -> Result<(), Error>;
+
+    /// Delete all items matching the given query.
+    ///
+    /// Equivalent to the `SecItemDelete` function. See:
+    /// <https://developer.apple.com/documentation/security/1395547-secitemdelete>
+    fn delete(&self, class: Class, query: &Query) -> Result<(), Error>;
+
+    /// Delete the keychain itself, along with all of the items it contains.
+    ///
+    /// Equivalent to the `SecKeychainDelete` function. See:
+    /// <https://developer.apple.com/documentation/security/1395206-seckeychaindelete>
+    fn delete_keychain(&self) -> Result<(), Error>;
+
+    /// Get the attributes of an item or key.
+    ///
+    /// Equivalent to the `SecKeyCopyAttributes` function. See:
+    /// <https://developer.apple.com/documentation/security/1643699-seckeycopyattributes>
+    fn attributes(&self, handle: &Handle) -> Result<AttributeMap, Error>;
+
+    /// Get the data (e.g. password) stored in an item.
+    ///
+    /// Equivalent to the `SecKeychainItemCopyContent` function. See:
+    /// <https://developer.apple.com/documentation/security/1397803-seckeychainitemcopycontent>
+    fn data(&self, handle: &Handle) -> Result<Vec<u8>, Error>;
+
+    /// Create a new key pair, returning `(public_key, private_key)` handles.
+    ///
+    /// Equivalent to the `SecKeyCreateRandomKey` function. See:
+    /// <https://developer.apple.com/documentation/security/1823694-seckeycreaterandomkey>
+    fn create_key_pair(&self, params: &KeyPairGenerateParams) -> Result<(Handle, Handle), Error>;
+
+    /// Generate a new key pair, returning `(public_key, private_key)` handles.
+    ///
+    /// Equivalent to the `SecKeyGeneratePair` function. See:
+    /// <https://developer.apple.com/documentation/security/1395339-seckeygeneratepair>
+    fn generate_key_pair(&self, params: &KeyPairGenerateParams) -> Result<(Handle, Handle), Error> {
+        self.create_key_pair(params)
+    }
+
+    /// Restore a key from its external representation.
+    ///
+    /// Equivalent to the `SecKeyCreateWithData` function. See:
+    /// <https://developer.apple.com/documentation/security/1643701-seckeycreatewithdata>
+    fn restore_key(&self, params: &RestoreKeyParams) -> Result<Handle, Error>;
+
+    /// Export a key as its external representation.
+    ///
+    /// Equivalent to the `SecKeyCopyExternalRepresentation` function. See:
+    /// <https://developer.apple.com/documentation/security/1643698-seckeycopyexternalrepresentation>
+    fn export_key(&self, key: &Handle) -> Result<Vec<u8>, Error>;
+
+    /// Get the public key associated with a private key. This works even if
+    /// the private key can't be exported (e.g. keys stored in the SEP).
+    ///
+    /// Equivalent to the `SecKeyCopyPublicKey` function. See:
+    /// <https://developer.apple.com/documentation/security/1643774-seckeycopypublickey>
+    fn copy_public_key(&self, key: &Handle) -> Result<Handle, Error>;
+
+    /// Determine whether a key is suitable for an operation using a certain
+    /// algorithm.
+    ///
+    /// Equivalent to the `SecKeyIsAlgorithmSupported` function. See:
+    /// <https://developer.apple.com/documentation/security/1644057-seckeyisalgorithmsupported>
+    fn is_algorithm_supported(
+        &self,
+        key: &Handle,
+        operation: KeyOperation,
+        alg: KeyAlgorithm,
+    ) -> bool;
+
+    /// Sign the given data with a private key.
+    ///
+    /// Equivalent to the `SecKeyCreateSignature` function. See:
+    /// <https://developer.apple.com/documentation/security/1643916-seckeycreatesignature>
+    fn sign(&self, key: &Handle, alg: KeyAlgorithm, data: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Verify a signature over the given data with a public key. Returns
+    /// an error with a kind of `ErrorKind::VerifyFailed` if the signature is
+    /// invalid.
+    ///
+    /// Equivalent to the `SecKeyVerifySignature` function. See:
+    /// <https://developer.apple.com/documentation/security/1643715-seckeyverifysignature>
+    fn verify(
+        &self,
+        key: &Handle,
+        alg: KeyAlgorithm,
+        signed_data: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, Error>;
+
+    /// Perform a Diffie-Hellman style key exchange between a private key and
+    /// a peer's public key (a handle returned by `restore_key`). For
+    /// algorithms which use a KDF, `requested_size` is the size of the
+    /// derived key in bytes and `shared_info` is the KDF's shared info.
+    ///
+    /// Equivalent to the `SecKeyCopyKeyExchangeResult` function. See:
+    /// <https://developer.apple.com/documentation/security/1644033-seckeycopykeyexchangeresult>
+    fn key_exchange(
+        &self,
+        key: &Handle,
+        alg: KeyAlgorithm,
+        public_key: &Handle,
+        requested_size: usize,
+        shared_info: &[u8],
+    ) -> Result<Vec<u8>, Error>;
+
+    /// Encrypt the given plaintext with a public key.
+    ///
+    /// Equivalent to the `SecKeyCreateEncryptedData` function. See:
+    /// <https://developer.apple.com/documentation/security/1643957-seckeycreateencrypteddata>
+    fn encrypt(&self, key: &Handle, alg: KeyAlgorithm, plaintext: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Decrypt the given ciphertext with a private key.
+    ///
+    /// Equivalent to the `SecKeyCreateDecryptedData` function. See:
+    /// <https://developer.apple.com/documentation/security/1644043-seckeycreatedecrypteddata>
+    fn decrypt(&self, key: &Handle, alg: KeyAlgorithm, ciphertext: &[u8])
+        -> Result<Vec<u8>, Error>;
+}
+
+/// Opaque reference to an item or key stored in a `KeychainBackend`.
+///
+/// Backends can store any type in a handle, and downcast it back to the
+/// original type when it's passed to them again.
+#[derive(Clone)]
+pub struct Handle(Arc<dyn Any + Send + Sync>);
+
+impl Handle {
+    /// Create a new handle wrapping the given backend-specific value
+    pub fn new<T>(value: T) -> Self
+    where
+        T: Any + Send + Sync,
+    {
+        Handle(Arc::new(value))
+    }
+
+    /// Borrow the backend-specific value in this handle, if it is a `T`
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.0.downcast_ref()
+    }
+}
+
+impl Debug for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle {{ ... }}")
+    }
+}
+
+/// Get the backend used by functions which don't take an explicit
+/// `Keychain` (e.g. `Key::find`, `KeyPair::generate`).
+///
+/// On macOS this is Keychain Services. On other platforms it's a
+/// process-wide `MemoryBackend`.
+pub(crate) fn default_backend() -> Arc<dyn KeychainBackend> {
+    static DEFAULT_BACKEND: OnceLock<Arc<dyn KeychainBackend>> = OnceLock::new();
+
+    DEFAULT_BACKEND
+        .get_or_init(|| {
+            #[cfg(target_os = "macos")]
+            let backend = SecurityFrameworkBackend::new();
+
+            #[cfg(not(target_os = "macos"))]
+            let backend = MemoryBackend::new();
+
+            Arc::new(backend)
+        })
+        .clone()
+}
@@ -0,0 +1,1805 @@
+//! Keychain backend which keeps keys on a hardware token (e.g. an HSM or
+//! smart card) accessed through a PKCS#11 module, such as SoftHSMv2 or
+//! OpenSC.
+//!
+//! See the PKCS#11 Cryptographic Token Interface Base Specification:
+//! <https://docs.oasis-open.org/pkcs11/pkcs11-base/v2.40/pkcs11-base-v2.40.html>
+
+use super::{Handle, KeychainBackend};
+use crate::{
+    attr::{AttrKeyClass, AttrKeyType, AttrKind, AttrTokenId, AttrValue, AttributeMap},
+    error::{Error, ErrorKind},
+    keychain::{
+        item::{Class, MatchLimit, Query},
+        key::{
+            AlgorithmFamily, DigestAlgorithm, KeyAlgorithm, KeyOperation, KeyPairGenerateParams,
+            RestoreKeyParams,
+        },
+    },
+    pkcs11::ffi::*,
+    software::{
+        aes_gcm_decrypt, ecies, encryption_algorithm, is_supported, prehash, signature_algorithm,
+        EncryptionScheme, PublicKey, SignatureScheme, GCM_TAG_SIZE,
+    },
+};
+use der::{asn1::OctetStringRef, Decode};
+use libc::{c_char, c_void};
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use sha1::Sha1;
+use sha2::{Sha224, Sha256, Sha384, Sha512};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    ffi::{CStr, CString},
+    fmt::{self, Debug},
+    mem,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    ptr,
+    sync::{Arc, Mutex, MutexGuard, Weak},
+};
+use zeroize::Zeroizing;
+
+/// Largest RSA modulus size supported by the software implementation of
+/// public key operations
+const MAX_RSA_KEY_SIZE: usize = 8192;
+
+/// Public exponent of generated RSA keys (65537)
+const RSA_PUBLIC_EXPONENT: &[u8] = &[0x01, 0x00, 0x01];
+
+/// Number of object handles to fetch with each call to `C_FindObjects`
+const FIND_BATCH_SIZE: usize = 64;
+
+/// Boolean key attributes which map directly onto `CKA_*` attributes,
+/// along with whether they're set on the private (as opposed to public)
+/// key when generating a key pair
+const KEY_USAGE_ATTRIBUTES: &[(AttrKind, CK_ATTRIBUTE_TYPE, bool)] = &[
+    (AttrKind::Sign, CKA_SIGN, true),
+    (AttrKind::Decrypt, CKA_DECRYPT, true),
+    (AttrKind::Derive, CKA_DERIVE, true),
+    (AttrKind::Unwrap, CKA_UNWRAP, true),
+    (AttrKind::Extractable, CKA_EXTRACTABLE, true),
+    (AttrKind::Sensitive, CKA_SENSITIVE, true),
+    (AttrKind::Verify, CKA_VERIFY, false),
+    (AttrKind::Encrypt, CKA_ENCRYPT, false),
+    (AttrKind::Wrap, CKA_WRAP, false),
+];
+
+/// Modules which are currently loaded, so each module is only loaded (and
+/// initialized) once no matter how many backends use it
+static MODULES: Mutex<Vec<Weak<Module>>> = Mutex::new(Vec::new());
+
+/// Call a function in a module's function list, returning its `CK_RV`
+/// (or `CKR_FUNCTION_NOT_SUPPORTED` if the module doesn't provide it)
+macro_rules! ck {
+    ($module:expr, $function:ident($($arg:expr),* $(,)?)) => {
+        match $module.functions().$function {
+            Some(function) => function($($arg),*),
+            None => CKR_FUNCTION_NOT_SUPPORTED,
+        }
+    };
+}
+
+/// Call a function in a module's function list, converting failures into
+/// an `Error`
+macro_rules! call {
+    ($module:expr, $function:ident($($arg:expr),* $(,)?)) => {
+        check(ck!($module, $function($($arg),*)), stringify!($function))
+    };
+}
+
+/// Keychain backend which generates and uses keys on a PKCS#11 token.
+///
+/// Key pairs are generated on the token with `C_GenerateKeyPair`, mapping
+/// `KeyPairGenerateParams` onto `CKA_*` attributes: `permanent` selects
+/// `CKA_TOKEN` (keys which aren't permanent are session objects, destroyed
+/// when the backend is dropped), the label and application tag become
+/// `CKA_LABEL` and `CKA_ID`, and `can_sign`, `extractable`, `sensitive`
+/// and the other key usage flags map onto the corresponding boolean
+/// attributes. Keys generated without an application tag are given the
+/// application label (i.e. the SHA-1 hash of the public key) as their
+/// `CKA_ID`, following the usual PKCS#11 convention, so the public and
+/// private keys of a pair can be matched up.
+///
+/// RSA and EC (P-256, P-384, and P-521) keys on the token can be found
+/// with `Query`, and report an `AttrTokenId::Pkcs11` token ID. Private key
+/// operations (signing, decryption, and key exchange) are performed by the
+/// token, whereas public key operations are performed in software using the
+/// public key read from the token. Private keys can't be exported, and
+/// passwords and other item classes are not supported.
+pub struct Pkcs11Backend {
+    /// Module providing access to the token
+    module: Arc<Module>,
+
+    /// Slot containing the token
+    slot: CK_SLOT_ID,
+
+    /// Read/write session with the token, which is locked for the duration
+    /// of each (potentially multi-part) operation
+    session: Mutex<CK_SESSION_HANDLE>,
+}
+
+impl Pkcs11Backend {
+    /// Load the PKCS#11 module at the given path (e.g.
+    /// `/usr/lib/softhsm/libsofthsm2.so`) and open a session with the token
+    /// with the given label, logging in as the normal user with the given
+    /// PIN.
+    ///
+    /// If no PIN is given, the user is logged in using the token's protected
+    /// authentication path (e.g. a PIN pad) if it has one. Otherwise only
+    /// public objects on the token can be used.
+    ///
+    /// Returns an error with a kind of `ErrorKind::NoSuchKeychain` if no
+    /// token with the given label is present, or `ErrorKind::AuthFailed` if
+    /// the PIN is incorrect.
+    pub fn open(module: &Path, token_label: &str, pin: Option<&str>) -> Result<Self, Error> {
+        let module = Module::load(module)?;
+        let (slot, token_info) = find_token(&module, token_label)?;
+
+        let mut session: CK_SESSION_HANDLE = 0;
+        unsafe {
+            call!(
+                module,
+                C_OpenSession(
+                    slot,
+                    CKF_SERIAL_SESSION | CKF_RW_SESSION,
+                    ptr::null_mut(),
+                    None,
+                    &mut session,
+                )
+            )?;
+        }
+
+        // Construct the backend first so the session is closed on failure
+        let backend = Self {
+            module,
+            slot,
+            session: Mutex::new(session),
+        };
+
+        let rv = match pin {
+            Some(pin) => unsafe {
+                ck!(
+                    backend.module,
+                    C_Login(
+                        session,
+                        CKU_USER,
+                        pin.as_ptr() as *mut u8,
+                        pin.len() as CK_ULONG,
+                    )
+                )
+            },
+            None if token_info.flags & CKF_PROTECTED_AUTHENTICATION_PATH != 0 => unsafe {
+                ck!(
+                    backend.module,
+                    C_Login(session, CKU_USER, ptr::null_mut(), 0)
+                )
+            },
+            None => CKR_OK,
+        };
+
+        match rv {
+            CKR_OK | CKR_USER_ALREADY_LOGGED_IN => Ok(backend),
+            rv => Err(pkcs11_error(rv, "C_Login")),
+        }
+    }
+
+    /// Lock the session
+    fn session(&self) -> MutexGuard<'_, CK_SESSION_HANDLE> {
+        self.session.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Find keys which match a query. Returns an error with a kind of
+    /// `ErrorKind::ItemNotFound` if no keys match.
+    fn find(
+        &self,
+        session: CK_SESSION_HANDLE,
+        query: &Query,
+        limit: MatchLimit,
+    ) -> Result<Vec<Pkcs11Key>, Error> {
+        let mut keys = self.keys(session)?;
+
+        let mut results: Vec<_> = query
+            .select(keys.iter().map(|key| &key.attrs), limit)
+            .into_iter()
+            .rev()
+            .map(|i| keys.swap_remove(i))
+            .collect();
+
+        if results.is_empty() {
+            return Err(Error::new(
+                ErrorKind::ItemNotFound,
+                "The specified item could not be found in the keychain.",
+            ));
+        }
+
+        results.reverse();
+        Ok(results)
+    }
+
+    /// Get all of the RSA and EC keys on the token (private keys first),
+    /// pairing private keys with their public keys
+    fn keys(&self, session: CK_SESSION_HANDLE) -> Result<Vec<Pkcs11Key>, Error> {
+        let public_keys = self.objects(session, CKO_PUBLIC_KEY)?;
+        let private_keys = self.objects(session, CKO_PRIVATE_KEY)?;
+        let mut keys = vec![];
+
+        for private_key in private_keys {
+            let public_key = public_keys
+                .iter()
+                .find(|public_key| private_key.pairs_with(public_key));
+
+            keys.push(Pkcs11Key::new(private_key, public_key));
+        }
+
+        for public_key in public_keys {
+            keys.push(Pkcs11Key::new(public_key, None));
+        }
+
+        Ok(keys)
+    }
+
+    /// Read all of the RSA and EC keys of the given class on the token
+    fn objects(
+        &self,
+        session: CK_SESSION_HANDLE,
+        class: CK_OBJECT_CLASS,
+    ) -> Result<Vec<KeyObject>, Error> {
+        let mut template = Template::default();
+        template.ulong(CKA_CLASS, class);
+
+        let mut objects = vec![];
+
+        for handle in self.find_objects(session, &mut template)? {
+            if let Some(object) = self.read_object(session, handle)? {
+                objects.push(object);
+            }
+        }
+
+        Ok(objects)
+    }
+
+    /// Find the handles of all objects matching a template
+    fn find_objects(
+        &self,
+        session: CK_SESSION_HANDLE,
+        template: &mut Template,
+    ) -> Result<Vec<CK_OBJECT_HANDLE>, Error> {
+        unsafe {
+            call!(
+                self.module,
+                C_FindObjectsInit(session, template.as_mut_ptr(), template.len())
+            )?;
+        }
+
+        let mut handles = vec![];
+
+        let result = loop {
+            let mut batch = [0 as CK_OBJECT_HANDLE; FIND_BATCH_SIZE];
+            let mut count: CK_ULONG = 0;
+
+            if let Err(e) = unsafe {
+                call!(
+                    self.module,
+                    C_FindObjects(
+                        session,
+                        batch.as_mut_ptr(),
+                        batch.len() as CK_ULONG,
+                        &mut count,
+                    )
+                )
+            } {
+                break Err(e);
+            }
+
+            if count == 0 {
+                break Ok(handles);
+            }
+
+            handles.extend_from_slice(&batch[..count as usize]);
+        };
+
+        unsafe {
+            ck!(self.module, C_FindObjectsFinal(session));
+        }
+
+        result
+    }
+
+    /// Read the attributes of a key, returning `None` if the object isn't
+    /// an RSA or EC key (on one of the supported curves)
+    fn read_object(
+        &self,
+        session: CK_SESSION_HANDLE,
+        handle: CK_OBJECT_HANDLE,
+    ) -> Result<Option<KeyObject>, Error> {
+        let mut types = vec![
+            CKA_CLASS,
+            CKA_KEY_TYPE,
+            CKA_ID,
+            CKA_LABEL,
+            CKA_TOKEN,
+            CKA_MODULUS,
+            CKA_PUBLIC_EXPONENT,
+            CKA_EC_PARAMS,
+            CKA_EC_POINT,
+        ];
+        types.extend(KEY_USAGE_ATTRIBUTES.iter().map(|(_, type_, _)| *type_));
+
+        let values = self.get_attributes(session, handle, &types)?;
+
+        let key_class = match ulong_value(values.get(&CKA_CLASS)) {
+            Some(CKO_PUBLIC_KEY) => AttrKeyClass::Public,
+            Some(CKO_PRIVATE_KEY) => AttrKeyClass::Private,
+            _ => return Ok(None),
+        };
+
+        let ec_params = values.get(&CKA_EC_PARAMS).cloned();
+
+        let (key_type, key_size, public_key) = match ulong_value(values.get(&CKA_KEY_TYPE)) {
+            Some(CKK_RSA) => {
+                let public_key = match (values.get(&CKA_MODULUS), values.get(&CKA_PUBLIC_EXPONENT))
+                {
+                    (Some(modulus), Some(exponent)) => rsa_public_key(modulus, exponent).ok(),
+                    _ => None,
+                };
+
+                let key_size = values
+                    .get(&CKA_MODULUS)
+                    .map(|modulus| BigUint::from_bytes_be(modulus).bits());
+
+                (AttrKeyType::Rsa, key_size, public_key)
+            }
+            Some(CKK_EC) => {
+                let key_size = match ec_params.as_deref().and_then(curve_size) {
+                    Some(key_size) => key_size,
+                    None => return Ok(None),
+                };
+
+                let public_key = match (&ec_params, values.get(&CKA_EC_POINT)) {
+                    (Some(params), Some(point)) => ec_public_key(params, point).ok(),
+                    _ => None,
+                };
+
+                (AttrKeyType::EcSecPrimeRandom, Some(key_size), public_key)
+            }
+            _ => return Ok(None),
+        };
+
+        let mut attrs = AttributeMap::new();
+        attrs.insert_attr(&key_class);
+        attrs.insert_attr(&key_type);
+        attrs.insert_attr(&AttrTokenId::Pkcs11);
+
+        if let Some(key_size) = key_size {
+            attrs.insert(AttrKind::KeySizeInBits, key_size as i64);
+        }
+
+        let id = values.get(&CKA_ID).cloned().unwrap_or_default();
+
+        if !id.is_empty() {
+            attrs.insert(AttrKind::ApplicationTag, id.clone());
+        }
+
+        if let Some(label) = values.get(&CKA_LABEL).filter(|label| !label.is_empty()) {
+            attrs.insert(AttrKind::Label, String::from_utf8_lossy(label).into_owned());
+        }
+
+        if let Some(token) = bool_value(values.get(&CKA_TOKEN)) {
+            attrs.insert(AttrKind::Permanent, token);
+        }
+
+        for (kind, type_, _) in KEY_USAGE_ATTRIBUTES {
+            if let Some(value) = bool_value(values.get(type_)) {
+                attrs.insert(*kind, value);
+            }
+        }
+
+        Ok(Some(KeyObject {
+            handle,
+            key_class,
+            key_type,
+            id,
+            ec_params,
+            public_key,
+            attrs,
+        }))
+    }
+
+    /// Read the given attributes of an object, omitting attributes the
+    /// object doesn't have (or whose values are sensitive)
+    fn get_attributes(
+        &self,
+        session: CK_SESSION_HANDLE,
+        handle: CK_OBJECT_HANDLE,
+        types: &[CK_ATTRIBUTE_TYPE],
+    ) -> Result<HashMap<CK_ATTRIBUTE_TYPE, Vec<u8>>, Error> {
+        let mut template: Vec<_> = types
+            .iter()
+            .map(|type_| CK_ATTRIBUTE {
+                type_: *type_,
+                pValue: ptr::null_mut(),
+                ulValueLen: 0,
+            })
+            .collect();
+
+        // Get the lengths of the values, then the values themselves
+        let rv = unsafe {
+            ck!(
+                self.module,
+                C_GetAttributeValue(
+                    session,
+                    handle,
+                    template.as_mut_ptr(),
+                    template.len() as CK_ULONG,
+                )
+            )
+        };
+        check_get_attributes(rv)?;
+
+        let mut values: Vec<_> = template
+            .iter_mut()
+            .filter(|attr| attr.ulValueLen != CK_UNAVAILABLE_INFORMATION)
+            .map(|attr| {
+                let mut value = vec![0u8; attr.ulValueLen as usize];
+                attr.pValue = value.as_mut_ptr() as *mut c_void;
+                (attr.type_, value)
+            })
+            .collect();
+
+        template.retain(|attr| !attr.pValue.is_null());
+
+        let rv = unsafe {
+            ck!(
+                self.module,
+                C_GetAttributeValue(
+                    session,
+                    handle,
+                    template.as_mut_ptr(),
+                    template.len() as CK_ULONG,
+                )
+            )
+        };
+        check_get_attributes(rv)?;
+
+        Ok(values
+            .drain(..)
+            .zip(template.iter())
+            .filter(|(_, attr)| attr.ulValueLen != CK_UNAVAILABLE_INFORMATION)
+            .map(|((type_, mut value), attr)| {
+                value.truncate(attr.ulValueLen as usize);
+                (type_, value)
+            })
+            .collect())
+    }
+
+    /// Set attributes of an object
+    fn set_attributes(
+        &self,
+        session: CK_SESSION_HANDLE,
+        handle: CK_OBJECT_HANDLE,
+        template: &mut Template,
+    ) -> Result<(), Error> {
+        unsafe {
+            call!(
+                self.module,
+                C_SetAttributeValue(session, handle, template.as_mut_ptr(), template.len())
+            )
+        }
+    }
+
+    /// Sign the given data with a private key using the given mechanism
+    fn sign_with(
+        &self,
+        object: CK_OBJECT_HANDLE,
+        mechanism: &mut CK_MECHANISM,
+        data: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let session = self.session();
+
+        unsafe {
+            call!(self.module, C_SignInit(*session, mechanism, object))?;
+        }
+
+        single_part(self.module.functions().C_Sign, "C_Sign", *session, data)
+    }
+
+    /// Decrypt the given ciphertext with a private key using the given
+    /// mechanism
+    fn decrypt_with(
+        &self,
+        object: CK_OBJECT_HANDLE,
+        mechanism: &mut CK_MECHANISM,
+        ciphertext: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let session = self.session();
+
+        unsafe {
+            call!(self.module, C_DecryptInit(*session, mechanism, object))?;
+        }
+
+        single_part(
+            self.module.functions().C_Decrypt,
+            "C_Decrypt",
+            *session,
+            ciphertext,
+        )
+        .map(Zeroizing::new)
+    }
+
+    /// Decrypt the given ciphertext using RSA-OAEP with the given digest
+    fn decrypt_oaep(
+        &self,
+        object: CK_OBJECT_HANDLE,
+        digest_alg: DigestAlgorithm,
+        ciphertext: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let (hash_alg, mgf) = hash_mechanism(digest_alg);
+        let mut params = CK_RSA_PKCS_OAEP_PARAMS {
+            hashAlg: hash_alg,
+            mgf,
+            source: CKZ_DATA_SPECIFIED,
+            pSourceData: ptr::null_mut(),
+            ulSourceDataLen: 0,
+        };
+
+        self.decrypt_with(
+            object,
+            &mut mechanism_with_params(CKM_RSA_PKCS_OAEP, &mut params),
+            ciphertext,
+        )
+    }
+
+    /// Compute the raw ECDH shared secret (i.e. the x-coordinate of the
+    /// shared point) of a private key on the token and the given public key
+    fn diffie_hellman(
+        &self,
+        object: CK_OBJECT_HANDLE,
+        public_key: &PublicKey,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        if public_key.key_type() != AttrKeyType::EcSecPrimeRandom {
+            return Err(Error::new(
+                ErrorKind::Param,
+                "key exchange not supported by RSA keys",
+            ));
+        }
+
+        let mut point = public_key.to_external_representation();
+        let mut params = CK_ECDH1_DERIVE_PARAMS {
+            kdf: CKD_NULL,
+            ulSharedDataLen: 0,
+            pSharedData: ptr::null_mut(),
+            ulPublicDataLen: point.len() as CK_ULONG,
+            pPublicData: point.as_mut_ptr(),
+        };
+
+        let mut template = Template::default();
+        template.ulong(CKA_CLASS, CKO_SECRET_KEY);
+        template.ulong(CKA_KEY_TYPE, CKK_GENERIC_SECRET);
+        template.bool(CKA_TOKEN, false);
+        template.bool(CKA_SENSITIVE, false);
+        template.bool(CKA_EXTRACTABLE, true);
+        template.ulong(
+            CKA_VALUE_LEN,
+            public_key.key_size_in_bits().div_ceil(8) as CK_ULONG,
+        );
+
+        let session = self.session();
+        let mut secret: CK_OBJECT_HANDLE = 0;
+
+        unsafe {
+            call!(
+                self.module,
+                C_DeriveKey(
+                    *session,
+                    &mut mechanism_with_params(CKM_ECDH1_DERIVE, &mut params),
+                    object,
+                    template.as_mut_ptr(),
+                    template.len(),
+                    &mut secret,
+                )
+            )?;
+        }
+
+        let value = self.get_attributes(*session, secret, &[CKA_VALUE]);
+
+        unsafe {
+            ck!(self.module, C_DestroyObject(*session, secret));
+        }
+
+        value?
+            .remove(&CKA_VALUE)
+            .map(Zeroizing::new)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::DataNotAvailable,
+                    "couldn't read ECDH shared secret",
+                )
+            })
+    }
+}
+
+impl Debug for Pkcs11Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Pkcs11Backend {{ module: {:?}, slot: {} }}",
+            self.module.path, self.slot
+        )
+    }
+}
+
+impl Drop for Pkcs11Backend {
+    fn drop(&mut self) {
+        // Closing the last session with a token also logs out
+        unsafe {
+            ck!(self.module, C_CloseSession(*self.session()));
+        }
+    }
+}
+
+impl KeychainBackend for Pkcs11Backend {
+    fn add(&self, _class: Class, _attrs: &AttributeMap, _data: &[u8]) -> Result<Handle, Error> {
+        Err(unsupported_class())
+    }
+
+    fn copy_matching(
+        &self,
+        class: Class,
+        query: &Query,
+        limit: MatchLimit,
+    ) -> Result<Vec<Handle>, Error> {
+        if class != Class::Key {
+            return Err(unsupported_class());
+        }
+
+        let session = self.session();
+
+        Ok(self
+            .find(*session, query, limit)?
+            .into_iter()
+            .map(Handle::new)
+            .collect())
+    }
+
+    fn update(&self, class: Class, query: &Query, attrs: &AttributeMap) -> Result<(), Error> {
+        if class != Class::Key {
+            return Err(unsupported_class());
+        }
+
+        let mut template = Template::default();
+
+        for (kind, value) in attrs.iter() {
+            match kind {
+                AttrKind::Label => template.bytes(
+                    CKA_LABEL,
+                    value
+                        .as_str()
+                        .ok_or_else(|| invalid_value(*kind))?
+                        .as_bytes(),
+                ),
+                AttrKind::ApplicationTag => template.bytes(
+                    CKA_ID,
+                    value.as_bytes().ok_or_else(|| invalid_value(*kind))?,
+                ),
+                _ => match KEY_USAGE_ATTRIBUTES.iter().find(|(k, _, _)| k == kind) {
+                    Some((_, type_, _)) => {
+                        template.bool(*type_, value.as_bool().ok_or_else(|| invalid_value(*kind))?)
+                    }
+                    None => {
+                        return Err(Error::new(
+                            ErrorKind::NoSuchAttr,
+                            &format!("attribute unsupported by PKCS#11 tokens: {:?}", kind),
+                        ))
+                    }
+                },
+            }
+        }
+
+        let session = self.session();
+
+        for key in self.find(*session, query, MatchLimit::All)? {
+            if let Some(object) = key.object {
+                self.set_attributes(*session, object, &mut template)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delete(&self, class: Class, query: &Query) -> Result<(), Error> {
+        if class != Class::Key {
+            return Err(unsupported_class());
+        }
+
+        let session = self.session();
+
+        for key in self.find(*session, query, MatchLimit::All)? {
+            if let Some(object) = key.object {
+                unsafe {
+                    call!(self.module, C_DestroyObject(*session, object))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delete_keychain(&self) -> Result<(), Error> {
+        Err(Error::new(
+            ErrorKind::Unimplemented,
+            "PKCS#11 tokens can't be deleted (only reinitialized)",
+        ))
+    }
+
+    fn attributes(&self, handle: &Handle) -> Result<AttributeMap, Error> {
+        let key = key(handle)?;
+
+        let object = match key.object {
+            Some(object) => object,
+            None => return Ok(key.attrs.clone()),
+        };
+
+        // Re-read attributes from the token in case they've been updated
+        let session = self.session();
+        let object = self
+            .read_object(*session, object)?
+            .ok_or_else(|| Error::new(ErrorKind::InvalidItemRef, "object is not a key"))?;
+
+        let mut attrs = object.attrs;
+
+        if let Some(public_key) = object.public_key.as_ref().or(key.public_key.as_ref()) {
+            attrs.insert(AttrKind::ApplicationLabel, public_key.application_label());
+        }
+
+        Ok(attrs)
+    }
+
+    fn data(&self, handle: &Handle) -> Result<Vec<u8>, Error> {
+        self.export_key(handle)
+    }
+
+    fn create_key_pair(&self, params: &KeyPairGenerateParams) -> Result<(Handle, Handle), Error> {
+        let attrs = params.private_key_attributes();
+
+        if params.access_control_policy().is_some() {
+            return Err(Error::new(
+                ErrorKind::Unimplemented,
+                "access control policies unsupported by PKCS#11 tokens",
+            ));
+        }
+
+        if let Some(token_id) = attrs.get(AttrKind::TokenId) {
+            if token_id.as_str() != Some(AttrTokenId::Pkcs11.as_str()) {
+                return Err(Error::new(
+                    ErrorKind::Param,
+                    &format!("token unsupported by PKCS#11 backend: {:?}", token_id),
+                ));
+            }
+        }
+
+        let mut public_template = Template::default();
+        let mut private_template = Template::default();
+
+        let mechanism = match params.key_type() {
+            AttrKeyType::Rsa => {
+                public_template.ulong(CKA_MODULUS_BITS, params.key_size() as CK_ULONG);
+                public_template.bytes(CKA_PUBLIC_EXPONENT, RSA_PUBLIC_EXPONENT);
+                CKM_RSA_PKCS_KEY_PAIR_GEN
+            }
+            AttrKeyType::EcSecPrimeRandom => {
+                let ec_params = match params.key_size() {
+                    256 => EC_PARAMS_P256,
+                    384 => EC_PARAMS_P384,
+                    521 => EC_PARAMS_P521,
+                    key_size => {
+                        return Err(Error::new(
+                            ErrorKind::KeySizeNotAllowed,
+                            &format!("unsupported EC key size: {}", key_size),
+                        ))
+                    }
+                };
+
+                public_template.bytes(CKA_EC_PARAMS, ec_params);
+                CKM_EC_KEY_PAIR_GEN
+            }
+            AttrKeyType::Aes => {
+                return Err(Error::new(
+                    ErrorKind::Param,
+                    "symmetric keys are unsupported",
+                ))
+            }
+        };
+
+        let permanent = attrs.get(AttrKind::Permanent) == Some(&AttrValue::Bool(true));
+        private_template.bool(CKA_TOKEN, permanent);
+        private_template.bool(CKA_PRIVATE, true);
+        public_template.bool(CKA_TOKEN, permanent);
+
+        if let Some(label) = attrs.get_string(AttrKind::Label) {
+            private_template.bytes(CKA_LABEL, label.as_bytes());
+            public_template.bytes(CKA_LABEL, label.as_bytes());
+        }
+
+        if let Some(tag) = attrs.get_data(AttrKind::ApplicationTag) {
+            private_template.bytes(CKA_ID, tag);
+            public_template.bytes(CKA_ID, tag);
+        }
+
+        // Like software keys, keys can be used for any operation their type
+        // supports unless restricted
+        let rsa = params.key_type() == AttrKeyType::Rsa;
+        let defaults = [
+            (AttrKind::Sign, true),
+            (AttrKind::Verify, true),
+            (AttrKind::Decrypt, rsa),
+            (AttrKind::Encrypt, rsa),
+            (AttrKind::Derive, !rsa),
+        ];
+
+        for (kind, type_, private) in KEY_USAGE_ATTRIBUTES {
+            let value = match attrs.get(*kind) {
+                Some(value) => value.as_bool().ok_or_else(|| invalid_value(*kind))?,
+                None => match defaults.iter().find(|(k, _)| k == kind) {
+                    Some((_, true)) => true,
+                    _ => continue,
+                },
+            };
+
+            if *private {
+                private_template.bool(*type_, value);
+            } else {
+                public_template.bool(*type_, value);
+            }
+        }
+
+        let session = self.session();
+        let mut public_object: CK_OBJECT_HANDLE = 0;
+        let mut private_object: CK_OBJECT_HANDLE = 0;
+
+        unsafe {
+            call!(
+                self.module,
+                C_GenerateKeyPair(
+                    *session,
+                    &mut mechanism_without_params(mechanism),
+                    public_template.as_mut_ptr(),
+                    public_template.len(),
+                    private_template.as_mut_ptr(),
+                    private_template.len(),
+                    &mut public_object,
+                    &mut private_object,
+                )
+            )?;
+        }
+
+        let invalid_object =
+            || Error::new(ErrorKind::InvalidItemRef, "generated object is not a key");
+        let mut public_key = self
+            .read_object(*session, public_object)?
+            .ok_or_else(invalid_object)?;
+        let mut private_key = self
+            .read_object(*session, private_object)?
+            .ok_or_else(invalid_object)?;
+
+        // Identify the key pair by its application label by default
+        if public_key.id.is_empty() {
+            if let Some(label) = public_key
+                .public_key
+                .as_ref()
+                .map(PublicKey::application_label)
+            {
+                let mut template = Template::default();
+                template.bytes(CKA_ID, &label);
+                self.set_attributes(*session, public_object, &mut template)?;
+                self.set_attributes(*session, private_object, &mut template)?;
+
+                for object in &mut [&mut public_key, &mut private_key] {
+                    object.attrs.insert(AttrKind::ApplicationTag, label.clone());
+                    object.id = label.clone();
+                }
+            }
+        }
+
+        let private_key = Pkcs11Key::new(private_key, Some(&public_key));
+        let public_key = Pkcs11Key::new(public_key, None);
+        Ok((Handle::new(public_key), Handle::new(private_key)))
+    }
+
+    fn restore_key(&self, params: &RestoreKeyParams) -> Result<Handle, Error> {
+        if params.key_class != AttrKeyClass::Public {
+            return Err(Error::new(
+                ErrorKind::Unimplemented,
+                "private keys can't be imported into PKCS#11 tokens",
+            ));
+        }
+
+        let public_key =
+            PublicKey::from_external_representation(params.key_type, params.as_bytes())?;
+
+        let mut attrs = AttributeMap::new();
+        attrs.insert_attr(&AttrKeyClass::Public);
+        attrs.insert_attr(&public_key.key_type());
+        attrs.insert(
+            AttrKind::KeySizeInBits,
+            public_key.key_size_in_bits() as i64,
+        );
+        attrs.insert(AttrKind::ApplicationLabel, public_key.application_label());
+
+        Ok(Handle::new(Pkcs11Key {
+            object: None,
+            public_object: None,
+            public_key: Some(public_key),
+            attrs,
+        }))
+    }
+
+    fn export_key(&self, key_handle: &Handle) -> Result<Vec<u8>, Error> {
+        let key = key(key_handle)?;
+
+        if key.key_class() != Some(AttrKeyClass::Public) {
+            return Err(Error::new(
+                ErrorKind::DataNotAvailable,
+                "private keys can't be exported from PKCS#11 tokens",
+            ));
+        }
+
+        Ok(key
+            .public_key(KeyOperation::Encrypt)?
+            .to_external_representation())
+    }
+
+    fn copy_public_key(&self, key_handle: &Handle) -> Result<Handle, Error> {
+        let key = key(key_handle)?;
+
+        if key.key_class() == Some(AttrKeyClass::Public) {
+            return Ok(key_handle.clone());
+        }
+
+        if let Some(public_object) = key.public_object {
+            let session = self.session();
+
+            if let Some(object) = self.read_object(*session, public_object)? {
+                return Ok(Handle::new(Pkcs11Key::new(object, None)));
+            }
+        }
+
+        let public_key = key.public_key.as_ref().ok_or_else(|| {
+            Error::new(
+                ErrorKind::ItemNotFound,
+                "couldn't find the public key of this private key on the token",
+            )
+        })?;
+
+        self.restore_key(&RestoreKeyParams {
+            key_class: AttrKeyClass::Public,
+            key_type: public_key.key_type(),
+            key_data: public_key.to_external_representation(),
+        })
+    }
+
+    fn is_algorithm_supported(
+        &self,
+        key_handle: &Handle,
+        operation: KeyOperation,
+        alg: KeyAlgorithm,
+    ) -> bool {
+        let key = match key(key_handle) {
+            Ok(key) => key,
+            Err(_) => return false,
+        };
+
+        let (key_class, key_type) = match (key.key_class(), key.key_type()) {
+            (Some(key_class), Some(key_type)) => (key_class, key_type),
+            _ => return false,
+        };
+
+        let (usable, usage) = match operation {
+            KeyOperation::Sign => (key.object.is_some(), AttrKind::Sign),
+            KeyOperation::Decrypt => (key.object.is_some(), AttrKind::Decrypt),
+            KeyOperation::KeyExchange => (key.object.is_some(), AttrKind::Derive),
+            KeyOperation::Verify => (key.public_key.is_some(), AttrKind::Verify),
+            KeyOperation::Encrypt => (key.public_key.is_some(), AttrKind::Encrypt),
+        };
+
+        usable
+            && key.attrs.get(usage) != Some(&AttrValue::Bool(false))
+            && is_supported(key_class, key_type, operation, alg)
+    }
+
+    fn sign(&self, key_handle: &Handle, alg: KeyAlgorithm, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let key = key(key_handle)?;
+        let object = key.private_object(KeyOperation::Sign)?;
+        let scheme = signature_algorithm(alg).ok_or_else(|| unsupported(alg))?;
+        let prehash = prehash(alg, data)?;
+        let digest_alg = alg.descriptor().digest;
+
+        match (key.key_type(), scheme) {
+            (Some(AttrKeyType::EcSecPrimeRandom), SignatureScheme::EcdsaFixed) => {
+                self.sign_with(object, &mut mechanism_without_params(CKM_ECDSA), &prehash)
+            }
+            (Some(AttrKeyType::EcSecPrimeRandom), SignatureScheme::EcdsaDer) => {
+                let signature =
+                    self.sign_with(object, &mut mechanism_without_params(CKM_ECDSA), &prehash)?;
+                ecdsa_der(&signature)
+            }
+            (Some(AttrKeyType::Rsa), SignatureScheme::RsaRaw) => self.sign_with(
+                object,
+                &mut mechanism_without_params(CKM_RSA_X_509),
+                &prehash,
+            ),
+            (Some(AttrKeyType::Rsa), SignatureScheme::RsaPkcs1v15) => {
+                let mut digest_info = digest_alg.map(digest_info_prefix).unwrap_or_default();
+                digest_info.extend_from_slice(&prehash);
+                self.sign_with(
+                    object,
+                    &mut mechanism_without_params(CKM_RSA_PKCS),
+                    &digest_info,
+                )
+            }
+            (Some(AttrKeyType::Rsa), SignatureScheme::RsaPss) => {
+                let digest_alg = digest_alg.ok_or_else(|| unsupported(alg))?;
+                let (hash_alg, mgf) = hash_mechanism(digest_alg);
+                let mut params = CK_RSA_PKCS_PSS_PARAMS {
+                    hashAlg: hash_alg,
+                    mgf,
+                    sLen: digest_alg.output_size() as CK_ULONG,
+                };
+
+                self.sign_with(
+                    object,
+                    &mut mechanism_with_params(CKM_RSA_PKCS_PSS, &mut params),
+                    &prehash,
+                )
+            }
+            _ => Err(unsupported(alg)),
+        }
+    }
+
+    fn verify(
+        &self,
+        key_handle: &Handle,
+        alg: KeyAlgorithm,
+        signed_data: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, Error> {
+        key(key_handle)?
+            .public_key(KeyOperation::Verify)?
+            .verify(alg, signed_data, signature)?;
+
+        Ok(true)
+    }
+
+    fn key_exchange(
+        &self,
+        key_handle: &Handle,
+        alg: KeyAlgorithm,
+        public_key: &Handle,
+        requested_size: usize,
+        shared_info: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let object = key(key_handle)?.private_object(KeyOperation::KeyExchange)?;
+        let descriptor = alg.descriptor();
+
+        if descriptor.family != AlgorithmFamily::Ecdh {
+            return Err(unsupported(alg));
+        }
+
+        let public_key = key(public_key)?.public_key(KeyOperation::KeyExchange)?;
+        let shared_secret = self.diffie_hellman(object, public_key)?;
+
+        match descriptor.digest {
+            Some(_) if requested_size == 0 => Err(Error::new(
+                ErrorKind::Param,
+                &format!("{} requires a requested size", alg),
+            )),
+            Some(kdf) => Ok(kdf
+                .x963_kdf(&shared_secret, shared_info, requested_size)
+                .to_vec()),
+            None => Ok(shared_secret.to_vec()),
+        }
+    }
+
+    fn encrypt(
+        &self,
+        key_handle: &Handle,
+        alg: KeyAlgorithm,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        key(key_handle)?
+            .public_key(KeyOperation::Encrypt)?
+            .encrypt(alg, plaintext)
+    }
+
+    fn decrypt(
+        &self,
+        key_handle: &Handle,
+        alg: KeyAlgorithm,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let key = key(key_handle)?;
+        let object = key.private_object(KeyOperation::Decrypt)?;
+        let scheme = encryption_algorithm(alg).ok_or_else(|| unsupported(alg))?;
+
+        let plaintext = match (key.key_type(), scheme) {
+            (Some(AttrKeyType::Rsa), EncryptionScheme::RsaRaw) => self.decrypt_with(
+                object,
+                &mut mechanism_without_params(CKM_RSA_X_509),
+                ciphertext,
+            )?,
+            (Some(AttrKeyType::Rsa), EncryptionScheme::RsaPkcs1) => self.decrypt_with(
+                object,
+                &mut mechanism_without_params(CKM_RSA_PKCS),
+                ciphertext,
+            )?,
+            (Some(AttrKeyType::Rsa), EncryptionScheme::RsaOaep(digest_alg)) => {
+                self.decrypt_oaep(object, digest_alg, ciphertext)?
+            }
+            (Some(AttrKeyType::Rsa), EncryptionScheme::RsaOaepAesGcm(digest_alg)) => {
+                // The public key is the AES-GCM additional data
+                let public_key = key.public_key.as_ref().ok_or_else(|| unsupported(alg))?;
+                let modulus_size = public_key.key_size_in_bits().div_ceil(8);
+
+                if ciphertext.len() < modulus_size + GCM_TAG_SIZE {
+                    return Err(Error::new(
+                        ErrorKind::DecryptionFailed,
+                        "invalid RSA ciphertext",
+                    ));
+                }
+
+                let (wrapped_key, sealed) = ciphertext.split_at(modulus_size);
+                let session_key = self.decrypt_oaep(object, digest_alg, wrapped_key)?;
+                return aes_gcm_decrypt(
+                    &session_key,
+                    [0u8; 16],
+                    &public_key.to_external_representation(),
+                    sealed,
+                );
+            }
+            (Some(AttrKeyType::EcSecPrimeRandom), EncryptionScheme::Ecies { kdf, variable_iv }) => {
+                let public_key = key.public_key.as_ref().ok_or_else(|| unsupported(alg))?;
+
+                return ecies::decrypt(
+                    public_key,
+                    |ephemeral_key| self.diffie_hellman(object, ephemeral_key),
+                    kdf,
+                    variable_iv,
+                    ciphertext,
+                );
+            }
+            _ => return Err(unsupported(alg)),
+        };
+
+        Ok(plaintext.to_vec())
+    }
+}
+
+/// Loaded PKCS#11 module
+struct Module {
+    /// Canonical path to the module
+    path: PathBuf,
+
+    /// Handle returned by `dlopen`
+    library: *mut c_void,
+
+    /// Function list returned by `C_GetFunctionList`
+    functions: *const CK_FUNCTION_LIST,
+
+    /// Did we initialize the module (as opposed to some other code in this
+    /// process)?
+    initialized: bool,
+}
+
+// PKCS#11 modules are initialized with `CKF_OS_LOCKING_OK`, making them
+// safe to call from multiple threads
+unsafe impl Send for Module {}
+unsafe impl Sync for Module {}
+
+impl Module {
+    /// Load and initialize the module at the given path, or get the module
+    /// if it's already loaded
+    fn load(path: &Path) -> Result<Arc<Self>, Error> {
+        let mut modules = MODULES.lock().unwrap_or_else(|e| e.into_inner());
+        modules.retain(|module| module.strong_count() > 0);
+
+        let path = path.canonicalize().map_err(|e| {
+            Error::new(
+                ErrorKind::NotAvailable,
+                &format!("couldn't load PKCS#11 module {}: {}", path.display(), e),
+            )
+        })?;
+
+        if let Some(module) = modules
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|module| module.path == path)
+        {
+            return Ok(module);
+        }
+
+        let module = Arc::new(Self::open(path)?);
+        modules.push(Arc::downgrade(&module));
+        Ok(module)
+    }
+
+    /// Load the module at the given path with `dlopen` and initialize it
+    fn open(path: PathBuf) -> Result<Self, Error> {
+        let filename = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| Error::new(ErrorKind::Param, &e))?;
+
+        let library = unsafe { libc::dlopen(filename.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+
+        if library.is_null() {
+            return Err(dl_error(&path));
+        }
+
+        let mut module = Self {
+            path,
+            library,
+            functions: ptr::null(),
+            initialized: false,
+        };
+
+        let symbol =
+            unsafe { libc::dlsym(library, b"C_GetFunctionList\0".as_ptr() as *const c_char) };
+
+        if symbol.is_null() {
+            return Err(dl_error(&module.path));
+        }
+
+        unsafe {
+            let get_function_list: CK_C_GetFunctionList = mem::transmute(symbol);
+            check(
+                get_function_list(&mut module.functions),
+                "C_GetFunctionList",
+            )?;
+        }
+
+        if module.functions.is_null() {
+            return Err(pkcs11_error(CKR_GENERAL_ERROR, "C_GetFunctionList"));
+        }
+
+        let mut args = CK_C_INITIALIZE_ARGS {
+            CreateMutex: None,
+            DestroyMutex: None,
+            LockMutex: None,
+            UnlockMutex: None,
+            flags: CKF_OS_LOCKING_OK,
+            pReserved: ptr::null_mut(),
+        };
+
+        let rv = unsafe {
+            ck!(
+                module,
+                C_Initialize(&mut args as *mut CK_C_INITIALIZE_ARGS as *mut c_void)
+            )
+        };
+
+        match rv {
+            CKR_OK => module.initialized = true,
+            CKR_CRYPTOKI_ALREADY_INITIALIZED => (),
+            rv => return Err(pkcs11_error(rv, "C_Initialize")),
+        }
+
+        Ok(module)
+    }
+
+    /// Get the module's function list
+    fn functions(&self) -> &CK_FUNCTION_LIST {
+        unsafe { &*self.functions }
+    }
+}
+
+impl Drop for Module {
+    fn drop(&mut self) {
+        unsafe {
+            if self.initialized {
+                ck!(self, C_Finalize(ptr::null_mut()));
+            }
+
+            libc::dlclose(self.library);
+        }
+    }
+}
+
+/// Attributes of a key object read from a token
+struct KeyObject {
+    /// Handle of the object
+    handle: CK_OBJECT_HANDLE,
+
+    /// Class of the key
+    key_class: AttrKeyClass,
+
+    /// Type of the key
+    key_type: AttrKeyType,
+
+    /// Key identifier (`CKA_ID`)
+    id: Vec<u8>,
+
+    /// Curve of EC keys (`CKA_EC_PARAMS`)
+    ec_params: Option<Vec<u8>>,
+
+    /// Public key (or public component of a private key), if the object's
+    /// attributes include it
+    public_key: Option<PublicKey>,
+
+    /// Attributes of the key, excluding the application label
+    attrs: AttributeMap,
+}
+
+impl KeyObject {
+    /// Is this a private key whose public key is the given object?
+    fn pairs_with(&self, public_key: &KeyObject) -> bool {
+        if self.key_class != AttrKeyClass::Private
+            || public_key.key_class != AttrKeyClass::Public
+            || self.key_type != public_key.key_type
+        {
+            return false;
+        }
+
+        match (&self.public_key, &public_key.public_key) {
+            (Some(a), Some(b)) => a == b,
+            _ => {
+                !self.id.is_empty()
+                    && self.id == public_key.id
+                    && self.ec_params == public_key.ec_params
+            }
+        }
+    }
+}
+
+/// Keys referenced by the handles a `Pkcs11Backend` returns
+struct Pkcs11Key {
+    /// Object on the token, or `None` for public keys held in software
+    object: Option<CK_OBJECT_HANDLE>,
+
+    /// Public key object on the token which corresponds to a private key
+    public_object: Option<CK_OBJECT_HANDLE>,
+
+    /// Public key (or public component of a private key)
+    public_key: Option<PublicKey>,
+
+    /// Attributes of the key as of when it was found
+    attrs: AttributeMap,
+}
+
+impl Pkcs11Key {
+    /// Create a key from an object on the token, using the public key
+    /// object it pairs with (if any) to fill in its public key
+    fn new(object: KeyObject, public_object: Option<&KeyObject>) -> Self {
+        let public_key = object
+            .public_key
+            .or_else(|| public_object.and_then(|public_object| public_object.public_key.clone()));
+
+        let mut attrs = object.attrs;
+
+        if let Some(public_key) = &public_key {
+            attrs.insert(AttrKind::ApplicationLabel, public_key.application_label());
+        }
+
+        Self {
+            object: Some(object.handle),
+            public_object: public_object.map(|public_object| public_object.handle),
+            public_key,
+            attrs,
+        }
+    }
+
+    /// Get the class of this key
+    fn key_class(&self) -> Option<AttrKeyClass> {
+        self.attrs
+            .get(AttrKind::KeyClass)
+            .and_then(AttrKeyClass::from_value)
+    }
+
+    /// Get the type of this key
+    fn key_type(&self) -> Option<AttrKeyType> {
+        self.attrs
+            .get(AttrKind::KeyType)
+            .and_then(AttrKeyType::from_value)
+    }
+
+    /// Get the object of a private key on the token, or an error if this
+    /// key can't perform the given (private key) operation
+    fn private_object(&self, operation: KeyOperation) -> Result<CK_OBJECT_HANDLE, Error> {
+        match (self.key_class(), self.object) {
+            (Some(AttrKeyClass::Private), Some(object)) => Ok(object),
+            _ => Err(wrong_key_class(operation)),
+        }
+    }
+
+    /// Get a public key, or an error if this key can't perform the given
+    /// (public key) operation
+    fn public_key(&self, operation: KeyOperation) -> Result<&PublicKey, Error> {
+        match (self.key_class(), &self.public_key) {
+            (Some(AttrKeyClass::Public), Some(public_key)) => Ok(public_key),
+            _ => Err(wrong_key_class(operation)),
+        }
+    }
+}
+
+/// Attribute template which owns the values of its attributes, for passing
+/// to functions which take a `CK_ATTRIBUTE` array
+#[derive(Default)]
+struct Template {
+    /// Attributes pointing into `values`
+    attrs: Vec<CK_ATTRIBUTE>,
+
+    /// Values of the attributes
+    values: Vec<Vec<u8>>,
+}
+
+impl Template {
+    /// Add an attribute with the given value
+    fn bytes(&mut self, type_: CK_ATTRIBUTE_TYPE, value: &[u8]) {
+        let mut value = value.to_vec();
+
+        self.attrs.push(CK_ATTRIBUTE {
+            type_,
+            pValue: value.as_mut_ptr() as *mut c_void,
+            ulValueLen: value.len() as CK_ULONG,
+        });
+
+        // Moving the vector doesn't move its contents
+        self.values.push(value);
+    }
+
+    /// Add a `CK_BBOOL` attribute
+    fn bool(&mut self, type_: CK_ATTRIBUTE_TYPE, value: bool) {
+        self.bytes(type_, &[if value { CK_TRUE } else { CK_FALSE }]);
+    }
+
+    /// Add a `CK_ULONG` attribute
+    fn ulong(&mut self, type_: CK_ATTRIBUTE_TYPE, value: CK_ULONG) {
+        self.bytes(type_, &value.to_ne_bytes());
+    }
+
+    /// Get a pointer to the attributes
+    fn as_mut_ptr(&mut self) -> *mut CK_ATTRIBUTE {
+        self.attrs.as_mut_ptr()
+    }
+
+    /// Get the number of attributes
+    fn len(&self) -> CK_ULONG {
+        self.attrs.len() as CK_ULONG
+    }
+}
+
+/// Find the slot containing the token with the given label
+fn find_token(module: &Module, label: &str) -> Result<(CK_SLOT_ID, CK_TOKEN_INFO), Error> {
+    let mut count: CK_ULONG = 0;
+
+    unsafe {
+        call!(module, C_GetSlotList(CK_TRUE, ptr::null_mut(), &mut count))?;
+    }
+
+    let mut slots = vec![0 as CK_SLOT_ID; count as usize];
+
+    unsafe {
+        call!(
+            module,
+            C_GetSlotList(CK_TRUE, slots.as_mut_ptr(), &mut count)
+        )?;
+    }
+
+    slots.truncate(count as usize);
+
+    for slot in slots {
+        let mut info = CK_TOKEN_INFO::default();
+
+        if unsafe { ck!(module, C_GetTokenInfo(slot, &mut info)) } == CKR_OK
+            && padded_str(&info.label) == label
+        {
+            return Ok((slot, info));
+        }
+    }
+
+    Err(Error::new(
+        ErrorKind::NoSuchKeychain,
+        &format!("no PKCS#11 token labeled {:?}", label),
+    ))
+}
+
+/// Perform a single-part operation which has been initialized (e.g.
+/// `C_Sign` after `C_SignInit`), first querying the size of the output
+fn single_part(
+    function: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG) -> CK_RV,
+    >,
+    name: &str,
+    session: CK_SESSION_HANDLE,
+    input: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let function = function.ok_or_else(|| pkcs11_error(CKR_FUNCTION_NOT_SUPPORTED, name))?;
+    let input_ptr = input.as_ptr() as *mut u8;
+    let input_len = input.len() as CK_ULONG;
+    let mut output_len: CK_ULONG = 0;
+
+    unsafe {
+        check(
+            function(
+                session,
+                input_ptr,
+                input_len,
+                ptr::null_mut(),
+                &mut output_len,
+            ),
+            name,
+        )?;
+    }
+
+    let mut output = vec![0u8; output_len as usize];
+
+    unsafe {
+        check(
+            function(
+                session,
+                input_ptr,
+                input_len,
+                output.as_mut_ptr(),
+                &mut output_len,
+            ),
+            name,
+        )?;
+    }
+
+    output.truncate(output_len as usize);
+    Ok(output)
+}
+
+/// Create a mechanism without parameters
+fn mechanism_without_params(mechanism: CK_MECHANISM_TYPE) -> CK_MECHANISM {
+    CK_MECHANISM {
+        mechanism,
+        pParameter: ptr::null_mut(),
+        ulParameterLen: 0,
+    }
+}
+
+/// Create a mechanism with the given parameters, which must outlive it
+fn mechanism_with_params<T>(mechanism: CK_MECHANISM_TYPE, params: &mut T) -> CK_MECHANISM {
+    CK_MECHANISM {
+        mechanism,
+        pParameter: params as *mut T as *mut c_void,
+        ulParameterLen: size_of::<T>() as CK_ULONG,
+    }
+}
+
+/// Get the hash mechanism and MGF1 function for a digest algorithm
+fn hash_mechanism(digest_alg: DigestAlgorithm) -> (CK_MECHANISM_TYPE, CK_RSA_PKCS_MGF_TYPE) {
+    match digest_alg {
+        DigestAlgorithm::Sha1 => (CKM_SHA_1, CKG_MGF1_SHA1),
+        DigestAlgorithm::Sha224 => (CKM_SHA224, CKG_MGF1_SHA224),
+        DigestAlgorithm::Sha256 => (CKM_SHA256, CKG_MGF1_SHA256),
+        DigestAlgorithm::Sha384 => (CKM_SHA384, CKG_MGF1_SHA384),
+        DigestAlgorithm::Sha512 => (CKM_SHA512, CKG_MGF1_SHA512),
+    }
+}
+
+/// Get the DER-encoded `DigestInfo` prefix which `CKM_RSA_PKCS` signatures
+/// expect before a digest
+fn digest_info_prefix(digest_alg: DigestAlgorithm) -> Vec<u8> {
+    let scheme = match digest_alg {
+        DigestAlgorithm::Sha1 => Pkcs1v15Sign::new::<Sha1>(),
+        DigestAlgorithm::Sha224 => Pkcs1v15Sign::new::<Sha224>(),
+        DigestAlgorithm::Sha256 => Pkcs1v15Sign::new::<Sha256>(),
+        DigestAlgorithm::Sha384 => Pkcs1v15Sign::new::<Sha384>(),
+        DigestAlgorithm::Sha512 => Pkcs1v15Sign::new::<Sha512>(),
+    };
+
+    scheme.prefix.into_vec()
+}
+
+/// Encode a fixed-size `r || s` ECDSA signature (as `CKM_ECDSA` produces)
+/// as ASN.1 DER
+fn ecdsa_der(signature: &[u8]) -> Result<Vec<u8>, Error> {
+    let invalid_signature = |_| Error::new(ErrorKind::Decode, "invalid ECDSA signature from token");
+
+    Ok(match signature.len() {
+        64 => p256::ecdsa::Signature::from_slice(signature)
+            .map_err(invalid_signature)?
+            .to_der()
+            .as_bytes()
+            .to_vec(),
+        96 => p384::ecdsa::Signature::from_slice(signature)
+            .map_err(invalid_signature)?
+            .to_der()
+            .as_bytes()
+            .to_vec(),
+        132 => p521::ecdsa::Signature::from_slice(signature)
+            .map_err(invalid_signature)?
+            .to_der()
+            .as_bytes()
+            .to_vec(),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::Decode,
+                "invalid ECDSA signature from token",
+            ))
+        }
+    })
+}
+
+/// Get the key size of the curve with the given `CKA_EC_PARAMS`
+fn curve_size(ec_params: &[u8]) -> Option<usize> {
+    match ec_params {
+        EC_PARAMS_P256 => Some(256),
+        EC_PARAMS_P384 => Some(384),
+        EC_PARAMS_P521 => Some(521),
+        _ => None,
+    }
+}
+
+/// Decode an EC public key from its `CKA_EC_PARAMS` and `CKA_EC_POINT`,
+/// the latter of which is usually a DER-encoded `OCTET STRING` containing
+/// the point, although some tokens return the point itself
+fn ec_public_key(ec_params: &[u8], point: &[u8]) -> Result<PublicKey, Error> {
+    let point_size = curve_size(ec_params)
+        .map(|key_size| 1 + 2 * key_size.div_ceil(8))
+        .ok_or_else(|| Error::new(ErrorKind::Unimplemented, "unsupported curve"))?;
+
+    let point = if point.len() == point_size {
+        point
+    } else {
+        OctetStringRef::from_der(point)
+            .map_err(|e| Error::new(ErrorKind::Decode, &e))?
+            .as_bytes()
+    };
+
+    PublicKey::from_external_representation(AttrKeyType::EcSecPrimeRandom, point)
+}
+
+/// Decode an RSA public key from its `CKA_MODULUS` and
+/// `CKA_PUBLIC_EXPONENT`
+fn rsa_public_key(modulus: &[u8], exponent: &[u8]) -> Result<PublicKey, Error> {
+    let public_key = RsaPublicKey::new_with_max_size(
+        BigUint::from_bytes_be(modulus),
+        BigUint::from_bytes_be(exponent),
+        MAX_RSA_KEY_SIZE,
+    )
+    .map(PublicKey::Rsa)
+    .map_err(|e| Error::new(ErrorKind::Decode, &e))?;
+
+    public_key.check_size()?;
+    Ok(public_key)
+}
+
+/// Decode a `CK_BBOOL` attribute value
+fn bool_value(value: Option<&Vec<u8>>) -> Option<bool> {
+    value
+        .and_then(|value| value.first())
+        .map(|b| *b != CK_FALSE)
+}
+
+/// Decode a `CK_ULONG` attribute value
+fn ulong_value(value: Option<&Vec<u8>>) -> Option<CK_ULONG> {
+    let bytes = value?.as_slice().try_into().ok()?;
+    Some(CK_ULONG::from_ne_bytes(bytes))
+}
+
+/// Decode a fixed-length, space-padded string from a PKCS#11 structure
+fn padded_str(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches([' ', '\0'])
+        .to_owned()
+}
+
+/// Get the key referenced by a handle
+fn key(handle: &Handle) -> Result<&Pkcs11Key, Error> {
+    handle.downcast_ref::<Pkcs11Key>().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidItemRef,
+            "handle does not belong to a Pkcs11Backend",
+        )
+    })
+}
+
+/// Check the result of `C_GetAttributeValue`, which reports attributes
+/// which are unavailable without failing outright
+fn check_get_attributes(rv: CK_RV) -> Result<(), Error> {
+    match rv {
+        CKR_ATTRIBUTE_SENSITIVE | CKR_ATTRIBUTE_TYPE_INVALID => Ok(()),
+        rv => check(rv, "C_GetAttributeValue"),
+    }
+}
+
+/// Convert a `CK_RV` returned by the given function into a result
+fn check(rv: CK_RV, function: &str) -> Result<(), Error> {
+    if rv == CKR_OK {
+        Ok(())
+    } else {
+        Err(pkcs11_error(rv, function))
+    }
+}
+
+/// Convert a `CK_RV` returned by the given function into an `Error` with
+/// the closest `ErrorKind`
+fn pkcs11_error(rv: CK_RV, function: &str) -> Error {
+    let kind = match rv {
+        CKR_PIN_INCORRECT
+        | CKR_PIN_INVALID
+        | CKR_PIN_LEN_RANGE
+        | CKR_PIN_EXPIRED
+        | CKR_PIN_LOCKED
+        | CKR_USER_NOT_LOGGED_IN => ErrorKind::AuthFailed,
+        CKR_OBJECT_HANDLE_INVALID | CKR_KEY_HANDLE_INVALID => ErrorKind::InvalidItemRef,
+        CKR_FUNCTION_NOT_SUPPORTED | CKR_MECHANISM_INVALID => ErrorKind::Unimplemented,
+        CKR_KEY_SIZE_RANGE => ErrorKind::KeySizeNotAllowed,
+        CKR_ATTRIBUTE_READ_ONLY => ErrorKind::ReadOnlyAttr,
+        CKR_ATTRIBUTE_TYPE_INVALID => ErrorKind::NoSuchAttr,
+        CKR_ATTRIBUTE_SENSITIVE => ErrorKind::DataNotAvailable,
+        CKR_TOKEN_WRITE_PROTECTED | CKR_SESSION_READ_ONLY => ErrorKind::ReadOnly,
+        CKR_SIGNATURE_INVALID | CKR_SIGNATURE_LEN_RANGE => ErrorKind::VerifyFailed,
+        CKR_ENCRYPTED_DATA_INVALID | CKR_ENCRYPTED_DATA_LEN_RANGE => ErrorKind::DecryptionFailed,
+        CKR_ARGUMENTS_BAD
+        | CKR_ATTRIBUTE_VALUE_INVALID
+        | CKR_DATA_INVALID
+        | CKR_DATA_LEN_RANGE
+        | CKR_KEY_TYPE_INCONSISTENT
+        | CKR_KEY_FUNCTION_NOT_PERMITTED
+        | CKR_MECHANISM_PARAM_INVALID
+        | CKR_TEMPLATE_INCOMPLETE
+        | CKR_TEMPLATE_INCONSISTENT => ErrorKind::Param,
+        _ => ErrorKind::NotAvailable,
+    };
+
+    let name = rv_name(rv)
+        .map(str::to_owned)
+        .unwrap_or_else(|| format!("{:#x}", rv));
+
+    Error::new(kind, &format!("{} failed: {}", function, name))
+}
+
+/// Error for failures to load a module with `dlopen`
+fn dl_error(path: &Path) -> Error {
+    let message = unsafe {
+        let message = libc::dlerror();
+
+        if message.is_null() {
+            "unknown error".to_owned()
+        } else {
+            CStr::from_ptr(message).to_string_lossy().into_owned()
+        }
+    };
+
+    Error::new(
+        ErrorKind::NotAvailable,
+        &format!(
+            "couldn't load PKCS#11 module {}: {}",
+            path.display(),
+            message
+        ),
+    )
+}
+
+/// Error for attribute values of the wrong type
+fn invalid_value(kind: AttrKind) -> Error {
+    Error::new(
+        ErrorKind::Param,
+        &format!("invalid value for attribute {:?}", kind),
+    )
+}
+
+/// Error for algorithms which can't be used with a particular key
+fn unsupported(alg: KeyAlgorithm) -> Error {
+    Error::new(
+        ErrorKind::Param,
+        &format!("algorithm not supported by key: {:?}", alg),
+    )
+}
+
+/// Error for attempting an operation with the wrong class of key
+fn wrong_key_class(operation: KeyOperation) -> Error {
+    Error::new(
+        ErrorKind::Param,
+        &format!("{:?} operation not supported by this key", operation),
+    )
+}
+
+/// Error for item classes PKCS#11 tokens can't store
+fn unsupported_class() -> Error {
+    Error::new(
+        ErrorKind::Unimplemented,
+        "PKCS#11 backend only supports keys generated on the token",
+    )
+}
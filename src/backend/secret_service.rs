@@ -0,0 +1,688 @@
+//! Keychain backend which stores passwords using the freedesktop.org Secret
+//! Service API (e.g. GNOME Keyring or KWallet) over D-Bus.
+//!
+//! See the Secret Service API specification:
+//! <https://specifications.freedesktop.org/secret-service-spec/latest/>
+
+use super::{memory::is_duplicate, Handle, KeychainBackend};
+use crate::{
+    attr::{AttrKind, AttrValue, AttributeMap},
+    error::{Error, ErrorKind},
+    keychain::{
+        item::{Class, MatchLimit, Query},
+        key::{KeyAlgorithm, KeyOperation, KeyPairGenerateParams, RestoreKeyParams},
+    },
+};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fmt::{self, Debug},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use zbus::{
+    blocking::{proxy::Builder as ProxyBuilder, Connection, Proxy},
+    proxy::CacheProperties,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value},
+    DBusError,
+};
+
+/// Well-known bus name of the Secret Service
+const SERVICE_NAME: &str = "org.freedesktop.secrets";
+
+/// Object path of the Secret Service
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+
+/// Secret Service interface
+const SERVICE_INTERFACE: &str = "org.freedesktop.Secret.Service";
+
+/// Collection interface
+const COLLECTION_INTERFACE: &str = "org.freedesktop.Secret.Collection";
+
+/// Item interface
+const ITEM_INTERFACE: &str = "org.freedesktop.Secret.Item";
+
+/// Prompt interface
+const PROMPT_INTERFACE: &str = "org.freedesktop.Secret.Prompt";
+
+/// Session interface
+const SESSION_INTERFACE: &str = "org.freedesktop.Secret.Session";
+
+/// Alias of the collection items are stored in
+const DEFAULT_COLLECTION: &str = "default";
+
+/// Attribute libsecret uses to record an item's schema
+const SCHEMA_ATTRIBUTE: &str = "xdg:schema";
+
+/// Schema of generic password items
+const GENERIC_PASSWORD_SCHEMA: &str = "org.freedesktop.Secret.Generic";
+
+/// Schema of Internet password items
+const INTERNET_PASSWORD_SCHEMA: &str = "org.gnome.keyring.NetworkPassword";
+
+/// Content type of stored passwords
+const CONTENT_TYPE: &str = "text/plain";
+
+/// Object path used in place of a prompt when none is needed
+const NO_PROMPT: &str = "/";
+
+/// Secrets as transferred over D-Bus: `(session, parameters, value,
+/// content_type)`
+type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+/// Keychain backend which stores `GenericPassword` and `InternetPassword`
+/// items in the default collection of a Secret Service (e.g. the "login"
+/// keyring of GNOME Keyring).
+///
+/// Items are stored using the same schemas as libsecret, so e.g. the
+/// `service` and `account` of a generic password correspond to the
+/// `service` and `account` Secret Service attributes, and the `server`,
+/// `account`, and `protocol` of an Internet password correspond to the
+/// `server`, `user`, and `protocol` attributes. Item labels are mapped to
+/// Secret Service labels.
+///
+/// Locked collections are unlocked on demand, which may prompt the user.
+/// Secrets are transferred using the `plain` algorithm, i.e. unencrypted
+/// (but only to the Secret Service over the local session bus).
+///
+/// Cryptographic keys are not supported.
+pub struct SecretServiceBackend {
+    /// D-Bus connection to the bus hosting the Secret Service
+    connection: Connection,
+
+    /// Collection items are stored in
+    collection: OwnedObjectPath,
+
+    /// Session used to transfer secrets
+    session: OwnedObjectPath,
+}
+
+impl SecretServiceBackend {
+    /// Connect to the Secret Service on the session bus
+    pub fn new() -> Result<Self, Error> {
+        Self::from_connection(Connection::session().map_err(dbus_error)?)
+    }
+
+    /// Connect to the Secret Service on the D-Bus bus with the given
+    /// address, e.g. `unix:path=/run/user/1000/bus`
+    pub fn with_address(address: &str) -> Result<Self, Error> {
+        let connection = zbus::blocking::connection::Builder::address(address)
+            .and_then(|builder| builder.build())
+            .map_err(dbus_error)?;
+
+        Self::from_connection(connection)
+    }
+
+    /// Open a session with the Secret Service and find the default
+    /// collection
+    fn from_connection(connection: Connection) -> Result<Self, Error> {
+        let service = proxy(&connection, SERVICE_PATH, SERVICE_INTERFACE)?;
+
+        let (_, session): (OwnedValue, OwnedObjectPath) = service
+            .call("OpenSession", &("plain", Value::from("")))
+            .map_err(dbus_error)?;
+
+        let collection: OwnedObjectPath = service
+            .call("ReadAlias", &(DEFAULT_COLLECTION,))
+            .map_err(dbus_error)?;
+
+        if collection.as_str() == NO_PROMPT {
+            return Err(Error::new(
+                ErrorKind::NoDefaultKeychain,
+                "Secret Service has no default collection",
+            ));
+        }
+
+        Ok(Self {
+            connection,
+            collection,
+            session,
+        })
+    }
+
+    /// Create a proxy for an object exported by the Secret Service
+    fn proxy<'p>(&self, path: &'p str, interface: &'static str) -> Result<Proxy<'p>, Error> {
+        proxy(&self.connection, path, interface)
+    }
+
+    /// Unlock the collection if it's locked, prompting the user if needed
+    fn unlock(&self) -> Result<(), Error> {
+        let collection = self.proxy(self.collection.as_str(), COLLECTION_INTERFACE)?;
+
+        if !collection
+            .get_property::<bool>("Locked")
+            .map_err(dbus_error)?
+        {
+            return Ok(());
+        }
+
+        let service = self.proxy(SERVICE_PATH, SERVICE_INTERFACE)?;
+        let (unlocked, prompt): (Vec<OwnedObjectPath>, OwnedObjectPath) = service
+            .call("Unlock", &(vec![&self.collection],))
+            .map_err(dbus_error)?;
+
+        if unlocked.contains(&self.collection) {
+            return Ok(());
+        }
+
+        let unlocked: Vec<OwnedObjectPath> = self
+            .prompt(&prompt)?
+            .map(|result| result.try_into().map_err(dbus_error))
+            .transpose()?
+            .unwrap_or_default();
+
+        if unlocked.contains(&self.collection) {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::AuthFailed,
+                "couldn't unlock Secret Service collection",
+            ))
+        }
+    }
+
+    /// Show a prompt (if one is required) and wait for it to complete,
+    /// returning its result. Returns an error with a kind of
+    /// `ErrorKind::AuthFailed` if the prompt is dismissed.
+    fn prompt(&self, path: &ObjectPath<'_>) -> Result<Option<OwnedValue>, Error> {
+        if path.as_str() == NO_PROMPT {
+            return Ok(None);
+        }
+
+        let prompt = self.proxy(path.as_str(), PROMPT_INTERFACE)?;
+        let mut completed = prompt.receive_signal("Completed").map_err(dbus_error)?;
+        prompt
+            .call::<_, _, ()>("Prompt", &("",))
+            .map_err(dbus_error)?;
+
+        let message = completed
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::NotAvailable, "Secret Service prompt vanished"))?;
+
+        let (dismissed, result): (bool, OwnedValue) =
+            message.body().deserialize().map_err(dbus_error)?;
+
+        if dismissed {
+            Err(Error::new(
+                ErrorKind::AuthFailed,
+                "Secret Service prompt was dismissed",
+            ))
+        } else {
+            Ok(Some(result))
+        }
+    }
+
+    /// Find items of the given class which match a query, returning their
+    /// object paths and attributes
+    fn find(
+        &self,
+        class: Class,
+        query: &Query,
+        limit: MatchLimit,
+    ) -> Result<Vec<(OwnedObjectPath, AttributeMap)>, Error> {
+        let mut search = search_attributes(class)?;
+
+        for (kind, name) in attribute_names(class) {
+            if let Some(AttrValue::String(value)) = query.attributes().get(*kind) {
+                search.insert(name, value.clone());
+            }
+        }
+
+        self.unlock()?;
+
+        let paths: Vec<OwnedObjectPath> = self
+            .proxy(&self.collection, COLLECTION_INTERFACE)?
+            .call("SearchItems", &(search,))
+            .map_err(dbus_error)?;
+
+        let items = paths
+            .into_iter()
+            .map(|path| {
+                let attrs = self.item_attributes(class, &path)?;
+                Ok((path, attrs))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(query
+            .select(items.iter().map(|(_, attrs)| attrs), limit)
+            .into_iter()
+            .map(|i| items[i].clone())
+            .collect())
+    }
+
+    /// Get the attributes of an item
+    fn item_attributes(&self, class: Class, path: &ObjectPath<'_>) -> Result<AttributeMap, Error> {
+        let item = self.proxy(path.as_str(), ITEM_INTERFACE)?;
+        let attributes: HashMap<String, String> =
+            item.get_property("Attributes").map_err(dbus_error)?;
+        let label: String = item.get_property("Label").map_err(dbus_error)?;
+        let created: u64 = item.get_property("Created").map_err(dbus_error)?;
+        let modified: u64 = item.get_property("Modified").map_err(dbus_error)?;
+
+        let mut attrs = AttributeMap::new();
+
+        for (kind, name) in attribute_names(class) {
+            if let Some(value) = attributes.get(*name) {
+                attrs.insert(*kind, value.as_str());
+            }
+        }
+
+        attrs.insert(AttrKind::Label, label);
+        attrs.insert(AttrKind::CreationDate, timestamp(created));
+        attrs.insert(AttrKind::ModificationDate, timestamp(modified));
+        Ok(attrs)
+    }
+
+    /// Ensure items with the given attributes wouldn't duplicate any other
+    /// item in the collection (besides the ones in `except`)
+    fn check_duplicates(
+        &self,
+        class: Class,
+        attrs: &AttributeMap,
+        except: &[OwnedObjectPath],
+    ) -> Result<(), Error> {
+        for (path, other) in self.find(class, &Query::new(), MatchLimit::All)? {
+            if !except.contains(&path) && is_duplicate(class, attrs, &other) {
+                return Err(Error::new(
+                    ErrorKind::DuplicateItem,
+                    "The specified item already exists in the keychain.",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Debug for SecretServiceBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "SecretServiceBackend {{ collection: {:?} }}",
+            self.collection.as_str()
+        )
+    }
+}
+
+impl Drop for SecretServiceBackend {
+    fn drop(&mut self) {
+        if let Ok(session) = self.proxy(self.session.as_str(), SESSION_INTERFACE) {
+            let _ = session.call::<_, _, ()>("Close", &());
+        }
+    }
+}
+
+impl KeychainBackend for SecretServiceBackend {
+    fn add(&self, class: Class, attrs: &AttributeMap, data: &[u8]) -> Result<Handle, Error> {
+        let (attributes, label) = secret_attributes(class, attrs)?;
+        self.check_duplicates(class, attrs, &[])?;
+
+        // Like Keychain Services, label items with their service or server
+        // by default
+        let label = label
+            .or_else(|| {
+                attrs
+                    .get(AttrKind::Service)
+                    .or_else(|| attrs.get(AttrKind::Server))
+                    .and_then(AttrValue::as_str)
+                    .map(ToOwned::to_owned)
+            })
+            .unwrap_or_default();
+
+        let mut properties = HashMap::new();
+        properties.insert("org.freedesktop.Secret.Item.Label", Value::from(label));
+        properties.insert(
+            "org.freedesktop.Secret.Item.Attributes",
+            Value::from(attributes),
+        );
+
+        let secret = (&self.session, Vec::<u8>::new(), data, CONTENT_TYPE);
+
+        let (item, prompt): (OwnedObjectPath, OwnedObjectPath) = self
+            .proxy(&self.collection, COLLECTION_INTERFACE)?
+            .call("CreateItem", &(properties, &secret, false))
+            .map_err(dbus_error)?;
+
+        let item = match self.prompt(&prompt)? {
+            Some(result) => result.try_into().map_err(dbus_error)?,
+            None => item,
+        };
+
+        Ok(Handle::new(SecretItem { class, path: item }))
+    }
+
+    fn copy_matching(
+        &self,
+        class: Class,
+        query: &Query,
+        limit: MatchLimit,
+    ) -> Result<Vec<Handle>, Error> {
+        let handles: Vec<_> = self
+            .find(class, query, limit)?
+            .into_iter()
+            .map(|(path, _)| Handle::new(SecretItem { class, path }))
+            .collect();
+
+        if handles.is_empty() {
+            Err(Error::new(
+                ErrorKind::ItemNotFound,
+                "The specified item could not be found in the keychain.",
+            ))
+        } else {
+            Ok(handles)
+        }
+    }
+
+    fn update(&self, class: Class, query: &Query, attrs: &AttributeMap) -> Result<(), Error> {
+        let items = self.find(class, query, MatchLimit::All)?;
+
+        if items.is_empty() {
+            return Err(Error::new(
+                ErrorKind::ItemNotFound,
+                "The specified item could not be found in the keychain.",
+            ));
+        }
+
+        let all_items = self.find(class, &Query::new(), MatchLimit::All)?;
+        let updates: Vec<_> = items
+            .into_iter()
+            .map(|(path, existing)| {
+                let mut updated = existing;
+                updated.extend(attrs.iter().map(|(k, v)| (*k, v.clone())));
+                (path, updated)
+            })
+            .collect();
+
+        // Ensure the update doesn't cause two items to share a primary key
+        for (path, updated) in &updates {
+            for (other_path, other) in &all_items {
+                let other = updates
+                    .iter()
+                    .find(|(p, _)| p == other_path)
+                    .map(|(_, attrs)| attrs)
+                    .unwrap_or(other);
+
+                if other_path != path && is_duplicate(class, updated, other) {
+                    return Err(Error::new(
+                        ErrorKind::DuplicateItem,
+                        "The specified item already exists in the keychain.",
+                    ));
+                }
+            }
+        }
+
+        for (path, updated) in updates {
+            let (attributes, label) = secret_attributes(class, &updated)?;
+            let item = self.proxy(path.as_str(), ITEM_INTERFACE)?;
+
+            item.set_property("Attributes", attributes)
+                .map_err(dbus_error)?;
+
+            if let Some(label) = label {
+                item.set_property("Label", label).map_err(dbus_error)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delete(&self, class: Class, query: &Query) -> Result<(), Error> {
+        let items = self.find(class, query, MatchLimit::All)?;
+
+        if items.is_empty() {
+            return Err(Error::new(
+                ErrorKind::ItemNotFound,
+                "The specified item could not be found in the keychain.",
+            ));
+        }
+
+        for (path, _) in items {
+            let prompt: OwnedObjectPath = self
+                .proxy(&path, ITEM_INTERFACE)?
+                .call("Delete", &())
+                .map_err(dbus_error)?;
+
+            self.prompt(&prompt)?;
+        }
+
+        Ok(())
+    }
+
+    fn delete_keychain(&self) -> Result<(), Error> {
+        let prompt: OwnedObjectPath = self
+            .proxy(&self.collection, COLLECTION_INTERFACE)?
+            .call("Delete", &())
+            .map_err(dbus_error)?;
+
+        self.prompt(&prompt)?;
+        Ok(())
+    }
+
+    fn attributes(&self, handle: &Handle) -> Result<AttributeMap, Error> {
+        let item = item(handle)?;
+        self.item_attributes(item.class, &item.path)
+    }
+
+    fn data(&self, handle: &Handle) -> Result<Vec<u8>, Error> {
+        self.unlock()?;
+
+        let (_, _, value, _): Secret = self
+            .proxy(item(handle)?.path.as_str(), ITEM_INTERFACE)?
+            .call("GetSecret", &(&self.session,))
+            .map_err(dbus_error)?;
+
+        Ok(value)
+    }
+
+    fn create_key_pair(&self, _params: &KeyPairGenerateParams) -> Result<(Handle, Handle), Error> {
+        Err(keys_unsupported())
+    }
+
+    fn restore_key(&self, _params: &RestoreKeyParams) -> Result<Handle, Error> {
+        Err(keys_unsupported())
+    }
+
+    fn export_key(&self, _key: &Handle) -> Result<Vec<u8>, Error> {
+        Err(keys_unsupported())
+    }
+
+    fn copy_public_key(&self, _key: &Handle) -> Result<Handle, Error> {
+        Err(keys_unsupported())
+    }
+
+    fn is_algorithm_supported(
+        &self,
+        _key: &Handle,
+        _operation: KeyOperation,
+        _alg: KeyAlgorithm,
+    ) -> bool {
+        false
+    }
+
+    fn sign(&self, _key: &Handle, _alg: KeyAlgorithm, _data: &[u8]) -> Result<Vec<u8>, Error> {
+        Err(keys_unsupported())
+    }
+
+    fn verify(
+        &self,
+        _key: &Handle,
+        _alg: KeyAlgorithm,
+        _signed_data: &[u8],
+        _signature: &[u8],
+    ) -> Result<bool, Error> {
+        Err(keys_unsupported())
+    }
+
+    fn key_exchange(
+        &self,
+        _key: &Handle,
+        _alg: KeyAlgorithm,
+        _public_key: &Handle,
+        _requested_size: usize,
+        _shared_info: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        Err(keys_unsupported())
+    }
+
+    fn encrypt(
+        &self,
+        _key: &Handle,
+        _alg: KeyAlgorithm,
+        _plaintext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        Err(keys_unsupported())
+    }
+
+    fn decrypt(
+        &self,
+        _key: &Handle,
+        _alg: KeyAlgorithm,
+        _ciphertext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        Err(keys_unsupported())
+    }
+}
+
+/// Items stored in the Secret Service
+struct SecretItem {
+    /// Class of this item
+    class: Class,
+
+    /// Object path of this item
+    path: OwnedObjectPath,
+}
+
+/// Get the item referenced by a handle
+fn item(handle: &Handle) -> Result<&SecretItem, Error> {
+    handle.downcast_ref::<SecretItem>().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidItemRef,
+            "handle does not belong to a SecretServiceBackend",
+        )
+    })
+}
+
+/// Create an uncached proxy for an object exported by the Secret Service
+fn proxy<'p>(
+    connection: &Connection,
+    path: &'p str,
+    interface: &'static str,
+) -> Result<Proxy<'p>, Error> {
+    ProxyBuilder::new(connection)
+        .destination(SERVICE_NAME)
+        .and_then(|builder| builder.path(path))
+        .and_then(|builder| builder.interface(interface))
+        .and_then(|builder| builder.cache_properties(CacheProperties::No).build())
+        .map_err(dbus_error)
+}
+
+/// Secret Service attribute names of the keychain attributes of items of
+/// the given class, following libsecret's schemas
+fn attribute_names(class: Class) -> &'static [(AttrKind, &'static str)] {
+    match class {
+        Class::GenericPassword => &[
+            (AttrKind::Account, "account"),
+            (AttrKind::Service, "service"),
+        ],
+        Class::InternetPassword => &[
+            (AttrKind::Account, "user"),
+            (AttrKind::Server, "server"),
+            (AttrKind::Protocol, "protocol"),
+        ],
+        _ => &[],
+    }
+}
+
+/// Secret Service attributes which identify items of the given class
+fn search_attributes(class: Class) -> Result<HashMap<&'static str, String>, Error> {
+    let schema = match class {
+        Class::GenericPassword => GENERIC_PASSWORD_SCHEMA,
+        Class::InternetPassword => INTERNET_PASSWORD_SCHEMA,
+        _ => return Err(keys_unsupported()),
+    };
+
+    let mut attributes = HashMap::new();
+    attributes.insert(SCHEMA_ATTRIBUTE, schema.to_owned());
+    Ok(attributes)
+}
+
+/// Convert keychain item attributes to Secret Service attributes and a label
+fn secret_attributes(
+    class: Class,
+    attrs: &AttributeMap,
+) -> Result<(HashMap<&'static str, String>, Option<String>), Error> {
+    let mut attributes = search_attributes(class)?;
+    let mut label = None;
+
+    for (kind, value) in attrs.iter() {
+        match (kind, value) {
+            (AttrKind::CreationDate, _) | (AttrKind::ModificationDate, _) => continue,
+            (AttrKind::Synchronizable, AttrValue::Bool(false)) => continue,
+            (AttrKind::Label, AttrValue::String(value)) => {
+                label = Some(value.clone());
+                continue;
+            }
+            _ => (),
+        }
+
+        let name = attribute_names(class)
+            .iter()
+            .find(|(k, _)| k == kind)
+            .map(|(_, name)| *name)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NoSuchAttr,
+                    &format!("attribute {:?} unsupported by Secret Service", kind),
+                )
+            })?;
+
+        let value = value.as_str().ok_or_else(|| {
+            Error::new(
+                ErrorKind::Param,
+                &format!("attribute {:?} is not a string: {:?}", kind, value),
+            )
+        })?;
+
+        attributes.insert(name, value.to_owned());
+    }
+
+    Ok((attributes, label))
+}
+
+/// Convert a Secret Service timestamp (seconds since the Unix epoch)
+fn timestamp(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// Error for operations on keys, which the Secret Service can't store
+fn keys_unsupported() -> Error {
+    Error::new(
+        ErrorKind::Unimplemented,
+        "Secret Service backend only supports password items",
+    )
+}
+
+/// Convert a D-Bus error into an `Error` with the closest `ErrorKind`
+fn dbus_error<E: Into<zbus::Error>>(error: E) -> Error {
+    let error = error.into();
+    let name = match &error {
+        zbus::Error::MethodError(name, _, _) => Some(name.to_string()),
+        zbus::Error::FDO(e) => Some(e.name().to_string()),
+        _ => None,
+    };
+
+    let kind = match name.as_deref() {
+        Some("org.freedesktop.Secret.Error.IsLocked") => ErrorKind::InteractionNotAllowed,
+        Some("org.freedesktop.Secret.Error.NoSuchObject")
+        | Some("org.freedesktop.DBus.Error.UnknownObject") => ErrorKind::ItemNotFound,
+        Some("org.freedesktop.DBus.Error.AccessDenied") => ErrorKind::AuthFailed,
+        Some("org.freedesktop.DBus.Error.UnknownMethod")
+        | Some("org.freedesktop.DBus.Error.NotSupported") => ErrorKind::Unimplemented,
+        Some(_) => ErrorKind::NotAvailable,
+        None => match error {
+            zbus::Error::Variant(_) | zbus::Error::InvalidReply => ErrorKind::Decode,
+            _ => ErrorKind::NotAvailable,
+        },
+    };
+
+    Error::new(kind, &error)
+}
@@ -0,0 +1,499 @@
+//! Keychain backend which uses the macOS Security Framework (i.e. Keychain
+//! Services itself).
+
+use super::{Handle, KeychainBackend};
+use crate::{
+    attr::{AttrKind, AttrValue, AttributeMap},
+    dictionary::{Dictionary, DictionaryBuilder},
+    error::{Error, ErrorKind},
+    ffi::*,
+    keychain::{
+        item::{Class, MatchLimit, Query},
+        key::{KeyAlgorithm, KeyOperation, KeyPairGenerateParams, RestoreKeyParams},
+    },
+};
+use core_foundation::{
+    array::CFArray,
+    base::{CFIndexConvertible, CFType, CFTypeRef, TCFType},
+    data::CFData,
+    error::CFErrorRef,
+};
+use std::{mem, os::raw::c_void, ptr, slice};
+
+/// Keychain backend which uses Keychain Services (via the macOS Security
+/// Framework).
+#[derive(Clone, Debug, Default)]
+pub struct SecurityFrameworkBackend {
+    /// Keychain to add items to (if not the default keychain)
+    keychain: Option<SecKeychain>,
+}
+
+impl SecurityFrameworkBackend {
+    /// Create a backend which uses the user's default keychain(s)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a backend which stores items in a particular keychain
+    pub(crate) fn with_keychain(keychain: SecKeychain) -> Self {
+        Self {
+            keychain: Some(keychain),
+        }
+    }
+
+    /// Build a query dictionary for the given class of items
+    fn query(&self, class: Class, query: &Query) -> DictionaryBuilder {
+        let mut params = DictionaryBuilder::from(query);
+        params.add_class(class);
+        params
+    }
+
+    /// Wrap a reference returned from `SecItemAdd`/`SecItemCopyMatching`
+    fn handle(class: Class, item_ref: CFTypeRef) -> Handle {
+        let item = unsafe { CFType::wrap_under_get_rule(item_ref) };
+
+        if class == Class::Key {
+            Handle::new(unsafe { SecKey::wrap_under_get_rule(item.as_CFTypeRef()) })
+        } else {
+            Handle::new(unsafe { SecKeychainItem::wrap_under_get_rule(item.as_CFTypeRef()) })
+        }
+    }
+}
+
+impl KeychainBackend for SecurityFrameworkBackend {
+    fn add(&self, class: Class, attrs: &AttributeMap, data: &[u8]) -> Result<Handle, Error> {
+        let mut params = DictionaryBuilder::from(attrs);
+        params.add_class(class);
+        params.add(unsafe { kSecValueData }, &CFData::from_buffer(data));
+        params.add_boolean(unsafe { kSecReturnRef }, true);
+
+        if let Some(keychain) = &self.keychain {
+            params.add(unsafe { kSecUseKeychain }, keychain);
+        }
+
+        let mut result: CFTypeRef = ptr::null_mut();
+        let status =
+            unsafe { SecItemAdd(Dictionary::from(params).as_concrete_TypeRef(), &mut result) };
+
+        if let Some(e) = Error::maybe_from_OSStatus(status) {
+            return Err(e);
+        }
+
+        let handle = Self::handle(class, result);
+        unsafe { core_foundation::base::CFRelease(result) };
+        Ok(handle)
+    }
+
+    fn copy_matching(
+        &self,
+        class: Class,
+        query: &Query,
+        limit: MatchLimit,
+    ) -> Result<Vec<Handle>, Error> {
+        let mut params = self.query(class, query);
+        params.add(unsafe { kSecMatchLimit }, &limit.as_CFType());
+        params.add_boolean(unsafe { kSecReturnRef }, true);
+
+        let mut result: CFTypeRef = ptr::null_mut();
+        let status = unsafe {
+            SecItemCopyMatching(Dictionary::from(params).as_concrete_TypeRef(), &mut result)
+        };
+
+        // Return an error if the status was unsuccessful
+        if let Some(e) = Error::maybe_from_OSStatus(status) {
+            return Err(e);
+        }
+
+        let result = unsafe { CFType::wrap_under_create_rule(result) };
+
+        // Queries which match more than one item return an array
+        Ok(match result.downcast::<CFArray>() {
+            Some(array) => array
+                .get_all_values()
+                .into_iter()
+                .map(|item_ref| Self::handle(class, item_ref))
+                .collect(),
+            None => vec![Self::handle(class, result.as_CFTypeRef())],
+        })
+    }
+
+    fn update(&self, class: Class, query: &Query, attrs: &AttributeMap) -> Result<(), Error> {
+        let status = unsafe {
+            SecItemUpdate(
+                Dictionary::from(self.query(class, query)).as_concrete_TypeRef(),
+                Dictionary::from(DictionaryBuilder::from(attrs)).as_concrete_TypeRef(),
+            )
+        };
+
+        match Error::maybe_from_OSStatus(status) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn delete(&self, class: Class, query: &Query) -> Result<(), Error> {
+        let status = unsafe {
+            SecItemDelete(Dictionary::from(self.query(class, query)).as_concrete_TypeRef())
+        };
+
+        match Error::maybe_from_OSStatus(status) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn delete_keychain(&self) -> Result<(), Error> {
+        let keychain = self.keychain.as_ref().ok_or_else(|| {
+            Error::new(
+                ErrorKind::NoSuchKeychain,
+                "backend is not associated with a particular keychain",
+            )
+        })?;
+
+        let status = unsafe { SecKeychainDelete(keychain.as_concrete_TypeRef()) };
+
+        match Error::maybe_from_OSStatus(status) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn attributes(&self, handle: &Handle) -> Result<AttributeMap, Error> {
+        if let Some(key) = handle.downcast_ref::<SecKey>() {
+            let attrs = unsafe {
+                Dictionary::wrap_under_create_rule(SecKeyCopyAttributes(key.as_concrete_TypeRef()))
+            };
+            return Ok(AttributeMap::from(&attrs));
+        }
+
+        let item = item(handle)?;
+        let mut attrs: SecKeychainAttributeList = unsafe { mem::zeroed() };
+
+        let status = unsafe {
+            SecKeychainItemCopyContent(
+                item.as_concrete_TypeRef(),
+                ptr::null_mut(),
+                &mut attrs,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+
+        if let Some(e) = Error::maybe_from_OSStatus(status) {
+            return Err(e);
+        }
+
+        let mut result = AttributeMap::new();
+
+        for attr in attrs.iter() {
+            if let (Some(kind), Some(data)) = (AttrKind::from_tag(attr.tag()), attr.data()) {
+                let value = match String::from_utf8(data.into()) {
+                    Ok(string) => AttrValue::String(string),
+                    Err(e) => AttrValue::Data(e.into_bytes()),
+                };
+
+                result.insert(kind, value);
+            }
+        }
+
+        Error::maybe_from_OSStatus(unsafe {
+            SecKeychainItemFreeContent(&mut attrs, ptr::null_mut())
+        })
+        .map_or(Ok(result), Err)
+    }
+
+    fn data(&self, handle: &Handle) -> Result<Vec<u8>, Error> {
+        let item = item(handle)?;
+        let mut result_ptr: *mut c_void = ptr::null_mut();
+        let mut length = 0;
+
+        let status = unsafe {
+            SecKeychainItemCopyContent(
+                item.as_concrete_TypeRef(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut length,
+                &mut result_ptr,
+            )
+        };
+
+        if let Some(e) = Error::maybe_from_OSStatus(status) {
+            Err(e)
+        } else if result_ptr.is_null() {
+            Err(Error::new(
+                ErrorKind::MissingEntitlement,
+                "SecKeychainItemCopyContent refused to return data",
+            ))
+        } else {
+            // Copy the data into a vector we've allocated
+            let result = Vec::from(unsafe {
+                slice::from_raw_parts(result_ptr as *const u8, length as usize)
+            });
+
+            // Free the original data
+            Error::maybe_from_OSStatus(unsafe {
+                SecKeychainItemFreeContent(ptr::null_mut(), result_ptr)
+            })
+            .map_or(Ok(result), Err)
+        }
+    }
+
+    fn create_key_pair(&self, params: &KeyPairGenerateParams) -> Result<(Handle, Handle), Error> {
+        let mut error: CFErrorRef = ptr::null_mut();
+        let private_key_ref: KeyRef = unsafe {
+            SecKeyCreateRandomKey(Dictionary::from(params).as_concrete_TypeRef(), &mut error)
+        };
+
+        if private_key_ref.is_null() {
+            Err(error.into())
+        } else {
+            let public_key_ref = unsafe { SecKeyCopyPublicKey(private_key_ref) };
+            assert!(!public_key_ref.is_null());
+
+            Ok(unsafe {
+                (
+                    Handle::new(SecKey::wrap_under_create_rule(public_key_ref)),
+                    Handle::new(SecKey::wrap_under_create_rule(private_key_ref)),
+                )
+            })
+        }
+    }
+
+    fn generate_key_pair(&self, params: &KeyPairGenerateParams) -> Result<(Handle, Handle), Error> {
+        let mut public_key_ref: KeyRef = ptr::null_mut();
+        let mut private_key_ref: KeyRef = ptr::null_mut();
+
+        let status = unsafe {
+            SecKeyGeneratePair(
+                Dictionary::from(params).as_concrete_TypeRef(),
+                &mut public_key_ref,
+                &mut private_key_ref,
+            )
+        };
+
+        // Return an error if the status was unsuccessful
+        if let Some(e) = Error::maybe_from_OSStatus(status) {
+            return Err(e);
+        }
+
+        assert!(!public_key_ref.is_null());
+        assert!(!private_key_ref.is_null());
+
+        Ok(unsafe {
+            (
+                Handle::new(SecKey::wrap_under_create_rule(public_key_ref)),
+                Handle::new(SecKey::wrap_under_create_rule(private_key_ref)),
+            )
+        })
+    }
+
+    fn restore_key(&self, params: &RestoreKeyParams) -> Result<Handle, Error> {
+        let mut error: CFErrorRef = ptr::null_mut();
+        let key_ref = unsafe {
+            SecKeyCreateWithData(
+                CFData::from_buffer(params.as_bytes()).as_concrete_TypeRef(),
+                Dictionary::from(DictionaryBuilder::from(&params.attributes()))
+                    .as_concrete_TypeRef(),
+                &mut error,
+            )
+        };
+
+        if error.is_null() {
+            Ok(Handle::new(unsafe {
+                SecKey::wrap_under_create_rule(key_ref)
+            }))
+        } else {
+            Err(error.into())
+        }
+    }
+
+    fn export_key(&self, key: &Handle) -> Result<Vec<u8>, Error> {
+        let mut error: CFErrorRef = ptr::null_mut();
+        let data = unsafe {
+            SecKeyCopyExternalRepresentation(sec_key(key)?.as_concrete_TypeRef(), &mut error)
+        };
+
+        if error.is_null() {
+            Ok(unsafe { CFData::wrap_under_create_rule(data) }.to_vec())
+        } else {
+            Err(error.into())
+        }
+    }
+
+    fn copy_public_key(&self, key: &Handle) -> Result<Handle, Error> {
+        let public_key_ref = unsafe { SecKeyCopyPublicKey(sec_key(key)?.as_concrete_TypeRef()) };
+
+        if public_key_ref.is_null() {
+            Err(Error::new(
+                ErrorKind::ItemNotFound,
+                "no public key associated with this key",
+            ))
+        } else {
+            Ok(Handle::new(unsafe {
+                SecKey::wrap_under_create_rule(public_key_ref)
+            }))
+        }
+    }
+
+    fn is_algorithm_supported(
+        &self,
+        key: &Handle,
+        operation: KeyOperation,
+        alg: KeyAlgorithm,
+    ) -> bool {
+        let key = match sec_key(key) {
+            Ok(key) => key,
+            Err(_) => return false,
+        };
+
+        let res = unsafe {
+            SecKeyIsAlgorithmSupported(
+                key.as_concrete_TypeRef(),
+                operation.to_CFIndex(),
+                alg.as_CFString().as_CFTypeRef(),
+            )
+        };
+
+        res == 1
+    }
+
+    fn sign(&self, key: &Handle, alg: KeyAlgorithm, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut error: CFErrorRef = ptr::null_mut();
+        let signature = unsafe {
+            SecKeyCreateSignature(
+                sec_key(key)?.as_concrete_TypeRef(),
+                alg.as_CFString().as_CFTypeRef(),
+                CFData::from_buffer(data).as_concrete_TypeRef(),
+                &mut error,
+            )
+        };
+
+        if error.is_null() {
+            Ok(unsafe { CFData::wrap_under_create_rule(signature) }.to_vec())
+        } else {
+            Err(error.into())
+        }
+    }
+
+    fn verify(
+        &self,
+        key: &Handle,
+        alg: KeyAlgorithm,
+        signed_data: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, Error> {
+        let mut error: CFErrorRef = ptr::null_mut();
+        let result = unsafe {
+            SecKeyVerifySignature(
+                sec_key(key)?.as_concrete_TypeRef(),
+                alg.as_CFString().as_CFTypeRef(),
+                CFData::from_buffer(signed_data).as_concrete_TypeRef(),
+                CFData::from_buffer(signature).as_concrete_TypeRef(),
+                &mut error,
+            )
+        };
+
+        if error.is_null() {
+            Ok(result == 0x1)
+        } else {
+            Err(error.into())
+        }
+    }
+
+    fn key_exchange(
+        &self,
+        key: &Handle,
+        alg: KeyAlgorithm,
+        public_key: &Handle,
+        requested_size: usize,
+        shared_info: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let mut params = DictionaryBuilder::new();
+
+        if requested_size > 0 {
+            params.add_number(
+                unsafe { kSecKeyKeyExchangeParameterRequestedSize },
+                requested_size as i64,
+            );
+        }
+
+        if !shared_info.is_empty() {
+            params.add(
+                unsafe { kSecKeyKeyExchangeParameterSharedInfo },
+                &CFData::from_buffer(shared_info),
+            );
+        }
+
+        let mut error: CFErrorRef = ptr::null_mut();
+        let result = unsafe {
+            SecKeyCopyKeyExchangeResult(
+                sec_key(key)?.as_concrete_TypeRef(),
+                alg.as_CFString().as_CFTypeRef(),
+                sec_key(public_key)?.as_concrete_TypeRef(),
+                Dictionary::from(params).as_concrete_TypeRef(),
+                &mut error,
+            )
+        };
+
+        if error.is_null() {
+            Ok(unsafe { CFData::wrap_under_create_rule(result) }.to_vec())
+        } else {
+            Err(error.into())
+        }
+    }
+
+    fn encrypt(&self, key: &Handle, alg: KeyAlgorithm, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut error: CFErrorRef = ptr::null_mut();
+        let ciphertext = unsafe {
+            SecKeyCreateEncryptedData(
+                sec_key(key)?.as_concrete_TypeRef(),
+                alg.as_CFString().as_CFTypeRef(),
+                CFData::from_buffer(plaintext).as_concrete_TypeRef(),
+                &mut error,
+            )
+        };
+
+        if error.is_null() {
+            Ok(unsafe { CFData::wrap_under_create_rule(ciphertext) }.to_vec())
+        } else {
+            Err(error.into())
+        }
+    }
+
+    fn decrypt(
+        &self,
+        key: &Handle,
+        alg: KeyAlgorithm,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let mut error: CFErrorRef = ptr::null_mut();
+        let plaintext = unsafe {
+            SecKeyCreateDecryptedData(
+                sec_key(key)?.as_concrete_TypeRef(),
+                alg.as_CFString().as_CFTypeRef(),
+                CFData::from_buffer(ciphertext).as_concrete_TypeRef(),
+                &mut error,
+            )
+        };
+
+        if error.is_null() {
+            Ok(unsafe { CFData::wrap_under_create_rule(plaintext) }.to_vec())
+        } else {
+            Err(error.into())
+        }
+    }
+}
+
+/// Get the `SecKey` referenced by a handle
+fn sec_key(handle: &Handle) -> Result<&SecKey, Error> {
+    handle
+        .downcast_ref::<SecKey>()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidItemRef, "handle is not a SecKey"))
+}
+
+/// Get the `SecKeychainItem` referenced by a handle
+fn item(handle: &Handle) -> Result<&SecKeychainItem, Error> {
+    handle
+        .downcast_ref::<SecKeychainItem>()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidItemRef, "handle is not a SecKeychainItem"))
+}
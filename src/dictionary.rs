@@ -1,16 +1,28 @@
 //! Builder for constructing a `CFDictionary` from attribute pairs.
 
-use crate::{attr::TAttr, ffi::kSecClass, keychain::item};
+use crate::{
+    attr::{AttrKind, AttrValue, AttributeMap},
+    ffi::kSecClass,
+    keychain::item,
+};
 use core_foundation::{
     self,
     base::{CFType, TCFType},
     boolean::CFBoolean,
+    data::CFData,
+    date::{CFAbsoluteTime, CFDate},
+    dictionary::CFDictionary,
     number::CFNumber,
     string::{CFString, CFStringRef},
 };
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Number of seconds between the Unix epoch and the Core Foundation epoch
+/// (i.e. `kCFAbsoluteTimeIntervalSince1970`)
+const ABSOLUTE_TIME_INTERVAL_SINCE_1970: CFAbsoluteTime = 978_307_200.0;
 
 /// All CFDictionary types we use follow this signature
-pub(crate) type Dictionary = core_foundation::dictionary::CFDictionary<CFType, CFType>;
+pub(crate) type Dictionary = CFDictionary<CFType, CFType>;
 
 /// Builder for attribute/parameter dictionaries we pass as arguments.
 // TODO: ensure there are no duplicate items, e.g. with `HashMap`/`BTreeMap`
@@ -36,9 +48,28 @@ impl DictionaryBuilder {
         ))
     }
 
-    /// Add an attribute (i.e. `TSecAttr`) to the dictionary
-    pub(crate) fn add_attr(&mut self, attr: &dyn TAttr) {
-        self.add(attr.kind(), &attr.as_CFType())
+    /// Add all of the attributes in the given `AttributeMap` to the dictionary
+    pub(crate) fn add_attributes(&mut self, attrs: &AttributeMap) {
+        for (kind, value) in attrs.iter() {
+            self.add_value(*kind, value);
+        }
+    }
+
+    /// Add a key/value pair with an `AttrValue` value to the dictionary
+    pub(crate) fn add_value<K>(&mut self, key: K, value: &AttrValue)
+    where
+        K: Into<CFStringRef>,
+    {
+        match value {
+            AttrValue::Bool(b) => self.add_boolean(key, *b),
+            AttrValue::Data(bytes) => self.add(key, &CFData::from_buffer(bytes)),
+            AttrValue::Date(time) => self.add(key, &CFDate::new(abs_time(*time))),
+            AttrValue::Dictionary(attrs) => {
+                self.add(key, &Dictionary::from(DictionaryBuilder::from(attrs)))
+            }
+            AttrValue::Integer(n) => self.add_number(key, *n),
+            AttrValue::String(string) => self.add_string(key, string),
+        }
     }
 
     /// Add a key/value pair with a `bool` value to the dictionary
@@ -77,3 +108,66 @@ impl From<DictionaryBuilder> for Dictionary {
         Dictionary::from_CFType_pairs(&builder.0)
     }
 }
+
+impl<'a> From<&'a AttributeMap> for DictionaryBuilder {
+    fn from(attrs: &AttributeMap) -> DictionaryBuilder {
+        let mut result = DictionaryBuilder::new();
+        result.add_attributes(attrs);
+        result
+    }
+}
+
+impl<'a> From<&'a Dictionary> for AttributeMap {
+    fn from(dictionary: &Dictionary) -> AttributeMap {
+        let mut result = AttributeMap::new();
+
+        for kind in AttrKind::ALL {
+            if let Some(value) = dictionary.find(*kind).and_then(|v| attr_value(&v)) {
+                result.insert(*kind, value);
+            }
+        }
+
+        result
+    }
+}
+
+/// Convert a `CFType` into an `AttrValue` (if it's a type we support)
+pub(crate) fn attr_value(value: &CFType) -> Option<AttrValue> {
+    if let Some(string) = value.downcast::<CFString>() {
+        Some(AttrValue::String(string.to_string()))
+    } else if let Some(data) = value.downcast::<CFData>() {
+        Some(AttrValue::Data(data.bytes().into()))
+    } else if let Some(boolean) = value.downcast::<CFBoolean>() {
+        Some(AttrValue::Bool(boolean.into()))
+    } else if let Some(date) = value.downcast::<CFDate>() {
+        Some(AttrValue::Date(system_time(date.abs_time())))
+    } else if let Some(dictionary) = value.downcast::<CFDictionary>() {
+        let dictionary =
+            unsafe { Dictionary::wrap_under_get_rule(dictionary.as_concrete_TypeRef()) };
+        Some(AttrValue::Dictionary(AttributeMap::from(&dictionary)))
+    } else if let Some(number) = value.downcast::<CFNumber>() {
+        number.to_i64().map(AttrValue::Integer)
+    } else {
+        None
+    }
+}
+
+/// Convert a `SystemTime` into a `CFAbsoluteTime` (i.e. seconds relative to
+/// the Core Foundation epoch of 2001-01-01T00:00:00Z)
+fn abs_time(time: SystemTime) -> CFAbsoluteTime {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs_f64() - ABSOLUTE_TIME_INTERVAL_SINCE_1970,
+        Err(e) => -e.duration().as_secs_f64() - ABSOLUTE_TIME_INTERVAL_SINCE_1970,
+    }
+}
+
+/// Convert a `CFAbsoluteTime` into a `SystemTime`
+fn system_time(abs_time: CFAbsoluteTime) -> SystemTime {
+    let unix_time = abs_time + ABSOLUTE_TIME_INTERVAL_SINCE_1970;
+
+    if unix_time >= 0.0 {
+        UNIX_EPOCH + Duration::from_secs_f64(unix_time)
+    } else {
+        UNIX_EPOCH - Duration::from_secs_f64(-unix_time)
+    }
+}
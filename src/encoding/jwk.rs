@@ -0,0 +1,433 @@
+//! JSON Web Keys (JWK) as described in RFC 7517, using the elliptic curve
+//! and RSA key parameters from RFC 7518 section 6, along with JWK
+//! thumbprints as described in RFC 7638.
+//!
+//! Conversions go through Keychain Services' external representations:
+//! EC members are sliced out of ANSI X9.63 `04 || X || Y || K` bytestrings,
+//! and RSA members are the fields of the PKCS#1 `RSAPublicKey` and
+//! `RSAPrivateKey` structures.
+
+use crate::{
+    attr::AttrKeyType,
+    error::{Error, ErrorKind},
+    key::{EcCurve, Key, RestoreKeyParams},
+    software::{PrivateKey, PublicKey, SoftwareKey},
+};
+use base64ct::{Base64UrlUnpadded, Encoding};
+use rsa::pkcs1::{
+    self,
+    der::{asn1::UintRef, Decode, Encode},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::{self, Debug, Display};
+use zeroize::{Zeroize, Zeroizing};
+
+/// JSON Web Key (RFC 7517) for an elliptic curve or RSA key.
+///
+/// All members other than `kty`, `kid`, and `crv` are base64url-encoded
+/// (without padding) big endian integers. Private key members are zeroized
+/// when the `Jwk` is dropped.
+#[derive(Clone, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Jwk {
+    /// Key type: `EC` or `RSA`
+    pub kty: String,
+
+    /// Key ID. Keys exported by this crate use their JWK thumbprint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+
+    /// Elliptic curve: `P-256`, `P-384`, or `P-521`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+
+    /// Elliptic curve public key x-coordinate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+
+    /// Elliptic curve public key y-coordinate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+
+    /// Elliptic curve secret scalar or RSA private exponent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub d: Option<String>,
+
+    /// RSA modulus
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+
+    /// RSA public exponent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+
+    /// RSA first prime factor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p: Option<String>,
+
+    /// RSA second prime factor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+
+    /// RSA first factor CRT exponent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dp: Option<String>,
+
+    /// RSA second factor CRT exponent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dq: Option<String>,
+
+    /// RSA first CRT coefficient
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qi: Option<String>,
+}
+
+impl Jwk {
+    /// Create an empty JWK of the given key type
+    fn new(kty: &str) -> Self {
+        Jwk {
+            kty: kty.to_owned(),
+            kid: None,
+            crv: None,
+            x: None,
+            y: None,
+            d: None,
+            n: None,
+            e: None,
+            p: None,
+            q: None,
+            dp: None,
+            dq: None,
+            qi: None,
+        }
+    }
+
+    /// Parse a JWK from its JSON serialization
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(|e| invalid_jwk(&e))
+    }
+
+    /// Serialize this JWK as JSON
+    pub fn to_json(&self) -> Zeroizing<String> {
+        Zeroizing::new(serde_json::to_string(self).expect("JWK serialization failed"))
+    }
+
+    /// Does this JWK contain a private key?
+    pub fn is_private(&self) -> bool {
+        self.d.is_some()
+    }
+
+    /// Get a copy of this JWK with all private key members removed
+    pub fn to_public(&self) -> Self {
+        let mut jwk = Jwk::new(&self.kty);
+        jwk.kid = self.kid.clone();
+        jwk.crv = self.crv.clone();
+        jwk.x = self.x.clone();
+        jwk.y = self.y.clone();
+        jwk.n = self.n.clone();
+        jwk.e = self.e.clone();
+        jwk
+    }
+
+    /// Compute the RFC 7638 SHA-256 thumbprint of this JWK, i.e. the
+    /// base64url-encoded hash of its required public key members in
+    /// lexicographic order. Suitable for use as a `kid`.
+    pub fn thumbprint(&self) -> Result<String, Error> {
+        let members = match self.kty.as_str() {
+            "EC" => vec![
+                ("crv", member(&self.crv, "crv")?),
+                ("kty", &self.kty),
+                ("x", member(&self.x, "x")?),
+                ("y", member(&self.y, "y")?),
+            ],
+            "RSA" => vec![
+                ("e", member(&self.e, "e")?),
+                ("kty", &self.kty),
+                ("n", member(&self.n, "n")?),
+            ],
+            kty => return Err(unsupported_kty(kty)),
+        };
+
+        let json = members
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "\"{}\":{}",
+                    name,
+                    serde_json::to_string(value).expect("JWK serialization failed")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        Ok(Base64UrlUnpadded::encode_string(&Sha256::digest(
+            format!("{{{}}}", json).as_bytes(),
+        )))
+    }
+}
+
+impl Debug for Jwk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Jwk {{ kty: {:?}, kid: {:?}, crv: {:?}, private: {} }}",
+            self.kty,
+            self.kid,
+            self.crv,
+            self.is_private()
+        )
+    }
+}
+
+impl Drop for Jwk {
+    fn drop(&mut self) {
+        self.d.zeroize();
+        self.p.zeroize();
+        self.q.zeroize();
+        self.dp.zeroize();
+        self.dq.zeroize();
+        self.qi.zeroize();
+    }
+}
+
+/// Encode a software key as a JWK, with its thumbprint as the `kid`
+pub(crate) fn encode(key: &SoftwareKey) -> Result<Jwk, Error> {
+    let public_key = key.public_key();
+    let mut jwk = match &public_key {
+        PublicKey::Rsa(_) => Jwk::new("RSA"),
+        _ => Jwk::new("EC"),
+    };
+
+    match key {
+        SoftwareKey::Public(PublicKey::Rsa(_)) => {
+            let der = public_key.to_external_representation();
+            let pk = pkcs1::RsaPublicKey::from_der(&der).map_err(|e| invalid_jwk(&e))?;
+            jwk.n = Some(encode_member(pk.modulus.as_bytes()));
+            jwk.e = Some(encode_member(pk.public_exponent.as_bytes()));
+        }
+        SoftwareKey::Private(PrivateKey::Rsa(_)) => {
+            let der = key.to_external_representation();
+            let sk = pkcs1::RsaPrivateKey::from_der(&der).map_err(|e| invalid_jwk(&e))?;
+            jwk.n = Some(encode_member(sk.modulus.as_bytes()));
+            jwk.e = Some(encode_member(sk.public_exponent.as_bytes()));
+            jwk.d = Some(encode_member(sk.private_exponent.as_bytes()));
+            jwk.p = Some(encode_member(sk.prime1.as_bytes()));
+            jwk.q = Some(encode_member(sk.prime2.as_bytes()));
+            jwk.dp = Some(encode_member(sk.exponent1.as_bytes()));
+            jwk.dq = Some(encode_member(sk.exponent2.as_bytes()));
+            jwk.qi = Some(encode_member(sk.coefficient.as_bytes()));
+        }
+        _ => {
+            let curve = EcCurve::from_key_size(public_key.key_size_in_bits())
+                .expect("unsupported elliptic curve");
+            let size = curve.field_size();
+            let bytes = key.to_external_representation();
+
+            jwk.crv = Some(curve_name(curve).to_owned());
+            jwk.x = Some(encode_member(&bytes[1..1 + size]));
+            jwk.y = Some(encode_member(&bytes[1 + size..1 + 2 * size]));
+
+            if let SoftwareKey::Private(_) = key {
+                jwk.d = Some(encode_member(&bytes[1 + 2 * size..]));
+            }
+        }
+    }
+
+    jwk.kid = Some(jwk.thumbprint()?);
+    Ok(jwk)
+}
+
+/// Decode a JWK as a software key
+pub(crate) fn decode(jwk: &Jwk) -> Result<SoftwareKey, Error> {
+    let key = match jwk.kty.as_str() {
+        "EC" => decode_ec(jwk),
+        "RSA" => decode_rsa(jwk),
+        kty => return Err(unsupported_kty(kty)),
+    };
+
+    // Invalid key material is a decoding error
+    key.map_err(|e| match e.kind() {
+        ErrorKind::Param => invalid_jwk(&e),
+        _ => e,
+    })
+}
+
+/// Decode an elliptic curve JWK
+fn decode_ec(jwk: &Jwk) -> Result<SoftwareKey, Error> {
+    let curve = match member(&jwk.crv, "crv")?.as_str() {
+        "P-256" => EcCurve::P256,
+        "P-384" => EcCurve::P384,
+        "P-521" => EcCurve::P521,
+        crv => {
+            return Err(Error::new(
+                ErrorKind::Unimplemented,
+                &format!("unsupported JWK curve: {}", crv),
+            ))
+        }
+    };
+
+    let mut bytes = Zeroizing::new(vec![0x04]);
+
+    for (value, name) in &[(&jwk.x, "x"), (&jwk.y, "y"), (&jwk.d, "d")] {
+        if *name == "d" && value.is_none() {
+            break;
+        }
+
+        let field = decode_member(value, name)?;
+
+        if field.len() != curve.field_size() {
+            return Err(invalid_jwk(&format!("wrong length for `{}`", name)));
+        }
+
+        bytes.extend_from_slice(&field);
+    }
+
+    if jwk.is_private() {
+        PrivateKey::from_external_representation(AttrKeyType::EcSecPrimeRandom, &bytes)
+            .map(SoftwareKey::Private)
+    } else {
+        PublicKey::from_external_representation(AttrKeyType::EcSecPrimeRandom, &bytes)
+            .map(SoftwareKey::Public)
+    }
+}
+
+/// Decode an RSA JWK
+fn decode_rsa(jwk: &Jwk) -> Result<SoftwareKey, Error> {
+    let n = decode_member(&jwk.n, "n")?;
+    let e = decode_member(&jwk.e, "e")?;
+
+    if !jwk.is_private() {
+        let der = pkcs1::RsaPublicKey {
+            modulus: uint(&n)?,
+            public_exponent: uint(&e)?,
+        }
+        .to_der()
+        .map_err(|e| invalid_jwk(&e))?;
+
+        return PublicKey::from_external_representation(AttrKeyType::Rsa, &der)
+            .map(SoftwareKey::Public);
+    }
+
+    if jwk.p.is_none() {
+        return Err(Error::new(
+            ErrorKind::Unimplemented,
+            "RSA JWKs without prime factors (`p`, `q`) are unsupported",
+        ));
+    }
+
+    let d = decode_member(&jwk.d, "d")?;
+    let p = decode_member(&jwk.p, "p")?;
+    let q = decode_member(&jwk.q, "q")?;
+    let dp = decode_member(&jwk.dp, "dp")?;
+    let dq = decode_member(&jwk.dq, "dq")?;
+    let qi = decode_member(&jwk.qi, "qi")?;
+
+    let der = Zeroizing::new(
+        pkcs1::RsaPrivateKey {
+            modulus: uint(&n)?,
+            public_exponent: uint(&e)?,
+            private_exponent: uint(&d)?,
+            prime1: uint(&p)?,
+            prime2: uint(&q)?,
+            exponent1: uint(&dp)?,
+            exponent2: uint(&dq)?,
+            coefficient: uint(&qi)?,
+            other_prime_infos: None,
+        }
+        .to_der()
+        .map_err(|e| invalid_jwk(&e))?,
+    );
+
+    PrivateKey::from_external_representation(AttrKeyType::Rsa, &der).map(SoftwareKey::Private)
+}
+
+impl RestoreKeyParams {
+    /// Create parameters for restoring a key from a JWK. Elliptic curve
+    /// (`EC`) keys on the `P-256`, `P-384`, and `P-521` curves and `RSA`
+    /// keys are supported. JWKs with a `d` member are restored as private
+    /// keys, and RSA private keys must include their CRT parameters.
+    pub fn from_jwk(jwk: &Jwk) -> Result<Self, Error> {
+        Ok(Self::from_software_key(&decode(jwk)?))
+    }
+
+    /// Serialize these parameters as a JWK
+    pub fn to_jwk(&self) -> Result<Jwk, Error> {
+        encode(&self.software_key()?)
+    }
+}
+
+impl Key {
+    /// Restore a key from a JWK.
+    pub fn from_jwk(jwk: &Jwk) -> Result<Self, Error> {
+        Self::from_external_representation(RestoreKeyParams::from_jwk(jwk)?)
+    }
+
+    /// Export this key as a JWK, including the private key members if this
+    /// is a private key (in which case it must be extractable). The `kid`
+    /// is set to the key's JWK thumbprint.
+    pub fn to_jwk(&self) -> Result<Jwk, Error> {
+        encode(&self.software_key()?)
+    }
+
+    /// Export the public key (or public component of a private key) as a
+    /// JWK. This works with private keys which aren't extractable, e.g.
+    /// keys stored in the SEP.
+    pub fn to_public_jwk(&self) -> Result<Jwk, Error> {
+        encode(&self.public_key()?.software_key()?)
+    }
+
+    /// Compute the RFC 7638 JWK thumbprint of this key's public key.
+    ///
+    /// Like the `AttrApplicationLabel`, this is a hash of the public key
+    /// (the EC point or RSA modulus and exponent), so it can serve as a
+    /// deterministic `kid` for the key.
+    pub fn jwk_thumbprint(&self) -> Result<String, Error> {
+        self.to_public_jwk()?.thumbprint()
+    }
+}
+
+/// Get the JWK `crv` name for an elliptic curve
+fn curve_name(curve: EcCurve) -> &'static str {
+    match curve {
+        EcCurve::P256 => "P-256",
+        EcCurve::P384 => "P-384",
+        EcCurve::P521 => "P-521",
+    }
+}
+
+/// Base64url-encode a JWK member
+fn encode_member(bytes: &[u8]) -> String {
+    Base64UrlUnpadded::encode_string(bytes)
+}
+
+/// Get a required JWK member
+fn member<'a>(value: &'a Option<String>, name: &str) -> Result<&'a String, Error> {
+    value
+        .as_ref()
+        .ok_or_else(|| invalid_jwk(&format!("missing `{}`", name)))
+}
+
+/// Decode a required base64url-encoded JWK member
+fn decode_member(value: &Option<String>, name: &str) -> Result<Zeroizing<Vec<u8>>, Error> {
+    Base64UrlUnpadded::decode_vec(member(value, name)?)
+        .map(Zeroizing::new)
+        .map_err(|_| invalid_jwk(&format!("invalid base64url for `{}`", name)))
+}
+
+/// Convert big endian bytes into an ASN.1 `INTEGER`
+fn uint(bytes: &[u8]) -> Result<UintRef<'_>, Error> {
+    UintRef::new(bytes).map_err(|e| invalid_jwk(&e))
+}
+
+/// Error for malformed JWKs
+fn invalid_jwk<E: Display + ?Sized>(err: &E) -> Error {
+    Error::new(ErrorKind::Decode, &format!("invalid JWK: {}", err))
+}
+
+/// Error for key types we don't support
+fn unsupported_kty(kty: &str) -> Error {
+    Error::new(
+        ErrorKind::Unimplemented,
+        &format!("unsupported JWK key type: {}", kty),
+    )
+}
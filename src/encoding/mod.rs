@@ -0,0 +1,14 @@
+//! Pure Rust encoders and decoders for the key formats used outside of
+//! Keychain Services, which natively only understands ANSI X9.63 (EC) and
+//! PKCS#1 (RSA) keys.
+
+pub(crate) mod jwk;
+pub(crate) mod openssh;
+pub(crate) mod pbes2;
+pub(crate) mod pem;
+pub(crate) mod pkcs8;
+pub(crate) mod sec1;
+pub(crate) mod spki;
+
+pub use self::jwk::Jwk;
+pub use self::pbes2::{Pbes2Cipher, Pbes2Kdf, Pbes2Params};
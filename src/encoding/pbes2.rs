@@ -0,0 +1,461 @@
+//! Password-Based Encryption Scheme 2 (PBES2) from PKCS#5 (RFC 8018), as
+//! used by encrypted PKCS#8 `EncryptedPrivateKeyInfo` documents.
+//!
+//! Supports PBKDF2-HMAC-SHA256 (RFC 8018) and scrypt (RFC 7914) key
+//! derivation, and AES-256-CBC (RFC 8018) and AES-256-GCM (RFC 5084)
+//! encryption.
+
+use crate::error::{Error, ErrorKind};
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit};
+use der::{
+    asn1::{AnyRef, ObjectIdentifier, OctetStringRef},
+    Choice, Decode, DecodeValue, Encode, Sequence,
+};
+use rand_core::{OsRng, RngCore};
+use sha2::Sha256;
+use spki::AlgorithmIdentifierRef;
+use std::fmt::Display;
+use zeroize::Zeroizing;
+
+/// `id-PBES2` from RFC 8018
+const PBES2_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.5.13");
+
+/// `id-PBKDF2` from RFC 8018
+const PBKDF2_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.5.12");
+
+/// `id-scrypt` from RFC 7914
+const SCRYPT_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.11591.4.11");
+
+/// `id-hmacWithSHA256` from RFC 8018
+const HMAC_WITH_SHA256_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.2.9");
+
+/// `id-aes256-CBC` from RFC 8018
+const AES_256_CBC_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.1.42");
+
+/// `id-aes256-GCM` from RFC 5084
+const AES_256_GCM_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.1.46");
+
+/// Size of an AES-256 key
+const KEY_SIZE: usize = 32;
+
+/// Size of the random salt passed to the KDF
+const SALT_SIZE: usize = 16;
+
+/// Size of an AES-CBC initialization vector
+const CBC_IV_SIZE: usize = 16;
+
+/// Size of an AES-GCM nonce
+const GCM_NONCE_SIZE: usize = 12;
+
+/// Size of an AES-GCM authentication tag
+const GCM_TAG_SIZE: u8 = 16;
+
+/// Default number of PBKDF2 iterations (OWASP's recommendation for
+/// PBKDF2-HMAC-SHA256 as of 2023)
+const DEFAULT_PBKDF2_ITERATIONS: u32 = 600_000;
+
+/// Maximum number of PBKDF2 iterations we'll perform for an encrypted
+/// document
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+
+/// Maximum amount of memory (`128 * r * N` bytes) we'll let scrypt
+/// parameters from an encrypted document consume
+const MAX_SCRYPT_MEMORY: u64 = 1 << 30;
+
+/// Key derivation functions for password-based encryption
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Pbes2Kdf {
+    /// PBKDF2 using HMAC-SHA-256 as the pseudorandom function
+    Pbkdf2Sha256 {
+        /// Number of iterations
+        iterations: u32,
+    },
+
+    /// scrypt (RFC 7914)
+    Scrypt {
+        /// Base 2 logarithm of the CPU/memory cost parameter `N`
+        log_n: u8,
+
+        /// Block size parameter
+        r: u32,
+
+        /// Parallelization parameter
+        p: u32,
+    },
+}
+
+impl Pbes2Kdf {
+    /// Derive a 256-bit key from the given password and salt, rejecting
+    /// PBKDF2 and scrypt parameters which would take an unreasonable amount
+    /// of time or memory
+    pub(crate) fn derive_key(
+        self,
+        password: &[u8],
+        salt: &[u8],
+    ) -> Result<Zeroizing<[u8; KEY_SIZE]>, Error> {
+        let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+
+        match self {
+            Pbes2Kdf::Pbkdf2Sha256 { iterations } => {
+                if iterations == 0 || iterations > MAX_PBKDF2_ITERATIONS {
+                    return Err(unsupported_params("PBKDF2 iteration count"));
+                }
+
+                pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, iterations, &mut *key);
+            }
+            Pbes2Kdf::Scrypt { log_n, r, p } => {
+                let memory = 1u64
+                    .checked_shl(u32::from(log_n))
+                    .and_then(|n| n.checked_mul(128))
+                    .and_then(|m| m.checked_mul(u64::from(r)));
+
+                if memory.unwrap_or(u64::MAX) > MAX_SCRYPT_MEMORY {
+                    return Err(unsupported_params("scrypt memory cost"));
+                }
+
+                let scrypt_params = scrypt::Params::new(log_n, r, p, KEY_SIZE)
+                    .map_err(|_| unsupported_params("scrypt parameters"))?;
+
+                scrypt::scrypt(password, salt, &scrypt_params, &mut *key)
+                    .map_err(|_| unsupported_params("scrypt parameters"))?;
+            }
+        }
+
+        Ok(key)
+    }
+}
+
+/// Ciphers for password-based encryption
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Pbes2Cipher {
+    /// AES-256 in CBC mode with PKCS#7 padding. Supported by `openssl pkcs8`.
+    Aes256Cbc,
+
+    /// AES-256 in GCM mode with a 16-byte authentication tag. Note that
+    /// OpenSSL does not support AEAD ciphers in PBES2.
+    Aes256Gcm,
+}
+
+/// Parameters for password-based encryption of private keys using PBES2.
+///
+/// The default is PBKDF2-HMAC-SHA256 with 600,000 iterations and
+/// AES-256-CBC, which can be decrypted with `openssl pkcs8`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Pbes2Params {
+    kdf: Pbes2Kdf,
+    cipher: Pbes2Cipher,
+}
+
+impl Pbes2Params {
+    /// Create new PBES2 parameters
+    pub fn new(kdf: Pbes2Kdf, cipher: Pbes2Cipher) -> Self {
+        Self { kdf, cipher }
+    }
+
+    /// Use PBKDF2-HMAC-SHA256 with the given number of iterations and
+    /// AES-256-CBC
+    pub fn pbkdf2_sha256(iterations: u32) -> Self {
+        Self::new(
+            Pbes2Kdf::Pbkdf2Sha256 { iterations },
+            Pbes2Cipher::Aes256Cbc,
+        )
+    }
+
+    /// Use scrypt with the given parameters and AES-256-CBC
+    pub fn scrypt(log_n: u8, r: u32, p: u32) -> Self {
+        Self::new(Pbes2Kdf::Scrypt { log_n, r, p }, Pbes2Cipher::Aes256Cbc)
+    }
+
+    /// Set the cipher used to encrypt the data
+    pub fn cipher(mut self, cipher: Pbes2Cipher) -> Self {
+        self.cipher = cipher;
+        self
+    }
+}
+
+impl Default for Pbes2Params {
+    fn default() -> Self {
+        Self::pbkdf2_sha256(DEFAULT_PBKDF2_ITERATIONS)
+    }
+}
+
+/// `EncryptedPrivateKeyInfo` from RFC 5208
+#[derive(Sequence)]
+struct EncryptedPrivateKeyInfo<'a> {
+    encryption_algorithm: AlgorithmIdentifierRef<'a>,
+    encrypted_data: OctetStringRef<'a>,
+}
+
+/// `PBES2-params` from RFC 8018
+#[derive(Sequence)]
+struct Pbes2Parameters<'a> {
+    key_derivation_func: AlgorithmIdentifierRef<'a>,
+    encryption_scheme: AlgorithmIdentifierRef<'a>,
+}
+
+/// `PBKDF2-params` from RFC 8018 (only supporting a specified salt)
+#[derive(Sequence)]
+struct Pbkdf2Parameters<'a> {
+    salt: OctetStringRef<'a>,
+    iteration_count: u32,
+    #[asn1(optional = "true")]
+    key_length: Option<u16>,
+    #[asn1(optional = "true")]
+    prf: Option<AlgorithmIdentifierRef<'a>>,
+}
+
+/// `scrypt-params` from RFC 7914
+#[derive(Sequence)]
+struct ScryptParameters<'a> {
+    salt: OctetStringRef<'a>,
+    cost_parameter: u64,
+    block_size: u32,
+    parallelization_parameter: u32,
+    #[asn1(optional = "true")]
+    key_length: Option<u16>,
+}
+
+/// `GCMParameters` from RFC 5084
+#[derive(Sequence)]
+struct GcmParameters<'a> {
+    nonce: OctetStringRef<'a>,
+    #[asn1(optional = "true")]
+    icv_len: Option<u8>,
+}
+
+/// Encrypt a DER document with the given password, producing an ASN.1 DER
+/// `EncryptedPrivateKeyInfo` document
+pub(crate) fn encrypt(
+    plaintext: &[u8],
+    password: &[u8],
+    params: &Pbes2Params,
+) -> Result<Vec<u8>, Error> {
+    let mut salt = [0u8; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+
+    let key = params.kdf.derive_key(password, &salt)?;
+
+    let kdf_params = match params.kdf {
+        Pbes2Kdf::Pbkdf2Sha256 { iterations } => Pbkdf2Parameters {
+            salt: OctetStringRef::new(&salt).unwrap(),
+            iteration_count: iterations,
+            key_length: None,
+            prf: Some(AlgorithmIdentifierRef {
+                oid: HMAC_WITH_SHA256_OID,
+                parameters: Some(AnyRef::NULL),
+            }),
+        }
+        .to_der(),
+        Pbes2Kdf::Scrypt { log_n, r, p } => ScryptParameters {
+            salt: OctetStringRef::new(&salt).unwrap(),
+            cost_parameter: 1 << log_n,
+            block_size: r,
+            parallelization_parameter: p,
+            key_length: None,
+        }
+        .to_der(),
+    }
+    .expect("PBES2 KDF parameter serialization failed");
+
+    let (ciphertext, cipher_params) = match params.cipher {
+        Pbes2Cipher::Aes256Cbc => {
+            let mut iv = [0u8; CBC_IV_SIZE];
+            OsRng.fill_bytes(&mut iv);
+
+            let ciphertext = cbc::Encryptor::<aes::Aes256>::new(&(*key).into(), &iv.into())
+                .encrypt_padded_vec_mut::<Pkcs7>(plaintext);
+
+            (ciphertext, OctetStringRef::new(&iv).unwrap().to_der())
+        }
+        Pbes2Cipher::Aes256Gcm => {
+            let mut nonce = [0u8; GCM_NONCE_SIZE];
+            OsRng.fill_bytes(&mut nonce);
+
+            let ciphertext = Aes256Gcm::new(&(*key).into())
+                .encrypt(&nonce.into(), plaintext)
+                .expect("AES-GCM encryption failed");
+
+            let gcm_params = GcmParameters {
+                nonce: OctetStringRef::new(&nonce).unwrap(),
+                icv_len: Some(GCM_TAG_SIZE),
+            };
+
+            (ciphertext, gcm_params.to_der())
+        }
+    };
+    let cipher_params = cipher_params.expect("PBES2 cipher parameter serialization failed");
+
+    let pbes2_params = Pbes2Parameters {
+        key_derivation_func: AlgorithmIdentifierRef {
+            oid: match params.kdf {
+                Pbes2Kdf::Pbkdf2Sha256 { .. } => PBKDF2_OID,
+                Pbes2Kdf::Scrypt { .. } => SCRYPT_OID,
+            },
+            parameters: Some(AnyRef::from_der(&kdf_params).unwrap()),
+        },
+        encryption_scheme: AlgorithmIdentifierRef {
+            oid: match params.cipher {
+                Pbes2Cipher::Aes256Cbc => AES_256_CBC_OID,
+                Pbes2Cipher::Aes256Gcm => AES_256_GCM_OID,
+            },
+            parameters: Some(AnyRef::from_der(&cipher_params).unwrap()),
+        },
+    }
+    .to_der()
+    .expect("PBES2 parameter serialization failed");
+
+    Ok(EncryptedPrivateKeyInfo {
+        encryption_algorithm: AlgorithmIdentifierRef {
+            oid: PBES2_OID,
+            parameters: Some(AnyRef::from_der(&pbes2_params).unwrap()),
+        },
+        encrypted_data: OctetStringRef::new(&ciphertext).unwrap(),
+    }
+    .to_der()
+    .expect("EncryptedPrivateKeyInfo serialization failed"))
+}
+
+/// Decrypt an ASN.1 DER `EncryptedPrivateKeyInfo` document with the given
+/// password, returning an error with a kind of `ErrorKind::DecryptionFailed`
+/// if the password is incorrect.
+pub(crate) fn decrypt(der: &[u8], password: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let info = EncryptedPrivateKeyInfo::from_der(der).map_err(|e| invalid_pbes2(&e))?;
+
+    if info.encryption_algorithm.oid != PBES2_OID {
+        return Err(unsupported(
+            "encryption scheme",
+            info.encryption_algorithm.oid,
+        ));
+    }
+
+    let pbes2_params: Pbes2Parameters<'_> = decode_parameters(&info.encryption_algorithm)?;
+    let key = derive_key(&pbes2_params.key_derivation_func, password)?;
+    let ciphertext = info.encrypted_data.as_bytes();
+    let cipher = &pbes2_params.encryption_scheme;
+
+    let plaintext = match cipher.oid {
+        AES_256_CBC_OID => {
+            let iv: OctetStringRef<'_> = decode_parameters(cipher)?;
+
+            if iv.as_bytes().len() != CBC_IV_SIZE {
+                return Err(invalid_pbes2(&"bad AES-CBC IV size"));
+            }
+
+            cbc::Decryptor::<aes::Aes256>::new(&(*key).into(), iv.as_bytes().into())
+                .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+                .map_err(|_| decryption_failed())?
+        }
+        AES_256_GCM_OID => {
+            let gcm_params: GcmParameters<'_> = decode_parameters(cipher)?;
+
+            if gcm_params.nonce.as_bytes().len() != GCM_NONCE_SIZE {
+                return Err(unsupported_params("AES-GCM nonce size"));
+            }
+
+            if gcm_params.icv_len != Some(GCM_TAG_SIZE) {
+                return Err(unsupported_params("AES-GCM tag size"));
+            }
+
+            Aes256Gcm::new(&(*key).into())
+                .decrypt(gcm_params.nonce.as_bytes().into(), ciphertext)
+                .map_err(|_| decryption_failed())?
+        }
+        oid => return Err(unsupported("cipher", oid)),
+    };
+
+    Ok(Zeroizing::new(plaintext))
+}
+
+/// Derive the encryption key from the password using the given KDF
+fn derive_key(
+    kdf: &AlgorithmIdentifierRef<'_>,
+    password: &[u8],
+) -> Result<Zeroizing<[u8; KEY_SIZE]>, Error> {
+    match kdf.oid {
+        PBKDF2_OID => {
+            let params: Pbkdf2Parameters<'_> = decode_parameters(kdf)?;
+            check_key_length(params.key_length)?;
+
+            match params.prf {
+                Some(prf) if prf.oid == HMAC_WITH_SHA256_OID => (),
+                Some(prf) => return Err(unsupported("PBKDF2 PRF", prf.oid)),
+                None => return Err(unsupported_params("PBKDF2 with HMAC-SHA1")),
+            }
+
+            Pbes2Kdf::Pbkdf2Sha256 {
+                iterations: params.iteration_count,
+            }
+            .derive_key(password, params.salt.as_bytes())
+        }
+        SCRYPT_OID => {
+            let params: ScryptParameters<'_> = decode_parameters(kdf)?;
+            check_key_length(params.key_length)?;
+
+            let n = params.cost_parameter;
+
+            if !n.is_power_of_two() || n < 2 {
+                return Err(invalid_pbes2(&"scrypt cost parameter is not a power of 2"));
+            }
+
+            Pbes2Kdf::Scrypt {
+                log_n: n.trailing_zeros() as u8,
+                r: params.block_size,
+                p: params.parallelization_parameter,
+            }
+            .derive_key(password, params.salt.as_bytes())
+        }
+        oid => Err(unsupported("key derivation function", oid)),
+    }
+}
+
+/// Decode the parameters of an algorithm identifier
+fn decode_parameters<'a, T>(alg: &AlgorithmIdentifierRef<'a>) -> Result<T, Error>
+where
+    T: Choice<'a> + DecodeValue<'a>,
+{
+    alg.parameters
+        .ok_or_else(|| invalid_pbes2(&format!("missing parameters for {}", alg.oid)))?
+        .decode_as()
+        .map_err(|e| invalid_pbes2(&e))
+}
+
+/// Ensure the key length (if specified) is the one AES-256 requires
+fn check_key_length(key_length: Option<u16>) -> Result<(), Error> {
+    match key_length {
+        Some(len) if usize::from(len) != KEY_SIZE => Err(invalid_pbes2(&"bad key length")),
+        _ => Ok(()),
+    }
+}
+
+/// Error for data which couldn't be decrypted (e.g. wrong password)
+pub(crate) fn decryption_failed() -> Error {
+    Error::new(
+        ErrorKind::DecryptionFailed,
+        "decryption failed (wrong password?)",
+    )
+}
+
+/// Error for malformed encrypted documents
+fn invalid_pbes2<E: Display + ?Sized>(err: &E) -> Error {
+    Error::new(
+        ErrorKind::Decode,
+        &format!("invalid encrypted private key: {}", err),
+    )
+}
+
+/// Error for algorithms we don't support
+fn unsupported(description: &str, oid: ObjectIdentifier) -> Error {
+    Error::new(
+        ErrorKind::Unimplemented,
+        &format!("unsupported PBES2 {}: {}", description, oid),
+    )
+}
+
+/// Error for algorithm parameters we don't support
+fn unsupported_params(description: &str) -> Error {
+    Error::new(
+        ErrorKind::Unimplemented,
+        &format!("unsupported {}", description),
+    )
+}
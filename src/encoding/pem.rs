@@ -0,0 +1,27 @@
+//! Privacy-Enhanced Mail (PEM) "textual encoding" of DER documents as
+//! described in RFC 7468.
+
+use crate::error::{Error, ErrorKind};
+use spki::der::pem::{self, LineEnding};
+use zeroize::Zeroizing;
+
+/// Encode the given DER document as PEM with the given type label
+pub(crate) fn encode(label: &str, der: &[u8]) -> Result<String, Error> {
+    pem::encode_string(label, LineEnding::LF, der).map_err(|e| Error::new(ErrorKind::Param, &e))
+}
+
+/// Decode a PEM document, ensuring it has the expected type label
+pub(crate) fn decode(label: &str, pem: &str) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let (actual_label, der) =
+        pem::decode_vec(pem.trim().as_bytes()).map_err(|e| Error::new(ErrorKind::Decode, &e))?;
+    let der = Zeroizing::new(der);
+
+    if actual_label != label {
+        return Err(Error::new(
+            ErrorKind::Decode,
+            &format!("expected PEM label {:?}, got {:?}", label, actual_label),
+        ));
+    }
+
+    Ok(der)
+}
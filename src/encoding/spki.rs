@@ -0,0 +1,138 @@
+//! X.509 SubjectPublicKeyInfo (SPKI) encoding of public keys as described
+//! in RFC 5280, using the algorithm identifiers from RFC 3279 (RSA) and
+//! RFC 5480 (elliptic curve keys with named curves).
+
+use super::pem;
+use crate::{
+    error::{Error, ErrorKind},
+    key::{Key, RestoreKeyParams},
+    software::{PublicKey, SoftwareKey},
+};
+use p256::{
+    elliptic_curve::ALGORITHM_OID as EC_PUBLIC_KEY_OID,
+    pkcs8::{AssociatedOid, DecodePublicKey, EncodePublicKey},
+    NistP256,
+};
+use p384::NistP384;
+use p521::NistP521;
+use rsa::{pkcs1::ALGORITHM_OID as RSA_ENCRYPTION_OID, RsaPublicKey};
+use spki::{der::Decode, SubjectPublicKeyInfoRef};
+use std::fmt::Display;
+
+/// PEM type label for SPKI public keys
+pub(crate) const PEM_LABEL: &str = "PUBLIC KEY";
+
+/// Encode a public key as an ASN.1 DER SPKI document
+pub(crate) fn encode(public_key: &PublicKey) -> Vec<u8> {
+    match public_key {
+        PublicKey::P256(pk) => pk.to_public_key_der(),
+        PublicKey::P384(pk) => pk.to_public_key_der(),
+        PublicKey::P521(pk) => pk.to_public_key_der(),
+        PublicKey::Rsa(pk) => pk.to_public_key_der(),
+    }
+    .expect("SPKI public key serialization failed")
+    .into_vec()
+}
+
+/// Decode an ASN.1 DER SPKI document. Elliptic curve points may be
+/// compressed or uncompressed.
+pub(crate) fn decode(der: &[u8]) -> Result<PublicKey, Error> {
+    let spki = SubjectPublicKeyInfoRef::from_der(der).map_err(|e| invalid_spki(&e))?;
+
+    let public_key = match spki.algorithm.oid {
+        EC_PUBLIC_KEY_OID => match spki
+            .algorithm
+            .parameters_oid()
+            .map_err(|e| invalid_spki(&e))?
+        {
+            NistP256::OID => p256::PublicKey::from_public_key_der(der).map(PublicKey::P256),
+            NistP384::OID => p384::PublicKey::from_public_key_der(der).map(PublicKey::P384),
+            NistP521::OID => p521::PublicKey::from_public_key_der(der).map(PublicKey::P521),
+            oid => {
+                return Err(Error::new(
+                    ErrorKind::Unimplemented,
+                    &format!("unsupported elliptic curve: {}", oid),
+                ))
+            }
+        },
+        RSA_ENCRYPTION_OID => RsaPublicKey::from_public_key_der(der).map(PublicKey::Rsa),
+        oid => {
+            return Err(Error::new(
+                ErrorKind::Unimplemented,
+                &format!("unsupported public key algorithm: {}", oid),
+            ))
+        }
+    }
+    .map_err(|e| invalid_spki(&e))?;
+
+    public_key.check_size()?;
+    Ok(public_key)
+}
+
+impl RestoreKeyParams {
+    /// Create parameters for restoring a public key from an X.509
+    /// SubjectPublicKeyInfo (SPKI) document encoded as ASN.1 DER.
+    ///
+    /// Supports RSA (`rsaEncryption`) keys and elliptic curve
+    /// (`id-ecPublicKey`) keys on the P-256, P-384, and P-521 named curves.
+    pub fn from_spki_der(der: &[u8]) -> Result<Self, Error> {
+        Ok(Self::from_software_key(&SoftwareKey::Public(decode(der)?)))
+    }
+
+    /// Create parameters for restoring a public key from a PEM encoded
+    /// X.509 SubjectPublicKeyInfo (SPKI) document, i.e.
+    /// `-----BEGIN PUBLIC KEY-----`.
+    pub fn from_spki_pem(pem: &str) -> Result<Self, Error> {
+        Self::from_spki_der(&pem::decode(PEM_LABEL, pem)?)
+    }
+
+    /// Encode the public key (or public component of a private key) these
+    /// parameters describe as an X.509 SubjectPublicKeyInfo (SPKI) document
+    /// in ASN.1 DER.
+    pub fn to_spki_der(&self) -> Result<Vec<u8>, Error> {
+        Ok(encode(&self.software_key()?.public_key()))
+    }
+
+    /// Encode the public key (or public component of a private key) these
+    /// parameters describe as a PEM encoded X.509 SubjectPublicKeyInfo (SPKI)
+    /// document.
+    pub fn to_spki_pem(&self) -> Result<String, Error> {
+        pem::encode(PEM_LABEL, &self.to_spki_der()?)
+    }
+}
+
+impl Key {
+    /// Restore a public key from an X.509 SubjectPublicKeyInfo (SPKI)
+    /// document encoded as ASN.1 DER.
+    pub fn from_spki_der(der: &[u8]) -> Result<Self, Error> {
+        Self::from_external_representation(RestoreKeyParams::from_spki_der(der)?)
+    }
+
+    /// Restore a public key from a PEM encoded X.509 SubjectPublicKeyInfo
+    /// (SPKI) document.
+    pub fn from_spki_pem(pem: &str) -> Result<Self, Error> {
+        Self::from_external_representation(RestoreKeyParams::from_spki_pem(pem)?)
+    }
+
+    /// Export the public key (or public component of a private key) as an
+    /// X.509 SubjectPublicKeyInfo (SPKI) document encoded as ASN.1 DER.
+    /// This works with private keys which aren't extractable, e.g. keys
+    /// stored in the SEP.
+    pub fn to_spki_der(&self) -> Result<Vec<u8>, Error> {
+        Ok(encode(&self.public_key()?.software_key()?.public_key()))
+    }
+
+    /// Export the public key (or public component of a private key) as a
+    /// PEM encoded X.509 SubjectPublicKeyInfo (SPKI) document.
+    pub fn to_spki_pem(&self) -> Result<String, Error> {
+        pem::encode(PEM_LABEL, &self.to_spki_der()?)
+    }
+}
+
+/// Error for malformed SPKI documents
+fn invalid_spki<E: Display>(err: &E) -> Error {
+    Error::new(
+        ErrorKind::Decode,
+        &format!("invalid SubjectPublicKeyInfo: {}", err),
+    )
+}
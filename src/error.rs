@@ -1,21 +1,41 @@
 //! Error types
 
+#[cfg(target_os = "macos")]
 use crate::ffi::*;
+#[cfg(target_os = "macos")]
 use core_foundation::{
     base::{CFRelease, CFTypeRef, OSStatus, TCFType},
     error::{CFErrorCopyDescription, CFErrorGetCode, CFErrorGetDomain, CFErrorRef},
     string::CFString,
 };
 use failure::{Backtrace, Fail};
+#[cfg(target_os = "macos")]
+use std::ptr;
 use std::{
     fmt::{self, Display},
-    io, ptr,
+    io,
 };
 
+/// Result codes returned from Security Framework functions.
+#[cfg(not(target_os = "macos"))]
+type OSStatus = i32;
+
+/// Error domain used by `CFError`s which wrap an `OSStatus` code.
+#[cfg(target_os = "macos")]
+const NSOSStatusErrorDomain: &str = "NSOSStatusErrorDomain";
+
 /// No error occurred.
 /// <https://developer.apple.com/documentation/security/errsecsuccess>
 const errSecSuccess: OSStatus = 0;
 
+/// Function or operation not implemented.
+/// <https://developer.apple.com/documentation/security/errsecunimplemented>
+pub(crate) const errSecUnimplemented: OSStatus = -4;
+
+/// One or more parameters passed to a function were not valid.
+/// <https://developer.apple.com/documentation/security/errsecparam>
+pub(crate) const errSecParam: OSStatus = -50;
+
 /// Authentication and/or authorization failed.
 /// <https://developer.apple.com/documentation/security/errsecauthfailed>
 const errSecAuthFailed: OSStatus = -25293;
@@ -28,6 +48,10 @@ const errSecBufferTooSmall: OSStatus = -25301;
 /// <https://developer.apple.com/documentation/security/errseccreatechainfailed>
 const errSecCreateChainFailed: OSStatus = -25318;
 
+/// Unable to decode the provided data.
+/// <https://developer.apple.com/documentation/security/errsecdecode>
+const errSecDecode: OSStatus = -26275;
+
 /// Data too large for the given data type.
 /// <https://developer.apple.com/documentation/security/errsecdatatoolarge>
 const errSecDataTooLarge: OSStatus = -25302;
@@ -146,6 +170,10 @@ const errSecReadOnlyAttr: OSStatus = -25309;
 /// <https://developer.apple.com/documentation/security/errsecwrongversion>
 const errSecWrongSecVersion: OSStatus = -25310;
 
+/// A cryptographic verification failure has occurred.
+/// <https://developer.apple.com/documentation/security/errsecverifyfailed>
+pub(crate) const errSecVerifyFailed: OSStatus = -67808;
+
 /// Error type.
 ///
 /// Wrapper for the `CFError` type:
@@ -176,10 +204,15 @@ impl Error {
             None
         } else {
             let kind = ErrorKind::from(status);
+
+            #[cfg(target_os = "macos")]
             let description = unsafe {
                 CFString::wrap_under_create_rule(SecCopyErrorMessageString(status, ptr::null()))
             };
 
+            #[cfg(not(target_os = "macos"))]
+            let description = kind.to_string();
+
             Some(Error::new(kind, &description))
         }
     }
@@ -206,6 +239,13 @@ impl Fail for Error {
     }
 }
 
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::new(ErrorKind::Io { kind: error.kind() }, &error)
+    }
+}
+
+#[cfg(target_os = "macos")]
 impl From<CFErrorRef> for Error {
     /// Creates an `Error` with copies of all error data on the Rust heap.
     ///
@@ -231,8 +271,22 @@ impl From<CFErrorRef> for Error {
 }
 
 /// Kinds of errors.
-#[derive(Clone, Debug, Fail)]
+#[derive(Clone, Debug, Eq, Fail, PartialEq)]
 pub enum ErrorKind {
+    /// Function or operation not implemented.
+    ///
+    /// Wrapper for the `errSecUnimplemented` status code. See:
+    /// <https://developer.apple.com/documentation/security/errsecunimplemented>
+    #[fail(display = "unimplemented")]
+    Unimplemented,
+
+    /// One or more parameters passed to a function were not valid.
+    ///
+    /// Wrapper for the `errSecParam` status code. See:
+    /// <https://developer.apple.com/documentation/security/errsecparam>
+    #[fail(display = "invalid parameter")]
+    Param,
+
     /// Authentication and/or authorization failed.
     ///
     /// Wrapper for the `errSecAuthFailed` status code. See:
@@ -254,6 +308,13 @@ pub enum ErrorKind {
     #[fail(display = "certificate chain creation attempt failed")]
     CreateChainFailed,
 
+    /// Unable to decode the provided data.
+    ///
+    /// Wrapper for the `errSecDecode` status code. See:
+    /// <https://developer.apple.com/documentation/security/errsecdecode>
+    #[fail(display = "unable to decode the provided data")]
+    Decode,
+
     /// Data too large for the given data type.
     ///
     /// Wrapper for the `errSecDataTooLarge` status code. See:
@@ -452,6 +513,20 @@ pub enum ErrorKind {
     #[fail(display = "wrong version")]
     WrongSecVersion,
 
+    /// A cryptographic verification failure has occurred.
+    ///
+    /// Wrapper for the `errSecVerifyFailed` status code. See:
+    /// <https://developer.apple.com/documentation/security/errsecverifyfailed>
+    #[fail(display = "verification failed")]
+    VerifyFailed,
+
+    /// Password-protected data (e.g. an encrypted PKCS#8 private key) could
+    /// not be decrypted, typically because the password was wrong.
+    ///
+    /// This doesn't correspond to any `OSStatus` code.
+    #[fail(display = "decryption failed")]
+    DecryptionFailed,
+
     /// Input/output errors.
     ///
     /// Wrapper for errno codes we know/commonly encounter.
@@ -498,12 +573,18 @@ pub enum ErrorKind {
     },
 }
 
+#[cfg(target_os = "macos")]
 impl From<CFErrorRef> for ErrorKind {
     fn from(error_ref: CFErrorRef) -> ErrorKind {
-        ErrorKind::CFError {
-            code: unsafe { CFErrorGetCode(error_ref) } as i64,
-            domain: unsafe { CFString::wrap_under_get_rule(CFErrorGetDomain(error_ref)) }
-                .to_string(),
+        let code = unsafe { CFErrorGetCode(error_ref) } as i64;
+        let domain =
+            unsafe { CFString::wrap_under_get_rule(CFErrorGetDomain(error_ref)) }.to_string();
+
+        // `CFError`s in this domain wrap an `OSStatus` we can decode
+        if domain == NSOSStatusErrorDomain {
+            ErrorKind::from(code as OSStatus)
+        } else {
+            ErrorKind::CFError { code, domain }
         }
     }
 }
@@ -511,9 +592,12 @@ impl From<CFErrorRef> for ErrorKind {
 impl From<OSStatus> for ErrorKind {
     fn from(status: OSStatus) -> ErrorKind {
         match status {
+            errSecUnimplemented => ErrorKind::Unimplemented,
+            errSecParam => ErrorKind::Param,
             errSecAuthFailed => ErrorKind::AuthFailed,
             errSecBufferTooSmall => ErrorKind::BufferTooSmall,
             errSecCreateChainFailed => ErrorKind::CreateChainFailed,
+            errSecDecode => ErrorKind::Decode,
             errSecDataTooLarge => ErrorKind::DataTooLarge,
             errSecDataNotAvailable => ErrorKind::DataNotAvailable,
             errSecDataNotModifiable => ErrorKind::DataNotModifiable,
@@ -542,6 +626,7 @@ impl From<OSStatus> for ErrorKind {
             errSecReadOnly => ErrorKind::ReadOnly,
             errSecReadOnlyAttr => ErrorKind::ReadOnlyAttr,
             errSecWrongSecVersion => ErrorKind::WrongSecVersion,
+            errSecVerifyFailed => ErrorKind::VerifyFailed,
             errSecErrnoBase..=errSecErrnoLimit => match (status - errSecErrnoBase) as u8 {
                 1 => ErrorKind::Io {
                     kind: io::ErrorKind::PermissionDenied,
@@ -153,7 +153,7 @@ pub(super) struct SecKeychainAttributeList {
 
 impl SecKeychainAttributeList {
     /// Get an iterator over this attribute list.
-    pub(crate) fn iter(&self) -> slice::Iter<SecKeychainAttribute> {
+    pub(crate) fn iter(&self) -> slice::Iter<'_, SecKeychainAttribute> {
         self.as_slice().iter()
     }
 
@@ -184,6 +184,7 @@ extern "C" {
     pub(crate) static kSecAttrCanVerify: CFStringRef;
     pub(crate) static kSecAttrCanWrap: CFStringRef;
     pub(crate) static kSecAttrCanUnwrap: CFStringRef;
+    pub(crate) static kSecAttrCreationDate: CFStringRef;
     pub(crate) static kSecAttrIsExtractable: CFStringRef;
     pub(crate) static kSecAttrIsPermanent: CFStringRef;
     pub(crate) static kSecAttrIsSensitive: CFStringRef;
@@ -197,6 +198,7 @@ extern "C" {
     pub(crate) static kSecAttrKeyTypeECSECPrimeRandom: CFStringRef;
     pub(crate) static kSecAttrKeySizeInBits: CFStringRef;
     pub(crate) static kSecAttrLabel: CFStringRef;
+    pub(crate) static kSecAttrModificationDate: CFStringRef;
     pub(crate) static kSecAttrProtocol: CFStringRef;
     pub(crate) static kSecAttrProtocolFTP: CFStringRef;
     pub(crate) static kSecAttrProtocolFTPAccount: CFStringRef;
@@ -331,6 +333,8 @@ extern "C" {
     pub(crate) static kSecKeyEncrypt: CFStringRef;
     pub(crate) static kSecKeyEndDate: CFStringRef;
     pub(crate) static kSecKeyExtractable: CFStringRef;
+    pub(crate) static kSecKeyKeyExchangeParameterRequestedSize: CFStringRef;
+    pub(crate) static kSecKeyKeyExchangeParameterSharedInfo: CFStringRef;
     pub(crate) static kSecKeyKeySizeInBits: CFStringRef;
     pub(crate) static kSecKeyKeyType: CFStringRef;
     pub(crate) static kSecKeyModifiable: CFStringRef;
@@ -347,6 +351,7 @@ extern "C" {
     pub(crate) static kSecMatchLimitOne: CFStringRef;
     pub(crate) static kSecMatchLimitAll: CFStringRef;
     pub(crate) static kSecPrivateKeyAttrs: CFStringRef;
+    pub(crate) static kSecPublicKeyAttrs: CFStringRef;
     pub(crate) static kSecReturnRef: CFStringRef;
     pub(crate) static kSecUseKeychain: CFStringRef;
     pub(crate) static kSecUseOperationPrompt: CFStringRef;
@@ -366,6 +371,10 @@ extern "C" {
     pub(crate) fn SecItemAdd(attributes: CFDictionaryRef, result: *mut CFTypeRef) -> OSStatus;
     pub(crate) fn SecItemDelete(attributes: CFDictionaryRef) -> OSStatus;
     pub(crate) fn SecItemCopyMatching(query: CFDictionaryRef, result: *mut CFTypeRef) -> OSStatus;
+    pub(crate) fn SecItemUpdate(
+        query: CFDictionaryRef,
+        attributes_to_update: CFDictionaryRef,
+    ) -> OSStatus;
     pub(crate) fn SecKeyCopyAttributes(key: KeyRef) -> CFDictionaryRef;
     pub(crate) fn SecKeyCreateWithData(
         keyData: CFDataRef,
@@ -416,6 +425,13 @@ extern "C" {
         algorithm: CFTypeRef,
     ) -> u8;
     pub(crate) fn SecKeyCopyPublicKey(privatekey: KeyRef) -> KeyRef;
+    pub(crate) fn SecKeyCopyKeyExchangeResult(
+        privateKey: KeyRef,
+        algorithm: CFTypeRef,
+        publicKey: KeyRef,
+        parameters: CFDictionaryRef,
+        error: *mut CFErrorRef,
+    ) -> CFDataRef;
     pub(crate) fn SecKeyGetTypeID() -> CFTypeID;
     pub(crate) fn SecKeychainCopyDefault(keychain: *mut KeychainRef) -> OSStatus;
     pub(crate) fn SecKeychainCreate(
@@ -441,3 +457,62 @@ extern "C" {
         data: *mut c_void,
     ) -> OSStatus;
 }
+
+declare_TCFType! {
+    /// Wrapper for the `SecAccessControl`/`SecAccessControlRef` types:
+    /// <https://developer.apple.com/documentation/security/secaccesscontrolref>
+    SecAccessControl, AccessControlRef
+}
+
+impl_TCFType!(
+    SecAccessControl,
+    AccessControlRef,
+    SecAccessControlGetTypeID
+);
+
+impl Debug for SecAccessControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // TODO: display more information about `SecAccessControl`s
+        write!(f, "SecAccessControl {{ ... }}")
+    }
+}
+
+declare_TCFType! {
+    /// Wrapper for the `SecKey`/`SecKeyRef` types:
+    /// <https://developer.apple.com/documentation/security/seckeyref>
+    SecKey, KeyRef
+}
+
+impl_TCFType!(SecKey, KeyRef, SecKeyGetTypeID);
+
+declare_TCFType! {
+    /// Wrapper for the `SecKeychain`/`SecKeychainRef` types:
+    /// <https://developer.apple.com/documentation/security/seckeychainref>
+    SecKeychain, KeychainRef
+}
+
+impl_TCFType!(SecKeychain, KeychainRef, SecKeychainGetTypeID);
+
+impl Debug for SecKeychain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecKeychain {{ ... }}")
+    }
+}
+
+declare_TCFType! {
+    /// Wrapper for the `SecKeychainItem`/`SecKeychainItemRef` types:
+    /// <https://developer.apple.com/documentation/security/seckeychainitemref>
+    SecKeychainItem, ItemRef
+}
+
+impl_TCFType!(SecKeychainItem, ItemRef, SecKeychainItemGetTypeID);
+
+// Security Framework objects are immutable and documented as thread-safe.
+unsafe impl Send for SecAccessControl {}
+unsafe impl Sync for SecAccessControl {}
+unsafe impl Send for SecKey {}
+unsafe impl Sync for SecKey {}
+unsafe impl Send for SecKeychain {}
+unsafe impl Sync for SecKeychain {}
+unsafe impl Send for SecKeychainItem {}
+unsafe impl Sync for SecKeychainItem {}
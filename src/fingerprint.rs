@@ -0,0 +1,105 @@
+//! Public key fingerprints, computed in pure Rust from any public key
+//! encoding this library supports.
+//!
+//! This type doesn't map directly to any type in the Keychain Services API,
+//! but computes the same `kSecAttrApplicationLabel` Keychain Services
+//! assigns to keys, along with OpenSSH fingerprints and SPKI pins, so keys
+//! can be identified without access to a keychain.
+
+use crate::{
+    attr::{AttrApplicationLabel, AttrKeyType},
+    encoding::{openssh, spki},
+    error::Error,
+    key::{Key, RestoreKeyParams},
+    software::PublicKey,
+};
+use base64ct::{Base64, Encoding};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+/// Fingerprints of a public key
+#[derive(Clone, Debug)]
+pub struct Fingerprint {
+    public_key: PublicKey,
+    openssh_fingerprint: ssh_key::Fingerprint,
+}
+
+impl Fingerprint {
+    /// Compute fingerprints of the public key (or public component of a
+    /// private key) described by the given `RestoreKeyParams`, which can
+    /// be created from any supported encoding (e.g. X9.63, PKCS#1, SPKI,
+    /// OpenSSH, or JWK).
+    pub fn new(params: &RestoreKeyParams) -> Result<Self, Error> {
+        Self::from_public_key(params.software_key()?.public_key())
+    }
+
+    /// Compute fingerprints of a software public key
+    fn from_public_key(public_key: PublicKey) -> Result<Self, Error> {
+        let openssh_fingerprint = openssh::fingerprint(&public_key)?;
+
+        Ok(Self {
+            public_key,
+            openssh_fingerprint,
+        })
+    }
+
+    /// Get the `kSecAttrApplicationLabel` Keychain Services assigns to both
+    /// keys in a pair, i.e. the SHA-1 hash of the public key (the
+    /// `04 || X || Y` point for EC keys or the modulus for RSA keys).
+    ///
+    /// Suitable for finding the key in a keychain using
+    /// `Query::application_label`.
+    pub fn application_label(&self) -> AttrApplicationLabel {
+        AttrApplicationLabel(self.public_key.application_label())
+    }
+
+    /// Get the OpenSSH SHA-256 fingerprint of this key as displayed by
+    /// `ssh-keygen -l`, e.g. `SHA256:qaRf8zjbjbNC8FTZ3wRLUXul1wE84mQuGpw2yEsgPNU`
+    pub fn openssh_sha256(&self) -> String {
+        self.openssh_fingerprint.to_string()
+    }
+
+    /// Get the legacy OpenSSH MD5 fingerprint of this key as displayed by
+    /// `ssh-keygen -l -E md5`, e.g. `MD5:b8:c7:7f:0e:...`
+    pub fn openssh_md5(&self) -> Result<String, Error> {
+        let digest = Md5::digest(openssh::public_key_blob(&self.public_key)?);
+        let hex: Vec<_> = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+        Ok(format!("MD5:{}", hex.join(":")))
+    }
+
+    /// Get the base64-encoded SHA-256 hash of this key's X.509
+    /// SubjectPublicKeyInfo, as used for HTTP Public Key Pinning
+    /// (`pin-sha256`, RFC 7469) and other SPKI pinning schemes.
+    pub fn spki_sha256(&self) -> String {
+        Base64::encode_string(&Sha256::digest(spki::encode(&self.public_key)))
+    }
+
+    /// Render the OpenSSH "randomart" visualization of this key's SHA-256
+    /// fingerprint as displayed by `ssh-keygen -lv`
+    pub fn randomart(&self) -> String {
+        let key_type = match self.public_key.key_type() {
+            AttrKeyType::Rsa => "RSA",
+            _ => "ECDSA",
+        };
+
+        let header = format!("[{} {}]", key_type, self.public_key.key_size_in_bits());
+        self.openssh_fingerprint.to_randomart(&header)
+    }
+}
+
+impl RestoreKeyParams {
+    /// Compute fingerprints of the public key (or public component of a
+    /// private key) these parameters describe
+    pub fn fingerprint(&self) -> Result<Fingerprint, Error> {
+        Fingerprint::new(self)
+    }
+}
+
+impl Key {
+    /// Compute fingerprints of this key's public key. Works with private
+    /// keys which aren't extractable (e.g. SEP keys), as only the public
+    /// key is exported.
+    pub fn fingerprint(&self) -> Result<Fingerprint, Error> {
+        Fingerprint::from_public_key(self.public_key()?.software_key()?.public_key())
+    }
+}
@@ -0,0 +1,631 @@
+//! Hybrid Public Key Encryption (HPKE, RFC 9180) in the base and auth modes
+//! using DHKEM with the NIST P-256 and P-384 curves.
+//!
+//! Recipients open messages using a private `Key`, which performs the
+//! Diffie-Hellman operations, so it works with keys stored in the SEP.
+//! Senders encrypt to a recipient's public key in pure Rust. In the auth
+//! mode, the sender's static key is also a `Key`.
+
+use crate::{
+    attr::{AttrKeyClass, AttrKeyType},
+    error::{Error, ErrorKind},
+    key::{Key, KeyAlgorithm, RestoreKeyParams},
+    software::{PrivateKey, PublicKey},
+};
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes128Gcm, Aes256Gcm, KeyInit,
+};
+use hkdf::Hkdf;
+use sha2::{Sha256, Sha384, Sha512};
+use zeroize::Zeroizing;
+
+/// HPKE mode identifier for the base mode
+const MODE_BASE: u8 = 0x00;
+
+/// HPKE mode identifier for the auth mode
+const MODE_AUTH: u8 = 0x02;
+
+/// Size of an AEAD nonce for all supported AEADs (`Nn`)
+const NONCE_SIZE: usize = 12;
+
+/// Key Encapsulation Mechanisms (RFC 9180 section 7.1)
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum HpkeKem {
+    /// DHKEM(P-256, HKDF-SHA256)
+    DhKemP256HkdfSha256,
+
+    /// DHKEM(P-384, HKDF-SHA384)
+    DhKemP384HkdfSha384,
+}
+
+impl HpkeKem {
+    /// Get the identifier for this KEM
+    pub fn id(self) -> u16 {
+        match self {
+            HpkeKem::DhKemP256HkdfSha256 => 0x0010,
+            HpkeKem::DhKemP384HkdfSha384 => 0x0011,
+        }
+    }
+
+    /// Get the size of the curve's keys in bits
+    pub fn key_size_in_bits(self) -> usize {
+        match self {
+            HpkeKem::DhKemP256HkdfSha256 => 256,
+            HpkeKem::DhKemP384HkdfSha384 => 384,
+        }
+    }
+
+    /// Size of an encapsulated key or serialized public key (`Nenc`)
+    fn public_key_size(self) -> usize {
+        match self {
+            HpkeKem::DhKemP256HkdfSha256 => 65,
+            HpkeKem::DhKemP384HkdfSha384 => 97,
+        }
+    }
+
+    /// KDF used by this KEM, whose output size is the size of the KEM
+    /// shared secret (`Nsecret`)
+    fn kdf(self) -> HpkeKdf {
+        match self {
+            HpkeKem::DhKemP256HkdfSha256 => HpkeKdf::HkdfSha256,
+            HpkeKem::DhKemP384HkdfSha384 => HpkeKdf::HkdfSha384,
+        }
+    }
+
+    /// Context string used in this KEM's labeled KDF invocations
+    fn suite_id(self) -> Vec<u8> {
+        let mut suite_id = b"KEM".to_vec();
+        suite_id.extend_from_slice(&self.id().to_be_bytes());
+        suite_id
+    }
+
+    /// Derive the KEM shared secret from the concatenated DH outputs
+    fn extract_and_expand(
+        self,
+        dh: &[u8],
+        kem_context: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let kdf = self.kdf();
+        let suite_id = self.suite_id();
+        let eae_prk = labeled_extract(kdf, &suite_id, &[], b"eae_prk", dh);
+
+        labeled_expand(
+            kdf,
+            &suite_id,
+            &eae_prk,
+            b"shared_secret",
+            kem_context,
+            kdf.output_size(),
+        )
+    }
+
+    /// Parse a serialized public key (i.e. an uncompressed point)
+    fn deserialize_public_key(self, bytes: &[u8]) -> Result<PublicKey, Error> {
+        let invalid_key = || Error::new(ErrorKind::Decode, "invalid HPKE public key");
+
+        if bytes.len() != self.public_key_size() {
+            return Err(invalid_key());
+        }
+
+        PublicKey::from_external_representation(AttrKeyType::EcSecPrimeRandom, bytes)
+            .map_err(|_| invalid_key())
+    }
+
+    /// Ensure the given key is a private key on this KEM's curve
+    fn check_private_key(self, key: &Key) -> Result<(), Error> {
+        if key.class() != Some(AttrKeyClass::Private)
+            || key.key_type() != Some(AttrKeyType::EcSecPrimeRandom)
+            || key.key_size_in_bits() != Some(self.key_size_in_bits())
+        {
+            return Err(Error::new(
+                ErrorKind::Param,
+                &format!(
+                    "{:?} requires a private P-{} key",
+                    self,
+                    self.key_size_in_bits()
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Key Derivation Functions (RFC 9180 section 7.2)
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum HpkeKdf {
+    /// HKDF-SHA256
+    HkdfSha256,
+
+    /// HKDF-SHA384
+    HkdfSha384,
+
+    /// HKDF-SHA512
+    HkdfSha512,
+}
+
+impl HpkeKdf {
+    /// Get the identifier for this KDF
+    pub fn id(self) -> u16 {
+        match self {
+            HpkeKdf::HkdfSha256 => 0x0001,
+            HpkeKdf::HkdfSha384 => 0x0002,
+            HpkeKdf::HkdfSha512 => 0x0003,
+        }
+    }
+
+    /// Size of the underlying hash function's output (`Nh`)
+    fn output_size(self) -> usize {
+        match self {
+            HpkeKdf::HkdfSha256 => 32,
+            HpkeKdf::HkdfSha384 => 48,
+            HpkeKdf::HkdfSha512 => 64,
+        }
+    }
+
+    /// HKDF-Extract
+    fn extract(self, salt: &[u8], ikm: &[u8]) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(match self {
+            HpkeKdf::HkdfSha256 => Hkdf::<Sha256>::extract(Some(salt), ikm).0.to_vec(),
+            HpkeKdf::HkdfSha384 => Hkdf::<Sha384>::extract(Some(salt), ikm).0.to_vec(),
+            HpkeKdf::HkdfSha512 => Hkdf::<Sha512>::extract(Some(salt), ikm).0.to_vec(),
+        })
+    }
+
+    /// HKDF-Expand
+    fn expand(self, prk: &[u8], info: &[u8], len: usize) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let mut okm = Zeroizing::new(vec![0u8; len]);
+
+        match self {
+            HpkeKdf::HkdfSha256 => Hkdf::<Sha256>::from_prk(prk)
+                .expect("invalid PRK")
+                .expand(info, &mut okm),
+            HpkeKdf::HkdfSha384 => Hkdf::<Sha384>::from_prk(prk)
+                .expect("invalid PRK")
+                .expand(info, &mut okm),
+            HpkeKdf::HkdfSha512 => Hkdf::<Sha512>::from_prk(prk)
+                .expect("invalid PRK")
+                .expand(info, &mut okm),
+        }
+        .map_err(|_| Error::new(ErrorKind::Param, "requested HKDF output is too long"))?;
+
+        Ok(okm)
+    }
+}
+
+/// Authenticated Encryption with Associated Data algorithms (RFC 9180
+/// section 7.3)
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum HpkeAead {
+    /// AES-128-GCM
+    Aes128Gcm,
+
+    /// AES-256-GCM
+    Aes256Gcm,
+
+    /// Export-only: the context can only be used to export secrets
+    ExportOnly,
+}
+
+impl HpkeAead {
+    /// Get the identifier for this AEAD
+    pub fn id(self) -> u16 {
+        match self {
+            HpkeAead::Aes128Gcm => 0x0001,
+            HpkeAead::Aes256Gcm => 0x0002,
+            HpkeAead::ExportOnly => 0xFFFF,
+        }
+    }
+
+    /// Size of this AEAD's key (`Nk`)
+    fn key_size(self) -> usize {
+        match self {
+            HpkeAead::Aes128Gcm => 16,
+            HpkeAead::Aes256Gcm => 32,
+            HpkeAead::ExportOnly => 0,
+        }
+    }
+
+    /// Encrypt a plaintext, appending the authentication tag
+    fn seal(
+        self,
+        key: &[u8],
+        nonce: [u8; NONCE_SIZE],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+
+        match self {
+            HpkeAead::Aes128Gcm => Aes128Gcm::new_from_slice(key)
+                .expect("invalid AES-128 key")
+                .encrypt(&nonce.into(), payload),
+            HpkeAead::Aes256Gcm => Aes256Gcm::new_from_slice(key)
+                .expect("invalid AES-256 key")
+                .encrypt(&nonce.into(), payload),
+            HpkeAead::ExportOnly => return Err(export_only()),
+        }
+        .map_err(|_| Error::new(ErrorKind::Param, "HPKE encryption failed"))
+    }
+
+    /// Decrypt and authenticate a ciphertext
+    fn open(
+        self,
+        key: &[u8],
+        nonce: [u8; NONCE_SIZE],
+        aad: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let payload = Payload {
+            msg: ciphertext,
+            aad,
+        };
+
+        match self {
+            HpkeAead::Aes128Gcm => Aes128Gcm::new_from_slice(key)
+                .expect("invalid AES-128 key")
+                .decrypt(&nonce.into(), payload),
+            HpkeAead::Aes256Gcm => Aes256Gcm::new_from_slice(key)
+                .expect("invalid AES-256 key")
+                .decrypt(&nonce.into(), payload),
+            HpkeAead::ExportOnly => return Err(export_only()),
+        }
+        .map_err(|_| Error::new(ErrorKind::DecryptionFailed, "HPKE decryption failed"))
+    }
+}
+
+/// HPKE ciphersuites: a combination of KEM, KDF, and AEAD
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct HpkeSuite {
+    /// Key Encapsulation Mechanism
+    pub kem: HpkeKem,
+
+    /// Key Derivation Function
+    pub kdf: HpkeKdf,
+
+    /// AEAD algorithm
+    pub aead: HpkeAead,
+}
+
+impl HpkeSuite {
+    /// Create a new `HpkeSuite`
+    pub fn new(kem: HpkeKem, kdf: HpkeKdf, aead: HpkeAead) -> Self {
+        Self { kem, kdf, aead }
+    }
+
+    /// Set up a sender context in the base mode for the given recipient
+    /// public key (i.e. an uncompressed `04 || X || Y` point).
+    ///
+    /// Returns the encapsulated key to send to the recipient along with
+    /// the context used to seal messages.
+    pub fn setup_base_sender(
+        &self,
+        recipient_public_key: &[u8],
+        info: &[u8],
+    ) -> Result<(Vec<u8>, HpkeSender), Error> {
+        let (shared_secret, enc) = self.encap(recipient_public_key, None)?;
+        let context = self.key_schedule(MODE_BASE, &shared_secret, info)?;
+        Ok((enc, HpkeSender(context)))
+    }
+
+    /// Set up a sender context in the auth mode, which authenticates the
+    /// sender to the recipient as the holder of the given private key.
+    pub fn setup_auth_sender(
+        &self,
+        recipient_public_key: &[u8],
+        info: &[u8],
+        sender_key: &Key,
+    ) -> Result<(Vec<u8>, HpkeSender), Error> {
+        let (shared_secret, enc) = self.encap(recipient_public_key, Some(sender_key))?;
+        let context = self.key_schedule(MODE_AUTH, &shared_secret, info)?;
+        Ok((enc, HpkeSender(context)))
+    }
+
+    /// Set up a recipient context in the base mode from the encapsulated
+    /// key sent by the sender, using the recipient's private key.
+    pub fn setup_base_recipient(
+        &self,
+        recipient_key: &Key,
+        enc: &[u8],
+        info: &[u8],
+    ) -> Result<HpkeRecipient, Error> {
+        let shared_secret = self.decap(recipient_key, enc, None)?;
+        let context = self.key_schedule(MODE_BASE, &shared_secret, info)?;
+        Ok(HpkeRecipient(context))
+    }
+
+    /// Set up a recipient context in the auth mode, which only succeeds in
+    /// opening messages from the holder of the sender's private key.
+    pub fn setup_auth_recipient(
+        &self,
+        recipient_key: &Key,
+        enc: &[u8],
+        info: &[u8],
+        sender_public_key: &[u8],
+    ) -> Result<HpkeRecipient, Error> {
+        let shared_secret = self.decap(recipient_key, enc, Some(sender_public_key))?;
+        let context = self.key_schedule(MODE_AUTH, &shared_secret, info)?;
+        Ok(HpkeRecipient(context))
+    }
+
+    /// Encrypt a single message to the given recipient public key in the
+    /// base mode, returning the encapsulated key and the ciphertext.
+    pub fn seal(
+        &self,
+        recipient_public_key: &[u8],
+        info: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let (enc, mut sender) = self.setup_base_sender(recipient_public_key, info)?;
+        let ciphertext = sender.seal(aad, plaintext)?;
+        Ok((enc, ciphertext))
+    }
+
+    /// Decrypt a single message produced by `HpkeSuite::seal`.
+    ///
+    /// Returns an error with a kind of `ErrorKind::DecryptionFailed` if the
+    /// message wasn't encrypted to this key or has been tampered with.
+    pub fn open(
+        &self,
+        recipient_key: &Key,
+        enc: &[u8],
+        info: &[u8],
+        aad: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        self.setup_base_recipient(recipient_key, enc, info)?
+            .open(aad, ciphertext)
+    }
+
+    /// Context string used in the key schedule's labeled KDF invocations
+    fn suite_id(&self) -> Vec<u8> {
+        let mut suite_id = b"HPKE".to_vec();
+
+        for id in &[self.kem.id(), self.kdf.id(), self.aead.id()] {
+            suite_id.extend_from_slice(&id.to_be_bytes());
+        }
+
+        suite_id
+    }
+
+    /// Generate an ephemeral key and derive a shared secret with the
+    /// recipient (`Encap` and `AuthEncap`)
+    fn encap(
+        &self,
+        recipient_public_key: &[u8],
+        sender_key: Option<&Key>,
+    ) -> Result<(Zeroizing<Vec<u8>>, Vec<u8>), Error> {
+        let public_key = self.kem.deserialize_public_key(recipient_public_key)?;
+        let ephemeral_key =
+            PrivateKey::generate(AttrKeyType::EcSecPrimeRandom, self.kem.key_size_in_bits())?;
+
+        let enc = ephemeral_key.public_key().to_external_representation();
+        let mut dh = ephemeral_key.diffie_hellman(&public_key)?;
+        let mut kem_context = [enc.as_slice(), recipient_public_key].concat();
+
+        if let Some(sender_key) = sender_key {
+            self.kem.check_private_key(sender_key)?;
+            dh.extend_from_slice(&diffie_hellman(sender_key, recipient_public_key)?);
+            kem_context.extend_from_slice(&sender_key.public_key()?.to_external_representation()?);
+        }
+
+        let shared_secret = self.kem.extract_and_expand(&dh, &kem_context)?;
+        Ok((shared_secret, enc))
+    }
+
+    /// Derive the shared secret from an encapsulated key using the
+    /// recipient's private key (`Decap` and `AuthDecap`)
+    fn decap(
+        &self,
+        recipient_key: &Key,
+        enc: &[u8],
+        sender_public_key: Option<&[u8]>,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        self.kem.check_private_key(recipient_key)?;
+        self.kem.deserialize_public_key(enc)?;
+
+        let mut dh = diffie_hellman(recipient_key, enc)?;
+        let mut kem_context = enc.to_vec();
+        kem_context.extend_from_slice(&recipient_key.public_key()?.to_external_representation()?);
+
+        if let Some(sender_public_key) = sender_public_key {
+            self.kem.deserialize_public_key(sender_public_key)?;
+            dh.extend_from_slice(&diffie_hellman(recipient_key, sender_public_key)?);
+            kem_context.extend_from_slice(sender_public_key);
+        }
+
+        self.kem.extract_and_expand(&dh, &kem_context)
+    }
+
+    /// Derive the AEAD key, base nonce, and exporter secret from the KEM
+    /// shared secret (RFC 9180 section 5.1)
+    fn key_schedule(&self, mode: u8, shared_secret: &[u8], info: &[u8]) -> Result<Context, Error> {
+        let suite_id = self.suite_id();
+        let psk_id_hash = labeled_extract(self.kdf, &suite_id, &[], b"psk_id_hash", &[]);
+        let info_hash = labeled_extract(self.kdf, &suite_id, &[], b"info_hash", info);
+
+        let mut key_schedule_context = vec![mode];
+        key_schedule_context.extend_from_slice(&psk_id_hash);
+        key_schedule_context.extend_from_slice(&info_hash);
+
+        let secret = labeled_extract(self.kdf, &suite_id, shared_secret, b"secret", &[]);
+        let expand = |label: &[u8], len| {
+            labeled_expand(
+                self.kdf,
+                &suite_id,
+                &secret,
+                label,
+                &key_schedule_context,
+                len,
+            )
+        };
+
+        let (key, base_nonce) = match self.aead {
+            HpkeAead::ExportOnly => (Zeroizing::new(vec![]), Zeroizing::new(vec![])),
+            aead => (
+                expand(b"key", aead.key_size())?,
+                expand(b"base_nonce", NONCE_SIZE)?,
+            ),
+        };
+
+        Ok(Context {
+            suite: *self,
+            key,
+            base_nonce,
+            exporter_secret: expand(b"exp", self.kdf.output_size())?,
+            seq: 0,
+        })
+    }
+}
+
+/// Sender context for sealing messages to a recipient
+pub struct HpkeSender(Context);
+
+impl HpkeSender {
+    /// Encrypt the next message with the given additional authenticated
+    /// data. Messages must be opened in the order they were sealed.
+    pub fn seal(&mut self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        let ciphertext = self
+            .0
+            .suite
+            .aead
+            .seal(&self.0.key, self.0.nonce(), aad, plaintext)?;
+
+        self.0.increment_seq()?;
+        Ok(ciphertext)
+    }
+
+    /// Export a secret of `len` bytes for the given exporter context
+    pub fn export(&self, exporter_context: &[u8], len: usize) -> Result<Zeroizing<Vec<u8>>, Error> {
+        self.0.export(exporter_context, len)
+    }
+}
+
+/// Recipient context for opening messages from a sender
+pub struct HpkeRecipient(Context);
+
+impl HpkeRecipient {
+    /// Decrypt the next message with the given additional authenticated
+    /// data.
+    ///
+    /// Returns an error with a kind of `ErrorKind::DecryptionFailed` if the
+    /// message has been tampered with or is out of order.
+    pub fn open(&mut self, aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        let plaintext = self
+            .0
+            .suite
+            .aead
+            .open(&self.0.key, self.0.nonce(), aad, ciphertext)?;
+
+        self.0.increment_seq()?;
+        Ok(plaintext)
+    }
+
+    /// Export a secret of `len` bytes for the given exporter context
+    pub fn export(&self, exporter_context: &[u8], len: usize) -> Result<Zeroizing<Vec<u8>>, Error> {
+        self.0.export(exporter_context, len)
+    }
+}
+
+/// Encryption context state shared by senders and recipients
+struct Context {
+    suite: HpkeSuite,
+    key: Zeroizing<Vec<u8>>,
+    base_nonce: Zeroizing<Vec<u8>>,
+    exporter_secret: Zeroizing<Vec<u8>>,
+    seq: u64,
+}
+
+impl Context {
+    /// Compute the nonce for the current sequence number
+    fn nonce(&self) -> [u8; NONCE_SIZE] {
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce[NONCE_SIZE - 8..].copy_from_slice(&self.seq.to_be_bytes());
+
+        for (n, b) in nonce.iter_mut().zip(self.base_nonce.iter()) {
+            *n ^= b;
+        }
+
+        nonce
+    }
+
+    /// Advance to the next sequence number
+    fn increment_seq(&mut self) -> Result<(), Error> {
+        self.seq = self
+            .seq
+            .checked_add(1)
+            .ok_or_else(|| Error::new(ErrorKind::Param, "HPKE message limit reached"))?;
+
+        Ok(())
+    }
+
+    /// Export a secret from the exporter secret (RFC 9180 section 5.3)
+    fn export(&self, exporter_context: &[u8], len: usize) -> Result<Zeroizing<Vec<u8>>, Error> {
+        labeled_expand(
+            self.suite.kdf,
+            &self.suite.suite_id(),
+            &self.exporter_secret,
+            b"sec",
+            exporter_context,
+            len,
+        )
+    }
+}
+
+/// Compute the raw ECDH shared secret between a private key and a
+/// serialized public key
+fn diffie_hellman(private_key: &Key, public_key: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let params = RestoreKeyParams {
+        key_class: AttrKeyClass::Public,
+        key_type: AttrKeyType::EcSecPrimeRandom,
+        key_data: public_key.to_vec(),
+    };
+
+    private_key.key_exchange_with_params(KeyAlgorithm::ECDHKeyExchangeStandard, &params, 0, &[])
+}
+
+/// `LabeledExtract` (RFC 9180 section 4)
+fn labeled_extract(
+    kdf: HpkeKdf,
+    suite_id: &[u8],
+    salt: &[u8],
+    label: &[u8],
+    ikm: &[u8],
+) -> Zeroizing<Vec<u8>> {
+    let labeled_ikm = Zeroizing::new([b"HPKE-v1", suite_id, label, ikm].concat());
+    kdf.extract(salt, &labeled_ikm)
+}
+
+/// `LabeledExpand` (RFC 9180 section 4)
+fn labeled_expand(
+    kdf: HpkeKdf,
+    suite_id: &[u8],
+    prk: &[u8],
+    label: &[u8],
+    info: &[u8],
+    len: usize,
+) -> Result<Zeroizing<Vec<u8>>, Error> {
+    if len > u16::MAX as usize {
+        return Err(Error::new(
+            ErrorKind::Param,
+            "requested HKDF output is too long",
+        ));
+    }
+
+    let length = (len as u16).to_be_bytes();
+    let labeled_info = [&length[..], b"HPKE-v1", suite_id, label, info].concat();
+    kdf.expand(prk, &labeled_info, len)
+}
+
+/// Error for attempting to seal or open with an export-only context
+fn export_only() -> Error {
+    Error::new(
+        ErrorKind::Param,
+        "export-only HPKE contexts can't seal or open messages",
+    )
+}
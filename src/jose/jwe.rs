@@ -0,0 +1,864 @@
+//! JSON Web Encryption (RFC 7516) using the compact and (flattened or
+//! general) JSON serializations.
+//!
+//! Content encryption keys are agreed using ECDH-ES, either directly or
+//! wrapped with AES Key Wrap, or encrypted using RSAES-OAEP (RFC 7518
+//! section 4). Decryption uses a private `Key`, so it works with keys stored
+//! in the SEP, whereas encryption to a `Jwk` is implemented in pure Rust.
+
+use crate::{
+    attr::AttrKeyType,
+    ciphertext::Ciphertext,
+    encoding::jwk::{self, Jwk},
+    error::{Error, ErrorKind},
+    key::{Key, KeyAlgorithm, RestoreKeyParams},
+    software::{PrivateKey, SoftwareKey},
+};
+use aes::{
+    cipher::{BlockDecrypt, BlockEncrypt},
+    Aes256, Block,
+};
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes128Gcm, Aes256Gcm, KeyInit,
+};
+use base64ct::{Base64UrlUnpadded, Encoding};
+use rand_core::{OsRng, RngCore};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+use zeroize::Zeroizing;
+
+/// Size of an AES-GCM initialization vector
+const GCM_IV_SIZE: usize = 12;
+
+/// Size of an AES-GCM authentication tag
+const GCM_TAG_SIZE: usize = 16;
+
+/// Initial value for AES Key Wrap (RFC 3394 section 2.2.3.1)
+const KEY_WRAP_IV: [u8; 8] = [0xA6; 8];
+
+/// JWE key management algorithms (RFC 7518 section 4.1)
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum JweAlgorithm {
+    /// Elliptic Curve Diffie-Hellman Ephemeral Static key agreement using
+    /// Concat KDF, with the agreed key used directly as the content
+    /// encryption key (`ECDH-ES`)
+    EcdhEs,
+
+    /// ECDH-ES using Concat KDF, with the agreed key used to wrap the
+    /// content encryption key using 256-bit AES Key Wrap
+    /// (`ECDH-ES+A256KW`)
+    EcdhEsA256Kw,
+
+    /// RSAES-OAEP using SHA-256 and MGF1 with SHA-256 (`RSA-OAEP-256`)
+    RsaOaep256,
+}
+
+impl JweAlgorithm {
+    /// Get the `alg` header parameter value for this algorithm
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JweAlgorithm::EcdhEs => "ECDH-ES",
+            JweAlgorithm::EcdhEsA256Kw => "ECDH-ES+A256KW",
+            JweAlgorithm::RsaOaep256 => "RSA-OAEP-256",
+        }
+    }
+
+    /// Get the type of key this algorithm is used with
+    pub fn key_type(self) -> AttrKeyType {
+        match self {
+            JweAlgorithm::EcdhEs | JweAlgorithm::EcdhEsA256Kw => AttrKeyType::EcSecPrimeRandom,
+            JweAlgorithm::RsaOaep256 => AttrKeyType::Rsa,
+        }
+    }
+}
+
+impl Display for JweAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for JweAlgorithm {
+    type Err = Error;
+
+    fn from_str(alg: &str) -> Result<Self, Error> {
+        match alg {
+            "ECDH-ES" => Ok(JweAlgorithm::EcdhEs),
+            "ECDH-ES+A256KW" => Ok(JweAlgorithm::EcdhEsA256Kw),
+            "RSA-OAEP-256" => Ok(JweAlgorithm::RsaOaep256),
+            _ => Err(Error::new(
+                ErrorKind::Unimplemented,
+                &format!("unsupported JWE algorithm: {:?}", alg),
+            )),
+        }
+    }
+}
+
+/// JWE content encryption algorithms (RFC 7518 section 5.1)
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum JweEncryption {
+    /// AES-GCM using a 128-bit key
+    A128GCM,
+
+    /// AES-GCM using a 256-bit key
+    A256GCM,
+}
+
+impl JweEncryption {
+    /// Get the `enc` header parameter value for this algorithm
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JweEncryption::A128GCM => "A128GCM",
+            JweEncryption::A256GCM => "A256GCM",
+        }
+    }
+
+    /// Get the size of a content encryption key in bytes
+    pub fn key_size(self) -> usize {
+        match self {
+            JweEncryption::A128GCM => 16,
+            JweEncryption::A256GCM => 32,
+        }
+    }
+
+    /// Encrypt the given plaintext, returning the ciphertext and tag
+    fn encrypt(
+        self,
+        key: &[u8],
+        iv: &[u8],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let nonce = gcm_nonce(iv)?.into();
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+
+        let mut ciphertext = match self {
+            JweEncryption::A128GCM => Aes128Gcm::new_from_slice(key)
+                .map_err(|e| Error::new(ErrorKind::Param, &e))?
+                .encrypt(&nonce, payload),
+            JweEncryption::A256GCM => Aes256Gcm::new_from_slice(key)
+                .map_err(|e| Error::new(ErrorKind::Param, &e))?
+                .encrypt(&nonce, payload),
+        }
+        .map_err(|e| Error::new(ErrorKind::Param, &e))?;
+
+        let tag = ciphertext.split_off(ciphertext.len() - GCM_TAG_SIZE);
+        Ok((ciphertext, tag))
+    }
+
+    /// Decrypt and authenticate the given ciphertext
+    fn decrypt(
+        self,
+        key: &[u8],
+        iv: &[u8],
+        ciphertext: &[u8],
+        tag: &[u8],
+        aad: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        if key.len() != self.key_size() {
+            return Err(decryption_failed());
+        }
+
+        if tag.len() != GCM_TAG_SIZE {
+            return Err(invalid_jwe("invalid AES-GCM tag"));
+        }
+
+        let nonce = gcm_nonce(iv)?.into();
+        let mut msg = ciphertext.to_vec();
+        msg.extend_from_slice(tag);
+
+        let payload = Payload { msg: &msg, aad };
+
+        match self {
+            JweEncryption::A128GCM => Aes128Gcm::new_from_slice(key)
+                .map_err(|e| Error::new(ErrorKind::Param, &e))?
+                .decrypt(&nonce, payload),
+            JweEncryption::A256GCM => Aes256Gcm::new_from_slice(key)
+                .map_err(|e| Error::new(ErrorKind::Param, &e))?
+                .decrypt(&nonce, payload),
+        }
+        .map(Zeroizing::new)
+        .map_err(|_| decryption_failed())
+    }
+}
+
+impl Display for JweEncryption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for JweEncryption {
+    type Err = Error;
+
+    fn from_str(enc: &str) -> Result<Self, Error> {
+        match enc {
+            "A128GCM" => Ok(JweEncryption::A128GCM),
+            "A256GCM" => Ok(JweEncryption::A256GCM),
+            _ => Err(Error::new(
+                ErrorKind::Unimplemented,
+                &format!("unsupported JWE encryption algorithm: {:?}", enc),
+            )),
+        }
+    }
+}
+
+/// JWE header (RFC 7516 section 4).
+///
+/// The `alg` and `enc` parameters are always set from the header's
+/// `JweAlgorithm` and `JweEncryption`, and `epk` is set when encrypting
+/// using ECDH-ES. Other parameters can be added with `JweHeader::param`,
+/// e.g. `apu` and `apv` (base64url-encoded), which are used as inputs to
+/// the ECDH-ES key derivation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JweHeader {
+    alg: JweAlgorithm,
+    enc: JweEncryption,
+    params: Map<String, Value>,
+}
+
+impl JweHeader {
+    /// Create a new header for the given algorithms
+    pub fn new(alg: JweAlgorithm, enc: JweEncryption) -> Self {
+        Self {
+            alg,
+            enc,
+            params: Map::new(),
+        }
+    }
+
+    /// Get the key management algorithm
+    pub fn algorithm(&self) -> JweAlgorithm {
+        self.alg
+    }
+
+    /// Get the content encryption algorithm
+    pub fn encryption(&self) -> JweEncryption {
+        self.enc
+    }
+
+    /// Get the value of a header parameter
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        match name {
+            "alg" | "enc" => None,
+            _ => self.params.get(name),
+        }
+    }
+
+    /// Get the key ID (`kid`), if present
+    pub fn key_id(&self) -> Option<&str> {
+        self.get("kid").and_then(Value::as_str)
+    }
+
+    /// Set the key ID (`kid`) of the recipient's key
+    pub fn kid<S: Into<String>>(self, kid: S) -> Self {
+        self.param("kid", kid.into())
+    }
+
+    /// Set the content type of the plaintext (`cty`), e.g. `JWT` for
+    /// nested JWTs
+    pub fn cty<S: Into<String>>(self, cty: S) -> Self {
+        self.param("cty", cty.into())
+    }
+
+    /// Set an arbitrary header parameter. Setting `alg` or `enc` has no
+    /// effect.
+    pub fn param<V: Into<Value>>(mut self, name: &str, value: V) -> Self {
+        if name != "alg" && name != "enc" {
+            self.params.insert(name.to_owned(), value.into());
+        }
+
+        self
+    }
+
+    /// Serialize this header as JSON
+    fn to_json(&self) -> String {
+        let mut params = self.params.clone();
+        params.insert("alg".to_owned(), self.alg.as_str().into());
+        params.insert("enc".to_owned(), self.enc.as_str().into());
+        Value::Object(params).to_string()
+    }
+
+    /// Parse the (joint) header parameters of a JWE
+    fn from_params(mut params: Map<String, Value>) -> Result<Self, Error> {
+        // We don't understand any extensions, so must reject critical ones
+        if params.contains_key("crit") || params.contains_key("zip") {
+            return Err(Error::new(
+                ErrorKind::Unimplemented,
+                "unsupported JWE header parameters (crit or zip)",
+            ));
+        }
+
+        let alg = match params.remove("alg") {
+            Some(Value::String(alg)) => alg.parse()?,
+            _ => return Err(invalid_jwe("missing alg header parameter")),
+        };
+
+        let enc = match params.remove("enc") {
+            Some(Value::String(enc)) => enc.parse()?,
+            _ => return Err(invalid_jwe("missing enc header parameter")),
+        };
+
+        Ok(Self { alg, enc, params })
+    }
+
+    /// Get a base64url-encoded binary header parameter (e.g. `apu`)
+    fn binary_param(&self, name: &str) -> Result<Vec<u8>, Error> {
+        match self.get(name) {
+            Some(Value::String(value)) => decode_part(value),
+            Some(_) => Err(invalid_jwe(&format!("{} is not a string", name))),
+            None => Ok(vec![]),
+        }
+    }
+}
+
+/// Decrypted JWE: its header and plaintext, which is zeroized on drop
+#[derive(Clone, Debug)]
+pub struct Jwe {
+    header: JweHeader,
+    plaintext: Zeroizing<Vec<u8>>,
+    aad: Option<Vec<u8>>,
+}
+
+impl Jwe {
+    /// Get the header, i.e. the union of the protected, shared unprotected,
+    /// and per-recipient unprotected headers
+    pub fn header(&self) -> &JweHeader {
+        &self.header
+    }
+
+    /// Borrow the plaintext
+    pub fn plaintext(&self) -> &[u8] {
+        &self.plaintext
+    }
+
+    /// Get the additional authenticated data (`aad`) of a JWE in the JSON
+    /// serialization, if present
+    pub fn aad(&self) -> Option<&[u8]> {
+        self.aad.as_deref()
+    }
+}
+
+/// Encrypted JWE, as produced by `seal` or parsed from a serialization
+struct SealedJwe {
+    /// Base64url-encoded protected header
+    protected: String,
+    encrypted_key: Vec<u8>,
+    iv: Vec<u8>,
+    ciphertext: Vec<u8>,
+    tag: Vec<u8>,
+    aad: Option<Vec<u8>>,
+}
+
+impl SealedJwe {
+    /// Get the additional authenticated data for content encryption
+    /// (RFC 7516 section 5.1 step 14)
+    fn content_aad(&self) -> Vec<u8> {
+        match &self.aad {
+            Some(aad) => format!(
+                "{}.{}",
+                self.protected,
+                Base64UrlUnpadded::encode_string(aad)
+            )
+            .into_bytes(),
+            None => self.protected.clone().into_bytes(),
+        }
+    }
+
+    /// Serialize using the compact serialization
+    fn to_compact(&self) -> String {
+        [
+            self.protected.clone(),
+            Base64UrlUnpadded::encode_string(&self.encrypted_key),
+            Base64UrlUnpadded::encode_string(&self.iv),
+            Base64UrlUnpadded::encode_string(&self.ciphertext),
+            Base64UrlUnpadded::encode_string(&self.tag),
+        ]
+        .join(".")
+    }
+
+    /// Serialize using the flattened JSON serialization
+    fn to_json(&self) -> String {
+        let mut json = Map::new();
+        json.insert("protected".to_owned(), self.protected.clone().into());
+
+        if !self.encrypted_key.is_empty() {
+            json.insert(
+                "encrypted_key".to_owned(),
+                Base64UrlUnpadded::encode_string(&self.encrypted_key).into(),
+            );
+        }
+
+        if let Some(aad) = &self.aad {
+            json.insert(
+                "aad".to_owned(),
+                Base64UrlUnpadded::encode_string(aad).into(),
+            );
+        }
+
+        for &(name, value) in &[
+            ("iv", &self.iv),
+            ("ciphertext", &self.ciphertext),
+            ("tag", &self.tag),
+        ] {
+            json.insert(
+                name.to_owned(),
+                Base64UrlUnpadded::encode_string(value).into(),
+            );
+        }
+
+        Value::Object(json).to_string()
+    }
+}
+
+impl Key {
+    /// Decrypt a JWE in the compact or JSON serialization using this
+    /// private key. For JWEs with several recipients, each is tried in turn.
+    ///
+    /// Returns an error with a kind of `ErrorKind::DecryptionFailed` if the
+    /// JWE wasn't encrypted to this key or has been tampered with.
+    pub fn decrypt_jwe(&self, jwe: &str) -> Result<Jwe, Error> {
+        let jwe = jwe.trim();
+
+        if !jwe.starts_with('{') {
+            let (header, sealed) = parse_compact(jwe)?;
+            return self.open_jwe(header, &sealed);
+        }
+
+        let mut result = Err(decryption_failed());
+
+        for (header, sealed) in parse_json(jwe)? {
+            result = self.open_jwe(header, &sealed);
+
+            if result.is_ok() {
+                break;
+            }
+        }
+
+        result
+    }
+
+    /// Decrypt the content encryption key and content of a JWE
+    fn open_jwe(&self, header: JweHeader, sealed: &SealedJwe) -> Result<Jwe, Error> {
+        if self.key_type() != Some(header.alg.key_type()) {
+            return Err(decryption_failed());
+        }
+
+        let cek = match header.alg {
+            JweAlgorithm::RsaOaep256 => {
+                let ciphertext = Ciphertext::new(
+                    KeyAlgorithm::RSAEncryptionOAEPSHA256,
+                    sealed.encrypted_key.clone(),
+                );
+
+                Zeroizing::new(self.decrypt(ciphertext)?)
+            }
+            JweAlgorithm::EcdhEs | JweAlgorithm::EcdhEsA256Kw => {
+                let epk = match header.get("epk") {
+                    Some(epk) => serde_json::from_value::<Jwk>(epk.clone())
+                        .map_err(|_| invalid_jwe("invalid epk header parameter"))?,
+                    None => return Err(invalid_jwe("missing epk header parameter")),
+                };
+
+                let epk = RestoreKeyParams::from_software_key(&SoftwareKey::Public(
+                    jwk::decode(&epk)?.public_key(),
+                ));
+
+                let shared_secret = self.key_exchange_with_params(
+                    KeyAlgorithm::ECDHKeyExchangeStandard,
+                    &epk,
+                    0,
+                    &[],
+                )?;
+
+                unwrap_agreed_key(&header, &shared_secret, &sealed.encrypted_key)?
+            }
+        };
+
+        let plaintext = header.enc.decrypt(
+            &cek,
+            &sealed.iv,
+            &sealed.ciphertext,
+            &sealed.tag,
+            &sealed.content_aad(),
+        )?;
+
+        Ok(Jwe {
+            header,
+            plaintext,
+            aad: sealed.aad.clone(),
+        })
+    }
+}
+
+impl Jwk {
+    /// Encrypt the given plaintext to this JWK's public key, returning a
+    /// JWE in the compact serialization.
+    ///
+    /// Unlike `Key::decrypt_jwe`, this doesn't use a keychain, so it can be
+    /// used to encrypt to a (e.g. SEP) key's exported public JWK on any
+    /// platform.
+    pub fn encrypt_jwe(&self, header: &JweHeader, plaintext: &[u8]) -> Result<String, Error> {
+        seal(self, header, plaintext, None).map(|sealed| sealed.to_compact())
+    }
+
+    /// Encrypt the given plaintext to this JWK's public key, returning a
+    /// JWE in the flattened JSON serialization with the given additional
+    /// authenticated data (if any). All header parameters are protected.
+    pub fn encrypt_jwe_json(
+        &self,
+        header: &JweHeader,
+        plaintext: &[u8],
+        aad: Option<&[u8]>,
+    ) -> Result<String, Error> {
+        seal(self, header, plaintext, aad).map(|sealed| sealed.to_json())
+    }
+}
+
+/// Encrypt a plaintext to the public key in the given JWK
+fn seal(
+    jwk: &Jwk,
+    header: &JweHeader,
+    plaintext: &[u8],
+    aad: Option<&[u8]>,
+) -> Result<SealedJwe, Error> {
+    let public_key = jwk::decode(jwk)?.public_key();
+
+    if public_key.key_type() != header.alg.key_type() {
+        return Err(Error::new(
+            ErrorKind::Param,
+            &format!("JWE algorithm {} doesn't match key", header.alg),
+        ));
+    }
+
+    let mut header = header.clone();
+
+    let (cek, encrypted_key) = match header.alg {
+        JweAlgorithm::RsaOaep256 => {
+            let cek = random_bytes(header.enc.key_size());
+            let encrypted_key = public_key.encrypt(KeyAlgorithm::RSAEncryptionOAEPSHA256, &cek)?;
+            (cek, encrypted_key)
+        }
+        JweAlgorithm::EcdhEs | JweAlgorithm::EcdhEsA256Kw => {
+            let ephemeral_key =
+                PrivateKey::generate(AttrKeyType::EcSecPrimeRandom, public_key.key_size_in_bits())?;
+
+            let mut epk = jwk::encode(&SoftwareKey::Public(ephemeral_key.public_key()))?;
+            epk.kid = None;
+            header = header.param(
+                "epk",
+                serde_json::to_value(&epk).expect("JWK serialization failed"),
+            );
+
+            let shared_secret = ephemeral_key.diffie_hellman(&public_key)?;
+            wrap_agreed_key(&header, &shared_secret)?
+        }
+    };
+
+    let protected = Base64UrlUnpadded::encode_string(header.to_json().as_bytes());
+    let iv = random_bytes(GCM_IV_SIZE).to_vec();
+
+    let mut sealed = SealedJwe {
+        protected,
+        encrypted_key,
+        iv,
+        ciphertext: vec![],
+        tag: vec![],
+        aad: aad.map(<[u8]>::to_vec),
+    };
+
+    let (ciphertext, tag) =
+        header
+            .enc
+            .encrypt(&cek, &sealed.iv, plaintext, &sealed.content_aad())?;
+
+    sealed.ciphertext = ciphertext;
+    sealed.tag = tag;
+    Ok(sealed)
+}
+
+/// Derive the content encryption key from an ECDH-ES shared secret,
+/// returning it along with the JWE encrypted key
+fn wrap_agreed_key(
+    header: &JweHeader,
+    shared_secret: &[u8],
+) -> Result<(Zeroizing<Vec<u8>>, Vec<u8>), Error> {
+    let agreed_key = agree_key(header, shared_secret)?;
+
+    match header.alg {
+        JweAlgorithm::EcdhEsA256Kw => {
+            let cek = random_bytes(header.enc.key_size());
+            let encrypted_key = aes_key_wrap(&agreed_key, &cek);
+            Ok((cek, encrypted_key))
+        }
+        _ => Ok((agreed_key, vec![])),
+    }
+}
+
+/// Recover the content encryption key from an ECDH-ES shared secret and
+/// the JWE encrypted key
+fn unwrap_agreed_key(
+    header: &JweHeader,
+    shared_secret: &[u8],
+    encrypted_key: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let agreed_key = agree_key(header, shared_secret)?;
+
+    match header.alg {
+        JweAlgorithm::EcdhEsA256Kw => aes_key_unwrap(&agreed_key, encrypted_key),
+        _ if encrypted_key.is_empty() => Ok(agreed_key),
+        _ => Err(invalid_jwe("ECDH-ES must not have an encrypted key")),
+    }
+}
+
+/// Derive a key from an ECDH-ES shared secret using Concat KDF (RFC 7518
+/// section 4.6.2)
+fn agree_key(header: &JweHeader, shared_secret: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let (algorithm_id, key_size) = match header.alg {
+        JweAlgorithm::EcdhEsA256Kw => (header.alg.as_str(), 32),
+        _ => (header.enc.as_str(), header.enc.key_size()),
+    };
+
+    let mut other_info = vec![];
+
+    for field in &[
+        algorithm_id.as_bytes(),
+        &header.binary_param("apu")?,
+        &header.binary_param("apv")?,
+    ] {
+        other_info.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        other_info.extend_from_slice(field);
+    }
+
+    other_info.extend_from_slice(&((key_size * 8) as u32).to_be_bytes());
+
+    let mut key = Zeroizing::new(Vec::with_capacity(key_size + 32));
+    let mut counter = 1u32;
+
+    while key.len() < key_size {
+        let mut hasher = Sha256::new();
+        hasher.update(counter.to_be_bytes());
+        hasher.update(shared_secret);
+        hasher.update(&other_info);
+        key.extend_from_slice(&hasher.finalize());
+        counter += 1;
+    }
+
+    key.truncate(key_size);
+    Ok(key)
+}
+
+/// Wrap a key using AES Key Wrap (RFC 3394) with a 256-bit key
+fn aes_key_wrap(kek: &[u8], key: &[u8]) -> Vec<u8> {
+    let cipher = Aes256::new_from_slice(kek).expect("invalid AES-256 key");
+    let n = key.len() / 8;
+    let mut a = KEY_WRAP_IV;
+    let mut r = Zeroizing::new(key.to_vec());
+    let mut block = Block::default();
+
+    for j in 0..6 {
+        for i in 0..n {
+            block[..8].copy_from_slice(&a);
+            block[8..].copy_from_slice(&r[i * 8..(i + 1) * 8]);
+            cipher.encrypt_block(&mut block);
+
+            let t = ((n * j + i + 1) as u64).to_be_bytes();
+            for k in 0..8 {
+                a[k] = block[k] ^ t[k];
+            }
+            r[i * 8..(i + 1) * 8].copy_from_slice(&block[8..]);
+        }
+    }
+
+    let mut wrapped = a.to_vec();
+    wrapped.extend_from_slice(&r);
+    wrapped
+}
+
+/// Unwrap a key using AES Key Wrap (RFC 3394) with a 256-bit key
+fn aes_key_unwrap(kek: &[u8], wrapped: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+    if wrapped.len() < 24 || wrapped.len() % 8 != 0 {
+        return Err(decryption_failed());
+    }
+
+    let cipher = Aes256::new_from_slice(kek).expect("invalid AES-256 key");
+    let n = wrapped.len() / 8 - 1;
+    let mut a = [0u8; 8];
+    a.copy_from_slice(&wrapped[..8]);
+    let mut r = Zeroizing::new(wrapped[8..].to_vec());
+    let mut block = Block::default();
+
+    for j in (0..6).rev() {
+        for i in (0..n).rev() {
+            let t = ((n * j + i + 1) as u64).to_be_bytes();
+            for k in 0..8 {
+                block[k] = a[k] ^ t[k];
+            }
+            block[8..].copy_from_slice(&r[i * 8..(i + 1) * 8]);
+            cipher.decrypt_block(&mut block);
+
+            a.copy_from_slice(&block[..8]);
+            r[i * 8..(i + 1) * 8].copy_from_slice(&block[8..]);
+        }
+    }
+
+    if a == KEY_WRAP_IV {
+        Ok(r)
+    } else {
+        Err(decryption_failed())
+    }
+}
+
+/// Parse a JWE in the compact serialization
+fn parse_compact(jwe: &str) -> Result<(JweHeader, SealedJwe), Error> {
+    let parts: Vec<&str> = jwe.split('.').collect();
+
+    if parts.len() != 5 {
+        return Err(invalid_jwe("not a compact JWE"));
+    }
+
+    let header = match serde_json::from_slice(&decode_part(parts[0])?) {
+        Ok(Value::Object(params)) => JweHeader::from_params(params)?,
+        _ => return Err(invalid_jwe("header is not a JSON object")),
+    };
+
+    let sealed = SealedJwe {
+        protected: parts[0].to_owned(),
+        encrypted_key: decode_part(parts[1])?,
+        iv: decode_part(parts[2])?,
+        ciphertext: decode_part(parts[3])?,
+        tag: decode_part(parts[4])?,
+        aad: None,
+    };
+
+    Ok((header, sealed))
+}
+
+/// Parse a JWE in the general or flattened JSON serialization, returning
+/// the joint header and encrypted key for each recipient
+fn parse_json(jwe: &str) -> Result<Vec<(JweHeader, SealedJwe)>, Error> {
+    let json = match serde_json::from_str(jwe) {
+        Ok(Value::Object(json)) => json,
+        _ => return Err(invalid_jwe("not a JSON object")),
+    };
+
+    let protected = match json.get("protected") {
+        Some(Value::String(protected)) => protected.clone(),
+        Some(_) => return Err(invalid_jwe("protected is not a string")),
+        None => String::new(),
+    };
+
+    let mut shared_params = match protected.as_str() {
+        "" => Map::new(),
+        _ => match serde_json::from_slice(&decode_part(&protected)?) {
+            Ok(Value::Object(params)) => params,
+            _ => return Err(invalid_jwe("protected header is not a JSON object")),
+        },
+    };
+
+    if let Some(unprotected) = json.get("unprotected") {
+        merge_params(&mut shared_params, unprotected)?;
+    }
+
+    let recipients = match json.get("recipients") {
+        Some(Value::Array(recipients)) => recipients.clone(),
+        Some(_) => return Err(invalid_jwe("recipients is not an array")),
+        None => vec![Value::Object(json.clone())],
+    };
+
+    let mut result = vec![];
+
+    for recipient in &recipients {
+        let recipient = recipient
+            .as_object()
+            .ok_or_else(|| invalid_jwe("recipient is not a JSON object"))?;
+        let mut params = shared_params.clone();
+
+        if let Some(header) = recipient.get("header") {
+            merge_params(&mut params, header)?;
+        }
+
+        let sealed = SealedJwe {
+            protected: protected.clone(),
+            encrypted_key: json_member(recipient, "encrypted_key")?.unwrap_or_default(),
+            iv: json_member(&json, "iv")?.unwrap_or_default(),
+            ciphertext: json_member(&json, "ciphertext")?
+                .ok_or_else(|| invalid_jwe("missing ciphertext"))?,
+            tag: json_member(&json, "tag")?.unwrap_or_default(),
+            aad: json_member(&json, "aad")?,
+        };
+
+        result.push((JweHeader::from_params(params)?, sealed));
+    }
+
+    Ok(result)
+}
+
+/// Add the parameters in an unprotected header, which must be disjoint
+/// from the existing ones (RFC 7516 section 7.2.1)
+fn merge_params(params: &mut Map<String, Value>, header: &Value) -> Result<(), Error> {
+    let header = header
+        .as_object()
+        .ok_or_else(|| invalid_jwe("header is not a JSON object"))?;
+
+    for (name, value) in header {
+        if params.insert(name.clone(), value.clone()).is_some() {
+            return Err(invalid_jwe(&format!(
+                "duplicate header parameter: {}",
+                name
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Decode a base64url-encoded member of a JSON object, if present
+fn json_member(object: &Map<String, Value>, name: &str) -> Result<Option<Vec<u8>>, Error> {
+    match object.get(name) {
+        Some(Value::String(value)) => decode_part(value).map(Some),
+        Some(_) => Err(invalid_jwe(&format!("{} is not a string", name))),
+        None => Ok(None),
+    }
+}
+
+/// Generate random bytes (i.e. a key or IV)
+fn random_bytes(len: usize) -> Zeroizing<Vec<u8>> {
+    let mut bytes = Zeroizing::new(vec![0u8; len]);
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+/// Convert an IV into an AES-GCM nonce
+fn gcm_nonce(iv: &[u8]) -> Result<[u8; GCM_IV_SIZE], Error> {
+    if iv.len() != GCM_IV_SIZE {
+        return Err(invalid_jwe("invalid AES-GCM IV"));
+    }
+
+    let mut nonce = [0u8; GCM_IV_SIZE];
+    nonce.copy_from_slice(iv);
+    Ok(nonce)
+}
+
+/// Decode a base64url-encoded part of a JWE
+fn decode_part(part: &str) -> Result<Vec<u8>, Error> {
+    Base64UrlUnpadded::decode_vec(part).map_err(|_| invalid_jwe("invalid base64url"))
+}
+
+/// Error for malformed JWEs
+fn invalid_jwe(reason: &str) -> Error {
+    Error::new(ErrorKind::Decode, &format!("invalid JWE: {}", reason))
+}
+
+/// Error for JWEs we can't decrypt
+fn decryption_failed() -> Error {
+    Error::new(ErrorKind::DecryptionFailed, "JWE decryption failed")
+}
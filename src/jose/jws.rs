@@ -0,0 +1,416 @@
+//! Compact JSON Web Signatures (RFC 7515), including JSON Web Tokens
+//! (RFC 7519), which are JWSes whose payload is a JSON claims set.
+//!
+//! Keychain Services produces ASN.1 DER encoded ECDSA signatures, whereas
+//! JWS uses fixed-width `r || s` signatures (RFC 7518 section 3.4), so
+//! ECDSA signatures are converted between the two when signing and
+//! verifying.
+
+use crate::{
+    attr::AttrKeyType,
+    encoding::jwk::{self, Jwk},
+    error::{Error, ErrorKind},
+    key::{EcCurve, Key, KeyAlgorithm},
+    signature::Signature,
+};
+use base64ct::{Base64UrlUnpadded, Encoding};
+use serde_json::{Map, Value};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+/// JWS signature algorithms (RFC 7518 section 3.1)
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum JwsAlgorithm {
+    /// ECDSA using P-256 and SHA-256
+    ES256,
+
+    /// ECDSA using P-384 and SHA-384
+    ES384,
+
+    /// ECDSA using P-521 and SHA-512
+    ES512,
+
+    /// RSASSA-PKCS1-v1_5 using SHA-256
+    RS256,
+
+    /// RSASSA-PSS using SHA-256 and MGF1 with SHA-256
+    PS256,
+}
+
+impl JwsAlgorithm {
+    /// Get the `alg` header parameter value for this algorithm
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JwsAlgorithm::ES256 => "ES256",
+            JwsAlgorithm::ES384 => "ES384",
+            JwsAlgorithm::ES512 => "ES512",
+            JwsAlgorithm::RS256 => "RS256",
+            JwsAlgorithm::PS256 => "PS256",
+        }
+    }
+
+    /// Get the Keychain Services algorithm used to compute signatures
+    pub fn key_algorithm(self) -> KeyAlgorithm {
+        match self {
+            JwsAlgorithm::ES256 => KeyAlgorithm::ECDSASignatureMessageX962SHA256,
+            JwsAlgorithm::ES384 => KeyAlgorithm::ECDSASignatureMessageX962SHA384,
+            JwsAlgorithm::ES512 => KeyAlgorithm::ECDSASignatureMessageX962SHA512,
+            JwsAlgorithm::RS256 => KeyAlgorithm::RSASignatureMessagePKCS1v15SHA256,
+            JwsAlgorithm::PS256 => KeyAlgorithm::RSASignatureMessagePSSSHA256,
+        }
+    }
+
+    /// Get the type of key this algorithm is used with
+    pub fn key_type(self) -> AttrKeyType {
+        match self.ec_curve() {
+            Some(_) => AttrKeyType::EcSecPrimeRandom,
+            None => AttrKeyType::Rsa,
+        }
+    }
+
+    /// Get the elliptic curve this algorithm is used with, if it's an
+    /// ECDSA algorithm
+    pub fn ec_curve(self) -> Option<EcCurve> {
+        match self {
+            JwsAlgorithm::ES256 => Some(EcCurve::P256),
+            JwsAlgorithm::ES384 => Some(EcCurve::P384),
+            JwsAlgorithm::ES512 => Some(EcCurve::P521),
+            JwsAlgorithm::RS256 | JwsAlgorithm::PS256 => None,
+        }
+    }
+
+    /// Is this algorithm usable with a key of the given type and curve?
+    fn is_compatible(self, key_type: Option<AttrKeyType>, curve: Option<EcCurve>) -> bool {
+        key_type == Some(self.key_type()) && curve == self.ec_curve()
+    }
+}
+
+impl Display for JwsAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for JwsAlgorithm {
+    type Err = Error;
+
+    fn from_str(alg: &str) -> Result<Self, Error> {
+        match alg {
+            "ES256" => Ok(JwsAlgorithm::ES256),
+            "ES384" => Ok(JwsAlgorithm::ES384),
+            "ES512" => Ok(JwsAlgorithm::ES512),
+            "RS256" => Ok(JwsAlgorithm::RS256),
+            "PS256" => Ok(JwsAlgorithm::PS256),
+            _ => Err(Error::new(
+                ErrorKind::Unimplemented,
+                &format!("unsupported JWS algorithm: {:?}", alg),
+            )),
+        }
+    }
+}
+
+/// JWS protected header (RFC 7515 section 4).
+///
+/// The `alg` parameter is always set from the header's `JwsAlgorithm`.
+/// Other parameters (e.g. ACME's `nonce` and `url`) can be added with
+/// `JwsHeader::param`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JwsHeader {
+    alg: JwsAlgorithm,
+    params: Map<String, Value>,
+}
+
+impl JwsHeader {
+    /// Create a new header for the given algorithm
+    pub fn new(alg: JwsAlgorithm) -> Self {
+        Self {
+            alg,
+            params: Map::new(),
+        }
+    }
+
+    /// Decode the header of a compact JWS without verifying its signature,
+    /// e.g. to use its `kid` to find the key to verify it with
+    pub fn from_compact(token: &str) -> Result<Self, Error> {
+        CompactJws::parse(token).map(|jws| jws.header)
+    }
+
+    /// Get the signature algorithm
+    pub fn algorithm(&self) -> JwsAlgorithm {
+        self.alg
+    }
+
+    /// Get the value of a header parameter
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        match name {
+            "alg" => None,
+            _ => self.params.get(name),
+        }
+    }
+
+    /// Get the key ID (`kid`), if present
+    pub fn key_id(&self) -> Option<&str> {
+        self.get("kid").and_then(Value::as_str)
+    }
+
+    /// Set the key ID (`kid`), e.g. to `Key::jwk_thumbprint`
+    pub fn kid<S: Into<String>>(self, kid: S) -> Self {
+        self.param("kid", kid.into())
+    }
+
+    /// Set the media type of the complete JWS (`typ`), e.g. `JWT`
+    pub fn typ<S: Into<String>>(self, typ: S) -> Self {
+        self.param("typ", typ.into())
+    }
+
+    /// Embed the public members of the given JWK (`jwk`), as used by ACME
+    /// requests for new accounts
+    pub fn jwk(self, jwk: &Jwk) -> Self {
+        let value = serde_json::to_value(jwk.to_public()).expect("JWK serialization failed");
+        self.param("jwk", value)
+    }
+
+    /// Set an arbitrary header parameter. Setting `alg` has no effect.
+    pub fn param<V: Into<Value>>(mut self, name: &str, value: V) -> Self {
+        if name != "alg" {
+            self.params.insert(name.to_owned(), value.into());
+        }
+
+        self
+    }
+
+    /// Serialize this header as JSON
+    fn to_json(&self) -> String {
+        let mut params = self.params.clone();
+        params.insert("alg".to_owned(), self.alg.as_str().into());
+        Value::Object(params).to_string()
+    }
+
+    /// Parse a JSON header
+    fn from_json(json: &[u8]) -> Result<Self, Error> {
+        let mut params = match serde_json::from_slice(json) {
+            Ok(Value::Object(params)) => params,
+            _ => return Err(invalid_jws("header is not a JSON object")),
+        };
+
+        // We don't understand any extensions, so must reject critical ones
+        if params.contains_key("crit") {
+            return Err(Error::new(
+                ErrorKind::Unimplemented,
+                "unsupported critical JWS header parameters",
+            ));
+        }
+
+        let alg = match params.remove("alg") {
+            Some(Value::String(alg)) => alg.parse()?,
+            _ => return Err(invalid_jws("missing alg header parameter")),
+        };
+
+        Ok(Self { alg, params })
+    }
+}
+
+/// Verified JWS: a header and payload whose signature has been checked
+#[derive(Clone, Debug)]
+pub struct Jws {
+    header: JwsHeader,
+    payload: Vec<u8>,
+}
+
+impl Jws {
+    /// Get the protected header
+    pub fn header(&self) -> &JwsHeader {
+        &self.header
+    }
+
+    /// Borrow the payload
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Parse the payload as a JWT claims set (i.e. a JSON object).
+    ///
+    /// Time-based claims like `exp` and `nbf` are not checked.
+    pub fn claims(&self) -> Result<Map<String, Value>, Error> {
+        match serde_json::from_slice(&self.payload) {
+            Ok(Value::Object(claims)) => Ok(claims),
+            _ => Err(invalid_jws("JWT claims set is not a JSON object")),
+        }
+    }
+
+    /// Convert into the payload
+    pub fn into_payload(self) -> Vec<u8> {
+        self.payload
+    }
+}
+
+/// Decoded (but not verified) compact JWS
+struct CompactJws<'a> {
+    header: JwsHeader,
+    signing_input: &'a str,
+    payload: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl<'a> CompactJws<'a> {
+    /// Parse a compact JWS, i.e. `header.payload.signature`
+    fn parse(token: &'a str) -> Result<Self, Error> {
+        let token = token.trim();
+        let (signing_input, signature) = token
+            .rsplit_once('.')
+            .ok_or_else(|| invalid_jws("not a compact JWS"))?;
+        let (header, payload) = signing_input
+            .split_once('.')
+            .filter(|(_, payload)| !payload.contains('.'))
+            .ok_or_else(|| invalid_jws("not a compact JWS"))?;
+
+        Ok(Self {
+            header: JwsHeader::from_json(&decode_part(header)?)?,
+            signing_input,
+            payload: decode_part(payload)?,
+            signature: decode_part(signature)?,
+        })
+    }
+
+    /// Verify the signature using the given function, which is passed the
+    /// signing input and signature
+    fn verify<F>(
+        self,
+        key_type: Option<AttrKeyType>,
+        curve: Option<EcCurve>,
+        verify: F,
+    ) -> Result<Jws, Error>
+    where
+        F: FnOnce(&[u8], &Signature) -> Result<bool, Error>,
+    {
+        let alg = self.header.alg;
+
+        if !alg.is_compatible(key_type, curve) {
+            return Err(Error::new(
+                ErrorKind::VerifyFailed,
+                &format!("JWS algorithm {} doesn't match key", alg),
+            ));
+        }
+
+        let signature = match alg.ec_curve() {
+            Some(curve) => Signature::from_fixed(alg.key_algorithm(), curve, &self.signature)
+                .map_err(|_| Error::new(ErrorKind::VerifyFailed, "invalid JWS signature"))?,
+            None => Signature::new(alg.key_algorithm(), self.signature),
+        };
+
+        match verify(self.signing_input.as_bytes(), &signature) {
+            Ok(true) => Ok(Jws {
+                header: self.header,
+                payload: self.payload,
+            }),
+            Ok(false) => Err(Error::new(ErrorKind::VerifyFailed, "invalid JWS signature")),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Key {
+    /// Sign the given payload with this private key, returning a compact
+    /// JWS, i.e. `header.payload.signature`.
+    ///
+    /// The header's algorithm must match the key: `ES256`, `ES384`, and
+    /// `ES512` require P-256, P-384, and P-521 keys respectively, and
+    /// `RS256` and `PS256` require RSA keys.
+    pub fn sign_jws(&self, header: &JwsHeader, payload: &[u8]) -> Result<String, Error> {
+        let alg = header.alg;
+
+        if !alg.is_compatible(self.key_type(), self.ec_curve()) {
+            return Err(Error::new(
+                ErrorKind::Param,
+                &format!("JWS algorithm {} doesn't match key", alg),
+            ));
+        }
+
+        let signing_input = format!(
+            "{}.{}",
+            Base64UrlUnpadded::encode_string(header.to_json().as_bytes()),
+            Base64UrlUnpadded::encode_string(payload)
+        );
+
+        let signature = self.sign(alg.key_algorithm(), signing_input.as_bytes())?;
+        let signature = match alg.ec_curve() {
+            Some(curve) => signature.to_fixed(curve)?,
+            None => signature.into_vec(),
+        };
+
+        Ok(format!(
+            "{}.{}",
+            signing_input,
+            Base64UrlUnpadded::encode_string(&signature)
+        ))
+    }
+
+    /// Sign the given claims set with this private key, returning a JWT.
+    /// The `typ` header parameter is set to `JWT` unless the header
+    /// already contains one.
+    pub fn sign_jwt(
+        &self,
+        header: &JwsHeader,
+        claims: &Map<String, Value>,
+    ) -> Result<String, Error> {
+        let mut header = header.clone();
+
+        if header.get("typ").is_none() {
+            header = header.typ("JWT");
+        }
+
+        let payload = serde_json::to_vec(claims).expect("JWT serialization failed");
+        self.sign_jws(&header, &payload)
+    }
+
+    /// Verify a compact JWS (or JWT) using this key's public key, returning
+    /// its header and payload if the signature is valid.
+    ///
+    /// Returns an error with a kind of `ErrorKind::VerifyFailed` if the
+    /// signature is invalid or the JWS's algorithm doesn't match the key.
+    pub fn verify_jws(&self, token: &str) -> Result<Jws, Error> {
+        let jws = CompactJws::parse(token)?;
+        let public_key = self.public_key()?;
+
+        jws.verify(
+            public_key.key_type(),
+            public_key.ec_curve(),
+            |signed_data, signature| public_key.verify(signed_data, signature),
+        )
+    }
+}
+
+impl Jwk {
+    /// Verify a compact JWS (or JWT) using this JWK's public key, returning
+    /// its header and payload if the signature is valid.
+    ///
+    /// Unlike `Key::verify_jws`, this doesn't use a keychain, so it can be
+    /// used to check tokens on any platform.
+    pub fn verify_jws(&self, token: &str) -> Result<Jws, Error> {
+        let jws = CompactJws::parse(token)?;
+        let public_key = jwk::decode(self)?.public_key();
+        let curve = EcCurve::from_key_type(public_key.key_type(), public_key.key_size_in_bits());
+
+        jws.verify(
+            Some(public_key.key_type()),
+            curve,
+            |signed_data, signature| {
+                public_key
+                    .verify(signature.algorithm(), signed_data, signature.as_bytes())
+                    .map(|()| true)
+            },
+        )
+    }
+}
+
+/// Decode a base64url-encoded part of a compact JWS
+fn decode_part(part: &str) -> Result<Vec<u8>, Error> {
+    Base64UrlUnpadded::decode_vec(part).map_err(|_| invalid_jws("invalid base64url"))
+}
+
+/// Error for malformed JWSes
+fn invalid_jws(reason: &str) -> Error {
+    Error::new(ErrorKind::Decode, &format!("invalid JWS: {}", reason))
+}
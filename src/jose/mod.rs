@@ -0,0 +1,11 @@
+//! JSON Object Signing and Encryption (JOSE) using keychain keys.
+//!
+//! Supports compact JSON Web Signatures (JWS, RFC 7515), JSON Web Tokens
+//! (JWT, RFC 7519), and JSON Web Encryption (JWE, RFC 7516) using the
+//! algorithms from RFC 7518. Keys are described using the `Jwk` type.
+
+mod jwe;
+mod jws;
+
+pub use self::jwe::{Jwe, JweAlgorithm, JweEncryption, JweHeader};
+pub use self::jws::{Jws, JwsAlgorithm, JwsHeader};
@@ -1,12 +1,15 @@
+#[cfg(target_os = "macos")]
 use crate::ffi::*;
+#[cfg(target_os = "macos")]
 use core_foundation::{base::TCFType, string::CFString};
+use serde::{Deserialize, Serialize};
 
 /// Classes of keychain items supported by Keychain Services
 /// (not to be confused with `SecAttrClass` or `SecType`)
 ///
 /// Wrapper for the `kSecClass` attribute key. See:
 /// <https://developer.apple.com/documentation/security/ksecclass>
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Deserialize, Serialize)]
 pub enum Class {
     /// Generic password items.
     ///
@@ -41,6 +44,7 @@ pub enum Class {
 
 impl Class {
     /// Attempt to look up an attribute kind by its `FourCharacterCode`.
+    #[cfg(target_os = "macos")]
     // TODO: cache `FourCharacterCodes`? e.g. as `lazy_static`
     pub(crate) fn from_tag(tag: FourCharacterCode) -> Option<Self> {
         let result = unsafe {
@@ -61,8 +65,10 @@ impl Class {
 
         Some(result)
     }
+
     /// Get `CFString` containing the `kSecClass` dictionary value for
     /// this particular `SecClass`.
+    #[cfg(target_os = "macos")]
     pub fn as_CFString(self) -> CFString {
         unsafe {
             CFString::wrap_under_get_rule(match self {
@@ -76,6 +82,7 @@ impl Class {
     }
 }
 
+#[cfg(target_os = "macos")]
 impl From<FourCharacterCode> for Class {
     fn from(tag: FourCharacterCode) -> Self {
         Self::from_tag(tag).unwrap_or_else(|| panic!("invalid SecItemClass tag: {:?}", tag))
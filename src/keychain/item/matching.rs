@@ -0,0 +1,182 @@
+//! Pure Rust evaluation of `Query`s against item attributes, following the
+//! matching rules of `SecItemCopyMatching`.
+//!
+//! See "Search Attribute Keys and Values":
+//! <https://developer.apple.com/documentation/security/keychain_services/keychain_items/search_attribute_keys_and_values>
+
+use super::{MatchLimit, Query};
+use crate::attr::{AttrKind, AttrValue, AttributeMap};
+use std::fmt::{self, Display};
+
+/// Value of `kSecAttrSynchronizableAny`, which matches items regardless of
+/// whether or not they are synchronizable.
+pub(crate) const SYNCHRONIZABLE_ANY: &str = "syna";
+
+/// Explanation of why an item did not match a `Query`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Mismatch {
+    /// The query requires an attribute the item doesn't have
+    Missing {
+        /// Attribute the query matches on
+        kind: AttrKind,
+
+        /// Value the query requires
+        expected: AttrValue,
+    },
+
+    /// The item has a different value for an attribute than the query requires
+    Different {
+        /// Attribute the query matches on
+        kind: AttrKind,
+
+        /// Value the query requires
+        expected: AttrValue,
+
+        /// Value the item actually has
+        actual: AttrValue,
+    },
+}
+
+impl Mismatch {
+    /// Get the attribute which didn't match
+    pub fn kind(&self) -> AttrKind {
+        match self {
+            Mismatch::Missing { kind, .. } | Mismatch::Different { kind, .. } => *kind,
+        }
+    }
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Missing { kind, expected } => {
+                write!(
+                    f,
+                    "{:?}: expected {:?} but attribute is absent",
+                    kind, expected
+                )
+            }
+            Mismatch::Different {
+                kind,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{:?}: expected {:?} but found {:?}",
+                kind, expected, actual
+            ),
+        }
+    }
+}
+
+impl Query {
+    /// Does an item with the given attributes match this query?
+    pub fn matches(&self, attrs: &AttributeMap) -> bool {
+        self.explain(attrs).is_empty()
+    }
+
+    /// Explain why an item with the given attributes doesn't match this
+    /// query. Returns an empty `Vec` if the item matches.
+    ///
+    /// Attributes omitted from the query match any value, with the exception
+    /// of `kSecAttrSynchronizable`, which only matches non-synchronizable
+    /// items unless explicitly requested (see `Query::synchronizable_any`).
+    pub fn explain(&self, attrs: &AttributeMap) -> Vec<Mismatch> {
+        let mut mismatches = vec![];
+
+        for (kind, expected) in self.attributes() {
+            if let Some(mismatch) = compare(*kind, expected, attrs.get(*kind)) {
+                mismatches.push(mismatch);
+            }
+        }
+
+        if !self.attributes().contains(AttrKind::Synchronizable) {
+            let expected = AttrValue::Bool(false);
+
+            if let Some(mismatch) = compare(
+                AttrKind::Synchronizable,
+                &expected,
+                Some(attrs.get(AttrKind::Synchronizable).unwrap_or(&expected)),
+            ) {
+                mismatches.push(mismatch);
+            }
+        }
+
+        mismatches
+    }
+
+    /// Find the items among `candidates` which match this query, returning
+    /// their indices in order. At most `limit` items are returned, so e.g.
+    /// `MatchLimit::One` selects the first match.
+    pub fn select<'a, I>(&self, candidates: I, limit: MatchLimit) -> Vec<usize>
+    where
+        I: IntoIterator<Item = &'a AttributeMap>,
+    {
+        let max = match limit {
+            MatchLimit::One => 1,
+            MatchLimit::Number(n) => n,
+            MatchLimit::All => usize::MAX,
+        };
+
+        candidates
+            .into_iter()
+            .enumerate()
+            .filter(|(_, attrs)| self.matches(attrs))
+            .map(|(i, _)| i)
+            .take(max)
+            .collect()
+    }
+}
+
+/// Compare an attribute value in a query against an item's value
+fn compare(kind: AttrKind, expected: &AttrValue, actual: Option<&AttrValue>) -> Option<Mismatch> {
+    if kind == AttrKind::Synchronizable && expected.as_str() == Some(SYNCHRONIZABLE_ANY) {
+        return None;
+    }
+
+    let actual = match actual {
+        Some(actual) => actual,
+        None => {
+            return Some(Mismatch::Missing {
+                kind,
+                expected: expected.clone(),
+            })
+        }
+    };
+
+    if values_match(expected, actual) {
+        None
+    } else {
+        Some(Mismatch::Different {
+            kind,
+            expected: expected.clone(),
+            actual: actual.clone(),
+        })
+    }
+}
+
+/// Compare two attribute values, allowing for the different ways Keychain
+/// Services may encode the same value (e.g. `CFBoolean` vs `CFNumber`, or
+/// `CFString` vs UTF-8 `CFData` for attributes like `kSecAttrAccount`)
+fn values_match(expected: &AttrValue, actual: &AttrValue) -> bool {
+    match (expected, actual) {
+        (AttrValue::Bool(b), AttrValue::Integer(n))
+        | (AttrValue::Integer(n), AttrValue::Bool(b)) => *b == (*n != 0),
+        (AttrValue::String(s), other) | (other, AttrValue::String(s)) if other.matches_str(s) => {
+            true
+        }
+        (AttrValue::String(s), AttrValue::Data(d)) | (AttrValue::Data(d), AttrValue::String(s)) => {
+            s.as_bytes() == d.as_slice()
+        }
+        (AttrValue::Dictionary(expected), AttrValue::Dictionary(actual)) => {
+            expected.len() == actual.len()
+                && expected.iter().all(|(kind, value)| {
+                    actual
+                        .get(*kind)
+                        .map(|other| values_match(value, other))
+                        .unwrap_or(false)
+                })
+        }
+        _ => expected == actual,
+    }
+}
@@ -1,101 +1,60 @@
 //! Items stored in a keychain (e.g. certificates, keys, passwords)
 
 mod class;
+mod matching;
 mod password;
 mod query;
 
-pub use self::{class::*, password::*, query::*};
-use crate::{attr::AttrKind, error::*, ffi::*};
-use core_foundation::base::TCFType;
-use std::{mem, os::raw::c_void, ptr, slice};
-
-declare_TCFType! {
-    /// Items stored in the keychain.
-    ///
-    /// Wrapper for the `SecKeychainItem`/`SecKeychainItemRef` types:
-    /// <https://developer.apple.com/documentation/security/seckeychainitemref>
-    Item, ItemRef
+pub use self::{class::*, matching::Mismatch, password::*, query::*};
+use crate::{
+    attr::{AttrKind, AttrValue, AttributeMap},
+    backend::{Handle, KeychainBackend},
+    error::*,
+};
+use std::{
+    fmt::{self, Debug},
+    sync::Arc,
+};
+
+/// Items stored in the keychain.
+///
+/// Wrapper for the `SecKeychainItem`/`SecKeychainItemRef` types:
+/// <https://developer.apple.com/documentation/security/seckeychainitemref>
+#[derive(Clone)]
+pub struct Item {
+    backend: Arc<dyn KeychainBackend>,
+    handle: Handle,
+    class: Class,
 }
 
-impl_TCFType!(Item, ItemRef, SecKeychainItemGetTypeID);
-
 impl Item {
+    /// Create an `Item` from a handle returned by a `KeychainBackend`
+    pub(crate) fn new(backend: Arc<dyn KeychainBackend>, handle: Handle, class: Class) -> Self {
+        Item {
+            backend,
+            handle,
+            class,
+        }
+    }
+
     /// Get the class of this item
     pub fn class(&self) -> Class {
-        let mut result = FourCharacterCode::from(b"NULL");
-
-        Error::maybe_from_OSStatus(unsafe {
-            SecKeychainItemCopyContent(
-                self.as_concrete_TypeRef(),
-                &mut result,
-                ptr::null_mut(),
-                ptr::null_mut(),
-                ptr::null_mut(),
-            )
-        })
-        .unwrap();
-
-        result.into()
+        self.class
     }
 
     /// Get the raw data associated with this keychain item
     pub(crate) fn data(&self) -> Result<Vec<u8>, Error> {
-        let result_ptr: *mut u8 = ptr::null_mut();
-        let mut length = 0;
-
-        let status = unsafe {
-            SecKeychainItemCopyContent(
-                self.as_concrete_TypeRef(),
-                ptr::null_mut(),
-                ptr::null_mut(),
-                &mut length,
-                &mut (result_ptr as *mut c_void),
-            )
-        };
-
-        if let Some(e) = Error::maybe_from_OSStatus(status) {
-            Err(e)
-        } else if result_ptr.is_null() {
-            Err(Error::new(
-                ErrorKind::MissingEntitlement,
-                "SecKeychainItemCopyContent refused to return data",
-            ))
-        } else {
-            // Copy the data into a vector we've allocated
-            let result = Vec::from(unsafe { slice::from_raw_parts(result_ptr, length as usize) });
-
-            // Free the original data
-            Error::maybe_from_OSStatus(unsafe {
-                SecKeychainItemFreeContent(ptr::null_mut(), result_ptr as *mut c_void)
-            })
-            .unwrap();
-
-            Ok(result)
-        }
+        self.backend.data(&self.handle)
     }
 
-    /// Get an attribute of this item as a `String`.
-    // TODO: handle attribute types other than `String`?
-    pub(crate) fn attribute(&self, attr_kind: AttrKind) -> Result<String, Error> {
-        let mut attrs = unsafe { self.attributes() }?;
-
-        let result = attrs
-            .iter()
-            .find(|attr| {
-                if let Some(kind) = AttrKind::from_tag(attr.tag()) {
-                    kind == attr_kind
-                } else {
-                    false
-                }
-            })
-            .map(|attr| String::from_utf8(attr.data().unwrap().into()).unwrap());
-
-        Error::maybe_from_OSStatus(unsafe {
-            SecKeychainItemFreeContent(&mut attrs, ptr::null_mut())
-        })
-        .unwrap();
+    /// Get all of the attributes of this item
+    pub fn attributes(&self) -> Result<AttributeMap, Error> {
+        self.backend.attributes(&self.handle)
+    }
 
-        result.ok_or_else(|| {
+    /// Get a particular attribute of this item
+    pub fn attribute(&self, attr_kind: AttrKind) -> Result<AttrValue, Error> {
+        self.attributes()?.remove(attr_kind).ok_or_else(|| {
             Error::new(
                 ErrorKind::NoSuchAttr,
                 &format!("missing attribute {:?}", attr_kind),
@@ -103,23 +62,24 @@ impl Item {
         })
     }
 
-    /// Get the attributes of a keychain item. Note that this does not handle
-    /// deallocating the attribute list so the caller must take care to do so.
-    unsafe fn attributes(&self) -> Result<SecKeychainAttributeList, Error> {
-        let mut result: SecKeychainAttributeList = mem::zeroed();
-
-        let status = SecKeychainItemCopyContent(
-            self.as_concrete_TypeRef(),
-            ptr::null_mut(),
-            &mut result,
-            ptr::null_mut(),
-            ptr::null_mut(),
-        );
-
-        if let Some(e) = Error::maybe_from_OSStatus(status) {
-            Err(e)
-        } else {
-            Ok(result)
+    /// Get an attribute of this item as a `String`. Some attributes (e.g.
+    /// account names) may be stored as UTF-8 encoded data.
+    pub(crate) fn string_attribute(&self, attr_kind: AttrKind) -> Result<String, Error> {
+        match self.attribute(attr_kind)? {
+            AttrValue::String(string) => Ok(string),
+            AttrValue::Data(bytes) => {
+                String::from_utf8(bytes).map_err(|e| Error::new(ErrorKind::Param, &e))
+            }
+            other => Err(Error::new(
+                ErrorKind::Param,
+                &format!("attribute {:?} is not a string: {:?}", attr_kind, other),
+            )),
         }
     }
 }
+
+impl Debug for Item {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecKeychainItem {{ class: {:?} }}", self.class)
+    }
+}
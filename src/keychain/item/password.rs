@@ -1,4 +1,4 @@
-use crate::{attr::*, dictionary::DictionaryBuilder, error::Error, ffi::*, keychain::*};
+use crate::{attr::*, error::Error, keychain::*};
 use std::str;
 use zeroize::Zeroize;
 
@@ -13,33 +13,41 @@ impl GenericPassword {
         account: &str,
         password: &str,
     ) -> Result<Self, Error> {
-        let mut attrs = DictionaryBuilder::new();
-        attrs.add_class(item::Class::GenericPassword);
-        attrs.add_string(AttrKind::Service, service);
-        attrs.add_string(AttrKind::Account, account);
-        attrs.add_string(unsafe { kSecValueData }, password);
-
-        Ok(GenericPassword(keychain.add_item(attrs)?))
+        let mut attrs = AttributeMap::new();
+        attrs.insert(AttrKind::Service, service);
+        attrs.insert(AttrKind::Account, account);
+
+        Ok(GenericPassword(keychain.add_item(
+            Class::GenericPassword,
+            attrs,
+            password.as_bytes(),
+        )?))
     }
 
     /// Find a generic password in the given keychain.
     pub fn find(keychain: &Keychain, service: &str, account: &str) -> Result<Self, Error> {
-        let mut attrs = DictionaryBuilder::new();
-        attrs.add_class(item::Class::GenericPassword);
-        attrs.add_string(AttrKind::Service, service);
-        attrs.add_string(AttrKind::Account, account);
+        let mut attrs = AttributeMap::new();
+        attrs.insert(AttrKind::Service, service);
+        attrs.insert(AttrKind::Account, account);
 
-        Ok(GenericPassword(keychain.find_item(attrs)?))
+        Ok(GenericPassword(
+            keychain.find_item(Class::GenericPassword, attrs.into())?,
+        ))
     }
 
     /// Get the account this password is associated with
     pub fn account(&self) -> Result<String, Error> {
-        self.0.attribute(AttrKind::Account)
+        self.0.string_attribute(AttrKind::Account)
     }
 
     /// Get the service this password is associated with
     pub fn service(&self) -> Result<String, Error> {
-        self.0.attribute(AttrKind::Service)
+        self.0.string_attribute(AttrKind::Service)
+    }
+
+    /// Get all of the attributes of this password item
+    pub fn attributes(&self) -> Result<AttributeMap, Error> {
+        self.0.attributes()
     }
 
     /// Get the raw password value
@@ -59,13 +67,15 @@ impl InternetPassword {
         account: &str,
         password: &str,
     ) -> Result<Self, Error> {
-        let mut attrs = DictionaryBuilder::new();
-        attrs.add_class(item::Class::InternetPassword);
-        attrs.add_string(AttrKind::Server, server);
-        attrs.add_string(AttrKind::Account, account);
-        attrs.add_string(unsafe { kSecValueData }, password);
-
-        Ok(InternetPassword(keychain.add_item(attrs)?))
+        let mut attrs = AttributeMap::new();
+        attrs.insert(AttrKind::Server, server);
+        attrs.insert(AttrKind::Account, account);
+
+        Ok(InternetPassword(keychain.add_item(
+            Class::InternetPassword,
+            attrs,
+            password.as_bytes(),
+        )?))
     }
 
     /// Find an Internet password in the given keychain.
@@ -75,26 +85,32 @@ impl InternetPassword {
         account: &str,
         protocol: Option<AttrProtocol>,
     ) -> Result<Self, Error> {
-        let mut attrs = DictionaryBuilder::new();
-        attrs.add_class(item::Class::InternetPassword);
-        attrs.add_string(AttrKind::Server, server);
-        attrs.add_string(AttrKind::Account, account);
+        let mut attrs = AttributeMap::new();
+        attrs.insert(AttrKind::Server, server);
+        attrs.insert(AttrKind::Account, account);
 
         if let Some(proto) = protocol {
-            attrs.add_attr(&proto);
+            attrs.insert_attr(&proto);
         }
 
-        Ok(InternetPassword(keychain.find_item(attrs)?))
+        Ok(InternetPassword(
+            keychain.find_item(Class::InternetPassword, attrs.into())?,
+        ))
     }
 
     /// Get the account this password is associated with
     pub fn account(&self) -> Result<String, Error> {
-        self.0.attribute(AttrKind::Account)
+        self.0.string_attribute(AttrKind::Account)
     }
 
     /// Get the service this password is associated with
     pub fn server(&self) -> Result<String, Error> {
-        self.0.attribute(AttrKind::Server)
+        self.0.string_attribute(AttrKind::Server)
+    }
+
+    /// Get all of the attributes of this password item
+    pub fn attributes(&self) -> Result<AttributeMap, Error> {
+        self.0.attributes()
     }
 
     /// Get the raw password value
@@ -1,6 +1,10 @@
 //! Query the keychain, looking for particular items
 
-use crate::{attr::*, dictionary::DictionaryBuilder, ffi::*};
+use super::matching;
+use crate::attr::*;
+#[cfg(target_os = "macos")]
+use crate::{dictionary::DictionaryBuilder, ffi::*};
+#[cfg(target_os = "macos")]
 use core_foundation::{
     base::{CFType, TCFType},
     number::CFNumber,
@@ -36,6 +40,7 @@ pub enum MatchLimit {
 impl MatchLimit {
     /// Get `CFType` containing the `kSecMatchLimit` dictionary value for
     /// this particular `SecMatchLimit`.
+    #[cfg(target_os = "macos")]
     pub fn as_CFType(self) -> CFType {
         match self {
             MatchLimit::One => {
@@ -53,8 +58,11 @@ impl MatchLimit {
 ///
 /// For more information, see "Search Attribute Keys and Values":
 /// <https://developer.apple.com/documentation/security/keychain_services/keychain_items/search_attribute_keys_and_values>
-#[derive(Default, Debug)]
-pub struct Query(DictionaryBuilder);
+#[derive(Clone, Default, Debug)]
+pub struct Query {
+    attrs: AttributeMap,
+    operation_prompt: Option<String>,
+}
 
 impl Query {
     /// Create a new keychain item query builder
@@ -72,7 +80,7 @@ impl Query {
     /// Wrapper for the `kSecAttrApplicationLabel` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrlabel>
     pub fn application_label<L: Into<AttrApplicationLabel>>(mut self, label: L) -> Self {
-        self.0.add_attr(&label.into());
+        self.attrs.insert_attr(&label.into());
         self
     }
 
@@ -84,7 +92,7 @@ impl Query {
     where
         T: Into<AttrApplicationTag>,
     {
-        self.0.add_attr(&tag.into());
+        self.attrs.insert_attr(&tag.into());
         self
     }
 
@@ -93,7 +101,7 @@ impl Query {
     /// Wrapper for the `kSecAttrKeyClass` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrkeyclass>
     pub fn key_class(mut self, key_class: AttrKeyClass) -> Self {
-        self.0.add_attr(&key_class);
+        self.attrs.insert_attr(&key_class);
         self
     }
 
@@ -102,7 +110,7 @@ impl Query {
     /// Wrapper for the `kSecAttrKeyType` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrkeytype>
     pub fn key_type(mut self, key_type: AttrKeyType) -> Self {
-        self.0.add_attr(&key_type);
+        self.attrs.insert_attr(&key_type);
         self
     }
 
@@ -111,7 +119,7 @@ impl Query {
     /// Wrapper for the `kSecAttrLabel` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrlabel>
     pub fn label<L: Into<AttrLabel>>(mut self, label: L) -> Self {
-        self.0.add_attr(&label.into());
+        self.attrs.insert_attr(&label.into());
         self
     }
 
@@ -120,7 +128,7 @@ impl Query {
     /// Wrapper for the `kSecAttrIsPermanent` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrispermanent>
     pub fn permanent(mut self, value: bool) -> Self {
-        self.0.add_boolean(AttrKind::Permanent, value);
+        self.attrs.insert(AttrKind::Permanent, value);
         self
     }
 
@@ -129,7 +137,18 @@ impl Query {
     /// Wrapper for the `kSecAttrSynchronizable` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrsynchronizable>
     pub fn synchronizable(mut self, value: bool) -> Self {
-        self.0.add_boolean(AttrKind::Synchronizable, value);
+        self.attrs.insert(AttrKind::Synchronizable, value);
+        self
+    }
+
+    /// Query for items regardless of whether or not they're synchronizable.
+    /// By default, queries only match items which are not synchronizable.
+    ///
+    /// Wrapper for the `kSecAttrSynchronizableAny` attribute value. See:
+    /// <https://developer.apple.com/documentation/security/ksecattrsynchronizableany>
+    pub fn synchronizable_any(mut self) -> Self {
+        self.attrs
+            .insert(AttrKind::Synchronizable, matching::SYNCHRONIZABLE_ANY);
         self
     }
 
@@ -138,7 +157,7 @@ impl Query {
     /// Wrapper for the `kSecAttrIsSensitive` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrissensitive>
     pub fn sensitive(mut self, value: bool) -> Self {
-        self.0.add_boolean(AttrKind::Sensitive, value);
+        self.attrs.insert(AttrKind::Sensitive, value);
         self
     }
 
@@ -148,7 +167,7 @@ impl Query {
     /// Wrapper for the `kSecAttrTokenID` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrtokenid>
     pub fn token_id(mut self, value: AttrTokenId) -> Self {
-        self.0.add_attr(&value);
+        self.attrs.insert_attr(&value);
         self
     }
 
@@ -158,13 +177,41 @@ impl Query {
     /// Wrapper for the `kSecUseOperationPrompt`. See:
     /// <https://developer.apple.com/documentation/security/ksecuseoperationprompt>
     pub fn use_operation_prompt(mut self, value: &str) -> Self {
-        self.0.add_string(unsafe { kSecUseOperationPrompt }, value);
+        self.operation_prompt = Some(value.to_owned());
         self
     }
+
+    /// Get the attributes this query matches on
+    pub fn attributes(&self) -> &AttributeMap {
+        &self.attrs
+    }
+
+    /// Get the custom prompt to display when using keys returned from this
+    /// query (if any)
+    #[cfg(target_os = "macos")]
+    pub(crate) fn operation_prompt(&self) -> Option<&str> {
+        self.operation_prompt.as_ref().map(AsRef::as_ref)
+    }
+}
+
+impl From<AttributeMap> for Query {
+    fn from(attrs: AttributeMap) -> Query {
+        Query {
+            attrs,
+            operation_prompt: None,
+        }
+    }
 }
 
-impl From<Query> for DictionaryBuilder {
-    fn from(params: Query) -> DictionaryBuilder {
-        params.0
+#[cfg(target_os = "macos")]
+impl<'a> From<&'a Query> for DictionaryBuilder {
+    fn from(query: &Query) -> DictionaryBuilder {
+        let mut result = DictionaryBuilder::from(query.attributes());
+
+        if let Some(prompt) = query.operation_prompt() {
+            result.add_string(unsafe { kSecUseOperationPrompt }, prompt);
+        }
+
+        result
     }
 }
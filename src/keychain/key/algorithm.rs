@@ -1,11 +1,21 @@
+use crate::error::{Error, ErrorKind};
+#[cfg(target_os = "macos")]
 use crate::ffi::*;
+#[cfg(target_os = "macos")]
 use core_foundation::{base::TCFType, string::CFString};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+/// Prefix of the names of the `kSecKeyAlgorithm*` constants
+const NAME_PREFIX: &str = "kSecKeyAlgorithm";
 
 /// Cryptographic algorithms for use with keys stored in the keychain.
 ///
 /// Wrapper for `SecKeyAlgorithm`. See:
 /// <https://developer.apple.com/documentation/security/seckeyalgorithm>
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 pub enum KeyAlgorithm {
     /// Elliptic Curve Encryption Standard X963
     ECIESEncryptionStandardX963SHA1AESGCM,
@@ -238,6 +248,7 @@ pub enum KeyAlgorithm {
 impl KeyAlgorithm {
     /// Get `CFString` containing the `kSecKeyAlgorithm` dictionary value for
     /// a particular cryptographic algorithm.
+    #[cfg(target_os = "macos")]
     pub fn as_CFString(self) -> CFString {
         unsafe {
             CFString::wrap_under_get_rule(match self {
@@ -449,3 +460,28 @@ impl KeyAlgorithm {
         }
     }
 }
+
+impl Display for KeyAlgorithm {
+    /// Format this algorithm as the name of the corresponding
+    /// `kSecKeyAlgorithm*` constant, e.g. `kSecKeyAlgorithmRSAEncryptionPKCS1`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Variants are named after the constants, less the prefix
+        write!(f, "{}{:?}", NAME_PREFIX, self)
+    }
+}
+
+impl FromStr for KeyAlgorithm {
+    type Err = Error;
+
+    /// Parse the name of a `kSecKeyAlgorithm*` constant. The
+    /// `kSecKeyAlgorithm` prefix is optional.
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let name = s.strip_prefix(NAME_PREFIX).unwrap_or(s);
+
+        KeyAlgorithm::all()
+            .iter()
+            .find(|alg| format!("{:?}", alg) == name)
+            .cloned()
+            .ok_or_else(|| Error::new(ErrorKind::Param, &format!("unknown key algorithm: {:?}", s)))
+    }
+}
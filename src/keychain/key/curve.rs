@@ -0,0 +1,55 @@
+//! Elliptic curves supported by Keychain Services
+
+use crate::attr::AttrKeyType;
+
+/// NIST elliptic curves used by `AttrKeyType::EcSecPrimeRandom` keys.
+///
+/// Keychain Services doesn't identify curves explicitly: the curve of an
+/// elliptic curve key is implied by its size in bits (i.e.
+/// `kSecAttrKeySizeInBits`).
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum EcCurve {
+    /// NIST P-256 (a.k.a. secp256r1, prime256v1)
+    P256,
+
+    /// NIST P-384 (a.k.a. secp384r1)
+    P384,
+
+    /// NIST P-521 (a.k.a. secp521r1)
+    P521,
+}
+
+impl EcCurve {
+    /// Get the curve for an elliptic curve key of the given size in bits
+    pub fn from_key_size(key_size: usize) -> Option<Self> {
+        match key_size {
+            256 => Some(EcCurve::P256),
+            384 => Some(EcCurve::P384),
+            521 => Some(EcCurve::P521),
+            _ => None,
+        }
+    }
+
+    /// Get the curve for a key with the given type and size in bits
+    pub fn from_key_type(key_type: AttrKeyType, key_size: usize) -> Option<Self> {
+        match key_type {
+            AttrKeyType::EcSecPrimeRandom => Self::from_key_size(key_size),
+            _ => None,
+        }
+    }
+
+    /// Get the size of keys on this curve in bits
+    pub fn key_size(self) -> usize {
+        match self {
+            EcCurve::P256 => 256,
+            EcCurve::P384 => 384,
+            EcCurve::P521 => 521,
+        }
+    }
+
+    /// Get the size of a serialized field element (i.e. coordinate or
+    /// scalar) for this curve in bytes
+    pub fn field_size(self) -> usize {
+        self.key_size().div_ceil(8)
+    }
+}
@@ -0,0 +1,696 @@
+//! Structured descriptions of the algorithms `KeyAlgorithm` identifies
+
+use super::{KeyAlgorithm, KeyOperation};
+use crate::{
+    attr::AttrKeyType,
+    error::{Error, ErrorKind},
+};
+
+/// Families of cryptographic algorithms supported by Keychain Services
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum AlgorithmFamily {
+    /// Elliptic Curve Digital Signature Algorithm
+    Ecdsa,
+
+    /// Elliptic Curve Diffie-Hellman key exchange
+    Ecdh,
+
+    /// Elliptic Curve Integrated Encryption Scheme
+    Ecies,
+
+    /// RSA signatures and encryption
+    Rsa,
+}
+
+/// Padding used by RSA algorithms, or the signature encoding used by ECDSA
+/// algorithms
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum AlgorithmPadding {
+    /// Raw RSA operation with no padding
+    Raw,
+
+    /// PKCS#1 v1.5 padding (RSASSA-PKCS1-v1_5 or RSAES-PKCS1-v1_5)
+    Pkcs1v15,
+
+    /// RSASSA-PSS padding
+    Pss,
+
+    /// RSAES-OAEP padding
+    Oaep,
+
+    /// RSAES-OAEP wrapped session key with an AES-GCM encrypted payload
+    OaepAesGcm,
+
+    /// ASN.1 DER encoded ECDSA signatures (X9.62)
+    X962,
+
+    /// Fixed-width `r || s` ECDSA signatures (RFC 4754)
+    Rfc4754,
+}
+
+/// Digest algorithms used by `KeyAlgorithm`s
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum DigestAlgorithm {
+    /// SHA-1
+    Sha1,
+
+    /// SHA-224
+    Sha224,
+
+    /// SHA-256
+    Sha256,
+
+    /// SHA-384
+    Sha384,
+
+    /// SHA-512
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    /// Size of this digest function's output in bytes
+    pub fn output_size(self) -> usize {
+        match self {
+            DigestAlgorithm::Sha1 => 20,
+            DigestAlgorithm::Sha224 => 28,
+            DigestAlgorithm::Sha256 => 32,
+            DigestAlgorithm::Sha384 => 48,
+            DigestAlgorithm::Sha512 => 64,
+        }
+    }
+}
+
+/// Input to a signature algorithm
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum SignatureInput {
+    /// Precomputed digest of the message
+    Digest,
+
+    /// Message which the algorithm hashes itself
+    Message,
+}
+
+/// Structured description of a `KeyAlgorithm`, decomposing it into its
+/// components.
+///
+/// Every `KeyAlgorithm` has a unique descriptor, so algorithms can also be
+/// constructed from their components using `KeyAlgorithm::from_descriptor`.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct AlgorithmDescriptor {
+    /// Family of the algorithm
+    pub family: AlgorithmFamily,
+
+    /// Padding (RSA) or signature encoding (ECDSA), if any
+    pub padding: Option<AlgorithmPadding>,
+
+    /// Digest algorithm: the hash for signatures and OAEP, or the X9.63 KDF
+    /// hash for ECDH and ECIES. `None` for algorithms which don't hash or
+    /// accept digests of any size.
+    pub digest: Option<DigestAlgorithm>,
+
+    /// Input to signature algorithms (`None` for other algorithms)
+    pub input: Option<SignatureInput>,
+
+    /// Does this ECDH or ECIES algorithm use cofactor Diffie-Hellman?
+    pub cofactor: bool,
+
+    /// Does this ECIES algorithm derive its AES-GCM IV using the KDF (as
+    /// opposed to using an all-zero IV)?
+    pub variable_iv: bool,
+}
+
+impl AlgorithmDescriptor {
+    /// Get the type of key this algorithm can be used with
+    pub fn key_type(&self) -> AttrKeyType {
+        match self.family {
+            AlgorithmFamily::Rsa => AttrKeyType::Rsa,
+            _ => AttrKeyType::EcSecPrimeRandom,
+        }
+    }
+
+    /// Get the operations this algorithm can be used for
+    pub fn operations(&self) -> &'static [KeyOperation] {
+        match self.family {
+            AlgorithmFamily::Ecdh => &[KeyOperation::KeyExchange],
+            AlgorithmFamily::Ecies => &[KeyOperation::Encrypt, KeyOperation::Decrypt],
+            AlgorithmFamily::Ecdsa => &[KeyOperation::Sign, KeyOperation::Verify],
+            AlgorithmFamily::Rsa if self.input.is_some() => {
+                &[KeyOperation::Sign, KeyOperation::Verify]
+            }
+            AlgorithmFamily::Rsa => &[KeyOperation::Encrypt, KeyOperation::Decrypt],
+        }
+    }
+}
+
+/// All of the `KeyAlgorithm` variants
+const ALGORITHMS: &[KeyAlgorithm] = &[
+    KeyAlgorithm::ECIESEncryptionStandardX963SHA1AESGCM,
+    KeyAlgorithm::ECIESEncryptionStandardX963SHA224AESGCM,
+    KeyAlgorithm::ECIESEncryptionStandardX963SHA256AESGCM,
+    KeyAlgorithm::ECIESEncryptionStandardX963SHA384AESGCM,
+    KeyAlgorithm::ECIESEncryptionStandardX963SHA512AESGCM,
+    KeyAlgorithm::ECIESEncryptionStandardVariableIVX963SHA224AESGCM,
+    KeyAlgorithm::ECIESEncryptionStandardVariableIVX963SHA256AESGCM,
+    KeyAlgorithm::ECIESEncryptionStandardVariableIVX963SHA384AESGCM,
+    KeyAlgorithm::ECIESEncryptionStandardVariableIVX963SHA512AESGCM,
+    KeyAlgorithm::ECIESEncryptionCofactorVariableIVX963SHA224AESGCM,
+    KeyAlgorithm::ECIESEncryptionCofactorVariableIVX963SHA256AESGCM,
+    KeyAlgorithm::ECIESEncryptionCofactorVariableIVX963SHA384AESGCM,
+    KeyAlgorithm::ECIESEncryptionCofactorVariableIVX963SHA512AESGCM,
+    KeyAlgorithm::ECIESEncryptionCofactorX963SHA1AESGCM,
+    KeyAlgorithm::ECIESEncryptionCofactorX963SHA224AESGCM,
+    KeyAlgorithm::ECIESEncryptionCofactorX963SHA256AESGCM,
+    KeyAlgorithm::ECIESEncryptionCofactorX963SHA384AESGCM,
+    KeyAlgorithm::ECIESEncryptionCofactorX963SHA512AESGCM,
+    KeyAlgorithm::ECDSASignatureRFC4754,
+    KeyAlgorithm::ECDSASignatureDigestX962,
+    KeyAlgorithm::ECDSASignatureDigestX962SHA1,
+    KeyAlgorithm::ECDSASignatureDigestX962SHA224,
+    KeyAlgorithm::ECDSASignatureDigestX962SHA256,
+    KeyAlgorithm::ECDSASignatureDigestX962SHA384,
+    KeyAlgorithm::ECDSASignatureDigestX962SHA512,
+    KeyAlgorithm::ECDSASignatureMessageX962SHA1,
+    KeyAlgorithm::ECDSASignatureMessageX962SHA224,
+    KeyAlgorithm::ECDSASignatureMessageX962SHA256,
+    KeyAlgorithm::ECDSASignatureMessageX962SHA384,
+    KeyAlgorithm::ECDSASignatureMessageX962SHA512,
+    KeyAlgorithm::ECDHKeyExchangeCofactor,
+    KeyAlgorithm::ECDHKeyExchangeStandard,
+    KeyAlgorithm::ECDHKeyExchangeCofactorX963SHA1,
+    KeyAlgorithm::ECDHKeyExchangeStandardX963SHA1,
+    KeyAlgorithm::ECDHKeyExchangeCofactorX963SHA224,
+    KeyAlgorithm::ECDHKeyExchangeCofactorX963SHA256,
+    KeyAlgorithm::ECDHKeyExchangeCofactorX963SHA384,
+    KeyAlgorithm::ECDHKeyExchangeCofactorX963SHA512,
+    KeyAlgorithm::ECDHKeyExchangeStandardX963SHA224,
+    KeyAlgorithm::ECDHKeyExchangeStandardX963SHA256,
+    KeyAlgorithm::ECDHKeyExchangeStandardX963SHA384,
+    KeyAlgorithm::ECDHKeyExchangeStandardX963SHA512,
+    KeyAlgorithm::RSAEncryptionRaw,
+    KeyAlgorithm::RSAEncryptionPKCS1,
+    KeyAlgorithm::RSAEncryptionOAEPSHA1,
+    KeyAlgorithm::RSAEncryptionOAEPSHA224,
+    KeyAlgorithm::RSAEncryptionOAEPSHA256,
+    KeyAlgorithm::RSAEncryptionOAEPSHA384,
+    KeyAlgorithm::RSAEncryptionOAEPSHA512,
+    KeyAlgorithm::RSAEncryptionOAEPSHA1AESGCM,
+    KeyAlgorithm::RSAEncryptionOAEPSHA224AESGCM,
+    KeyAlgorithm::RSAEncryptionOAEPSHA256AESGCM,
+    KeyAlgorithm::RSAEncryptionOAEPSHA384AESGCM,
+    KeyAlgorithm::RSAEncryptionOAEPSHA512AESGCM,
+    KeyAlgorithm::RSASignatureRaw,
+    KeyAlgorithm::RSASignatureDigestPKCS1v15Raw,
+    KeyAlgorithm::RSASignatureDigestPKCS1v15SHA1,
+    KeyAlgorithm::RSASignatureDigestPKCS1v15SHA224,
+    KeyAlgorithm::RSASignatureDigestPKCS1v15SHA256,
+    KeyAlgorithm::RSASignatureDigestPKCS1v15SHA384,
+    KeyAlgorithm::RSASignatureDigestPKCS1v15SHA512,
+    KeyAlgorithm::RSASignatureMessagePKCS1v15SHA1,
+    KeyAlgorithm::RSASignatureMessagePKCS1v15SHA224,
+    KeyAlgorithm::RSASignatureMessagePKCS1v15SHA256,
+    KeyAlgorithm::RSASignatureMessagePKCS1v15SHA384,
+    KeyAlgorithm::RSASignatureMessagePKCS1v15SHA512,
+    KeyAlgorithm::RSASignatureDigestPSSSHA1,
+    KeyAlgorithm::RSASignatureDigestPSSSHA224,
+    KeyAlgorithm::RSASignatureDigestPSSSHA256,
+    KeyAlgorithm::RSASignatureDigestPSSSHA384,
+    KeyAlgorithm::RSASignatureDigestPSSSHA512,
+    KeyAlgorithm::RSASignatureMessagePSSSHA1,
+    KeyAlgorithm::RSASignatureMessagePSSSHA224,
+    KeyAlgorithm::RSASignatureMessagePSSSHA256,
+    KeyAlgorithm::RSASignatureMessagePSSSHA384,
+    KeyAlgorithm::RSASignatureMessagePSSSHA512,
+];
+
+impl KeyAlgorithm {
+    /// Get all of the `KeyAlgorithm`s
+    pub fn all() -> &'static [KeyAlgorithm] {
+        ALGORITHMS
+    }
+
+    /// Find the algorithm with the given descriptor, if any
+    pub fn from_descriptor(descriptor: &AlgorithmDescriptor) -> Option<Self> {
+        ALGORITHMS
+            .iter()
+            .find(|alg| alg.descriptor() == *descriptor)
+            .cloned()
+    }
+
+    /// Decompose this algorithm into its components
+    pub fn descriptor(self) -> AlgorithmDescriptor {
+        use self::{AlgorithmFamily::*, AlgorithmPadding::*, DigestAlgorithm::*};
+
+        let (family, padding, digest, input, cofactor, variable_iv) = match self {
+            KeyAlgorithm::ECIESEncryptionStandardX963SHA1AESGCM => {
+                (Ecies, None, Some(Sha1), None, false, false)
+            }
+            KeyAlgorithm::ECIESEncryptionStandardX963SHA224AESGCM => {
+                (Ecies, None, Some(Sha224), None, false, false)
+            }
+            KeyAlgorithm::ECIESEncryptionStandardX963SHA256AESGCM => {
+                (Ecies, None, Some(Sha256), None, false, false)
+            }
+            KeyAlgorithm::ECIESEncryptionStandardX963SHA384AESGCM => {
+                (Ecies, None, Some(Sha384), None, false, false)
+            }
+            KeyAlgorithm::ECIESEncryptionStandardX963SHA512AESGCM => {
+                (Ecies, None, Some(Sha512), None, false, false)
+            }
+            KeyAlgorithm::ECIESEncryptionStandardVariableIVX963SHA224AESGCM => {
+                (Ecies, None, Some(Sha224), None, false, true)
+            }
+            KeyAlgorithm::ECIESEncryptionStandardVariableIVX963SHA256AESGCM => {
+                (Ecies, None, Some(Sha256), None, false, true)
+            }
+            KeyAlgorithm::ECIESEncryptionStandardVariableIVX963SHA384AESGCM => {
+                (Ecies, None, Some(Sha384), None, false, true)
+            }
+            KeyAlgorithm::ECIESEncryptionStandardVariableIVX963SHA512AESGCM => {
+                (Ecies, None, Some(Sha512), None, false, true)
+            }
+            KeyAlgorithm::ECIESEncryptionCofactorVariableIVX963SHA224AESGCM => {
+                (Ecies, None, Some(Sha224), None, true, true)
+            }
+            KeyAlgorithm::ECIESEncryptionCofactorVariableIVX963SHA256AESGCM => {
+                (Ecies, None, Some(Sha256), None, true, true)
+            }
+            KeyAlgorithm::ECIESEncryptionCofactorVariableIVX963SHA384AESGCM => {
+                (Ecies, None, Some(Sha384), None, true, true)
+            }
+            KeyAlgorithm::ECIESEncryptionCofactorVariableIVX963SHA512AESGCM => {
+                (Ecies, None, Some(Sha512), None, true, true)
+            }
+            KeyAlgorithm::ECIESEncryptionCofactorX963SHA1AESGCM => {
+                (Ecies, None, Some(Sha1), None, true, false)
+            }
+            KeyAlgorithm::ECIESEncryptionCofactorX963SHA224AESGCM => {
+                (Ecies, None, Some(Sha224), None, true, false)
+            }
+            KeyAlgorithm::ECIESEncryptionCofactorX963SHA256AESGCM => {
+                (Ecies, None, Some(Sha256), None, true, false)
+            }
+            KeyAlgorithm::ECIESEncryptionCofactorX963SHA384AESGCM => {
+                (Ecies, None, Some(Sha384), None, true, false)
+            }
+            KeyAlgorithm::ECIESEncryptionCofactorX963SHA512AESGCM => {
+                (Ecies, None, Some(Sha512), None, true, false)
+            }
+            KeyAlgorithm::ECDSASignatureRFC4754 => (
+                Ecdsa,
+                Some(Rfc4754),
+                None,
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::ECDSASignatureDigestX962 => (
+                Ecdsa,
+                Some(X962),
+                None,
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::ECDSASignatureDigestX962SHA1 => (
+                Ecdsa,
+                Some(X962),
+                Some(Sha1),
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::ECDSASignatureDigestX962SHA224 => (
+                Ecdsa,
+                Some(X962),
+                Some(Sha224),
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::ECDSASignatureDigestX962SHA256 => (
+                Ecdsa,
+                Some(X962),
+                Some(Sha256),
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::ECDSASignatureDigestX962SHA384 => (
+                Ecdsa,
+                Some(X962),
+                Some(Sha384),
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::ECDSASignatureDigestX962SHA512 => (
+                Ecdsa,
+                Some(X962),
+                Some(Sha512),
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::ECDSASignatureMessageX962SHA1 => (
+                Ecdsa,
+                Some(X962),
+                Some(Sha1),
+                Some(SignatureInput::Message),
+                false,
+                false,
+            ),
+            KeyAlgorithm::ECDSASignatureMessageX962SHA224 => (
+                Ecdsa,
+                Some(X962),
+                Some(Sha224),
+                Some(SignatureInput::Message),
+                false,
+                false,
+            ),
+            KeyAlgorithm::ECDSASignatureMessageX962SHA256 => (
+                Ecdsa,
+                Some(X962),
+                Some(Sha256),
+                Some(SignatureInput::Message),
+                false,
+                false,
+            ),
+            KeyAlgorithm::ECDSASignatureMessageX962SHA384 => (
+                Ecdsa,
+                Some(X962),
+                Some(Sha384),
+                Some(SignatureInput::Message),
+                false,
+                false,
+            ),
+            KeyAlgorithm::ECDSASignatureMessageX962SHA512 => (
+                Ecdsa,
+                Some(X962),
+                Some(Sha512),
+                Some(SignatureInput::Message),
+                false,
+                false,
+            ),
+            KeyAlgorithm::ECDHKeyExchangeCofactor => (Ecdh, None, None, None, true, false),
+            KeyAlgorithm::ECDHKeyExchangeStandard => (Ecdh, None, None, None, false, false),
+            KeyAlgorithm::ECDHKeyExchangeCofactorX963SHA1 => {
+                (Ecdh, None, Some(Sha1), None, true, false)
+            }
+            KeyAlgorithm::ECDHKeyExchangeStandardX963SHA1 => {
+                (Ecdh, None, Some(Sha1), None, false, false)
+            }
+            KeyAlgorithm::ECDHKeyExchangeCofactorX963SHA224 => {
+                (Ecdh, None, Some(Sha224), None, true, false)
+            }
+            KeyAlgorithm::ECDHKeyExchangeCofactorX963SHA256 => {
+                (Ecdh, None, Some(Sha256), None, true, false)
+            }
+            KeyAlgorithm::ECDHKeyExchangeCofactorX963SHA384 => {
+                (Ecdh, None, Some(Sha384), None, true, false)
+            }
+            KeyAlgorithm::ECDHKeyExchangeCofactorX963SHA512 => {
+                (Ecdh, None, Some(Sha512), None, true, false)
+            }
+            KeyAlgorithm::ECDHKeyExchangeStandardX963SHA224 => {
+                (Ecdh, None, Some(Sha224), None, false, false)
+            }
+            KeyAlgorithm::ECDHKeyExchangeStandardX963SHA256 => {
+                (Ecdh, None, Some(Sha256), None, false, false)
+            }
+            KeyAlgorithm::ECDHKeyExchangeStandardX963SHA384 => {
+                (Ecdh, None, Some(Sha384), None, false, false)
+            }
+            KeyAlgorithm::ECDHKeyExchangeStandardX963SHA512 => {
+                (Ecdh, None, Some(Sha512), None, false, false)
+            }
+            KeyAlgorithm::RSAEncryptionRaw => (Rsa, Some(Raw), None, None, false, false),
+            KeyAlgorithm::RSAEncryptionPKCS1 => (Rsa, Some(Pkcs1v15), None, None, false, false),
+            KeyAlgorithm::RSAEncryptionOAEPSHA1 => {
+                (Rsa, Some(Oaep), Some(Sha1), None, false, false)
+            }
+            KeyAlgorithm::RSAEncryptionOAEPSHA224 => {
+                (Rsa, Some(Oaep), Some(Sha224), None, false, false)
+            }
+            KeyAlgorithm::RSAEncryptionOAEPSHA256 => {
+                (Rsa, Some(Oaep), Some(Sha256), None, false, false)
+            }
+            KeyAlgorithm::RSAEncryptionOAEPSHA384 => {
+                (Rsa, Some(Oaep), Some(Sha384), None, false, false)
+            }
+            KeyAlgorithm::RSAEncryptionOAEPSHA512 => {
+                (Rsa, Some(Oaep), Some(Sha512), None, false, false)
+            }
+            KeyAlgorithm::RSAEncryptionOAEPSHA1AESGCM => {
+                (Rsa, Some(OaepAesGcm), Some(Sha1), None, false, false)
+            }
+            KeyAlgorithm::RSAEncryptionOAEPSHA224AESGCM => {
+                (Rsa, Some(OaepAesGcm), Some(Sha224), None, false, false)
+            }
+            KeyAlgorithm::RSAEncryptionOAEPSHA256AESGCM => {
+                (Rsa, Some(OaepAesGcm), Some(Sha256), None, false, false)
+            }
+            KeyAlgorithm::RSAEncryptionOAEPSHA384AESGCM => {
+                (Rsa, Some(OaepAesGcm), Some(Sha384), None, false, false)
+            }
+            KeyAlgorithm::RSAEncryptionOAEPSHA512AESGCM => {
+                (Rsa, Some(OaepAesGcm), Some(Sha512), None, false, false)
+            }
+            KeyAlgorithm::RSASignatureRaw => (
+                Rsa,
+                Some(Raw),
+                None,
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureDigestPKCS1v15Raw => (
+                Rsa,
+                Some(Pkcs1v15),
+                None,
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureDigestPKCS1v15SHA1 => (
+                Rsa,
+                Some(Pkcs1v15),
+                Some(Sha1),
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureDigestPKCS1v15SHA224 => (
+                Rsa,
+                Some(Pkcs1v15),
+                Some(Sha224),
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureDigestPKCS1v15SHA256 => (
+                Rsa,
+                Some(Pkcs1v15),
+                Some(Sha256),
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureDigestPKCS1v15SHA384 => (
+                Rsa,
+                Some(Pkcs1v15),
+                Some(Sha384),
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureDigestPKCS1v15SHA512 => (
+                Rsa,
+                Some(Pkcs1v15),
+                Some(Sha512),
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureMessagePKCS1v15SHA1 => (
+                Rsa,
+                Some(Pkcs1v15),
+                Some(Sha1),
+                Some(SignatureInput::Message),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureMessagePKCS1v15SHA224 => (
+                Rsa,
+                Some(Pkcs1v15),
+                Some(Sha224),
+                Some(SignatureInput::Message),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureMessagePKCS1v15SHA256 => (
+                Rsa,
+                Some(Pkcs1v15),
+                Some(Sha256),
+                Some(SignatureInput::Message),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureMessagePKCS1v15SHA384 => (
+                Rsa,
+                Some(Pkcs1v15),
+                Some(Sha384),
+                Some(SignatureInput::Message),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureMessagePKCS1v15SHA512 => (
+                Rsa,
+                Some(Pkcs1v15),
+                Some(Sha512),
+                Some(SignatureInput::Message),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureDigestPSSSHA1 => (
+                Rsa,
+                Some(Pss),
+                Some(Sha1),
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureDigestPSSSHA224 => (
+                Rsa,
+                Some(Pss),
+                Some(Sha224),
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureDigestPSSSHA256 => (
+                Rsa,
+                Some(Pss),
+                Some(Sha256),
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureDigestPSSSHA384 => (
+                Rsa,
+                Some(Pss),
+                Some(Sha384),
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureDigestPSSSHA512 => (
+                Rsa,
+                Some(Pss),
+                Some(Sha512),
+                Some(SignatureInput::Digest),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureMessagePSSSHA1 => (
+                Rsa,
+                Some(Pss),
+                Some(Sha1),
+                Some(SignatureInput::Message),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureMessagePSSSHA224 => (
+                Rsa,
+                Some(Pss),
+                Some(Sha224),
+                Some(SignatureInput::Message),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureMessagePSSSHA256 => (
+                Rsa,
+                Some(Pss),
+                Some(Sha256),
+                Some(SignatureInput::Message),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureMessagePSSSHA384 => (
+                Rsa,
+                Some(Pss),
+                Some(Sha384),
+                Some(SignatureInput::Message),
+                false,
+                false,
+            ),
+            KeyAlgorithm::RSASignatureMessagePSSSHA512 => (
+                Rsa,
+                Some(Pss),
+                Some(Sha512),
+                Some(SignatureInput::Message),
+                false,
+                false,
+            ),
+        };
+
+        AlgorithmDescriptor {
+            family,
+            padding,
+            digest,
+            input,
+            cofactor,
+            variable_iv,
+        }
+    }
+
+    /// Get the variant of this `Message` signature algorithm which signs a
+    /// precomputed digest of the message instead (e.g.
+    /// `ECDSASignatureDigestX962SHA256` for `ECDSASignatureMessageX962SHA256`).
+    ///
+    /// Both variants produce signatures in the same format.
+    pub fn digest_variant(self) -> Option<Self> {
+        let descriptor = self.descriptor();
+
+        if descriptor.input != Some(SignatureInput::Message) {
+            return None;
+        }
+
+        Self::from_descriptor(&AlgorithmDescriptor {
+            input: Some(SignatureInput::Digest),
+            ..descriptor
+        })
+    }
+
+    /// Can this algorithm be used for the given operation with keys of the
+    /// given type?
+    pub fn is_compatible(self, operation: KeyOperation, key_type: AttrKeyType) -> bool {
+        self.check_compatible(operation, key_type).is_ok()
+    }
+
+    /// Ensure this algorithm can be used for the given operation with keys
+    /// of the given type, returning an error with a kind of
+    /// `ErrorKind::Param` if it can't
+    pub fn check_compatible(
+        self,
+        operation: KeyOperation,
+        key_type: AttrKeyType,
+    ) -> Result<(), Error> {
+        let descriptor = self.descriptor();
+
+        if descriptor.key_type() != key_type {
+            return Err(Error::new(
+                ErrorKind::Param,
+                &format!("{} can't be used with {:?} keys", self, key_type),
+            ));
+        }
+
+        if !descriptor.operations().contains(&operation) {
+            return Err(Error::new(
+                ErrorKind::Param,
+                &format!("{} can't be used for {:?} operations", self, operation),
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -1,107 +1,113 @@
 //! Keys stored in macOS Keychain Services.
 
 mod algorithm;
+mod curve;
+mod descriptor;
 mod operation;
 mod pair;
 
-pub use self::{algorithm::*, operation::*, pair::*};
+pub use self::{algorithm::*, curve::*, descriptor::*, operation::*, pair::*};
 use crate::{
     attr::*,
+    backend::{self, Handle, KeychainBackend},
     ciphertext::Ciphertext,
-    dictionary::{Dictionary, DictionaryBuilder},
-    error::Error,
-    ffi::*,
+    error::{Error, ErrorKind},
     keychain::item::{self, MatchLimit},
     signature::Signature,
-};
-use core_foundation::{
-    base::{CFIndexConvertible, CFType, CFTypeRef, FromVoid, TCFType},
-    data::{CFData, CFDataRef},
-    error::CFErrorRef,
-    string::{CFString, CFStringRef},
+    software::SoftwareKey,
 };
 use std::{
-    ffi::c_void,
     fmt::{self, Debug},
-    ptr,
+    io::Read,
+    sync::Arc,
 };
+use zeroize::Zeroizing;
 
-declare_TCFType! {
-    /// Object which represents a cryptographic key.
-    ///
-    /// Wrapper for the `SecKey`/`SecKeyRef` types:
-    /// <https://developer.apple.com/documentation/security/seckeyref>
-    Key, KeyRef
+/// Object which represents a cryptographic key.
+///
+/// Wrapper for the `SecKey`/`SecKeyRef` types:
+/// <https://developer.apple.com/documentation/security/seckeyref>
+#[derive(Clone)]
+pub struct Key {
+    backend: Arc<dyn KeychainBackend>,
+    handle: Handle,
 }
 
-impl_TCFType!(Key, KeyRef, SecKeyGetTypeID);
-
 impl Key {
     /// Find a `Key` in the keyring using the given `ItemQuery`.
     ///
     /// Wrapper for `SecItemCopyMatching`. See:
     /// <https://developer.apple.com/documentation/security/1398306-secitemcopymatching>
     pub fn find(query: item::Query) -> Result<Self, Error> {
-        let mut params = DictionaryBuilder::from(query);
-        params.add(unsafe { kSecClass }, &item::Class::Key.as_CFString());
-        params.add(unsafe { kSecMatchLimit }, &MatchLimit::One.as_CFType());
-        params.add_boolean(unsafe { kSecReturnRef }, true);
-
-        let mut result: KeyRef = ptr::null_mut();
-        let status = unsafe {
-            SecItemCopyMatching(
-                Dictionary::from(params).as_concrete_TypeRef(),
-                &mut result as &mut CFTypeRef,
-            )
-        };
+        Self::find_in(backend::default_backend(), &query)
+    }
 
-        // Return an error if the status was unsuccessful
-        if let Some(e) = Error::maybe_from_OSStatus(status) {
-            return Err(e);
-        }
+    /// Find a `Key` using the given backend.
+    pub(crate) fn find_in(
+        backend: Arc<dyn KeychainBackend>,
+        query: &item::Query,
+    ) -> Result<Self, Error> {
+        let handle = backend
+            .copy_matching(item::Class::Key, query, MatchLimit::One)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::ItemNotFound, "no matching keys found"))?;
+
+        Ok(Key::new(backend, handle))
+    }
 
-        Ok(unsafe { Key::wrap_under_create_rule(result) })
+    /// Create a `Key` from a handle returned by a `KeychainBackend`
+    pub(crate) fn new(backend: Arc<dyn KeychainBackend>, handle: Handle) -> Self {
+        Key { backend, handle }
     }
 
     /// Get the `AttrApplicationLabel` for this `Key`.
     pub fn application_label(&self) -> Option<AttrApplicationLabel> {
         self.attributes()
-            .find(AttrKind::ApplicationLabel)
-            .map(|tag| {
-                AttrApplicationLabel(unsafe {
-                    CFData::wrap_under_get_rule(tag.as_CFTypeRef() as CFDataRef)
-                })
-            })
+            .get_data(AttrKind::ApplicationLabel)
+            .map(AttrApplicationLabel::new)
     }
 
     /// Get the `AttrApplicationTag` for this `Key`.
     pub fn application_tag(&self) -> Option<AttrApplicationTag> {
-        self.attributes().find(AttrKind::ApplicationTag).map(|tag| {
-            AttrApplicationTag(unsafe {
-                CFData::wrap_under_get_rule(tag.as_CFTypeRef() as CFDataRef)
-            })
-        })
+        self.attributes()
+            .get_data(AttrKind::ApplicationTag)
+            .map(AttrApplicationTag::new)
     }
 
     /// Get the `AttrLabel` for this `Key`.
     pub fn label(&self) -> Option<AttrLabel> {
-        self.attributes().find(AttrKind::Label).map(|label| {
-            AttrLabel(unsafe { CFString::wrap_under_get_rule(label.as_CFTypeRef() as CFStringRef) })
-        })
+        self.attributes()
+            .get_string(AttrKind::Label)
+            .map(AttrLabel::new)
     }
 
     /// Get the `AttrKeyClass` for this `Key`.
     pub fn class(&self) -> Option<AttrKeyClass> {
         self.attributes()
-            .find(AttrKind::KeyClass)
-            .map(|class| AttrKeyClass::from(class.as_CFTypeRef() as CFStringRef))
+            .get(AttrKind::KeyClass)
+            .and_then(AttrKeyClass::from_value)
     }
 
     /// Get the `AttrKeyType` for this `Key`.
     pub fn key_type(&self) -> Option<AttrKeyType> {
         self.attributes()
-            .find(AttrKind::KeyType)
-            .map(|keytype| AttrKeyType::from(keytype.as_CFTypeRef() as CFStringRef))
+            .get(AttrKind::KeyType)
+            .and_then(AttrKeyType::from_value)
+    }
+
+    /// Get the size of this `Key` in bits.
+    pub fn key_size_in_bits(&self) -> Option<usize> {
+        match self.attributes().get(AttrKind::KeySizeInBits)? {
+            AttrValue::Integer(n) => Some(*n as usize),
+            AttrValue::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Get the elliptic curve this `Key` is on, if it's an elliptic curve key.
+    pub fn ec_curve(&self) -> Option<EcCurve> {
+        EcCurve::from_key_type(self.key_type()?, self.key_size_in_bits()?)
     }
 
     /// Determine whether a key is suitable for an operation using a certain algorithm
@@ -109,14 +115,8 @@ impl Key {
     /// Wrapper for the `SecKeyIsAlgorithmSupported` function. See:
     /// <https://developer.apple.com/documentation/security/1644057-seckeyisalgorithmsupported>
     pub fn is_supported(&self, operation: KeyOperation, alg: KeyAlgorithm) -> bool {
-        let res = unsafe {
-            SecKeyIsAlgorithmSupported(
-                self.as_concrete_TypeRef(),
-                operation.to_CFIndex(),
-                alg.as_CFString().as_CFTypeRef(),
-            )
-        };
-        res == 1
+        self.backend
+            .is_algorithm_supported(&self.handle, operation, alg)
     }
 
     /// Create a cryptographic signature of the given data using this key.
@@ -124,22 +124,9 @@ impl Key {
     /// Wrapper for the `SecKeyCreateSignature` function. See:
     /// <https://developer.apple.com/documentation/security/1643916-seckeycreatesignature>
     pub fn sign(&self, alg: KeyAlgorithm, data: &[u8]) -> Result<Signature, Error> {
-        let mut error: CFErrorRef = ptr::null_mut();
-        let signature = unsafe {
-            SecKeyCreateSignature(
-                self.as_concrete_TypeRef(),
-                alg.as_CFString().as_CFTypeRef(),
-                CFData::from_buffer(data).as_concrete_TypeRef(),
-                &mut error,
-            )
-        };
-
-        if error.is_null() {
-            let bytes = unsafe { CFData::wrap_under_create_rule(signature) }.to_vec();
-            Ok(Signature::new(alg, bytes))
-        } else {
-            Err(error.into())
-        }
+        self.check_compatible(KeyOperation::Sign, alg)?;
+        let bytes = self.backend.sign(&self.handle, alg, data)?;
+        Ok(Signature::new(alg, bytes))
     }
 
     /// Verifies the cryptographic signature of the given data using this key.
@@ -147,22 +134,36 @@ impl Key {
     /// Wrapper for the `SecKeyVerifySignature` function. See:
     /// <https://developer.apple.com/documentation/security/1643715-seckeyverifysignature>
     pub fn verify(&self, signed_data: &[u8], signature: &Signature) -> Result<bool, Error> {
-        let mut error: CFErrorRef = ptr::null_mut();
-        let result = unsafe {
-            SecKeyVerifySignature(
-                self.as_concrete_TypeRef(),
-                signature.algorithm().as_CFString().as_CFTypeRef(),
-                CFData::from_buffer(signed_data).as_concrete_TypeRef(),
-                CFData::from_buffer(signature.as_bytes()).as_concrete_TypeRef(),
-                &mut error,
-            )
-        };
+        self.check_compatible(KeyOperation::Verify, signature.algorithm())?;
+        self.backend.verify(
+            &self.handle,
+            signature.algorithm(),
+            signed_data,
+            signature.as_bytes(),
+        )
+    }
 
-        if error.is_null() {
-            Ok(result == 0x1)
-        } else {
-            Err(error.into())
-        }
+    /// Create a cryptographic signature of all of the data read from the
+    /// given reader, e.g. a file too large to load into memory.
+    ///
+    /// `alg` must be a `Message` signature algorithm (e.g.
+    /// `ECDSASignatureMessageX962SHA256`). The data is hashed incrementally
+    /// and the digest signed using the corresponding `Digest` algorithm, but
+    /// the resulting `Signature` is identical to one produced by `alg`.
+    pub fn sign_reader<R: Read>(&self, alg: KeyAlgorithm, reader: R) -> Result<Signature, Error> {
+        self.check_compatible(KeyOperation::Sign, alg)?;
+        let (digest_alg, digest) = prehash_reader(alg, reader)?;
+        let bytes = self.backend.sign(&self.handle, digest_alg, &digest)?;
+        Ok(Signature::new(alg, bytes))
+    }
+
+    /// Verify a signature over all of the data read from the given reader,
+    /// hashing it incrementally as `sign_reader` does.
+    pub fn verify_reader<R: Read>(&self, reader: R, signature: &Signature) -> Result<bool, Error> {
+        self.check_compatible(KeyOperation::Verify, signature.algorithm())?;
+        let (digest_alg, digest) = prehash_reader(signature.algorithm(), reader)?;
+        self.backend
+            .verify(&self.handle, digest_alg, &digest, signature.as_bytes())
     }
 
     /// Encrypts a block of data using a public key and specified algorithm
@@ -170,22 +171,9 @@ impl Key {
     /// Wrapper for the `SecKeyCreateEncryptedData` function. See:
     /// <https://developer.apple.com/documentation/security/1643957-seckeycreateencrypteddata>
     pub fn encrypt(&self, alg: KeyAlgorithm, plaintext: &[u8]) -> Result<Ciphertext, Error> {
-        let mut error: CFErrorRef = ptr::null_mut();
-        let ciphertext = unsafe {
-            SecKeyCreateEncryptedData(
-                self.as_concrete_TypeRef(),
-                alg.as_CFString().as_CFTypeRef(),
-                CFData::from_buffer(plaintext).as_concrete_TypeRef(),
-                &mut error,
-            )
-        };
-
-        if error.is_null() {
-            let bytes = unsafe { CFData::wrap_under_create_rule(ciphertext) }.to_vec();
-            Ok(Ciphertext::new(alg, bytes))
-        } else {
-            Err(error.into())
-        }
+        self.check_compatible(KeyOperation::Encrypt, alg)?;
+        let bytes = self.backend.encrypt(&self.handle, alg, plaintext)?;
+        Ok(Ciphertext::new(alg, bytes))
     }
 
     /// Decrypts a block of data using a private key and specified algorithm
@@ -193,21 +181,73 @@ impl Key {
     /// Wrapper for the `SecKeyCreateDecryptedData` function. See:
     /// <https://developer.apple.com/documentation/security/1644043-seckeycreatedecrypteddata>
     pub fn decrypt(&self, ciphertext: Ciphertext) -> Result<Vec<u8>, Error> {
-        let mut error: CFErrorRef = ptr::null_mut();
-        let plaintext = unsafe {
-            SecKeyCreateDecryptedData(
-                self.as_concrete_TypeRef(),
-                ciphertext.algorithm().as_CFString().as_CFTypeRef(),
-                CFData::from_buffer(ciphertext.as_ref()).as_concrete_TypeRef(),
-                &mut error,
-            )
+        self.check_compatible(KeyOperation::Decrypt, ciphertext.algorithm())?;
+        self.backend
+            .decrypt(&self.handle, ciphertext.algorithm(), ciphertext.as_ref())
+    }
+
+    /// Perform a key exchange between this private key and a peer's public
+    /// key using one of the `ECDHKeyExchange*` algorithms, returning the
+    /// shared secret.
+    ///
+    /// For the `X963` algorithms, the shared secret is passed through the
+    /// ANSI X9.63 KDF with the given `shared_info` to derive a key of
+    /// `requested_size` bytes. Other algorithms return the raw shared secret
+    /// (i.e. the x-coordinate of the shared point) and ignore these
+    /// parameters.
+    ///
+    /// Wrapper for the `SecKeyCopyKeyExchangeResult` function. See:
+    /// <https://developer.apple.com/documentation/security/1644033-seckeycopykeyexchangeresult>
+    pub fn key_exchange(
+        &self,
+        alg: KeyAlgorithm,
+        peer_public_key: &Key,
+        requested_size: usize,
+        shared_info: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        if peer_public_key.class() != Some(AttrKeyClass::Public) {
+            return Err(Error::new(
+                ErrorKind::Param,
+                "key exchange requires the peer's public key",
+            ));
+        }
+
+        let key_type = peer_public_key
+            .key_type()
+            .ok_or_else(|| Error::new(ErrorKind::Param, "unknown key type"))?;
+
+        // The peer's key may belong to a different backend
+        let params = RestoreKeyParams {
+            key_class: AttrKeyClass::Public,
+            key_type,
+            key_data: peer_public_key.to_external_representation()?,
         };
 
-        if error.is_null() {
-            let bytes = unsafe { CFData::wrap_under_create_rule(plaintext) }.to_vec();
-            Ok(bytes)
-        } else {
-            Err(error.into())
+        self.key_exchange_with_params(alg, &params, requested_size, shared_info)
+    }
+
+    /// Perform a key exchange with a peer's public key given as
+    /// `RestoreKeyParams`
+    pub(crate) fn key_exchange_with_params(
+        &self,
+        alg: KeyAlgorithm,
+        peer_public_key: &RestoreKeyParams,
+        requested_size: usize,
+        shared_info: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        self.check_compatible(KeyOperation::KeyExchange, alg)?;
+        let public_key = self.backend.restore_key(peer_public_key)?;
+        self.backend
+            .key_exchange(&self.handle, alg, &public_key, requested_size, shared_info)
+            .map(Zeroizing::new)
+    }
+
+    /// Ensure the given algorithm can be used for an operation with this
+    /// key before handing it to the backend
+    fn check_compatible(&self, operation: KeyOperation, alg: KeyAlgorithm) -> Result<(), Error> {
+        match self.key_type() {
+            Some(key_type) => alg.check_compatible(operation, key_type),
+            None => Ok(()),
         }
     }
 
@@ -216,29 +256,28 @@ impl Key {
     /// Wrapper for `SecItemDelete` function. See:
     /// <https://developer.apple.com/documentation/security/1395547-secitemdelete>
     pub fn delete(self) -> Result<(), Error> {
-        let mut query = DictionaryBuilder::new();
-        let key_class = self.class().unwrap();
-        query.add(unsafe { kSecClass }, &item::Class::Key.as_CFString());
-        query.add(unsafe { kSecAttrKeyClass }, &key_class.as_CFString());
-        if key_class == AttrKeyClass::Public {
-            query.add(unsafe { kSecAttrKeyType }, &self.key_type().unwrap().as_CFString());
-            query.add(
-                unsafe { kSecAttrApplicationTag },
-                &self.application_tag().unwrap().as_CFType(),
-            );
-        } else if key_class == AttrKeyClass::Private {
-            query.add(
-                unsafe { kSecAttrApplicationLabel },
-                &self.application_label().unwrap().as_CFType(),
-            );
-            query.add_boolean(unsafe { kSecReturnRef }, true);
+        let attrs = self.backend.attributes(&self.handle)?;
+        let mut query = AttributeMap::new();
+
+        for kind in &[
+            AttrKind::KeyClass,
+            AttrKind::KeyType,
+            AttrKind::ApplicationLabel,
+            AttrKind::ApplicationTag,
+        ] {
+            if let Some(value) = attrs.get(*kind) {
+                query.insert(*kind, value.clone());
+            }
         }
-        let status = unsafe { SecItemDelete(Dictionary::from(query).as_concrete_TypeRef()) };
-        if let Some(e) = Error::maybe_from_OSStatus(status) {
-            Err(e)
-        } else {
-            Ok(())
+
+        if query.get(AttrKind::KeyClass).is_none() {
+            return Err(Error::new(
+                ErrorKind::NoSuchAttr,
+                "key is missing a key class",
+            ));
         }
+
+        self.backend.delete(item::Class::Key, &query.into())
     }
 
     /// Export this key as an external representation.
@@ -260,15 +299,17 @@ impl Key {
     /// Wrapper for the `SecKeyCopyExternalRepresentation` function. See:
     /// <https://developer.apple.com/documentation/security/1643698-seckeycopyexternalrepresentation>
     pub fn to_external_representation(&self) -> Result<Vec<u8>, Error> {
-        let mut error: CFErrorRef = ptr::null_mut();
-        let data =
-            unsafe { SecKeyCopyExternalRepresentation(self.as_concrete_TypeRef(), &mut error) };
-
-        if error.is_null() {
-            Ok(unsafe { CFData::wrap_under_create_rule(data) }.to_vec())
-        } else {
-            Err(error.into())
-        }
+        self.backend.export_key(&self.handle)
+    }
+
+    /// Get the public key associated with this key. For public keys this
+    /// returns a copy of the key itself.
+    ///
+    /// Wrapper for the `SecKeyCopyPublicKey` function. See:
+    /// <https://developer.apple.com/documentation/security/1643774-seckeycopypublickey>
+    pub fn public_key(&self) -> Result<Self, Error> {
+        let handle = self.backend.copy_public_key(&self.handle)?;
+        Ok(Key::new(self.backend.clone(), handle))
     }
 
     /// Restores a key from an external representation of that key.
@@ -276,28 +317,30 @@ impl Key {
     /// Wrapper for the `SecKeyCreateWithData` function. See:
     /// <https://developer.apple.com/documentation/security/1643701-seckeycreatewithdata>
     pub fn from_external_representation(params: RestoreKeyParams) -> Result<Self, Error> {
-        let mut error: CFErrorRef = ptr::null_mut();
-        let data = unsafe {
-            SecKeyCreateWithData(
-                CFData::from_buffer(params.as_bytes()).as_concrete_TypeRef(),
-                params.attributes().as_concrete_TypeRef(),
-                &mut error,
-            )
-        };
+        let backend = backend::default_backend();
+        let handle = backend.restore_key(&params)?;
+        Ok(Key::new(backend, handle))
+    }
 
-        if error.is_null() {
-            Ok(unsafe { Key::wrap_under_create_rule(data) })
-        } else {
-            Err(error.into())
-        }
+    /// Load this key's external representation as a `SoftwareKey`
+    pub(crate) fn software_key(&self) -> Result<SoftwareKey, Error> {
+        let key_type = self
+            .key_type()
+            .ok_or_else(|| Error::new(ErrorKind::NoSuchAttr, "key is missing a key type"))?;
+        let key_class = self
+            .class()
+            .ok_or_else(|| Error::new(ErrorKind::NoSuchAttr, "key is missing a key class"))?;
+        let key_data = Zeroizing::new(self.to_external_representation()?);
+
+        SoftwareKey::from_external_representation(key_class, key_type, &key_data)
     }
 
     /// Fetch attributes for this `Key`.
     ///
     /// Wrapper for `SecKeyCopyAttributes`. See:
     /// <https://developer.apple.com/documentation/security/1643699-seckeycopyattributes>
-    fn attributes(&self) -> Dictionary {
-        unsafe { Dictionary::wrap_under_get_rule(SecKeyCopyAttributes(self.as_concrete_TypeRef())) }
+    fn attributes(&self) -> AttributeMap {
+        self.backend.attributes(&self.handle).unwrap_or_default()
     }
 }
 
@@ -312,3 +355,23 @@ impl Debug for Key {
         )
     }
 }
+
+/// Hash the data read from the given reader using the digest function of a
+/// `Message` signature algorithm, returning the corresponding `Digest`
+/// algorithm along with the digest
+fn prehash_reader<R: Read>(alg: KeyAlgorithm, reader: R) -> Result<(KeyAlgorithm, Vec<u8>), Error> {
+    let digest_alg = alg.digest_variant().ok_or_else(|| {
+        Error::new(
+            ErrorKind::Param,
+            &format!("{} is not a message signature algorithm", alg),
+        )
+    })?;
+
+    let digest = alg
+        .descriptor()
+        .digest
+        .expect("message signature algorithms have a digest")
+        .digest_reader(reader)?;
+
+    Ok((digest_alg, digest))
+}
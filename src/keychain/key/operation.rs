@@ -1,11 +1,14 @@
+#[cfg(target_os = "macos")]
 use core_foundation::base::{CFIndex, CFIndexConvertible};
 
+#[cfg(target_os = "macos")]
 use self::KeyOperation::*;
+
 /// Types of operations that a cryptographic key can perform
 ///
 /// Wrapper for `SecKeyOperationType`. See:
 /// <https://developer.apple.com/documentation/security/seckeyoperationtype>
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 pub enum KeyOperation {
     /// Decrypt operation
     Decrypt,
@@ -19,6 +22,7 @@ pub enum KeyOperation {
     Verify,
 }
 
+#[cfg(target_os = "macos")]
 impl CFIndexConvertible for KeyOperation {
     fn to_CFIndex(self) -> CFIndex {
         let i = match self {
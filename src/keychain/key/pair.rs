@@ -1,7 +1,25 @@
 use super::*;
-use crate::{access::AccessControl, dictionary::*, error::Error};
-use core_foundation::base::TCFType;
-use std::ptr;
+#[cfg(target_os = "macos")]
+use crate::dictionary::*;
+use crate::{
+    access::AccessControl,
+    backend::{self, KeychainBackend},
+    error::Error,
+};
+use std::{mem, sync::Arc};
+use zeroize::Zeroize;
+
+/// Attributes of generated key pairs which also apply to the public key
+const PUBLIC_KEY_ATTRS: &[AttrKind] = &[
+    AttrKind::Label,
+    AttrKind::ApplicationTag,
+    AttrKind::Permanent,
+    AttrKind::Synchronizable,
+    AttrKind::Derive,
+    AttrKind::Encrypt,
+    AttrKind::Verify,
+    AttrKind::Wrap,
+];
 
 /// Public key pairs (i.e. public and private key) stored in the keychain.
 #[derive(Debug)]
@@ -21,24 +39,9 @@ impl KeyPair {
     /// Wrapper for the `SecKeyCreateRandomKey` function see:
     /// <https://developer.apple.com/documentation/security/1823694-seckeycreaterandomkey>
     pub fn create(params: KeyPairGenerateParams) -> Result<KeyPair, Error> {
-        let mut error: CFErrorRef = ptr::null_mut();
-        let private_key_ref: KeyRef = unsafe {
-            SecKeyCreateRandomKey(Dictionary::from(params).as_concrete_TypeRef(), &mut error)
-        };
-        if private_key_ref.is_null() {
-            Err(error.into())
-        } else {
-            let public_key_ref = unsafe { SecKeyCopyPublicKey(private_key_ref) };
-            assert!(!public_key_ref.is_null());
-            assert!(!private_key_ref.is_null());
-
-            Ok(unsafe {
-                KeyPair {
-                    public_key: Key::wrap_under_create_rule(public_key_ref),
-                    private_key: Key::wrap_under_create_rule(private_key_ref),
-                }
-            })
-        }
+        let backend = backend::default_backend();
+        let (public_key, private_key) = backend.create_key_pair(&params)?;
+        Ok(KeyPair::new(backend, public_key, private_key))
     }
 
     /// Generate a public/private `KeyPair` using the given
@@ -47,31 +50,21 @@ impl KeyPair {
     /// Wrapper for the `SecKeyGeneratePair` function. See:
     /// <https://developer.apple.com/documentation/security/1395339-seckeygeneratepair>
     pub fn generate(params: KeyPairGenerateParams) -> Result<KeyPair, Error> {
-        let mut public_key_ref: KeyRef = ptr::null_mut();
-        let mut private_key_ref: KeyRef = ptr::null_mut();
-
-        let status = unsafe {
-            SecKeyGeneratePair(
-                Dictionary::from(params).as_concrete_TypeRef(),
-                &mut public_key_ref,
-                &mut private_key_ref,
-            )
-        };
-
-        // Return an error if the status was unsuccessful
-        if let Some(e) = Error::maybe_from_OSStatus(status) {
-            return Err(e);
-        }
-
-        assert!(!public_key_ref.is_null());
-        assert!(!private_key_ref.is_null());
+        let backend = backend::default_backend();
+        let (public_key, private_key) = backend.generate_key_pair(&params)?;
+        Ok(KeyPair::new(backend, public_key, private_key))
+    }
 
-        Ok(unsafe {
-            KeyPair {
-                public_key: Key::wrap_under_create_rule(public_key_ref),
-                private_key: Key::wrap_under_create_rule(private_key_ref),
-            }
-        })
+    /// Create a `KeyPair` from handles returned by a `KeychainBackend`
+    pub(crate) fn new(
+        backend: Arc<dyn KeychainBackend>,
+        public_key: Handle,
+        private_key: Handle,
+    ) -> Self {
+        KeyPair {
+            public_key: Key::new(backend.clone(), public_key),
+            private_key: Key::new(backend, private_key),
+        }
     }
 }
 
@@ -84,7 +77,8 @@ impl KeyPair {
 pub struct KeyPairGenerateParams {
     key_type: AttrKeyType,
     key_size: usize,
-    attrs: DictionaryBuilder,
+    access_control: Option<AccessControl>,
+    attrs: AttributeMap,
 }
 
 impl KeyPairGenerateParams {
@@ -93,6 +87,7 @@ impl KeyPairGenerateParams {
         Self {
             key_type,
             key_size,
+            access_control: None,
             attrs: <_>::default(),
         }
     }
@@ -102,7 +97,7 @@ impl KeyPairGenerateParams {
     /// Wrapper for the `kSecAttrAccessControl` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattraccesscontrol>
     pub fn access_control(mut self, access_control: &AccessControl) -> Self {
-        self.attrs.add(AttrKind::AccessControl, access_control);
+        self.access_control = Some(access_control.clone());
         self
     }
 
@@ -115,7 +110,7 @@ impl KeyPairGenerateParams {
     where
         T: Into<AttrApplicationTag>,
     {
-        self.attrs.add_attr(&tag.into());
+        self.attrs.insert_attr(&tag.into());
         self
     }
 
@@ -124,7 +119,7 @@ impl KeyPairGenerateParams {
     /// Wrapper for the `kSecKeyDerive` attribute key. See:
     /// <https://developer.apple.com/documentation/security/kseckeyderive>
     pub fn can_derive(mut self, value: bool) -> Self {
-        self.attrs.add_boolean(AttrKind::Derive, value);
+        self.attrs.insert(AttrKind::Derive, value);
         self
     }
 
@@ -133,7 +128,7 @@ impl KeyPairGenerateParams {
     /// Wrapper for the `kSecKeyDecrypt` attribute key. See:
     /// <https://developer.apple.com/documentation/security/kseckeydecrypt>
     pub fn can_decrypt(mut self, value: bool) -> Self {
-        self.attrs.add_boolean(AttrKind::Decrypt, value);
+        self.attrs.insert(AttrKind::Decrypt, value);
         self
     }
 
@@ -142,7 +137,7 @@ impl KeyPairGenerateParams {
     /// Wrapper for the `kSecKeyEncrypt` attribute key. See:
     /// <https://developer.apple.com/documentation/security/kseckeyencrypt>
     pub fn can_encrypt(mut self, value: bool) -> Self {
-        self.attrs.add_boolean(AttrKind::Encrypt, value);
+        self.attrs.insert(AttrKind::Encrypt, value);
         self
     }
 
@@ -151,7 +146,7 @@ impl KeyPairGenerateParams {
     /// Wrapper for the `kSecKeySign` attribute key. See:
     /// <https://developer.apple.com/documentation/security/kseckeysign>
     pub fn can_sign(mut self, value: bool) -> Self {
-        self.attrs.add_boolean(AttrKind::Sign, value);
+        self.attrs.insert(AttrKind::Sign, value);
         self
     }
 
@@ -160,7 +155,7 @@ impl KeyPairGenerateParams {
     /// Wrapper for the `kSecKeyVerify` attribute key. See:
     /// <https://developer.apple.com/documentation/security/kseckeyverify>
     pub fn can_verify(mut self, value: bool) -> Self {
-        self.attrs.add_boolean(AttrKind::Verify, value);
+        self.attrs.insert(AttrKind::Verify, value);
         self
     }
 
@@ -169,7 +164,7 @@ impl KeyPairGenerateParams {
     /// Wrapper for the `kSecKeyWrap` attribute key. See:
     /// <https://developer.apple.com/documentation/security/kseckeywrap>
     pub fn can_wrap(mut self, value: bool) -> Self {
-        self.attrs.add_boolean(AttrKind::Wrap, value);
+        self.attrs.insert(AttrKind::Wrap, value);
         self
     }
 
@@ -178,7 +173,7 @@ impl KeyPairGenerateParams {
     /// Wrapper for the `kSecKeyUnwrap` attribute key. See:
     /// <https://developer.apple.com/documentation/security/kseckeyunwrap>
     pub fn can_unwrap(mut self, value: bool) -> Self {
-        self.attrs.add_boolean(AttrKind::Unwrap, value);
+        self.attrs.insert(AttrKind::Unwrap, value);
         self
     }
 
@@ -187,7 +182,7 @@ impl KeyPairGenerateParams {
     /// Wrapper for the `kSecAttrKeyClass` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrkeyclass>
     pub fn key_class(mut self, value: AttrKeyClass) -> Self {
-        self.attrs.add(AttrKind::KeyClass, &value.as_CFString());
+        self.attrs.insert_attr(&value);
         self
     }
 
@@ -196,7 +191,7 @@ impl KeyPairGenerateParams {
     /// Wrapper for the `kSecKeyExtractable` attribute key. See:
     /// <https://developer.apple.com/documentation/security/kseckeyextractable>
     pub fn extractable(mut self, value: bool) -> Self {
-        self.attrs.add_boolean(AttrKind::Extractable, value);
+        self.attrs.insert(AttrKind::Extractable, value);
         self
     }
 
@@ -205,7 +200,7 @@ impl KeyPairGenerateParams {
     /// Wrapper for the `kSecAttrIsPermanent` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrispermanent>
     pub fn permanent(mut self, value: bool) -> Self {
-        self.attrs.add_boolean(AttrKind::Permanent, value);
+        self.attrs.insert(AttrKind::Permanent, value);
         self
     }
 
@@ -215,7 +210,7 @@ impl KeyPairGenerateParams {
     /// Wrapper for `kSecKeySensitive` attribute key. See
     /// <https://developer.apple.com/documentation/security/kseckeysensitive>
     pub fn sensitive(mut self, value: bool) -> Self {
-        self.attrs.add_boolean(AttrKind::Sensitive, value);
+        self.attrs.insert(AttrKind::Sensitive, value);
         self
     }
 
@@ -225,7 +220,7 @@ impl KeyPairGenerateParams {
     /// Wrapper for the `kSecAttrLabel` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrlabel>
     pub fn label<L: Into<AttrLabel>>(mut self, label: L) -> Self {
-        self.attrs.add_attr(&label.into());
+        self.attrs.insert_attr(&label.into());
         self
     }
 
@@ -235,7 +230,7 @@ impl KeyPairGenerateParams {
     /// Wrapper for the `kSecAttrSynchronizable` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrsynchronizable>
     pub fn synchronizable(mut self, value: bool) -> Self {
-        self.attrs.add_boolean(AttrKind::Synchronizable, value);
+        self.attrs.insert(AttrKind::Synchronizable, value);
         self
     }
 
@@ -244,19 +239,76 @@ impl KeyPairGenerateParams {
     /// Wrapper for the `kSecAttrTokenID` attribute key. See:
     /// <https://developer.apple.com/documentation/security/ksecattrtokenid>
     pub fn token_id(mut self, value: AttrTokenId) -> Self {
-        self.attrs.add_attr(&value);
+        self.attrs.insert_attr(&value);
         self
     }
+
+    /// Get the parameters for generating this key pair as an `AttributeMap`
+    /// (i.e. the dictionary passed to `SecKeyCreateRandomKey`), with
+    /// attributes of the private key nested under `kSecPrivateKeyAttrs`.
+    ///
+    /// The access control policy (if any) is not included, as it can't be
+    /// represented as an `AttrValue`.
+    pub fn attributes(&self) -> AttributeMap {
+        let mut result = AttributeMap::new();
+        result.insert_attr(&self.key_type);
+        result.insert(AttrKind::KeySizeInBits, self.key_size as i64);
+        result.insert(AttrKind::PrivateKeyAttrs, self.attrs.clone());
+        result
+    }
+
+    /// Get the type of key to generate
+    pub(crate) fn key_type(&self) -> AttrKeyType {
+        self.key_type
+    }
+
+    /// Get the size of the key to generate in bits
+    pub(crate) fn key_size(&self) -> usize {
+        self.key_size
+    }
+
+    /// Get the access control policy for the generated private key (if any)
+    pub(crate) fn access_control_policy(&self) -> Option<&AccessControl> {
+        self.access_control.as_ref()
+    }
+
+    /// Get the attributes to set on the generated private key
+    pub(crate) fn private_key_attributes(&self) -> &AttributeMap {
+        &self.attrs
+    }
+
+    /// Get the attributes to set on the generated public key, i.e. those
+    /// the two keys share (such as the label and application tag) and
+    /// those which govern public key operations
+    pub(crate) fn public_key_attributes(&self) -> AttributeMap {
+        let mut attrs = AttributeMap::new();
+
+        for kind in PUBLIC_KEY_ATTRS {
+            if let Some(value) = self.attrs.get(*kind) {
+                attrs.insert(*kind, value.clone());
+            }
+        }
+
+        attrs
+    }
 }
 
-impl From<KeyPairGenerateParams> for Dictionary {
-    fn from(params: KeyPairGenerateParams) -> Dictionary {
-        let mut result = DictionaryBuilder::new();
-        result.add_attr(&params.key_type);
-        result.add_number(AttrKind::KeySizeInBits, params.key_size as i64);
+#[cfg(target_os = "macos")]
+impl<'a> From<&'a KeyPairGenerateParams> for Dictionary {
+    fn from(params: &KeyPairGenerateParams) -> Dictionary {
+        let mut private_key_attrs = DictionaryBuilder::from(params.private_key_attributes());
+
+        if let Some(access_control) = params.access_control_policy() {
+            private_key_attrs.add(AttrKind::AccessControl, &access_control.as_CFType());
+        }
+
+        let mut attrs = params.attributes();
+        attrs.remove(AttrKind::PrivateKeyAttrs);
+
+        let mut result = DictionaryBuilder::from(&attrs);
         result.add(
-            unsafe { kSecPrivateKeyAttrs },
-            &Dictionary::from(params.attrs),
+            AttrKind::PrivateKeyAttrs,
+            &Dictionary::from(private_key_attrs),
         );
         result.into()
     }
@@ -283,6 +335,8 @@ pub struct RestoreKeyParams {
     ///     of the secret scalar, i.e. `04 || X || Y || K`
     ///
     /// All representations use fixed-size integers with leading zeroes.
+    ///
+    /// This buffer is zeroized when the parameters are dropped.
     pub key_data: Vec<u8>,
     /// The type of key algorithm
     pub key_type: AttrKeyType,
@@ -290,16 +344,38 @@ pub struct RestoreKeyParams {
 
 impl RestoreKeyParams {
     /// Return the attributes that will be used to restore the key
-    pub fn attributes(&self) -> Dictionary {
-        let mut result = DictionaryBuilder::new();
-        result.add_attr(&self.key_type);
-        result.add(AttrKind::KeyClass, &self.key_class.as_CFString());
-        result.add_number(AttrKind::KeySizeInBits, (self.key_data.len() * 8) as i64);
-        result.into()
+    pub fn attributes(&self) -> AttributeMap {
+        let mut result = AttributeMap::new();
+        result.insert_attr(&self.key_type);
+        result.insert_attr(&self.key_class);
+        result.insert(AttrKind::KeySizeInBits, (self.key_data.len() * 8) as i64);
+        result
     }
 
     /// Return the `key_data` as a slice
     pub fn as_bytes(&self) -> &[u8] {
         self.key_data.as_slice()
     }
+
+    /// Create parameters for restoring the given `SoftwareKey`
+    pub(crate) fn from_software_key(key: &SoftwareKey) -> Self {
+        let mut key_data = key.to_external_representation();
+
+        Self {
+            key_class: key.key_class(),
+            key_data: mem::take(&mut *key_data),
+            key_type: key.public_key().key_type(),
+        }
+    }
+
+    /// Parse the `key_data` as a `SoftwareKey`
+    pub(crate) fn software_key(&self) -> Result<SoftwareKey, Error> {
+        SoftwareKey::from_external_representation(self.key_class, self.key_type, self.as_bytes())
+    }
+}
+
+impl Drop for RestoreKeyParams {
+    fn drop(&mut self) {
+        self.key_data.zeroize();
+    }
 }
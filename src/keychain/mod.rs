@@ -3,32 +3,65 @@
 pub mod item;
 pub mod key;
 
-use self::item::MatchLimit;
-pub use self::{item::Item, key::Key};
-use crate::dictionary::*;
-use crate::error::Error;
-use crate::ffi::*;
-use core_foundation::base::{CFTypeRef, TCFType};
-use std::{ffi::CString, os::raw::c_char, os::unix::ffi::OsStrExt, path::Path, ptr};
-
-declare_TCFType! {
-    /// Keychains which store cryptographic keys, passwords, and other secrets.
-    ///
-    /// Wrapper for the `SecKeychain`/`SecKeychainRef` types:
-    /// <https://developer.apple.com/documentation/security/seckeychainref>
-    Keychain, KeychainRef
+use self::item::{Class, MatchLimit, Query};
+pub use self::{
+    item::Item,
+    key::{Key, KeyPair, KeyPairGenerateParams, RestoreKeyParams},
+};
+#[cfg(not(target_os = "macos"))]
+use crate::backend::FileBackend;
+use crate::{
+    attr::AttributeMap,
+    backend::KeychainBackend,
+    error::{Error, ErrorKind},
+};
+#[cfg(target_os = "macos")]
+use crate::{backend::SecurityFrameworkBackend, ffi::*};
+#[cfg(target_os = "macos")]
+use core_foundation::base::TCFType;
+#[cfg(target_os = "macos")]
+use std::{ffi::CString, os::raw::c_char, os::unix::ffi::OsStrExt, ptr};
+use std::{path::Path, sync::Arc};
+
+/// Keychains which store cryptographic keys, passwords, and other secrets.
+///
+/// Each keychain is backed by a `KeychainBackend`, which on macOS is
+/// Keychain Services itself (see `SecurityFrameworkBackend`).
+///
+/// Wrapper for the `SecKeychain`/`SecKeychainRef` types:
+/// <https://developer.apple.com/documentation/security/seckeychainref>
+#[derive(Clone, Debug)]
+pub struct Keychain {
+    backend: Arc<dyn KeychainBackend>,
 }
 
-impl_TCFType!(Keychain, KeychainRef, SecKeychainGetTypeID);
-
 impl Keychain {
+    /// Create a keychain which stores items using the given backend
+    pub fn new<B>(backend: B) -> Keychain
+    where
+        B: KeychainBackend + 'static,
+    {
+        Keychain {
+            backend: Arc::new(backend),
+        }
+    }
+
+    /// Borrow the backend which stores this keychain's items
+    pub fn backend(&self) -> &dyn KeychainBackend {
+        self.backend.as_ref()
+    }
+
     /// Find the default keychain. Returns an `Error` result with a kind of
     /// `ErrorKind::NoDefaultKeychain` if there is no default keychain.
     ///
     /// This is a non-panicking alternative to `Keychain::default()`.
     ///
+    /// On platforms other than macOS, the default keychain is a
+    /// process-wide `MemoryBackend`.
+    ///
     /// Wrapper for the `SecKeychainCopyDefault` function. See:
     /// <https://developer.apple.com/documentation/security/1400743-seckeychaincopydefault>
+    #[cfg(target_os = "macos")]
     pub fn find_default() -> Result<Keychain, Error> {
         let mut result: KeychainRef = ptr::null_mut();
         let status = unsafe { SecKeychainCopyDefault(&mut result) };
@@ -36,16 +69,34 @@ impl Keychain {
         if let Some(e) = Error::maybe_from_OSStatus(status) {
             Err(e)
         } else {
-            Ok(unsafe { Keychain::wrap_under_create_rule(result) })
+            let keychain = unsafe { SecKeychain::wrap_under_create_rule(result) };
+            Ok(Keychain::new(SecurityFrameworkBackend::with_keychain(
+                keychain,
+            )))
         }
     }
 
+    /// Find the default keychain. Returns an `Error` result with a kind of
+    /// `ErrorKind::NoDefaultKeychain` if there is no default keychain.
+    ///
+    /// This is a non-panicking alternative to `Keychain::default()`.
+    ///
+    /// On platforms other than macOS, the default keychain is a
+    /// process-wide `MemoryBackend`.
+    #[cfg(not(target_os = "macos"))]
+    pub fn find_default() -> Result<Keychain, Error> {
+        Ok(Keychain {
+            backend: crate::backend::default_backend(),
+        })
+    }
+
     /// Create a new keychain. Accepts a path where the new keychain will be
     /// located along with an optional password. If no password is given, the
     /// user will be prompted for a password.
     ///
     /// Wrapper for the `SecKeychainCreate` function. See:
     /// <https://developer.apple.com/documentation/security/1401214-seckeychaincreate>
+    #[cfg(target_os = "macos")]
     pub fn create(path: &Path, password: Option<&str>) -> Result<Keychain, Error> {
         let path_cstring = CString::new(path.as_os_str().as_bytes()).unwrap();
         let mut result: KeychainRef = ptr::null_mut();
@@ -76,24 +127,93 @@ impl Keychain {
         if let Some(e) = Error::maybe_from_OSStatus(status) {
             Err(e)
         } else {
-            Ok(unsafe { Keychain::wrap_under_create_rule(result) })
+            let keychain = unsafe { SecKeychain::wrap_under_create_rule(result) };
+            Ok(Keychain::new(SecurityFrameworkBackend::with_keychain(
+                keychain,
+            )))
         }
     }
 
+    /// Create a new keychain. Accepts a path where the new keychain will be
+    /// located along with a password, which is required on this platform.
+    ///
+    /// On platforms other than macOS, keychains are encrypted files (see
+    /// `FileBackend`). Use `FileBackend::open` to open an existing one.
+    #[cfg(not(target_os = "macos"))]
+    pub fn create(path: &Path, password: Option<&str>) -> Result<Keychain, Error> {
+        let password = password.ok_or_else(|| {
+            Error::new(
+                ErrorKind::Param,
+                "a password is required to create a keychain",
+            )
+        })?;
+
+        Ok(Keychain::new(FileBackend::create(path, password)?))
+    }
+
     /// Delete this keychain.
     ///
     /// Wrapper for the `SecKeychainDelete` function. See:
     /// <https://developer.apple.com/documentation/security/1395206-seckeychaindelete>
     pub fn delete(self) -> Result<(), Error> {
-        let status = unsafe { SecKeychainDelete(self.as_concrete_TypeRef()) };
+        self.backend.delete_keychain()
+    }
 
-        if let Some(e) = Error::maybe_from_OSStatus(status) {
-            Err(e)
-        } else {
-            Ok(())
+    /// Find a `Key` in this keychain using the given `Query`.
+    ///
+    /// Wrapper for `SecItemCopyMatching`. See:
+    /// <https://developer.apple.com/documentation/security/1398306-secitemcopymatching>
+    pub fn find_key(&self, query: Query) -> Result<Key, Error> {
+        Key::find_in(self.backend.clone(), &query)
+    }
+
+    /// Find all `Key`s in this keychain which match the given `Query`.
+    /// Returns an empty `Vec` if no keys match.
+    ///
+    /// Wrapper for `SecItemCopyMatching` with `kSecMatchLimitAll`. See:
+    /// <https://developer.apple.com/documentation/security/1398306-secitemcopymatching>
+    pub fn find_keys(&self, query: Query) -> Result<Vec<Key>, Error> {
+        match self
+            .backend
+            .copy_matching(Class::Key, &query, MatchLimit::All)
+        {
+            Ok(handles) => Ok(handles
+                .into_iter()
+                .map(|handle| Key::new(self.backend.clone(), handle))
+                .collect()),
+            Err(ref e) if *e.kind() == ErrorKind::ItemNotFound => Ok(vec![]),
+            Err(e) => Err(e),
         }
     }
 
+    /// Create a public/private `KeyPair` in this keychain.
+    ///
+    /// Wrapper for the `SecKeyCreateRandomKey` function see:
+    /// <https://developer.apple.com/documentation/security/1823694-seckeycreaterandomkey>
+    pub fn create_key_pair(&self, params: KeyPairGenerateParams) -> Result<KeyPair, Error> {
+        let (public_key, private_key) = self.backend.create_key_pair(&params)?;
+        Ok(KeyPair::new(self.backend.clone(), public_key, private_key))
+    }
+
+    /// Generate a public/private `KeyPair` in this keychain.
+    ///
+    /// Wrapper for the `SecKeyGeneratePair` function. See:
+    /// <https://developer.apple.com/documentation/security/1395339-seckeygeneratepair>
+    pub fn generate_key_pair(&self, params: KeyPairGenerateParams) -> Result<KeyPair, Error> {
+        let (public_key, private_key) = self.backend.generate_key_pair(&params)?;
+        Ok(KeyPair::new(self.backend.clone(), public_key, private_key))
+    }
+
+    /// Restore a `Key` from its external representation using this
+    /// keychain's backend.
+    ///
+    /// Wrapper for the `SecKeyCreateWithData` function. See:
+    /// <https://developer.apple.com/documentation/security/1643701-seckeycreatewithdata>
+    pub fn restore_key(&self, params: RestoreKeyParams) -> Result<Key, Error> {
+        let handle = self.backend.restore_key(&params)?;
+        Ok(Key::new(self.backend.clone(), handle))
+    }
+
     /// Find an item in this keychain.
     ///
     /// This is a private method we wrap using builders for querying various
@@ -101,24 +221,15 @@ impl Keychain {
     ///
     /// Wrapper for `SecItemCopyMatching`. See:
     /// <https://developer.apple.com/documentation/security/1398306-secitemcopymatching>
-    fn find_item(&self, mut attrs: DictionaryBuilder) -> Result<Item, Error> {
-        attrs.add(unsafe { kSecMatchLimit }, &MatchLimit::One.as_CFType());
-        attrs.add_boolean(unsafe { kSecReturnRef }, true);
-
-        let mut result: ItemRef = ptr::null_mut();
-        let status = unsafe {
-            SecItemCopyMatching(
-                Dictionary::from(attrs).as_concrete_TypeRef(),
-                &mut result as &mut CFTypeRef,
-            )
-        };
-
-        // Return an error if the status was unsuccessful
-        if let Some(e) = Error::maybe_from_OSStatus(status) {
-            return Err(e);
-        }
-
-        Ok(unsafe { Item::wrap_under_create_rule(result) })
+    fn find_item(&self, class: Class, query: Query) -> Result<Item, Error> {
+        let handle = self
+            .backend
+            .copy_matching(class, &query, MatchLimit::One)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::ItemNotFound, "no matching items found"))?;
+
+        Ok(Item::new(self.backend.clone(), handle, class))
     }
 
     /// Add an item to this keychain.
@@ -128,19 +239,9 @@ impl Keychain {
     ///
     /// Wrapper for the `SecItemAdd` function. See:
     /// <https://developer.apple.com/documentation/security/1401659-secitemadd>
-    fn add_item(&self, mut attrs: DictionaryBuilder) -> Result<Item, Error> {
-        attrs.add(unsafe { kSecUseKeychain }, self);
-        attrs.add_boolean(unsafe { kSecReturnRef }, true);
-
-        let mut result: ItemRef = ptr::null_mut();
-        let status =
-            unsafe { SecItemAdd(Dictionary::from(attrs).as_concrete_TypeRef(), &mut result) };
-
-        if let Some(e) = Error::maybe_from_OSStatus(status) {
-            Err(e)
-        } else {
-            Ok(unsafe { Item::wrap_under_create_rule(result) })
-        }
+    fn add_item(&self, class: Class, attrs: AttributeMap, data: &[u8]) -> Result<Item, Error> {
+        let handle = self.backend.add(class, &attrs, data)?;
+        Ok(Item::new(self.backend.clone(), handle, class))
     }
 }
 
@@ -9,6 +9,27 @@
 //! For more information on Keychain Services`, see:
 //! <https://developer.apple.com/documentation/security/keychain_services/keychains>
 //!
+//! ## Backends
+//!
+//! Keychains are backed by an implementation of the `KeychainBackend` trait.
+//! On macOS the default backend is Keychain Services itself. The pure Rust
+//! `MemoryBackend` is available on all platforms and is the default elsewhere,
+//! which is useful for testing code which uses this crate on e.g. Linux.
+//! The `FileBackend` stores items in a password-encrypted file, providing
+//! persistent keychains (see `Keychain::create`) on platforms other than
+//! macOS. On Linux desktops, the `SecretServiceBackend` stores passwords in
+//! the Secret Service (e.g. GNOME Keyring or KWallet), and for headless
+//! services the `KernelKeyringBackend` keeps them in the kernel's keyrings.
+//! The `Pkcs11Backend` keeps keys on a hardware token such as an HSM, which
+//! plays the role of the Secure Enclave on Linux.
+//!
+//! ## PKCS#11
+//!
+//! Conversely, the `pkcs11` module's `Pkcs11Provider` exposes the keys in any
+//! keychain to applications which support PKCS#11 modules, such as OpenSSL,
+//! OpenSSH, Firefox, and Java, by way of a `cdylib` crate like the
+//! `keychain-pkcs11` crate in this repository.
+//!
 //! ## Code Signing
 //!
 //! The Keychain Service API requires signed code to access much of its
@@ -42,26 +63,51 @@
 #![crate_type = "rlib"]
 #![allow(non_snake_case, non_upper_case_globals)]
 #![deny(warnings, missing_docs, unused_import_braces, unused_qualifications)]
+#![allow(clippy::tabs_in_doc_comments)] // the entitlements example is a verbatim plist
 
-#[cfg(not(target_os = "macos"))]
-compile_error!("This crate presently only compiles on macOS (see GH issue #5 for iOS support)");
-
+#[cfg(target_os = "macos")]
 #[macro_use]
 extern crate core_foundation;
 
 mod access;
 mod attr;
+pub mod backend;
 mod ciphertext;
+#[cfg(target_os = "macos")]
 mod dictionary;
+mod encoding;
+#[allow(non_local_definitions)] // `failure_derive` emits impls inside consts
 mod error;
+#[cfg(target_os = "macos")]
 mod ffi;
+mod fingerprint;
+mod hpke;
+pub mod jose;
 pub mod keychain;
+#[cfg(unix)]
+pub mod pkcs11;
 mod signature;
+mod software;
+#[cfg(unix)]
+pub mod ssh_agent;
 
 pub use crate::access::*;
 pub use crate::attr::*;
+pub use crate::backend::{FileBackend, Handle, KeychainBackend, MemoryBackend};
+#[cfg(target_os = "linux")]
+pub use crate::backend::{
+    KernelKeyring, KernelKeyringBackend, Pkcs11Backend, SecretServiceBackend,
+};
 pub use crate::ciphertext::*;
+pub use crate::encoding::{Jwk, Pbes2Cipher, Pbes2Kdf, Pbes2Params};
 pub use crate::error::*;
+pub use crate::fingerprint::*;
+pub use crate::hpke::*;
+pub use crate::jose::{Jwe, JweAlgorithm, JweEncryption, JweHeader, Jws, JwsAlgorithm, JwsHeader};
 pub use crate::key::*;
 pub use crate::keychain::*;
+#[cfg(unix)]
+pub use crate::pkcs11::Pkcs11Provider;
 pub use crate::signature::*;
+#[cfg(unix)]
+pub use crate::ssh_agent::SshAgent;
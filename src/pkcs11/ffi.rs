@@ -0,0 +1,700 @@
+//! PKCS#11 (Cryptoki) v2.40 interface definitions, i.e. the types,
+//! constants, and function list shared by PKCS#11 modules and the
+//! applications which load them.
+//!
+//! See the PKCS#11 Cryptographic Token Interface Base Specification:
+//! <https://docs.oasis-open.org/pkcs11/pkcs11-base/v2.40/pkcs11-base-v2.40.html>
+
+// Not every definition is used by both applications and modules
+#![allow(dead_code, non_camel_case_types)]
+
+use std::{ffi::c_void, os::raw::c_ulong};
+
+/// Unsigned integer of at least 32 bits (`unsigned long` on Unix)
+pub(crate) type CK_ULONG = c_ulong;
+
+/// Boolean value, i.e. `CK_TRUE` or `CK_FALSE`
+pub(crate) type CK_BBOOL = u8;
+
+/// Return values of PKCS#11 functions
+pub(crate) type CK_RV = CK_ULONG;
+
+/// Bit flags
+pub(crate) type CK_FLAGS = CK_ULONG;
+
+/// Identifier of a slot
+pub(crate) type CK_SLOT_ID = CK_ULONG;
+
+/// Identifier of a session
+pub(crate) type CK_SESSION_HANDLE = CK_ULONG;
+
+/// Identifier of an object
+pub(crate) type CK_OBJECT_HANDLE = CK_ULONG;
+
+/// Class of an object (`CKO_*`)
+pub(crate) type CK_OBJECT_CLASS = CK_ULONG;
+
+/// Type of a key (`CKK_*`)
+pub(crate) type CK_KEY_TYPE = CK_ULONG;
+
+/// Type of an attribute (`CKA_*`)
+pub(crate) type CK_ATTRIBUTE_TYPE = CK_ULONG;
+
+/// Type of a mechanism (`CKM_*`)
+pub(crate) type CK_MECHANISM_TYPE = CK_ULONG;
+
+/// Type of a user (`CKU_*`)
+pub(crate) type CK_USER_TYPE = CK_ULONG;
+
+/// State of a session (`CKS_*`)
+pub(crate) type CK_STATE = CK_ULONG;
+
+/// Type of notification passed to a `CK_NOTIFY` callback
+pub(crate) type CK_NOTIFICATION = CK_ULONG;
+
+/// Mask generation function used by RSA PSS and OAEP (`CKG_*`)
+pub(crate) type CK_RSA_PKCS_MGF_TYPE = CK_ULONG;
+
+/// Source of the OAEP encoding parameter (`CKZ_*`)
+pub(crate) type CK_RSA_PKCS_OAEP_SOURCE_TYPE = CK_ULONG;
+
+/// KDF applied to ECDH shared secrets (`CKD_*`)
+pub(crate) type CK_EC_KDF_TYPE = CK_ULONG;
+
+/// Callback applications pass to `C_OpenSession`
+pub(crate) type CK_NOTIFY =
+    Option<unsafe extern "C" fn(CK_SESSION_HANDLE, CK_NOTIFICATION, *mut c_void) -> CK_RV>;
+
+/// Value of `CK_ULONG` fields whose value is unavailable
+pub(crate) const CK_UNAVAILABLE_INFORMATION: CK_ULONG = !0;
+
+/// Value of `CK_ULONG` limits (e.g. session counts) which are unlimited
+pub(crate) const CK_EFFECTIVELY_INFINITE: CK_ULONG = 0;
+
+/// `CK_BBOOL` true value
+pub(crate) const CK_TRUE: CK_BBOOL = 1;
+
+/// `CK_BBOOL` false value
+pub(crate) const CK_FALSE: CK_BBOOL = 0;
+
+/// Version of PKCS#11 described by these definitions
+pub(crate) const CRYPTOKI_VERSION: CK_VERSION = CK_VERSION {
+    major: 2,
+    minor: 40,
+};
+
+/// Version numbers of libraries, tokens, and the PKCS#11 interface
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct CK_VERSION {
+    pub(crate) major: u8,
+    pub(crate) minor: u8,
+}
+
+/// General information about a PKCS#11 module (`C_GetInfo`)
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct CK_INFO {
+    pub(crate) cryptokiVersion: CK_VERSION,
+    pub(crate) manufacturerID: [u8; 32],
+    pub(crate) flags: CK_FLAGS,
+    pub(crate) libraryDescription: [u8; 32],
+    pub(crate) libraryVersion: CK_VERSION,
+}
+
+/// Information about a slot (`C_GetSlotInfo`)
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct CK_SLOT_INFO {
+    pub(crate) slotDescription: [u8; 64],
+    pub(crate) manufacturerID: [u8; 32],
+    pub(crate) flags: CK_FLAGS,
+    pub(crate) hardwareVersion: CK_VERSION,
+    pub(crate) firmwareVersion: CK_VERSION,
+}
+
+/// Information about a token (`C_GetTokenInfo`)
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct CK_TOKEN_INFO {
+    pub(crate) label: [u8; 32],
+    pub(crate) manufacturerID: [u8; 32],
+    pub(crate) model: [u8; 16],
+    pub(crate) serialNumber: [u8; 16],
+    pub(crate) flags: CK_FLAGS,
+    pub(crate) ulMaxSessionCount: CK_ULONG,
+    pub(crate) ulSessionCount: CK_ULONG,
+    pub(crate) ulMaxRwSessionCount: CK_ULONG,
+    pub(crate) ulRwSessionCount: CK_ULONG,
+    pub(crate) ulMaxPinLen: CK_ULONG,
+    pub(crate) ulMinPinLen: CK_ULONG,
+    pub(crate) ulTotalPublicMemory: CK_ULONG,
+    pub(crate) ulFreePublicMemory: CK_ULONG,
+    pub(crate) ulTotalPrivateMemory: CK_ULONG,
+    pub(crate) ulFreePrivateMemory: CK_ULONG,
+    pub(crate) hardwareVersion: CK_VERSION,
+    pub(crate) firmwareVersion: CK_VERSION,
+    pub(crate) utcTime: [u8; 16],
+}
+
+/// Information about a session (`C_GetSessionInfo`)
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct CK_SESSION_INFO {
+    pub(crate) slotID: CK_SLOT_ID,
+    pub(crate) state: CK_STATE,
+    pub(crate) flags: CK_FLAGS,
+    pub(crate) ulDeviceError: CK_ULONG,
+}
+
+/// Information about a mechanism (`C_GetMechanismInfo`)
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct CK_MECHANISM_INFO {
+    pub(crate) ulMinKeySize: CK_ULONG,
+    pub(crate) ulMaxKeySize: CK_ULONG,
+    pub(crate) flags: CK_FLAGS,
+}
+
+/// Type, value, and length of an attribute
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct CK_ATTRIBUTE {
+    pub(crate) type_: CK_ATTRIBUTE_TYPE,
+    pub(crate) pValue: *mut c_void,
+    pub(crate) ulValueLen: CK_ULONG,
+}
+
+/// Mechanism and its parameters
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct CK_MECHANISM {
+    pub(crate) mechanism: CK_MECHANISM_TYPE,
+    pub(crate) pParameter: *mut c_void,
+    pub(crate) ulParameterLen: CK_ULONG,
+}
+
+/// Parameters of `CKM_RSA_PKCS_PSS`
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct CK_RSA_PKCS_PSS_PARAMS {
+    pub(crate) hashAlg: CK_MECHANISM_TYPE,
+    pub(crate) mgf: CK_RSA_PKCS_MGF_TYPE,
+    pub(crate) sLen: CK_ULONG,
+}
+
+/// Parameters of `CKM_RSA_PKCS_OAEP`
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct CK_RSA_PKCS_OAEP_PARAMS {
+    pub(crate) hashAlg: CK_MECHANISM_TYPE,
+    pub(crate) mgf: CK_RSA_PKCS_MGF_TYPE,
+    pub(crate) source: CK_RSA_PKCS_OAEP_SOURCE_TYPE,
+    pub(crate) pSourceData: *mut c_void,
+    pub(crate) ulSourceDataLen: CK_ULONG,
+}
+
+/// Parameters of `CKM_ECDH1_DERIVE`
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct CK_ECDH1_DERIVE_PARAMS {
+    pub(crate) kdf: CK_EC_KDF_TYPE,
+    pub(crate) ulSharedDataLen: CK_ULONG,
+    pub(crate) pSharedData: *mut u8,
+    pub(crate) ulPublicDataLen: CK_ULONG,
+    pub(crate) pPublicData: *mut u8,
+}
+
+/// Arguments to `C_Initialize`
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct CK_C_INITIALIZE_ARGS {
+    pub(crate) CreateMutex: Option<unsafe extern "C" fn(*mut *mut c_void) -> CK_RV>,
+    pub(crate) DestroyMutex: Option<unsafe extern "C" fn(*mut c_void) -> CK_RV>,
+    pub(crate) LockMutex: Option<unsafe extern "C" fn(*mut c_void) -> CK_RV>,
+    pub(crate) UnlockMutex: Option<unsafe extern "C" fn(*mut c_void) -> CK_RV>,
+    pub(crate) flags: CK_FLAGS,
+    pub(crate) pReserved: *mut c_void,
+}
+
+/// Table of the functions exported by a PKCS#11 module, as returned by
+/// `C_GetFunctionList`. Fields must remain in the order the specification
+/// lists them.
+#[repr(C)]
+pub(crate) struct CK_FUNCTION_LIST {
+    pub(crate) version: CK_VERSION,
+    pub(crate) C_Initialize: Option<unsafe extern "C" fn(*mut c_void) -> CK_RV>,
+    pub(crate) C_Finalize: Option<unsafe extern "C" fn(*mut c_void) -> CK_RV>,
+    pub(crate) C_GetInfo: Option<unsafe extern "C" fn(*mut CK_INFO) -> CK_RV>,
+    pub(crate) C_GetFunctionList:
+        Option<unsafe extern "C" fn(*mut *const CK_FUNCTION_LIST) -> CK_RV>,
+    pub(crate) C_GetSlotList:
+        Option<unsafe extern "C" fn(CK_BBOOL, *mut CK_SLOT_ID, *mut CK_ULONG) -> CK_RV>,
+    pub(crate) C_GetSlotInfo: Option<unsafe extern "C" fn(CK_SLOT_ID, *mut CK_SLOT_INFO) -> CK_RV>,
+    pub(crate) C_GetTokenInfo:
+        Option<unsafe extern "C" fn(CK_SLOT_ID, *mut CK_TOKEN_INFO) -> CK_RV>,
+    pub(crate) C_GetMechanismList:
+        Option<unsafe extern "C" fn(CK_SLOT_ID, *mut CK_MECHANISM_TYPE, *mut CK_ULONG) -> CK_RV>,
+    pub(crate) C_GetMechanismInfo: Option<
+        unsafe extern "C" fn(CK_SLOT_ID, CK_MECHANISM_TYPE, *mut CK_MECHANISM_INFO) -> CK_RV,
+    >,
+    pub(crate) C_InitToken:
+        Option<unsafe extern "C" fn(CK_SLOT_ID, *mut u8, CK_ULONG, *mut u8) -> CK_RV>,
+    pub(crate) C_InitPIN:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG) -> CK_RV>,
+    pub(crate) C_SetPIN: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, CK_ULONG) -> CK_RV,
+    >,
+    pub(crate) C_OpenSession: Option<
+        unsafe extern "C" fn(
+            CK_SLOT_ID,
+            CK_FLAGS,
+            *mut c_void,
+            CK_NOTIFY,
+            *mut CK_SESSION_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_CloseSession: Option<unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV>,
+    pub(crate) C_CloseAllSessions: Option<unsafe extern "C" fn(CK_SLOT_ID) -> CK_RV>,
+    pub(crate) C_GetSessionInfo:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_SESSION_INFO) -> CK_RV>,
+    pub(crate) C_GetOperationState:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, *mut CK_ULONG) -> CK_RV>,
+    pub(crate) C_SetOperationState: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            *mut u8,
+            CK_ULONG,
+            CK_OBJECT_HANDLE,
+            CK_OBJECT_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_Login:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, CK_USER_TYPE, *mut u8, CK_ULONG) -> CK_RV>,
+    pub(crate) C_Logout: Option<unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV>,
+    pub(crate) C_CreateObject: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            *mut CK_ATTRIBUTE,
+            CK_ULONG,
+            *mut CK_OBJECT_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_CopyObject: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            CK_OBJECT_HANDLE,
+            *mut CK_ATTRIBUTE,
+            CK_ULONG,
+            *mut CK_OBJECT_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_DestroyObject:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, CK_OBJECT_HANDLE) -> CK_RV>,
+    pub(crate) C_GetObjectSize:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, CK_OBJECT_HANDLE, *mut CK_ULONG) -> CK_RV>,
+    pub(crate) C_GetAttributeValue: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            CK_OBJECT_HANDLE,
+            *mut CK_ATTRIBUTE,
+            CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_SetAttributeValue: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            CK_OBJECT_HANDLE,
+            *mut CK_ATTRIBUTE,
+            CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_FindObjectsInit:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_ATTRIBUTE, CK_ULONG) -> CK_RV>,
+    pub(crate) C_FindObjects: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            *mut CK_OBJECT_HANDLE,
+            CK_ULONG,
+            *mut CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_FindObjectsFinal: Option<unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV>,
+    pub(crate) C_EncryptInit: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE) -> CK_RV,
+    >,
+    pub(crate) C_Encrypt: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG) -> CK_RV,
+    >,
+    pub(crate) C_EncryptUpdate: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG) -> CK_RV,
+    >,
+    pub(crate) C_EncryptFinal:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, *mut CK_ULONG) -> CK_RV>,
+    pub(crate) C_DecryptInit: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE) -> CK_RV,
+    >,
+    pub(crate) C_Decrypt: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG) -> CK_RV,
+    >,
+    pub(crate) C_DecryptUpdate: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG) -> CK_RV,
+    >,
+    pub(crate) C_DecryptFinal:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, *mut CK_ULONG) -> CK_RV>,
+    pub(crate) C_DigestInit:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_MECHANISM) -> CK_RV>,
+    pub(crate) C_Digest: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG) -> CK_RV,
+    >,
+    pub(crate) C_DigestUpdate:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG) -> CK_RV>,
+    pub(crate) C_DigestKey:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, CK_OBJECT_HANDLE) -> CK_RV>,
+    pub(crate) C_DigestFinal:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, *mut CK_ULONG) -> CK_RV>,
+    pub(crate) C_SignInit: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE) -> CK_RV,
+    >,
+    pub(crate) C_Sign: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG) -> CK_RV,
+    >,
+    pub(crate) C_SignUpdate:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG) -> CK_RV>,
+    pub(crate) C_SignFinal:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, *mut CK_ULONG) -> CK_RV>,
+    pub(crate) C_SignRecoverInit: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE) -> CK_RV,
+    >,
+    pub(crate) C_SignRecover: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG) -> CK_RV,
+    >,
+    pub(crate) C_VerifyInit: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE) -> CK_RV,
+    >,
+    pub(crate) C_Verify: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, CK_ULONG) -> CK_RV,
+    >,
+    pub(crate) C_VerifyUpdate:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG) -> CK_RV>,
+    pub(crate) C_VerifyFinal:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG) -> CK_RV>,
+    pub(crate) C_VerifyRecoverInit: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE) -> CK_RV,
+    >,
+    pub(crate) C_VerifyRecover: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG) -> CK_RV,
+    >,
+    pub(crate) C_DigestEncryptUpdate: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG) -> CK_RV,
+    >,
+    pub(crate) C_DecryptDigestUpdate: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG) -> CK_RV,
+    >,
+    pub(crate) C_SignEncryptUpdate: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG) -> CK_RV,
+    >,
+    pub(crate) C_DecryptVerifyUpdate: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG) -> CK_RV,
+    >,
+    pub(crate) C_GenerateKey: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            *mut CK_MECHANISM,
+            *mut CK_ATTRIBUTE,
+            CK_ULONG,
+            *mut CK_OBJECT_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_GenerateKeyPair: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            *mut CK_MECHANISM,
+            *mut CK_ATTRIBUTE,
+            CK_ULONG,
+            *mut CK_ATTRIBUTE,
+            CK_ULONG,
+            *mut CK_OBJECT_HANDLE,
+            *mut CK_OBJECT_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_WrapKey: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            *mut CK_MECHANISM,
+            CK_OBJECT_HANDLE,
+            CK_OBJECT_HANDLE,
+            *mut u8,
+            *mut CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_UnwrapKey: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            *mut CK_MECHANISM,
+            CK_OBJECT_HANDLE,
+            *mut u8,
+            CK_ULONG,
+            *mut CK_ATTRIBUTE,
+            CK_ULONG,
+            *mut CK_OBJECT_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_DeriveKey: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            *mut CK_MECHANISM,
+            CK_OBJECT_HANDLE,
+            *mut CK_ATTRIBUTE,
+            CK_ULONG,
+            *mut CK_OBJECT_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub(crate) C_SeedRandom:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG) -> CK_RV>,
+    pub(crate) C_GenerateRandom:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut u8, CK_ULONG) -> CK_RV>,
+    pub(crate) C_GetFunctionStatus: Option<unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV>,
+    pub(crate) C_CancelFunction: Option<unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV>,
+    pub(crate) C_WaitForSlotEvent:
+        Option<unsafe extern "C" fn(CK_FLAGS, *mut CK_SLOT_ID, *mut c_void) -> CK_RV>,
+}
+
+/// Signature of `C_GetFunctionList`, the only symbol applications look up
+/// in a PKCS#11 module
+pub(crate) type CK_C_GetFunctionList = unsafe extern "C" fn(*mut *const CK_FUNCTION_LIST) -> CK_RV;
+
+// Return values (`CKR_*`)
+pub(crate) const CKR_OK: CK_RV = 0x0000;
+pub(crate) const CKR_HOST_MEMORY: CK_RV = 0x0002;
+pub(crate) const CKR_SLOT_ID_INVALID: CK_RV = 0x0003;
+pub(crate) const CKR_GENERAL_ERROR: CK_RV = 0x0005;
+pub(crate) const CKR_FUNCTION_FAILED: CK_RV = 0x0006;
+pub(crate) const CKR_ARGUMENTS_BAD: CK_RV = 0x0007;
+pub(crate) const CKR_ATTRIBUTE_READ_ONLY: CK_RV = 0x0010;
+pub(crate) const CKR_ATTRIBUTE_SENSITIVE: CK_RV = 0x0011;
+pub(crate) const CKR_ATTRIBUTE_TYPE_INVALID: CK_RV = 0x0012;
+pub(crate) const CKR_ATTRIBUTE_VALUE_INVALID: CK_RV = 0x0013;
+pub(crate) const CKR_DATA_INVALID: CK_RV = 0x0020;
+pub(crate) const CKR_DATA_LEN_RANGE: CK_RV = 0x0021;
+pub(crate) const CKR_DEVICE_ERROR: CK_RV = 0x0030;
+pub(crate) const CKR_DEVICE_MEMORY: CK_RV = 0x0031;
+pub(crate) const CKR_DEVICE_REMOVED: CK_RV = 0x0032;
+pub(crate) const CKR_ENCRYPTED_DATA_INVALID: CK_RV = 0x0040;
+pub(crate) const CKR_ENCRYPTED_DATA_LEN_RANGE: CK_RV = 0x0041;
+pub(crate) const CKR_FUNCTION_CANCELED: CK_RV = 0x0050;
+pub(crate) const CKR_FUNCTION_NOT_SUPPORTED: CK_RV = 0x0054;
+pub(crate) const CKR_KEY_HANDLE_INVALID: CK_RV = 0x0060;
+pub(crate) const CKR_KEY_SIZE_RANGE: CK_RV = 0x0062;
+pub(crate) const CKR_KEY_TYPE_INCONSISTENT: CK_RV = 0x0063;
+pub(crate) const CKR_KEY_FUNCTION_NOT_PERMITTED: CK_RV = 0x0068;
+pub(crate) const CKR_MECHANISM_INVALID: CK_RV = 0x0070;
+pub(crate) const CKR_MECHANISM_PARAM_INVALID: CK_RV = 0x0071;
+pub(crate) const CKR_OBJECT_HANDLE_INVALID: CK_RV = 0x0082;
+pub(crate) const CKR_OPERATION_ACTIVE: CK_RV = 0x0090;
+pub(crate) const CKR_OPERATION_NOT_INITIALIZED: CK_RV = 0x0091;
+pub(crate) const CKR_PIN_INCORRECT: CK_RV = 0x00A0;
+pub(crate) const CKR_PIN_INVALID: CK_RV = 0x00A1;
+pub(crate) const CKR_PIN_LEN_RANGE: CK_RV = 0x00A2;
+pub(crate) const CKR_PIN_EXPIRED: CK_RV = 0x00A3;
+pub(crate) const CKR_PIN_LOCKED: CK_RV = 0x00A4;
+pub(crate) const CKR_SESSION_HANDLE_INVALID: CK_RV = 0x00B3;
+pub(crate) const CKR_SESSION_PARALLEL_NOT_SUPPORTED: CK_RV = 0x00B4;
+pub(crate) const CKR_SESSION_READ_ONLY: CK_RV = 0x00B5;
+pub(crate) const CKR_SIGNATURE_INVALID: CK_RV = 0x00C0;
+pub(crate) const CKR_SIGNATURE_LEN_RANGE: CK_RV = 0x00C1;
+pub(crate) const CKR_TEMPLATE_INCOMPLETE: CK_RV = 0x00D0;
+pub(crate) const CKR_TEMPLATE_INCONSISTENT: CK_RV = 0x00D1;
+pub(crate) const CKR_TOKEN_NOT_PRESENT: CK_RV = 0x00E0;
+pub(crate) const CKR_TOKEN_NOT_RECOGNIZED: CK_RV = 0x00E1;
+pub(crate) const CKR_TOKEN_WRITE_PROTECTED: CK_RV = 0x00E2;
+pub(crate) const CKR_USER_ALREADY_LOGGED_IN: CK_RV = 0x0100;
+pub(crate) const CKR_USER_NOT_LOGGED_IN: CK_RV = 0x0101;
+pub(crate) const CKR_USER_TYPE_INVALID: CK_RV = 0x0103;
+pub(crate) const CKR_BUFFER_TOO_SMALL: CK_RV = 0x0150;
+pub(crate) const CKR_CRYPTOKI_NOT_INITIALIZED: CK_RV = 0x0190;
+pub(crate) const CKR_CRYPTOKI_ALREADY_INITIALIZED: CK_RV = 0x0191;
+
+// Object classes (`CKO_*`)
+pub(crate) const CKO_PUBLIC_KEY: CK_OBJECT_CLASS = 0x0002;
+pub(crate) const CKO_PRIVATE_KEY: CK_OBJECT_CLASS = 0x0003;
+pub(crate) const CKO_SECRET_KEY: CK_OBJECT_CLASS = 0x0004;
+
+// Key types (`CKK_*`)
+pub(crate) const CKK_RSA: CK_KEY_TYPE = 0x0000;
+pub(crate) const CKK_EC: CK_KEY_TYPE = 0x0003;
+pub(crate) const CKK_GENERIC_SECRET: CK_KEY_TYPE = 0x0010;
+
+// Attribute types (`CKA_*`)
+pub(crate) const CKA_CLASS: CK_ATTRIBUTE_TYPE = 0x0000;
+pub(crate) const CKA_TOKEN: CK_ATTRIBUTE_TYPE = 0x0001;
+pub(crate) const CKA_PRIVATE: CK_ATTRIBUTE_TYPE = 0x0002;
+pub(crate) const CKA_LABEL: CK_ATTRIBUTE_TYPE = 0x0003;
+pub(crate) const CKA_VALUE: CK_ATTRIBUTE_TYPE = 0x0011;
+pub(crate) const CKA_KEY_TYPE: CK_ATTRIBUTE_TYPE = 0x0100;
+pub(crate) const CKA_ID: CK_ATTRIBUTE_TYPE = 0x0102;
+pub(crate) const CKA_SENSITIVE: CK_ATTRIBUTE_TYPE = 0x0103;
+pub(crate) const CKA_ENCRYPT: CK_ATTRIBUTE_TYPE = 0x0104;
+pub(crate) const CKA_DECRYPT: CK_ATTRIBUTE_TYPE = 0x0105;
+pub(crate) const CKA_WRAP: CK_ATTRIBUTE_TYPE = 0x0106;
+pub(crate) const CKA_UNWRAP: CK_ATTRIBUTE_TYPE = 0x0107;
+pub(crate) const CKA_SIGN: CK_ATTRIBUTE_TYPE = 0x0108;
+pub(crate) const CKA_VERIFY: CK_ATTRIBUTE_TYPE = 0x010A;
+pub(crate) const CKA_DERIVE: CK_ATTRIBUTE_TYPE = 0x010C;
+pub(crate) const CKA_MODULUS: CK_ATTRIBUTE_TYPE = 0x0120;
+pub(crate) const CKA_MODULUS_BITS: CK_ATTRIBUTE_TYPE = 0x0121;
+pub(crate) const CKA_PUBLIC_EXPONENT: CK_ATTRIBUTE_TYPE = 0x0122;
+pub(crate) const CKA_PRIVATE_EXPONENT: CK_ATTRIBUTE_TYPE = 0x0123;
+pub(crate) const CKA_PRIME_1: CK_ATTRIBUTE_TYPE = 0x0124;
+pub(crate) const CKA_PRIME_2: CK_ATTRIBUTE_TYPE = 0x0125;
+pub(crate) const CKA_EXPONENT_1: CK_ATTRIBUTE_TYPE = 0x0126;
+pub(crate) const CKA_EXPONENT_2: CK_ATTRIBUTE_TYPE = 0x0127;
+pub(crate) const CKA_COEFFICIENT: CK_ATTRIBUTE_TYPE = 0x0128;
+pub(crate) const CKA_VALUE_LEN: CK_ATTRIBUTE_TYPE = 0x0161;
+pub(crate) const CKA_EXTRACTABLE: CK_ATTRIBUTE_TYPE = 0x0162;
+pub(crate) const CKA_ALWAYS_SENSITIVE: CK_ATTRIBUTE_TYPE = 0x0165;
+pub(crate) const CKA_NEVER_EXTRACTABLE: CK_ATTRIBUTE_TYPE = 0x0164;
+pub(crate) const CKA_MODIFIABLE: CK_ATTRIBUTE_TYPE = 0x0170;
+pub(crate) const CKA_EC_PARAMS: CK_ATTRIBUTE_TYPE = 0x0180;
+pub(crate) const CKA_EC_POINT: CK_ATTRIBUTE_TYPE = 0x0181;
+pub(crate) const CKA_ALWAYS_AUTHENTICATE: CK_ATTRIBUTE_TYPE = 0x0202;
+
+// Mechanisms (`CKM_*`)
+pub(crate) const CKM_RSA_PKCS_KEY_PAIR_GEN: CK_MECHANISM_TYPE = 0x0000;
+pub(crate) const CKM_RSA_PKCS: CK_MECHANISM_TYPE = 0x0001;
+pub(crate) const CKM_RSA_X_509: CK_MECHANISM_TYPE = 0x0003;
+pub(crate) const CKM_RSA_PKCS_OAEP: CK_MECHANISM_TYPE = 0x0009;
+pub(crate) const CKM_RSA_PKCS_PSS: CK_MECHANISM_TYPE = 0x000D;
+pub(crate) const CKM_SHA1_RSA_PKCS: CK_MECHANISM_TYPE = 0x0006;
+pub(crate) const CKM_SHA256_RSA_PKCS: CK_MECHANISM_TYPE = 0x0040;
+pub(crate) const CKM_SHA384_RSA_PKCS: CK_MECHANISM_TYPE = 0x0041;
+pub(crate) const CKM_SHA512_RSA_PKCS: CK_MECHANISM_TYPE = 0x0042;
+pub(crate) const CKM_SHA224_RSA_PKCS: CK_MECHANISM_TYPE = 0x0046;
+pub(crate) const CKM_SHA1_RSA_PKCS_PSS: CK_MECHANISM_TYPE = 0x000E;
+pub(crate) const CKM_SHA256_RSA_PKCS_PSS: CK_MECHANISM_TYPE = 0x0043;
+pub(crate) const CKM_SHA384_RSA_PKCS_PSS: CK_MECHANISM_TYPE = 0x0044;
+pub(crate) const CKM_SHA512_RSA_PKCS_PSS: CK_MECHANISM_TYPE = 0x0045;
+pub(crate) const CKM_SHA224_RSA_PKCS_PSS: CK_MECHANISM_TYPE = 0x0047;
+pub(crate) const CKM_SHA_1: CK_MECHANISM_TYPE = 0x0220;
+pub(crate) const CKM_SHA224: CK_MECHANISM_TYPE = 0x0255;
+pub(crate) const CKM_SHA256: CK_MECHANISM_TYPE = 0x0250;
+pub(crate) const CKM_SHA384: CK_MECHANISM_TYPE = 0x0260;
+pub(crate) const CKM_SHA512: CK_MECHANISM_TYPE = 0x0270;
+pub(crate) const CKM_EC_KEY_PAIR_GEN: CK_MECHANISM_TYPE = 0x1040;
+pub(crate) const CKM_ECDSA: CK_MECHANISM_TYPE = 0x1041;
+pub(crate) const CKM_ECDSA_SHA1: CK_MECHANISM_TYPE = 0x1042;
+pub(crate) const CKM_ECDSA_SHA224: CK_MECHANISM_TYPE = 0x1043;
+pub(crate) const CKM_ECDSA_SHA256: CK_MECHANISM_TYPE = 0x1044;
+pub(crate) const CKM_ECDSA_SHA384: CK_MECHANISM_TYPE = 0x1045;
+pub(crate) const CKM_ECDSA_SHA512: CK_MECHANISM_TYPE = 0x1046;
+pub(crate) const CKM_ECDH1_DERIVE: CK_MECHANISM_TYPE = 0x1050;
+
+// Mask generation functions (`CKG_*`)
+pub(crate) const CKG_MGF1_SHA1: CK_RSA_PKCS_MGF_TYPE = 0x0001;
+pub(crate) const CKG_MGF1_SHA256: CK_RSA_PKCS_MGF_TYPE = 0x0002;
+pub(crate) const CKG_MGF1_SHA384: CK_RSA_PKCS_MGF_TYPE = 0x0003;
+pub(crate) const CKG_MGF1_SHA512: CK_RSA_PKCS_MGF_TYPE = 0x0004;
+pub(crate) const CKG_MGF1_SHA224: CK_RSA_PKCS_MGF_TYPE = 0x0005;
+
+/// OAEP encoding parameter is specified in `pSourceData`
+pub(crate) const CKZ_DATA_SPECIFIED: CK_RSA_PKCS_OAEP_SOURCE_TYPE = 0x0001;
+
+/// ECDH shared secrets are used as-is, without a KDF
+pub(crate) const CKD_NULL: CK_EC_KDF_TYPE = 0x0001;
+
+// User types (`CKU_*`)
+pub(crate) const CKU_SO: CK_USER_TYPE = 0;
+pub(crate) const CKU_USER: CK_USER_TYPE = 1;
+
+// Session states (`CKS_*`)
+pub(crate) const CKS_RO_PUBLIC_SESSION: CK_STATE = 0;
+pub(crate) const CKS_RO_USER_FUNCTIONS: CK_STATE = 1;
+pub(crate) const CKS_RW_PUBLIC_SESSION: CK_STATE = 2;
+pub(crate) const CKS_RW_USER_FUNCTIONS: CK_STATE = 3;
+
+// Flags (`CKF_*`)
+pub(crate) const CKF_TOKEN_PRESENT: CK_FLAGS = 0x0001;
+pub(crate) const CKF_HW_SLOT: CK_FLAGS = 0x0004;
+pub(crate) const CKF_RW_SESSION: CK_FLAGS = 0x0002;
+pub(crate) const CKF_SERIAL_SESSION: CK_FLAGS = 0x0004;
+pub(crate) const CKF_OS_LOCKING_OK: CK_FLAGS = 0x0002;
+pub(crate) const CKF_LOGIN_REQUIRED: CK_FLAGS = 0x0004;
+pub(crate) const CKF_USER_PIN_INITIALIZED: CK_FLAGS = 0x0008;
+pub(crate) const CKF_PROTECTED_AUTHENTICATION_PATH: CK_FLAGS = 0x0100;
+pub(crate) const CKF_TOKEN_INITIALIZED: CK_FLAGS = 0x0400;
+pub(crate) const CKF_HW: CK_FLAGS = 0x0001;
+pub(crate) const CKF_ENCRYPT: CK_FLAGS = 0x0100;
+pub(crate) const CKF_DECRYPT: CK_FLAGS = 0x0200;
+pub(crate) const CKF_SIGN: CK_FLAGS = 0x0800;
+pub(crate) const CKF_VERIFY: CK_FLAGS = 0x2000;
+pub(crate) const CKF_GENERATE_KEY_PAIR: CK_FLAGS = 0x10000;
+pub(crate) const CKF_DERIVE: CK_FLAGS = 0x80000;
+pub(crate) const CKF_EC_F_P: CK_FLAGS = 0x0010_0000;
+pub(crate) const CKF_EC_UNCOMPRESS: CK_FLAGS = 0x0100_0000;
+
+/// DER encoding of the object identifier of NIST P-256 (`prime256v1`),
+/// as stored in `CKA_EC_PARAMS`
+pub(crate) const EC_PARAMS_P256: &[u8] =
+    &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+
+/// DER encoding of the object identifier of NIST P-384 (`secp384r1`)
+pub(crate) const EC_PARAMS_P384: &[u8] = &[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x22];
+
+/// DER encoding of the object identifier of NIST P-521 (`secp521r1`)
+pub(crate) const EC_PARAMS_P521: &[u8] = &[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x23];
+
+/// Get the name of a return value, for error messages
+pub(crate) fn rv_name(rv: CK_RV) -> Option<&'static str> {
+    Some(match rv {
+        CKR_OK => "CKR_OK",
+        CKR_HOST_MEMORY => "CKR_HOST_MEMORY",
+        CKR_SLOT_ID_INVALID => "CKR_SLOT_ID_INVALID",
+        CKR_GENERAL_ERROR => "CKR_GENERAL_ERROR",
+        CKR_FUNCTION_FAILED => "CKR_FUNCTION_FAILED",
+        CKR_ARGUMENTS_BAD => "CKR_ARGUMENTS_BAD",
+        CKR_ATTRIBUTE_READ_ONLY => "CKR_ATTRIBUTE_READ_ONLY",
+        CKR_ATTRIBUTE_SENSITIVE => "CKR_ATTRIBUTE_SENSITIVE",
+        CKR_ATTRIBUTE_TYPE_INVALID => "CKR_ATTRIBUTE_TYPE_INVALID",
+        CKR_ATTRIBUTE_VALUE_INVALID => "CKR_ATTRIBUTE_VALUE_INVALID",
+        CKR_DATA_INVALID => "CKR_DATA_INVALID",
+        CKR_DATA_LEN_RANGE => "CKR_DATA_LEN_RANGE",
+        CKR_DEVICE_ERROR => "CKR_DEVICE_ERROR",
+        CKR_DEVICE_MEMORY => "CKR_DEVICE_MEMORY",
+        CKR_DEVICE_REMOVED => "CKR_DEVICE_REMOVED",
+        CKR_ENCRYPTED_DATA_INVALID => "CKR_ENCRYPTED_DATA_INVALID",
+        CKR_ENCRYPTED_DATA_LEN_RANGE => "CKR_ENCRYPTED_DATA_LEN_RANGE",
+        CKR_FUNCTION_CANCELED => "CKR_FUNCTION_CANCELED",
+        CKR_FUNCTION_NOT_SUPPORTED => "CKR_FUNCTION_NOT_SUPPORTED",
+        CKR_KEY_HANDLE_INVALID => "CKR_KEY_HANDLE_INVALID",
+        CKR_KEY_SIZE_RANGE => "CKR_KEY_SIZE_RANGE",
+        CKR_KEY_TYPE_INCONSISTENT => "CKR_KEY_TYPE_INCONSISTENT",
+        CKR_KEY_FUNCTION_NOT_PERMITTED => "CKR_KEY_FUNCTION_NOT_PERMITTED",
+        CKR_MECHANISM_INVALID => "CKR_MECHANISM_INVALID",
+        CKR_MECHANISM_PARAM_INVALID => "CKR_MECHANISM_PARAM_INVALID",
+        CKR_OBJECT_HANDLE_INVALID => "CKR_OBJECT_HANDLE_INVALID",
+        CKR_OPERATION_ACTIVE => "CKR_OPERATION_ACTIVE",
+        CKR_OPERATION_NOT_INITIALIZED => "CKR_OPERATION_NOT_INITIALIZED",
+        CKR_PIN_INCORRECT => "CKR_PIN_INCORRECT",
+        CKR_PIN_INVALID => "CKR_PIN_INVALID",
+        CKR_PIN_LEN_RANGE => "CKR_PIN_LEN_RANGE",
+        CKR_PIN_EXPIRED => "CKR_PIN_EXPIRED",
+        CKR_PIN_LOCKED => "CKR_PIN_LOCKED",
+        CKR_SESSION_HANDLE_INVALID => "CKR_SESSION_HANDLE_INVALID",
+        CKR_SESSION_PARALLEL_NOT_SUPPORTED => "CKR_SESSION_PARALLEL_NOT_SUPPORTED",
+        CKR_SESSION_READ_ONLY => "CKR_SESSION_READ_ONLY",
+        CKR_SIGNATURE_INVALID => "CKR_SIGNATURE_INVALID",
+        CKR_SIGNATURE_LEN_RANGE => "CKR_SIGNATURE_LEN_RANGE",
+        CKR_TEMPLATE_INCOMPLETE => "CKR_TEMPLATE_INCOMPLETE",
+        CKR_TEMPLATE_INCONSISTENT => "CKR_TEMPLATE_INCONSISTENT",
+        CKR_TOKEN_NOT_PRESENT => "CKR_TOKEN_NOT_PRESENT",
+        CKR_TOKEN_NOT_RECOGNIZED => "CKR_TOKEN_NOT_RECOGNIZED",
+        CKR_TOKEN_WRITE_PROTECTED => "CKR_TOKEN_WRITE_PROTECTED",
+        CKR_USER_ALREADY_LOGGED_IN => "CKR_USER_ALREADY_LOGGED_IN",
+        CKR_USER_NOT_LOGGED_IN => "CKR_USER_NOT_LOGGED_IN",
+        CKR_USER_TYPE_INVALID => "CKR_USER_TYPE_INVALID",
+        CKR_BUFFER_TOO_SMALL => "CKR_BUFFER_TOO_SMALL",
+        CKR_CRYPTOKI_NOT_INITIALIZED => "CKR_CRYPTOKI_NOT_INITIALIZED",
+        CKR_CRYPTOKI_ALREADY_INITIALIZED => "CKR_CRYPTOKI_ALREADY_INITIALIZED",
+        _ => return None,
+    })
+}
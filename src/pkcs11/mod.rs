@@ -0,0 +1,17 @@
+//! PKCS#11 (Cryptoki) provider which exposes keys stored in a keychain, e.g.
+//! Secure Enclave keys, to applications which support PKCS#11 modules such
+//! as OpenSSL, OpenSSH (via `PKCS11Provider`), Firefox, and Java.
+//!
+//! This module implements the PKCS#11 API in terms of `Keychain` and `Key`,
+//! but a PKCS#11 module is a shared library: to build one, create a `cdylib`
+//! crate which exports a `C_GetFunctionList` function that installs a
+//! `Pkcs11Provider` and then calls this module's `C_GetFunctionList` (see
+//! the `keychain-pkcs11` crate in this repository).
+//!
+//! See the PKCS#11 Cryptographic Token Interface Base Specification:
+//! <https://docs.oasis-open.org/pkcs11/pkcs11-base/v2.40/pkcs11-base-v2.40.html>
+
+pub(crate) mod ffi;
+mod provider;
+
+pub use self::provider::{C_GetFunctionList, Pkcs11Provider};
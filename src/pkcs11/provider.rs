@@ -0,0 +1,1916 @@
+//! PKCS#11 provider: implementation of the PKCS#11 functions in terms of a
+//! keychain's keys.
+
+use super::ffi::*;
+use crate::{
+    attr::{AttrKeyClass, AttrKeyType},
+    ciphertext::Ciphertext,
+    error::{Error, ErrorKind},
+    key::{DigestAlgorithm, EcCurve, Key, KeyAlgorithm, KeyOperation, KeyPairGenerateParams},
+    keychain::{item::Query, Keychain},
+    software::PublicKey,
+};
+use der::{asn1::OctetStringRef, Encode};
+use rsa::traits::PublicKeyParts;
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryInto,
+    ffi::c_void,
+    fmt::{self, Debug},
+    mem::size_of,
+    os::raw::c_ulong,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice, str,
+    sync::{Mutex, MutexGuard, OnceLock},
+};
+use zeroize::Zeroizing;
+
+/// Identifier of the provider's only slot
+const SLOT_ID: CK_SLOT_ID = 0;
+
+/// Default label of the provider's token
+const DEFAULT_LABEL: &str = "keychain-services";
+
+/// Manufacturer reported for the library, slot, and token
+const MANUFACTURER_ID: &str = "keychain-services";
+
+/// Longest PIN (i.e. keychain password) accepted by `C_Login`
+const MAX_PIN_LEN: CK_ULONG = 1024;
+
+/// Mechanisms the provider supports, along with the type of key they're
+/// used with and their `CKF_*` flags
+const MECHANISMS: &[(CK_MECHANISM_TYPE, AttrKeyType, CK_FLAGS)] = &[
+    (CKM_ECDSA, AttrKeyType::EcSecPrimeRandom, CKF_SIGN),
+    (CKM_ECDSA_SHA1, AttrKeyType::EcSecPrimeRandom, CKF_SIGN),
+    (CKM_ECDSA_SHA224, AttrKeyType::EcSecPrimeRandom, CKF_SIGN),
+    (CKM_ECDSA_SHA256, AttrKeyType::EcSecPrimeRandom, CKF_SIGN),
+    (CKM_ECDSA_SHA384, AttrKeyType::EcSecPrimeRandom, CKF_SIGN),
+    (CKM_ECDSA_SHA512, AttrKeyType::EcSecPrimeRandom, CKF_SIGN),
+    (
+        CKM_EC_KEY_PAIR_GEN,
+        AttrKeyType::EcSecPrimeRandom,
+        CKF_GENERATE_KEY_PAIR,
+    ),
+    (CKM_RSA_X_509, AttrKeyType::Rsa, CKF_SIGN | CKF_DECRYPT),
+    (CKM_RSA_PKCS, AttrKeyType::Rsa, CKF_SIGN | CKF_DECRYPT),
+    (CKM_SHA1_RSA_PKCS, AttrKeyType::Rsa, CKF_SIGN),
+    (CKM_SHA224_RSA_PKCS, AttrKeyType::Rsa, CKF_SIGN),
+    (CKM_SHA256_RSA_PKCS, AttrKeyType::Rsa, CKF_SIGN),
+    (CKM_SHA384_RSA_PKCS, AttrKeyType::Rsa, CKF_SIGN),
+    (CKM_SHA512_RSA_PKCS, AttrKeyType::Rsa, CKF_SIGN),
+    (CKM_RSA_PKCS_PSS, AttrKeyType::Rsa, CKF_SIGN),
+    (CKM_SHA1_RSA_PKCS_PSS, AttrKeyType::Rsa, CKF_SIGN),
+    (CKM_SHA224_RSA_PKCS_PSS, AttrKeyType::Rsa, CKF_SIGN),
+    (CKM_SHA256_RSA_PKCS_PSS, AttrKeyType::Rsa, CKF_SIGN),
+    (CKM_SHA384_RSA_PKCS_PSS, AttrKeyType::Rsa, CKF_SIGN),
+    (CKM_SHA512_RSA_PKCS_PSS, AttrKeyType::Rsa, CKF_SIGN),
+    (CKM_RSA_PKCS_OAEP, AttrKeyType::Rsa, CKF_DECRYPT),
+    (
+        CKM_RSA_PKCS_KEY_PAIR_GEN,
+        AttrKeyType::Rsa,
+        CKF_GENERATE_KEY_PAIR,
+    ),
+];
+
+/// Attributes of private keys whose values are never revealed
+const SENSITIVE_ATTRIBUTES: &[CK_ATTRIBUTE_TYPE] = &[
+    CKA_VALUE,
+    CKA_PRIVATE_EXPONENT,
+    CKA_PRIME_1,
+    CKA_PRIME_2,
+    CKA_EXPONENT_1,
+    CKA_EXPONENT_2,
+    CKA_COEFFICIENT,
+];
+
+/// Boolean attributes of key pair templates which map onto
+/// `KeyPairGenerateParams`, along with whether they're set on the private
+/// (as opposed to public) key
+#[allow(clippy::type_complexity)]
+const KEY_PAIR_PARAMS: &[(
+    CK_ATTRIBUTE_TYPE,
+    bool,
+    fn(KeyPairGenerateParams, bool) -> KeyPairGenerateParams,
+)] = &[
+    (CKA_SIGN, true, KeyPairGenerateParams::can_sign),
+    (CKA_DECRYPT, true, KeyPairGenerateParams::can_decrypt),
+    (CKA_DERIVE, true, KeyPairGenerateParams::can_derive),
+    (CKA_UNWRAP, true, KeyPairGenerateParams::can_unwrap),
+    (CKA_EXTRACTABLE, true, KeyPairGenerateParams::extractable),
+    (CKA_SENSITIVE, true, KeyPairGenerateParams::sensitive),
+    (CKA_VERIFY, false, KeyPairGenerateParams::can_verify),
+    (CKA_ENCRYPT, false, KeyPairGenerateParams::can_encrypt),
+    (CKA_WRAP, false, KeyPairGenerateParams::can_wrap),
+];
+
+/// Provider served by `C_GetFunctionList`, set by `Pkcs11Provider::install`
+static PROVIDER: OnceLock<Pkcs11Provider> = OnceLock::new();
+
+/// State of the provider between `C_Initialize` and `C_Finalize`
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+/// Functions returned by `C_GetFunctionList`
+static FUNCTION_LIST: CK_FUNCTION_LIST = CK_FUNCTION_LIST {
+    version: CRYPTOKI_VERSION,
+    C_Initialize: Some(initialize),
+    C_Finalize: Some(finalize),
+    C_GetInfo: Some(get_info),
+    C_GetFunctionList: Some(get_function_list),
+    C_GetSlotList: Some(get_slot_list),
+    C_GetSlotInfo: Some(get_slot_info),
+    C_GetTokenInfo: Some(get_token_info),
+    C_GetMechanismList: Some(get_mechanism_list),
+    C_GetMechanismInfo: Some(get_mechanism_info),
+    C_InitToken: Some(init_token),
+    C_InitPIN: Some(init_pin),
+    C_SetPIN: Some(set_pin),
+    C_OpenSession: Some(open_session),
+    C_CloseSession: Some(close_session),
+    C_CloseAllSessions: Some(close_all_sessions),
+    C_GetSessionInfo: Some(get_session_info),
+    C_GetOperationState: Some(get_operation_state),
+    C_SetOperationState: Some(set_operation_state),
+    C_Login: Some(login),
+    C_Logout: Some(logout),
+    C_CreateObject: Some(create_object),
+    C_CopyObject: Some(copy_object),
+    C_DestroyObject: Some(destroy_object),
+    C_GetObjectSize: Some(get_object_size),
+    C_GetAttributeValue: Some(get_attribute_value),
+    C_SetAttributeValue: Some(set_attribute_value),
+    C_FindObjectsInit: Some(find_objects_init),
+    C_FindObjects: Some(find_objects),
+    C_FindObjectsFinal: Some(find_objects_final),
+    C_EncryptInit: Some(encrypt_init),
+    C_Encrypt: Some(encrypt),
+    C_EncryptUpdate: Some(encrypt_update),
+    C_EncryptFinal: Some(encrypt_final),
+    C_DecryptInit: Some(decrypt_init),
+    C_Decrypt: Some(decrypt),
+    C_DecryptUpdate: Some(decrypt_update),
+    C_DecryptFinal: Some(decrypt_final),
+    C_DigestInit: Some(digest_init),
+    C_Digest: Some(digest),
+    C_DigestUpdate: Some(digest_update),
+    C_DigestKey: Some(digest_key),
+    C_DigestFinal: Some(digest_final),
+    C_SignInit: Some(sign_init),
+    C_Sign: Some(sign),
+    C_SignUpdate: Some(sign_update),
+    C_SignFinal: Some(sign_final),
+    C_SignRecoverInit: Some(sign_recover_init),
+    C_SignRecover: Some(sign_recover),
+    C_VerifyInit: Some(verify_init),
+    C_Verify: Some(verify),
+    C_VerifyUpdate: Some(verify_update),
+    C_VerifyFinal: Some(verify_final),
+    C_VerifyRecoverInit: Some(verify_recover_init),
+    C_VerifyRecover: Some(verify_recover),
+    C_DigestEncryptUpdate: Some(digest_encrypt_update),
+    C_DecryptDigestUpdate: Some(decrypt_digest_update),
+    C_SignEncryptUpdate: Some(sign_encrypt_update),
+    C_DecryptVerifyUpdate: Some(decrypt_verify_update),
+    C_GenerateKey: Some(generate_key),
+    C_GenerateKeyPair: Some(generate_key_pair),
+    C_WrapKey: Some(wrap_key),
+    C_UnwrapKey: Some(unwrap_key),
+    C_DeriveKey: Some(derive_key),
+    C_SeedRandom: Some(seed_random),
+    C_GenerateRandom: Some(generate_random),
+    C_GetFunctionStatus: Some(get_function_status),
+    C_CancelFunction: Some(cancel_function),
+    C_WaitForSlotEvent: Some(wait_for_slot_event),
+};
+
+/// Define PKCS#11 functions which the provider doesn't support
+macro_rules! unsupported {
+    ($($function:ident($($arg:ty),*);)*) => {
+        $(
+            unsafe extern "C" fn $function($(_: $arg),*) -> CK_RV {
+                CKR_FUNCTION_NOT_SUPPORTED
+            }
+        )*
+    };
+}
+
+/// PKCS#11 provider serving the keys in a keychain which match a `Query`
+/// as the objects on a single token.
+///
+/// Applications can find keys (and the public keys of private keys) with
+/// `C_FindObjects`, read their public attributes (e.g. `CKA_ID`, which is
+/// the application tag or, if a key has none, its application label), sign
+/// with `C_Sign` (which uses `Key::sign`), decrypt with `C_Decrypt` (which
+/// uses `Key::decrypt`), and generate key pairs with `C_GenerateKeyPair`.
+/// Key pairs generated with `CKA_TOKEN` set are added to the keychain,
+/// whereas others are session objects which only exist until the session
+/// which generated them is closed. Public key operations are left to the
+/// application, and private keys can't be exported.
+///
+/// Keys are looked up whenever an application searches for objects, so
+/// keys added to or removed from the keychain are reflected immediately.
+/// Works with any `KeychainBackend`.
+pub struct Pkcs11Provider {
+    /// Label of the token
+    label: String,
+
+    /// Query which selects the keys on the token
+    query: Query,
+
+    /// Keychain the keys are stored in
+    keychain: TokenKeychain,
+}
+
+impl Pkcs11Provider {
+    /// Create a provider for the keys in the given keychain which match the
+    /// given query. The token doesn't require applications to log in.
+    pub fn new(keychain: Keychain, query: Query) -> Self {
+        Self {
+            label: DEFAULT_LABEL.to_owned(),
+            query,
+            keychain: TokenKeychain::Open(keychain),
+        }
+    }
+
+    /// Create a provider for the keys which match the given query in a
+    /// keychain which is opened using the PIN the user logs in to the token
+    /// with, e.g. the password of a `FileBackend` keychain. No keys are
+    /// visible until the user logs in.
+    ///
+    /// The `open` function should return an error with a kind of
+    /// `ErrorKind::AuthFailed` if the PIN is incorrect.
+    pub fn with_login<F>(query: Query, open: F) -> Self
+    where
+        F: Fn(&str) -> Result<Keychain, Error> + Send + Sync + 'static,
+    {
+        Self {
+            label: DEFAULT_LABEL.to_owned(),
+            query,
+            keychain: TokenKeychain::Login(Box::new(open)),
+        }
+    }
+
+    /// Set the label of the token, which applications use to select it
+    /// (e.g. in `pkcs11:` URIs). Labels are truncated to 32 bytes. Defaults
+    /// to `keychain-services`.
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = label.to_owned();
+        self
+    }
+
+    /// Install this provider as the one served by `C_GetFunctionList`.
+    /// Only one provider can be installed per process, so this returns an
+    /// error with a kind of `ErrorKind::Param` if one already is.
+    pub fn install(self) -> Result<(), Error> {
+        PROVIDER
+            .set(self)
+            .map_err(|_| Error::new(ErrorKind::Param, "a PKCS#11 provider is already installed"))
+    }
+
+    /// Does the token require the user to log in?
+    fn login_required(&self) -> bool {
+        match self.keychain {
+            TokenKeychain::Open(_) => false,
+            TokenKeychain::Login(_) => true,
+        }
+    }
+}
+
+impl Debug for Pkcs11Provider {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pkcs11Provider")
+            .field("label", &self.label)
+            .field("query", &self.query)
+            .field("login_required", &self.login_required())
+            .finish()
+    }
+}
+
+/// Get the provider's PKCS#11 function list.
+///
+/// PKCS#11 modules built with this crate must export a `C_GetFunctionList`
+/// function with this signature (using `#[no_mangle]`) which installs a
+/// `Pkcs11Provider` (if it hasn't already) and then calls this function.
+///
+/// # Safety
+///
+/// `ppFunctionList` must be null or valid for writes.
+pub unsafe extern "C" fn C_GetFunctionList(ppFunctionList: *mut *const c_void) -> c_ulong {
+    get_function_list(ppFunctionList as *mut *const CK_FUNCTION_LIST)
+}
+
+/// Keychain which holds the keys on a token
+enum TokenKeychain {
+    /// Keychain which is always available
+    Open(Keychain),
+
+    /// Function which opens a keychain using the user's PIN
+    Login(Box<OpenKeychain>),
+}
+
+/// Function which opens a keychain using a PIN
+type OpenKeychain = dyn Fn(&str) -> Result<Keychain, Error> + Send + Sync;
+
+/// State of the provider while it's initialized
+struct State {
+    /// Installed provider
+    provider: &'static Pkcs11Provider,
+
+    /// Keychain, unless the user needs to log in to open it
+    keychain: Option<Keychain>,
+
+    /// Is the user logged in?
+    logged_in: bool,
+
+    /// Open sessions
+    sessions: HashMap<CK_SESSION_HANDLE, Session>,
+
+    /// Objects applications have been given handles to
+    objects: BTreeMap<CK_OBJECT_HANDLE, Object>,
+
+    /// Next session or object handle
+    next_handle: CK_ULONG,
+}
+
+impl State {
+    /// Create the state of the given provider
+    fn new(provider: &'static Pkcs11Provider) -> Self {
+        let keychain = match &provider.keychain {
+            TokenKeychain::Open(keychain) => Some(keychain.clone()),
+            TokenKeychain::Login(_) => None,
+        };
+
+        Self {
+            provider,
+            keychain,
+            logged_in: false,
+            sessions: HashMap::new(),
+            objects: BTreeMap::new(),
+            next_handle: 1,
+        }
+    }
+
+    /// Allocate a new session or object handle
+    fn handle(&mut self) -> CK_ULONG {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        handle
+    }
+
+    /// Get an open session
+    fn session(&mut self, handle: CK_SESSION_HANDLE) -> Result<&mut Session, CK_RV> {
+        self.sessions
+            .get_mut(&handle)
+            .ok_or(CKR_SESSION_HANDLE_INVALID)
+    }
+
+    /// Get an object
+    fn object(&self, handle: CK_OBJECT_HANDLE) -> Result<&Object, CK_RV> {
+        self.objects.get(&handle).ok_or(CKR_OBJECT_HANDLE_INVALID)
+    }
+
+    /// Get the keychain, or an error if the user needs to log in first
+    fn keychain(&self) -> Result<Keychain, CK_RV> {
+        self.keychain.clone().ok_or(CKR_USER_NOT_LOGGED_IN)
+    }
+
+    /// Close a session, destroying its session objects. Closing the last
+    /// session logs the user out.
+    fn close_session(&mut self, handle: CK_SESSION_HANDLE) {
+        self.sessions.remove(&handle);
+        self.objects
+            .retain(|_, object| object.session != Some(handle));
+
+        if self.sessions.is_empty() {
+            self.logout();
+        }
+    }
+
+    /// Log the user out, closing the keychain if it was opened by logging in
+    fn logout(&mut self) {
+        if self.provider.login_required() {
+            self.keychain = None;
+            self.objects.clear();
+        }
+
+        self.logged_in = false;
+    }
+
+    /// Update the objects with the keys found by `find_keys`, keeping the
+    /// handles of objects which still exist
+    fn refresh(&mut self, keys: Vec<Key>) {
+        // The user may have logged out while the keys were looked up
+        if self.keychain.is_none() {
+            return;
+        }
+
+        let mut found: Vec<_> = keys
+            .into_iter()
+            .filter_map(|key| Object::new(key, None))
+            .collect();
+
+        // Keychains don't necessarily store the public keys of private keys
+        let public_keys: Vec<_> = found
+            .iter()
+            .filter(|object| object.key_class == AttrKeyClass::Private)
+            .filter_map(|private_key| {
+                let public_key = Object::public_key(private_key, None)?;
+
+                if found
+                    .iter()
+                    .any(|object| object.identity() == public_key.identity())
+                {
+                    None
+                } else {
+                    Some(public_key)
+                }
+            })
+            .collect();
+
+        found.extend(public_keys);
+
+        self.objects.retain(|_, object| {
+            object.session.is_some()
+                || found
+                    .iter()
+                    .any(|found| found.identity() == object.identity())
+        });
+
+        for object in found {
+            match self.objects.values_mut().find(|existing| {
+                existing.session.is_none() && existing.identity() == object.identity()
+            }) {
+                Some(existing) => *existing = object,
+                None => {
+                    let handle = self.handle();
+                    self.objects.insert(handle, object);
+                }
+            }
+        }
+    }
+}
+
+/// Look up the keys in the keychain, if it's open, without holding the lock
+/// on the provider's state while the keychain is searched
+fn find_keys() -> Result<Option<Vec<Key>>, CK_RV> {
+    let (keychain, query) = locked(|state| Ok((state.keychain.clone(), &state.provider.query)))?;
+
+    let keychain = match keychain {
+        Some(keychain) => keychain,
+        None => return Ok(None),
+    };
+
+    match keychain.find_keys(query.clone()) {
+        Ok(keys) => Ok(Some(keys)),
+        Err(ref e) if *e.kind() == ErrorKind::ItemNotFound => Ok(Some(vec![])),
+        Err(e) => Err(error_rv(&e)),
+    }
+}
+
+/// Session opened with `C_OpenSession`
+struct Session {
+    /// Flags the session was opened with
+    flags: CK_FLAGS,
+
+    /// Remaining results of an active `C_FindObjects` search
+    find: Option<Vec<CK_OBJECT_HANDLE>>,
+
+    /// Active signing or decryption operation
+    operation: Option<Operation>,
+}
+
+/// Signing or decryption operation
+#[derive(Clone)]
+struct Operation {
+    /// Identifier of the operation, which distinguishes it from later
+    /// operations in the same session
+    id: CK_ULONG,
+
+    /// Kind of operation
+    kind: OperationKind,
+
+    /// Private key performing the operation
+    key: Key,
+
+    /// Algorithm equivalent to the operation's mechanism
+    alg: KeyAlgorithm,
+
+    /// Curve of ECDSA keys, whose signatures must be converted to the
+    /// fixed-size `r || s` form PKCS#11 uses
+    curve: Option<EcCurve>,
+
+    /// Data to sign or decrypt
+    input: Vec<u8>,
+
+    /// Result of the operation, which is kept until the application has
+    /// supplied a large enough buffer for it
+    output: Option<Zeroizing<Vec<u8>>>,
+}
+
+impl Operation {
+    /// Perform the operation
+    fn perform(&self) -> Result<Vec<u8>, Error> {
+        match self.kind {
+            OperationKind::Sign => {
+                let signature = self.key.sign(self.alg, &self.input)?;
+
+                match self.curve {
+                    Some(curve) => signature.to_fixed(curve),
+                    None => Ok(signature.into_vec()),
+                }
+            }
+            OperationKind::Decrypt => self
+                .key
+                .decrypt(Ciphertext::new(self.alg, self.input.clone())),
+        }
+    }
+}
+
+/// Kinds of operations
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum OperationKind {
+    /// `C_SignInit`
+    Sign,
+
+    /// `C_DecryptInit`
+    Decrypt,
+}
+
+impl OperationKind {
+    /// Attribute which permits keys to perform this kind of operation
+    fn usage_attribute(self) -> CK_ATTRIBUTE_TYPE {
+        match self {
+            OperationKind::Sign => CKA_SIGN,
+            OperationKind::Decrypt => CKA_DECRYPT,
+        }
+    }
+
+    /// Get the algorithm equivalent to a mechanism used with a key of the
+    /// given type
+    unsafe fn algorithm(
+        self,
+        mechanism: &CK_MECHANISM,
+        key_type: AttrKeyType,
+    ) -> Result<KeyAlgorithm, CK_RV> {
+        use self::KeyAlgorithm::*;
+        use crate::attr::AttrKeyType::*;
+
+        Ok(match (self, key_type, mechanism.mechanism) {
+            (OperationKind::Sign, EcSecPrimeRandom, CKM_ECDSA) => ECDSASignatureRFC4754,
+            (OperationKind::Sign, EcSecPrimeRandom, CKM_ECDSA_SHA1) => {
+                ECDSASignatureMessageX962SHA1
+            }
+            (OperationKind::Sign, EcSecPrimeRandom, CKM_ECDSA_SHA224) => {
+                ECDSASignatureMessageX962SHA224
+            }
+            (OperationKind::Sign, EcSecPrimeRandom, CKM_ECDSA_SHA256) => {
+                ECDSASignatureMessageX962SHA256
+            }
+            (OperationKind::Sign, EcSecPrimeRandom, CKM_ECDSA_SHA384) => {
+                ECDSASignatureMessageX962SHA384
+            }
+            (OperationKind::Sign, EcSecPrimeRandom, CKM_ECDSA_SHA512) => {
+                ECDSASignatureMessageX962SHA512
+            }
+            (OperationKind::Sign, Rsa, CKM_RSA_X_509) => RSASignatureRaw,
+            (OperationKind::Sign, Rsa, CKM_RSA_PKCS) => RSASignatureDigestPKCS1v15Raw,
+            (OperationKind::Sign, Rsa, CKM_SHA1_RSA_PKCS) => RSASignatureMessagePKCS1v15SHA1,
+            (OperationKind::Sign, Rsa, CKM_SHA224_RSA_PKCS) => RSASignatureMessagePKCS1v15SHA224,
+            (OperationKind::Sign, Rsa, CKM_SHA256_RSA_PKCS) => RSASignatureMessagePKCS1v15SHA256,
+            (OperationKind::Sign, Rsa, CKM_SHA384_RSA_PKCS) => RSASignatureMessagePKCS1v15SHA384,
+            (OperationKind::Sign, Rsa, CKM_SHA512_RSA_PKCS) => RSASignatureMessagePKCS1v15SHA512,
+            (OperationKind::Sign, Rsa, CKM_RSA_PKCS_PSS) => match pss_digest(mechanism)? {
+                DigestAlgorithm::Sha1 => RSASignatureDigestPSSSHA1,
+                DigestAlgorithm::Sha224 => RSASignatureDigestPSSSHA224,
+                DigestAlgorithm::Sha256 => RSASignatureDigestPSSSHA256,
+                DigestAlgorithm::Sha384 => RSASignatureDigestPSSSHA384,
+                DigestAlgorithm::Sha512 => RSASignatureDigestPSSSHA512,
+            },
+            (OperationKind::Sign, Rsa, CKM_SHA1_RSA_PKCS_PSS) => {
+                pss_message(mechanism, DigestAlgorithm::Sha1, RSASignatureMessagePSSSHA1)?
+            }
+            (OperationKind::Sign, Rsa, CKM_SHA224_RSA_PKCS_PSS) => pss_message(
+                mechanism,
+                DigestAlgorithm::Sha224,
+                RSASignatureMessagePSSSHA224,
+            )?,
+            (OperationKind::Sign, Rsa, CKM_SHA256_RSA_PKCS_PSS) => pss_message(
+                mechanism,
+                DigestAlgorithm::Sha256,
+                RSASignatureMessagePSSSHA256,
+            )?,
+            (OperationKind::Sign, Rsa, CKM_SHA384_RSA_PKCS_PSS) => pss_message(
+                mechanism,
+                DigestAlgorithm::Sha384,
+                RSASignatureMessagePSSSHA384,
+            )?,
+            (OperationKind::Sign, Rsa, CKM_SHA512_RSA_PKCS_PSS) => pss_message(
+                mechanism,
+                DigestAlgorithm::Sha512,
+                RSASignatureMessagePSSSHA512,
+            )?,
+            (OperationKind::Decrypt, Rsa, CKM_RSA_X_509) => RSAEncryptionRaw,
+            (OperationKind::Decrypt, Rsa, CKM_RSA_PKCS) => RSAEncryptionPKCS1,
+            (OperationKind::Decrypt, Rsa, CKM_RSA_PKCS_OAEP) => {
+                let params = parameters::<CK_RSA_PKCS_OAEP_PARAMS>(mechanism)?;
+
+                // Keychain Services doesn't support OAEP labels
+                if params.ulSourceDataLen != 0 {
+                    return Err(CKR_MECHANISM_PARAM_INVALID);
+                }
+
+                match digest_algorithm(params.hashAlg, params.mgf)? {
+                    DigestAlgorithm::Sha1 => RSAEncryptionOAEPSHA1,
+                    DigestAlgorithm::Sha224 => RSAEncryptionOAEPSHA224,
+                    DigestAlgorithm::Sha256 => RSAEncryptionOAEPSHA256,
+                    DigestAlgorithm::Sha384 => RSAEncryptionOAEPSHA384,
+                    DigestAlgorithm::Sha512 => RSAEncryptionOAEPSHA512,
+                }
+            }
+            (_, _, mechanism) => {
+                return Err(if MECHANISMS.iter().any(|(m, _, _)| *m == mechanism) {
+                    CKR_KEY_TYPE_INCONSISTENT
+                } else {
+                    CKR_MECHANISM_INVALID
+                })
+            }
+        })
+    }
+
+    /// Convert an error from an operation into a `CK_RV`
+    fn error_rv(self, error: &Error) -> CK_RV {
+        match (self, error.kind()) {
+            (OperationKind::Decrypt, ErrorKind::DataTooLarge) => CKR_ENCRYPTED_DATA_LEN_RANGE,
+            (OperationKind::Decrypt, ErrorKind::Decode)
+            | (OperationKind::Decrypt, ErrorKind::DecryptionFailed) => CKR_ENCRYPTED_DATA_INVALID,
+            _ => error_rv(error),
+        }
+    }
+}
+
+/// Key object on the token
+struct Object {
+    /// Key in the keychain
+    key: Key,
+
+    /// Class of the key
+    key_class: AttrKeyClass,
+
+    /// Type of the key
+    key_type: AttrKeyType,
+
+    /// Curve of EC keys
+    curve: Option<EcCurve>,
+
+    /// Application label of the key, which identifies it along with its
+    /// class
+    application_label: Vec<u8>,
+
+    /// Session which owns the object, or `None` for token objects
+    session: Option<CK_SESSION_HANDLE>,
+
+    /// Values of the object's attributes
+    attrs: HashMap<CK_ATTRIBUTE_TYPE, Vec<u8>>,
+}
+
+impl Object {
+    /// Create an object for a key, returning `None` if the key isn't an
+    /// RSA or EC public or private key
+    fn new(key: Key, session: Option<CK_SESSION_HANDLE>) -> Option<Self> {
+        let key_class = key.class()?;
+        let key_type = key.key_type()?;
+
+        let public_key_bytes = match key_class {
+            AttrKeyClass::Public => key.to_external_representation().ok()?,
+            AttrKeyClass::Private => key.public_key().ok()?.to_external_representation().ok()?,
+            _ => return None,
+        };
+
+        let public_key =
+            PublicKey::from_external_representation(key_type, &public_key_bytes).ok()?;
+        let application_label = public_key.application_label();
+        let private = key_class == AttrKeyClass::Private;
+
+        let id = key
+            .application_tag()
+            .map(|tag| tag.as_bytes().to_vec())
+            .filter(|tag| !tag.is_empty())
+            .unwrap_or_else(|| application_label.clone());
+
+        let label = key
+            .label()
+            .map(|label| label.as_str().as_bytes().to_vec())
+            .unwrap_or_default();
+
+        let mut attrs = HashMap::new();
+        attrs.insert(
+            CKA_CLASS,
+            ulong_bytes(if private {
+                CKO_PRIVATE_KEY
+            } else {
+                CKO_PUBLIC_KEY
+            }),
+        );
+        attrs.insert(CKA_TOKEN, bool_bytes(session.is_none()));
+        attrs.insert(CKA_PRIVATE, bool_bytes(private));
+        attrs.insert(CKA_MODIFIABLE, bool_bytes(false));
+        attrs.insert(CKA_LABEL, label);
+        attrs.insert(CKA_ID, id);
+        attrs.insert(CKA_DERIVE, bool_bytes(false));
+
+        let (sign_alg, curve) = match (&public_key, key_type) {
+            (PublicKey::Rsa(rsa_key), AttrKeyType::Rsa) => {
+                attrs.insert(CKA_KEY_TYPE, ulong_bytes(CKK_RSA));
+                attrs.insert(CKA_MODULUS, rsa_key.n().to_bytes_be());
+                attrs.insert(CKA_PUBLIC_EXPONENT, rsa_key.e().to_bytes_be());
+
+                if !private {
+                    attrs.insert(
+                        CKA_MODULUS_BITS,
+                        ulong_bytes(public_key.key_size_in_bits() as CK_ULONG),
+                    );
+                }
+
+                (KeyAlgorithm::RSASignatureMessagePKCS1v15SHA256, None)
+            }
+            (_, AttrKeyType::EcSecPrimeRandom) => {
+                let ec_params = match public_key.key_size_in_bits() {
+                    256 => EC_PARAMS_P256,
+                    384 => EC_PARAMS_P384,
+                    521 => EC_PARAMS_P521,
+                    _ => return None,
+                };
+
+                let ec_point = OctetStringRef::new(&public_key_bytes).ok()?.to_der().ok()?;
+                attrs.insert(CKA_KEY_TYPE, ulong_bytes(CKK_EC));
+                attrs.insert(CKA_EC_PARAMS, ec_params.to_vec());
+                attrs.insert(CKA_EC_POINT, ec_point);
+
+                (
+                    KeyAlgorithm::ECDSASignatureMessageX962SHA256,
+                    key.ec_curve(),
+                )
+            }
+            _ => return None,
+        };
+
+        if private {
+            let can_sign = key.is_supported(KeyOperation::Sign, sign_alg);
+            let can_decrypt = key_type == AttrKeyType::Rsa
+                && key.is_supported(KeyOperation::Decrypt, KeyAlgorithm::RSAEncryptionPKCS1);
+
+            attrs.insert(CKA_SIGN, bool_bytes(can_sign));
+            attrs.insert(CKA_DECRYPT, bool_bytes(can_decrypt));
+            attrs.insert(CKA_UNWRAP, bool_bytes(false));
+            attrs.insert(CKA_SENSITIVE, bool_bytes(true));
+            attrs.insert(CKA_ALWAYS_SENSITIVE, bool_bytes(true));
+            attrs.insert(CKA_EXTRACTABLE, bool_bytes(false));
+            attrs.insert(CKA_NEVER_EXTRACTABLE, bool_bytes(true));
+            attrs.insert(CKA_ALWAYS_AUTHENTICATE, bool_bytes(false));
+        } else {
+            // Public key operations are left to applications
+            attrs.insert(CKA_VERIFY, bool_bytes(false));
+            attrs.insert(CKA_ENCRYPT, bool_bytes(false));
+            attrs.insert(CKA_WRAP, bool_bytes(false));
+        }
+
+        Some(Self {
+            key,
+            key_class,
+            key_type,
+            curve,
+            application_label,
+            session,
+            attrs,
+        })
+    }
+
+    /// Create an object for the public key of a private key object, with
+    /// the same label and ID
+    fn public_key(private_key: &Object, session: Option<CK_SESSION_HANDLE>) -> Option<Self> {
+        let mut public_key = Object::new(private_key.key.public_key().ok()?, session)?;
+
+        for attr in &[CKA_LABEL, CKA_ID] {
+            public_key
+                .attrs
+                .insert(*attr, private_key.attrs[attr].clone());
+        }
+
+        Some(public_key)
+    }
+
+    /// Class and application label, which identify a key
+    fn identity(&self) -> (AttrKeyClass, &[u8]) {
+        (self.key_class, &self.application_label)
+    }
+
+    /// Does the object have the given attribute value?
+    fn matches(&self, type_: CK_ATTRIBUTE_TYPE, value: &[u8]) -> bool {
+        self.attrs.get(&type_).map(Vec::as_slice) == Some(value)
+    }
+}
+
+/// Lock the provider's state
+fn lock() -> MutexGuard<'static, Option<State>> {
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Run a function with the provider's state locked, if it's initialized
+fn locked<T, F>(f: F) -> Result<T, CK_RV>
+where
+    F: FnOnce(&mut State) -> Result<T, CK_RV>,
+{
+    match lock().as_mut() {
+        Some(state) => f(state),
+        None => Err(CKR_CRYPTOKI_NOT_INITIALIZED),
+    }
+}
+
+/// Run the body of a PKCS#11 function, returning `CKR_GENERAL_ERROR`
+/// rather than unwinding into the application if it panics
+fn entry_point<F>(f: F) -> CK_RV
+where
+    F: FnOnce() -> Result<(), CK_RV>,
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => CKR_OK,
+        Ok(Err(rv)) => rv,
+        Err(_) => CKR_GENERAL_ERROR,
+    }
+}
+
+/// Run a function with the provider's state, if it's initialized
+fn with_state<F>(f: F) -> CK_RV
+where
+    F: FnOnce(&mut State) -> Result<(), CK_RV>,
+{
+    entry_point(|| locked(f))
+}
+
+unsafe extern "C" fn initialize(pInitArgs: *mut c_void) -> CK_RV {
+    // All of the provider's state is protected by its own mutex, so it's
+    // safe to use from multiple threads regardless of the locking arguments
+    if let Some(args) = (pInitArgs as *const CK_C_INITIALIZE_ARGS).as_ref() {
+        if !args.pReserved.is_null() {
+            return CKR_ARGUMENTS_BAD;
+        }
+    }
+
+    let provider = match PROVIDER.get() {
+        Some(provider) => provider,
+        None => return CKR_FUNCTION_FAILED,
+    };
+
+    let mut state = lock();
+
+    if state.is_some() {
+        return CKR_CRYPTOKI_ALREADY_INITIALIZED;
+    }
+
+    *state = Some(State::new(provider));
+    CKR_OK
+}
+
+unsafe extern "C" fn finalize(pReserved: *mut c_void) -> CK_RV {
+    if !pReserved.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+
+    match lock().take() {
+        Some(_) => CKR_OK,
+        None => CKR_CRYPTOKI_NOT_INITIALIZED,
+    }
+}
+
+unsafe extern "C" fn get_info(pInfo: *mut CK_INFO) -> CK_RV {
+    with_state(|_| {
+        let info = pInfo.as_mut().ok_or(CKR_ARGUMENTS_BAD)?;
+
+        *info = CK_INFO {
+            cryptokiVersion: CRYPTOKI_VERSION,
+            manufacturerID: padded(MANUFACTURER_ID),
+            flags: 0,
+            libraryDescription: padded("Keychain keys"),
+            libraryVersion: library_version(),
+        };
+
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn get_function_list(ppFunctionList: *mut *const CK_FUNCTION_LIST) -> CK_RV {
+    match ppFunctionList.as_mut() {
+        Some(function_list) => {
+            *function_list = &FUNCTION_LIST;
+            CKR_OK
+        }
+        None => CKR_ARGUMENTS_BAD,
+    }
+}
+
+unsafe extern "C" fn get_slot_list(
+    _tokenPresent: CK_BBOOL,
+    pSlotList: *mut CK_SLOT_ID,
+    pulCount: *mut CK_ULONG,
+) -> CK_RV {
+    // The token is always present
+    with_state(|_| write_list(&[SLOT_ID], pSlotList, pulCount).map(|_| ()))
+}
+
+unsafe extern "C" fn get_slot_info(slotID: CK_SLOT_ID, pInfo: *mut CK_SLOT_INFO) -> CK_RV {
+    with_state(|_| {
+        check_slot(slotID)?;
+        let info = pInfo.as_mut().ok_or(CKR_ARGUMENTS_BAD)?;
+
+        *info = CK_SLOT_INFO {
+            slotDescription: padded("Keychain"),
+            manufacturerID: padded(MANUFACTURER_ID),
+            flags: CKF_TOKEN_PRESENT,
+            hardwareVersion: CK_VERSION::default(),
+            firmwareVersion: CK_VERSION::default(),
+        };
+
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn get_token_info(slotID: CK_SLOT_ID, pInfo: *mut CK_TOKEN_INFO) -> CK_RV {
+    with_state(|state| {
+        check_slot(slotID)?;
+        let info = pInfo.as_mut().ok_or(CKR_ARGUMENTS_BAD)?;
+
+        let mut flags = CKF_TOKEN_INITIALIZED | CKF_USER_PIN_INITIALIZED;
+
+        if state.provider.login_required() {
+            flags |= CKF_LOGIN_REQUIRED;
+        }
+
+        let rw_sessions = state
+            .sessions
+            .values()
+            .filter(|session| session.flags & CKF_RW_SESSION != 0)
+            .count();
+
+        *info = CK_TOKEN_INFO {
+            label: padded(&state.provider.label),
+            manufacturerID: padded(MANUFACTURER_ID),
+            model: padded("Keychain"),
+            serialNumber: padded("0"),
+            flags,
+            ulMaxSessionCount: CK_EFFECTIVELY_INFINITE,
+            ulSessionCount: state.sessions.len() as CK_ULONG,
+            ulMaxRwSessionCount: CK_EFFECTIVELY_INFINITE,
+            ulRwSessionCount: rw_sessions as CK_ULONG,
+            ulMaxPinLen: MAX_PIN_LEN,
+            ulMinPinLen: 0,
+            ulTotalPublicMemory: CK_UNAVAILABLE_INFORMATION,
+            ulFreePublicMemory: CK_UNAVAILABLE_INFORMATION,
+            ulTotalPrivateMemory: CK_UNAVAILABLE_INFORMATION,
+            ulFreePrivateMemory: CK_UNAVAILABLE_INFORMATION,
+            hardwareVersion: CK_VERSION::default(),
+            firmwareVersion: library_version(),
+            utcTime: padded(""),
+        };
+
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn get_mechanism_list(
+    slotID: CK_SLOT_ID,
+    pMechanismList: *mut CK_MECHANISM_TYPE,
+    pulCount: *mut CK_ULONG,
+) -> CK_RV {
+    with_state(|_| {
+        check_slot(slotID)?;
+
+        let mechanisms: Vec<_> = MECHANISMS
+            .iter()
+            .map(|(mechanism, _, _)| *mechanism)
+            .collect();
+
+        write_list(&mechanisms, pMechanismList, pulCount).map(|_| ())
+    })
+}
+
+unsafe extern "C" fn get_mechanism_info(
+    slotID: CK_SLOT_ID,
+    type_: CK_MECHANISM_TYPE,
+    pInfo: *mut CK_MECHANISM_INFO,
+) -> CK_RV {
+    with_state(|_| {
+        check_slot(slotID)?;
+        let info = pInfo.as_mut().ok_or(CKR_ARGUMENTS_BAD)?;
+
+        let (key_type, flags) = MECHANISMS
+            .iter()
+            .find(|(mechanism, _, _)| *mechanism == type_)
+            .map(|(_, key_type, flags)| (*key_type, *flags))
+            .ok_or(CKR_MECHANISM_INVALID)?;
+
+        *info = match key_type {
+            AttrKeyType::Rsa => CK_MECHANISM_INFO {
+                ulMinKeySize: 1024,
+                ulMaxKeySize: 8192,
+                flags,
+            },
+            _ => CK_MECHANISM_INFO {
+                ulMinKeySize: 256,
+                ulMaxKeySize: 521,
+                flags: flags | CKF_EC_F_P | CKF_EC_UNCOMPRESS,
+            },
+        };
+
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn open_session(
+    slotID: CK_SLOT_ID,
+    flags: CK_FLAGS,
+    _pApplication: *mut c_void,
+    _Notify: CK_NOTIFY,
+    phSession: *mut CK_SESSION_HANDLE,
+) -> CK_RV {
+    with_state(|state| {
+        check_slot(slotID)?;
+
+        if flags & CKF_SERIAL_SESSION == 0 {
+            return Err(CKR_SESSION_PARALLEL_NOT_SUPPORTED);
+        }
+
+        let session_handle = phSession.as_mut().ok_or(CKR_ARGUMENTS_BAD)?;
+        let handle = state.handle();
+
+        state.sessions.insert(
+            handle,
+            Session {
+                flags,
+                find: None,
+                operation: None,
+            },
+        );
+
+        *session_handle = handle;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn close_session(hSession: CK_SESSION_HANDLE) -> CK_RV {
+    with_state(|state| {
+        state.session(hSession)?;
+        state.close_session(hSession);
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn close_all_sessions(slotID: CK_SLOT_ID) -> CK_RV {
+    with_state(|state| {
+        check_slot(slotID)?;
+        let handles: Vec<_> = state.sessions.keys().cloned().collect();
+
+        for handle in handles {
+            state.close_session(handle);
+        }
+
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn get_session_info(
+    hSession: CK_SESSION_HANDLE,
+    pInfo: *mut CK_SESSION_INFO,
+) -> CK_RV {
+    with_state(|state| {
+        let logged_in = state.logged_in;
+        let flags = state.session(hSession)?.flags;
+        let info = pInfo.as_mut().ok_or(CKR_ARGUMENTS_BAD)?;
+
+        let session_state = match (flags & CKF_RW_SESSION != 0, logged_in) {
+            (false, false) => CKS_RO_PUBLIC_SESSION,
+            (false, true) => CKS_RO_USER_FUNCTIONS,
+            (true, false) => CKS_RW_PUBLIC_SESSION,
+            (true, true) => CKS_RW_USER_FUNCTIONS,
+        };
+
+        *info = CK_SESSION_INFO {
+            slotID: SLOT_ID,
+            state: session_state,
+            flags,
+            ulDeviceError: 0,
+        };
+
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn login(
+    hSession: CK_SESSION_HANDLE,
+    userType: CK_USER_TYPE,
+    pPin: *mut u8,
+    ulPinLen: CK_ULONG,
+) -> CK_RV {
+    entry_point(|| {
+        let check_session = |state: &mut State| {
+            state.session(hSession)?;
+
+            if userType != CKU_USER {
+                return Err(CKR_USER_TYPE_INVALID);
+            }
+
+            if state.logged_in {
+                return Err(CKR_USER_ALREADY_LOGGED_IN);
+            }
+
+            Ok(state.provider)
+        };
+
+        let provider = locked(check_session)?;
+
+        // The keychain is opened without holding the lock, as it may be slow
+        // (e.g. deriving a key from the PIN)
+        let keychain = match &provider.keychain {
+            TokenKeychain::Login(open) => {
+                let pin = str::from_utf8(input(pPin, ulPinLen)?).map_err(|_| CKR_PIN_INVALID)?;
+
+                Some(open(pin).map_err(|e| match e.kind() {
+                    ErrorKind::AuthFailed => CKR_PIN_INCORRECT,
+                    _ => error_rv(&e),
+                })?)
+            }
+            TokenKeychain::Open(_) => None,
+        };
+
+        locked(|state| {
+            check_session(state)?;
+
+            if keychain.is_some() {
+                state.keychain = keychain;
+            }
+
+            state.logged_in = true;
+            Ok(())
+        })
+    })
+}
+
+unsafe extern "C" fn logout(hSession: CK_SESSION_HANDLE) -> CK_RV {
+    with_state(|state| {
+        state.session(hSession)?;
+
+        if !state.logged_in {
+            return Err(CKR_USER_NOT_LOGGED_IN);
+        }
+
+        state.logout();
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn destroy_object(
+    hSession: CK_SESSION_HANDLE,
+    hObject: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    with_state(|state| {
+        check_rw(state.session(hSession)?)?;
+        let object = state.object(hObject)?;
+
+        if object.session.is_none() {
+            match object.key.clone().delete() {
+                Err(ref e) if *e.kind() != ErrorKind::ItemNotFound => return Err(error_rv(e)),
+                _ => (),
+            }
+        }
+
+        state.objects.remove(&hObject);
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn get_attribute_value(
+    hSession: CK_SESSION_HANDLE,
+    hObject: CK_OBJECT_HANDLE,
+    pTemplate: *mut CK_ATTRIBUTE,
+    ulCount: CK_ULONG,
+) -> CK_RV {
+    with_state(|state| {
+        state.session(hSession)?;
+        let object = state.object(hObject)?;
+        let mut rv = CKR_OK;
+
+        for attr in template(pTemplate, ulCount)? {
+            let value = match object.attrs.get(&attr.type_) {
+                Some(value) => value,
+                None => {
+                    attr.ulValueLen = CK_UNAVAILABLE_INFORMATION;
+
+                    rv = if object.key_class == AttrKeyClass::Private
+                        && SENSITIVE_ATTRIBUTES.contains(&attr.type_)
+                    {
+                        CKR_ATTRIBUTE_SENSITIVE
+                    } else {
+                        CKR_ATTRIBUTE_TYPE_INVALID
+                    };
+
+                    continue;
+                }
+            };
+
+            if attr.pValue.is_null() {
+                attr.ulValueLen = value.len() as CK_ULONG;
+            } else if (attr.ulValueLen as usize) < value.len() {
+                attr.ulValueLen = CK_UNAVAILABLE_INFORMATION;
+                rv = CKR_BUFFER_TOO_SMALL;
+            } else {
+                ptr::copy_nonoverlapping(value.as_ptr(), attr.pValue as *mut u8, value.len());
+                attr.ulValueLen = value.len() as CK_ULONG;
+            }
+        }
+
+        check(rv)
+    })
+}
+
+unsafe extern "C" fn find_objects_init(
+    hSession: CK_SESSION_HANDLE,
+    pTemplate: *mut CK_ATTRIBUTE,
+    ulCount: CK_ULONG,
+) -> CK_RV {
+    entry_point(|| {
+        let check_session = |state: &mut State| match state.session(hSession)?.find {
+            Some(_) => Err(CKR_OPERATION_ACTIVE),
+            None => Ok(()),
+        };
+
+        locked(check_session)?;
+
+        let criteria = template(pTemplate, ulCount)?
+            .iter()
+            .map(|attr| Ok((attr.type_, value(attr)?)))
+            .collect::<Result<Vec<_>, CK_RV>>()?;
+
+        let keys = find_keys()?;
+
+        locked(|state| {
+            check_session(state)?;
+
+            if let Some(keys) = keys {
+                state.refresh(keys);
+            }
+
+            let results = state
+                .objects
+                .iter()
+                .filter(|(_, object)| {
+                    criteria
+                        .iter()
+                        .all(|(type_, value)| object.matches(*type_, value))
+                })
+                .map(|(handle, _)| *handle)
+                .collect();
+
+            state.session(hSession)?.find = Some(results);
+            Ok(())
+        })
+    })
+}
+
+unsafe extern "C" fn find_objects(
+    hSession: CK_SESSION_HANDLE,
+    phObject: *mut CK_OBJECT_HANDLE,
+    ulMaxObjectCount: CK_ULONG,
+    pulObjectCount: *mut CK_ULONG,
+) -> CK_RV {
+    with_state(|state| {
+        let results = state
+            .session(hSession)?
+            .find
+            .as_mut()
+            .ok_or(CKR_OPERATION_NOT_INITIALIZED)?;
+
+        let count = pulObjectCount.as_mut().ok_or(CKR_ARGUMENTS_BAD)?;
+        let n = results.len().min(ulMaxObjectCount as usize);
+
+        if n > 0 && phObject.is_null() {
+            return Err(CKR_ARGUMENTS_BAD);
+        }
+
+        for (i, handle) in results.drain(..n).enumerate() {
+            *phObject.add(i) = handle;
+        }
+
+        *count = n as CK_ULONG;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn find_objects_final(hSession: CK_SESSION_HANDLE) -> CK_RV {
+    with_state(|state| {
+        state
+            .session(hSession)?
+            .find
+            .take()
+            .map(|_| ())
+            .ok_or(CKR_OPERATION_NOT_INITIALIZED)
+    })
+}
+
+unsafe extern "C" fn decrypt_init(
+    hSession: CK_SESSION_HANDLE,
+    pMechanism: *mut CK_MECHANISM,
+    hKey: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    with_state(|state| operation_init(state, hSession, OperationKind::Decrypt, pMechanism, hKey))
+}
+
+unsafe extern "C" fn decrypt(
+    hSession: CK_SESSION_HANDLE,
+    pEncryptedData: *mut u8,
+    ulEncryptedDataLen: CK_ULONG,
+    pData: *mut u8,
+    pulDataLen: *mut CK_ULONG,
+) -> CK_RV {
+    entry_point(|| {
+        let ciphertext = input(pEncryptedData, ulEncryptedDataLen)?;
+        operation_finish(
+            hSession,
+            OperationKind::Decrypt,
+            Some(ciphertext),
+            pData,
+            pulDataLen,
+        )
+    })
+}
+
+unsafe extern "C" fn sign_init(
+    hSession: CK_SESSION_HANDLE,
+    pMechanism: *mut CK_MECHANISM,
+    hKey: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    with_state(|state| operation_init(state, hSession, OperationKind::Sign, pMechanism, hKey))
+}
+
+unsafe extern "C" fn sign(
+    hSession: CK_SESSION_HANDLE,
+    pData: *mut u8,
+    ulDataLen: CK_ULONG,
+    pSignature: *mut u8,
+    pulSignatureLen: *mut CK_ULONG,
+) -> CK_RV {
+    entry_point(|| {
+        let data = input(pData, ulDataLen)?;
+        operation_finish(
+            hSession,
+            OperationKind::Sign,
+            Some(data),
+            pSignature,
+            pulSignatureLen,
+        )
+    })
+}
+
+unsafe extern "C" fn sign_update(
+    hSession: CK_SESSION_HANDLE,
+    pPart: *mut u8,
+    ulPartLen: CK_ULONG,
+) -> CK_RV {
+    with_state(|state| {
+        let part = input(pPart, ulPartLen)?;
+        let session = state.session(hSession)?;
+
+        match &mut session.operation {
+            Some(operation)
+                if operation.kind == OperationKind::Sign && operation.output.is_none() =>
+            {
+                operation.input.extend_from_slice(part);
+                Ok(())
+            }
+            _ => Err(CKR_OPERATION_NOT_INITIALIZED),
+        }
+    })
+}
+
+unsafe extern "C" fn sign_final(
+    hSession: CK_SESSION_HANDLE,
+    pSignature: *mut u8,
+    pulSignatureLen: *mut CK_ULONG,
+) -> CK_RV {
+    entry_point(|| {
+        operation_finish(
+            hSession,
+            OperationKind::Sign,
+            None,
+            pSignature,
+            pulSignatureLen,
+        )
+    })
+}
+
+unsafe extern "C" fn generate_key_pair(
+    hSession: CK_SESSION_HANDLE,
+    pMechanism: *mut CK_MECHANISM,
+    pPublicKeyTemplate: *mut CK_ATTRIBUTE,
+    ulPublicKeyAttributeCount: CK_ULONG,
+    pPrivateKeyTemplate: *mut CK_ATTRIBUTE,
+    ulPrivateKeyAttributeCount: CK_ULONG,
+    phPublicKey: *mut CK_OBJECT_HANDLE,
+    phPrivateKey: *mut CK_OBJECT_HANDLE,
+) -> CK_RV {
+    with_state(|state| {
+        check_rw(state.session(hSession)?)?;
+        let keychain = state.keychain()?;
+        let mechanism = pMechanism.as_ref().ok_or(CKR_ARGUMENTS_BAD)?;
+
+        if phPublicKey.is_null() || phPrivateKey.is_null() {
+            return Err(CKR_ARGUMENTS_BAD);
+        }
+
+        let public_template = template_values(pPublicKeyTemplate, ulPublicKeyAttributeCount)?;
+        let private_template = template_values(pPrivateKeyTemplate, ulPrivateKeyAttributeCount)?;
+        let params = key_pair_params(mechanism, &public_template, &private_template)?;
+
+        // Objects are session objects unless `CKA_TOKEN` is set
+        let permanent = match private_template
+            .get(&CKA_TOKEN)
+            .or_else(|| public_template.get(&CKA_TOKEN))
+        {
+            Some(value) => bool_value(value)?,
+            None => false,
+        };
+
+        let (keypair, session) = if permanent {
+            (keychain.create_key_pair(params.permanent(true)), None)
+        } else {
+            (keychain.generate_key_pair(params), Some(hSession))
+        };
+
+        let keypair = keypair.map_err(|e| error_rv(&e))?;
+        let private_key = Object::new(keypair.private_key, session).ok_or(CKR_FUNCTION_FAILED)?;
+        let public_key = Object::public_key(&private_key, session).ok_or(CKR_FUNCTION_FAILED)?;
+
+        let public_handle = state.handle();
+        state.objects.insert(public_handle, public_key);
+        *phPublicKey = public_handle;
+
+        let private_handle = state.handle();
+        state.objects.insert(private_handle, private_key);
+        *phPrivateKey = private_handle;
+
+        Ok(())
+    })
+}
+
+unsupported! {
+    init_token(CK_SLOT_ID, *mut u8, CK_ULONG, *mut u8);
+    init_pin(CK_SESSION_HANDLE, *mut u8, CK_ULONG);
+    set_pin(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, CK_ULONG);
+    get_operation_state(CK_SESSION_HANDLE, *mut u8, *mut CK_ULONG);
+    set_operation_state(CK_SESSION_HANDLE, *mut u8, CK_ULONG, CK_OBJECT_HANDLE, CK_OBJECT_HANDLE);
+    create_object(CK_SESSION_HANDLE, *mut CK_ATTRIBUTE, CK_ULONG, *mut CK_OBJECT_HANDLE);
+    copy_object(
+        CK_SESSION_HANDLE,
+        CK_OBJECT_HANDLE,
+        *mut CK_ATTRIBUTE,
+        CK_ULONG,
+        *mut CK_OBJECT_HANDLE
+    );
+    get_object_size(CK_SESSION_HANDLE, CK_OBJECT_HANDLE, *mut CK_ULONG);
+    set_attribute_value(CK_SESSION_HANDLE, CK_OBJECT_HANDLE, *mut CK_ATTRIBUTE, CK_ULONG);
+    encrypt_init(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE);
+    encrypt(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG);
+    encrypt_update(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG);
+    encrypt_final(CK_SESSION_HANDLE, *mut u8, *mut CK_ULONG);
+    decrypt_update(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG);
+    decrypt_final(CK_SESSION_HANDLE, *mut u8, *mut CK_ULONG);
+    digest_init(CK_SESSION_HANDLE, *mut CK_MECHANISM);
+    digest(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG);
+    digest_update(CK_SESSION_HANDLE, *mut u8, CK_ULONG);
+    digest_key(CK_SESSION_HANDLE, CK_OBJECT_HANDLE);
+    digest_final(CK_SESSION_HANDLE, *mut u8, *mut CK_ULONG);
+    sign_recover_init(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE);
+    sign_recover(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG);
+    verify_init(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE);
+    verify(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, CK_ULONG);
+    verify_update(CK_SESSION_HANDLE, *mut u8, CK_ULONG);
+    verify_final(CK_SESSION_HANDLE, *mut u8, CK_ULONG);
+    verify_recover_init(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE);
+    verify_recover(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG);
+    digest_encrypt_update(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG);
+    decrypt_digest_update(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG);
+    sign_encrypt_update(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG);
+    decrypt_verify_update(CK_SESSION_HANDLE, *mut u8, CK_ULONG, *mut u8, *mut CK_ULONG);
+    generate_key(
+        CK_SESSION_HANDLE,
+        *mut CK_MECHANISM,
+        *mut CK_ATTRIBUTE,
+        CK_ULONG,
+        *mut CK_OBJECT_HANDLE
+    );
+    wrap_key(
+        CK_SESSION_HANDLE,
+        *mut CK_MECHANISM,
+        CK_OBJECT_HANDLE,
+        CK_OBJECT_HANDLE,
+        *mut u8,
+        *mut CK_ULONG
+    );
+    unwrap_key(
+        CK_SESSION_HANDLE,
+        *mut CK_MECHANISM,
+        CK_OBJECT_HANDLE,
+        *mut u8,
+        CK_ULONG,
+        *mut CK_ATTRIBUTE,
+        CK_ULONG,
+        *mut CK_OBJECT_HANDLE
+    );
+    derive_key(
+        CK_SESSION_HANDLE,
+        *mut CK_MECHANISM,
+        CK_OBJECT_HANDLE,
+        *mut CK_ATTRIBUTE,
+        CK_ULONG,
+        *mut CK_OBJECT_HANDLE
+    );
+    seed_random(CK_SESSION_HANDLE, *mut u8, CK_ULONG);
+    generate_random(CK_SESSION_HANDLE, *mut u8, CK_ULONG);
+    get_function_status(CK_SESSION_HANDLE);
+    cancel_function(CK_SESSION_HANDLE);
+    wait_for_slot_event(CK_FLAGS, *mut CK_SLOT_ID, *mut c_void);
+}
+
+/// Start a signing or decryption operation
+unsafe fn operation_init(
+    state: &mut State,
+    session: CK_SESSION_HANDLE,
+    kind: OperationKind,
+    mechanism: *const CK_MECHANISM,
+    key: CK_OBJECT_HANDLE,
+) -> Result<(), CK_RV> {
+    if state.session(session)?.operation.is_some() {
+        return Err(CKR_OPERATION_ACTIVE);
+    }
+
+    let id = state.handle();
+    let mechanism = mechanism.as_ref().ok_or(CKR_ARGUMENTS_BAD)?;
+    let object = state.object(key).map_err(|_| CKR_KEY_HANDLE_INVALID)?;
+
+    if object.key_class != AttrKeyClass::Private {
+        return Err(CKR_KEY_TYPE_INCONSISTENT);
+    }
+
+    if !object.matches(kind.usage_attribute(), &[CK_TRUE]) {
+        return Err(CKR_KEY_FUNCTION_NOT_PERMITTED);
+    }
+
+    let operation = Operation {
+        id,
+        kind,
+        key: object.key.clone(),
+        alg: kind.algorithm(mechanism, object.key_type)?,
+        curve: object.curve,
+        input: vec![],
+        output: None,
+    };
+
+    state.session(session)?.operation = Some(operation);
+    Ok(())
+}
+
+/// Finish a signing or decryption operation, optionally with a final part
+/// of the input, writing its output following the PKCS#11 conventions for
+/// output buffers. The operation remains active until the output is
+/// written (or fails).
+///
+/// The operation is performed without holding the lock on the provider's
+/// state, as signing or decrypting may be slow (or wait for the user).
+unsafe fn operation_finish(
+    session: CK_SESSION_HANDLE,
+    kind: OperationKind,
+    input: Option<&[u8]>,
+    output: *mut u8,
+    output_len: *mut CK_ULONG,
+) -> Result<(), CK_RV> {
+    let pending = locked(|state| {
+        let operation = match &mut state.session(session)?.operation {
+            Some(operation) if operation.kind == kind => operation,
+            _ => return Err(CKR_OPERATION_NOT_INITIALIZED),
+        };
+
+        if operation.output.is_some() {
+            return Ok(None);
+        }
+
+        if let Some(input) = input {
+            operation.input.extend_from_slice(input);
+        }
+
+        Ok(Some(operation.clone()))
+    })?;
+
+    let performed = pending.map(|operation| (operation.id, operation.perform()));
+    let id = performed.as_ref().map(|(id, _)| *id);
+
+    locked(|state| {
+        let session = state.session(session)?;
+
+        // The operation may have been replaced while it was performed
+        let operation = match &mut session.operation {
+            Some(operation)
+                if operation.kind == kind && id.unwrap_or(operation.id) == operation.id =>
+            {
+                operation
+            }
+            _ => return Err(CKR_OPERATION_NOT_INITIALIZED),
+        };
+
+        match performed {
+            Some((_, Ok(result))) => operation.output = Some(Zeroizing::new(result)),
+            Some((_, Err(e))) => {
+                session.operation = None;
+                return Err(kind.error_rv(&e));
+            }
+            None => (),
+        }
+
+        let result = match &operation.output {
+            Some(result) => write_list(result, output, output_len),
+            None => Err(CKR_GENERAL_ERROR),
+        };
+
+        match result {
+            Ok(false) | Err(CKR_BUFFER_TOO_SMALL) => (),
+            _ => session.operation = None,
+        }
+
+        result.map(|_| ())
+    })
+}
+
+/// Get the parameters of a key pair generation mechanism from templates
+fn key_pair_params(
+    mechanism: &CK_MECHANISM,
+    public_template: &HashMap<CK_ATTRIBUTE_TYPE, &[u8]>,
+    private_template: &HashMap<CK_ATTRIBUTE_TYPE, &[u8]>,
+) -> Result<KeyPairGenerateParams, CK_RV> {
+    let mut params = match mechanism.mechanism {
+        CKM_EC_KEY_PAIR_GEN => {
+            let key_size = match public_template.get(&CKA_EC_PARAMS) {
+                Some(&EC_PARAMS_P256) => 256,
+                Some(&EC_PARAMS_P384) => 384,
+                Some(&EC_PARAMS_P521) => 521,
+                Some(_) => return Err(CKR_ATTRIBUTE_VALUE_INVALID),
+                None => return Err(CKR_TEMPLATE_INCOMPLETE),
+            };
+
+            KeyPairGenerateParams::new(AttrKeyType::EcSecPrimeRandom, key_size)
+        }
+        CKM_RSA_PKCS_KEY_PAIR_GEN => {
+            let key_size = match public_template.get(&CKA_MODULUS_BITS) {
+                Some(value) => ulong_value(value)?,
+                None => return Err(CKR_TEMPLATE_INCOMPLETE),
+            };
+
+            // Keys are always generated with a public exponent of 65537
+            if let Some(exponent) = public_template.get(&CKA_PUBLIC_EXPONENT) {
+                let start = exponent
+                    .iter()
+                    .position(|b| *b != 0)
+                    .unwrap_or(exponent.len());
+
+                if exponent[start..] != [0x01, 0x00, 0x01] {
+                    return Err(CKR_ATTRIBUTE_VALUE_INVALID);
+                }
+            }
+
+            KeyPairGenerateParams::new(AttrKeyType::Rsa, key_size as usize)
+        }
+        _ => return Err(CKR_MECHANISM_INVALID),
+    };
+
+    if let Some(label) = private_template
+        .get(&CKA_LABEL)
+        .or_else(|| public_template.get(&CKA_LABEL))
+    {
+        let label = str::from_utf8(label).map_err(|_| CKR_ATTRIBUTE_VALUE_INVALID)?;
+        params = params.label(label);
+    }
+
+    if let Some(id) = private_template
+        .get(&CKA_ID)
+        .or_else(|| public_template.get(&CKA_ID))
+        .filter(|id| !id.is_empty())
+    {
+        params = params.application_tag(*id);
+    }
+
+    for (type_, private, setter) in KEY_PAIR_PARAMS {
+        let template = if *private {
+            private_template
+        } else {
+            public_template
+        };
+
+        if let Some(value) = template.get(type_) {
+            params = setter(params, bool_value(value)?);
+        }
+    }
+
+    Ok(params)
+}
+
+/// Get the digest of a PSS signature mechanism's parameters. Keychain
+/// Services uses salts the size of the digest.
+unsafe fn pss_digest(mechanism: &CK_MECHANISM) -> Result<DigestAlgorithm, CK_RV> {
+    let params = parameters::<CK_RSA_PKCS_PSS_PARAMS>(mechanism)?;
+    let digest_alg = digest_algorithm(params.hashAlg, params.mgf)?;
+
+    if params.sLen as usize != digest_alg.output_size() {
+        return Err(CKR_MECHANISM_PARAM_INVALID);
+    }
+
+    Ok(digest_alg)
+}
+
+/// Check the parameters of a PSS mechanism which hashes the message with
+/// the given digest, returning the equivalent algorithm
+unsafe fn pss_message(
+    mechanism: &CK_MECHANISM,
+    digest_alg: DigestAlgorithm,
+    alg: KeyAlgorithm,
+) -> Result<KeyAlgorithm, CK_RV> {
+    if pss_digest(mechanism)? != digest_alg {
+        return Err(CKR_MECHANISM_PARAM_INVALID);
+    }
+
+    Ok(alg)
+}
+
+/// Get the digest algorithm for a hash mechanism and MGF1 function, which
+/// must use the same digest
+fn digest_algorithm(
+    hash_alg: CK_MECHANISM_TYPE,
+    mgf: CK_RSA_PKCS_MGF_TYPE,
+) -> Result<DigestAlgorithm, CK_RV> {
+    match (hash_alg, mgf) {
+        (CKM_SHA_1, CKG_MGF1_SHA1) => Ok(DigestAlgorithm::Sha1),
+        (CKM_SHA224, CKG_MGF1_SHA224) => Ok(DigestAlgorithm::Sha224),
+        (CKM_SHA256, CKG_MGF1_SHA256) => Ok(DigestAlgorithm::Sha256),
+        (CKM_SHA384, CKG_MGF1_SHA384) => Ok(DigestAlgorithm::Sha384),
+        (CKM_SHA512, CKG_MGF1_SHA512) => Ok(DigestAlgorithm::Sha512),
+        _ => Err(CKR_MECHANISM_PARAM_INVALID),
+    }
+}
+
+/// Get a mechanism's parameters
+unsafe fn parameters<T: Copy>(mechanism: &CK_MECHANISM) -> Result<T, CK_RV> {
+    if mechanism.pParameter.is_null() || mechanism.ulParameterLen as usize != size_of::<T>() {
+        return Err(CKR_MECHANISM_PARAM_INVALID);
+    }
+
+    Ok(ptr::read_unaligned(mechanism.pParameter as *const T))
+}
+
+/// Get a template passed by an application
+unsafe fn template<'a>(
+    attrs: *mut CK_ATTRIBUTE,
+    count: CK_ULONG,
+) -> Result<&'a mut [CK_ATTRIBUTE], CK_RV> {
+    match (attrs.is_null(), count) {
+        (_, 0) => Ok(&mut []),
+        (true, _) => Err(CKR_ARGUMENTS_BAD),
+        (false, count) => Ok(slice::from_raw_parts_mut(attrs, count as usize)),
+    }
+}
+
+/// Get the attribute values of a template passed by an application
+unsafe fn template_values<'a>(
+    attrs: *mut CK_ATTRIBUTE,
+    count: CK_ULONG,
+) -> Result<HashMap<CK_ATTRIBUTE_TYPE, &'a [u8]>, CK_RV> {
+    template(attrs, count)?
+        .iter()
+        .map(|attr| Ok((attr.type_, value(attr)?)))
+        .collect()
+}
+
+/// Get the value of an attribute passed by an application
+unsafe fn value<'a>(attr: &CK_ATTRIBUTE) -> Result<&'a [u8], CK_RV> {
+    input(attr.pValue as *const u8, attr.ulValueLen)
+}
+
+/// Get an input buffer passed by an application
+unsafe fn input<'a>(data: *const u8, len: CK_ULONG) -> Result<&'a [u8], CK_RV> {
+    match (data.is_null(), len) {
+        (_, 0) => Ok(&[]),
+        (true, _) => Err(CKR_ARGUMENTS_BAD),
+        (false, len) => Ok(slice::from_raw_parts(data, len as usize)),
+    }
+}
+
+/// Write a list of items to a buffer passed by an application, or just
+/// the number of items if the buffer is null. Returns whether the items
+/// were written.
+unsafe fn write_list<T: Copy>(
+    items: &[T],
+    buffer: *mut T,
+    len: *mut CK_ULONG,
+) -> Result<bool, CK_RV> {
+    let len = len.as_mut().ok_or(CKR_ARGUMENTS_BAD)?;
+    let capacity = *len as usize;
+    *len = items.len() as CK_ULONG;
+
+    if buffer.is_null() {
+        return Ok(false);
+    }
+
+    if capacity < items.len() {
+        return Err(CKR_BUFFER_TOO_SMALL);
+    }
+
+    ptr::copy_nonoverlapping(items.as_ptr(), buffer, items.len());
+    Ok(true)
+}
+
+/// Check that a slot ID is the provider's slot
+fn check_slot(slot: CK_SLOT_ID) -> Result<(), CK_RV> {
+    if slot == SLOT_ID {
+        Ok(())
+    } else {
+        Err(CKR_SLOT_ID_INVALID)
+    }
+}
+
+/// Check that a session is a read/write session
+fn check_rw(session: &Session) -> Result<(), CK_RV> {
+    if session.flags & CKF_RW_SESSION != 0 {
+        Ok(())
+    } else {
+        Err(CKR_SESSION_READ_ONLY)
+    }
+}
+
+/// Convert a `CK_RV` into a result
+fn check(rv: CK_RV) -> Result<(), CK_RV> {
+    if rv == CKR_OK {
+        Ok(())
+    } else {
+        Err(rv)
+    }
+}
+
+/// Convert an error into the closest `CK_RV`
+fn error_rv(error: &Error) -> CK_RV {
+    match error.kind() {
+        ErrorKind::AuthFailed | ErrorKind::InteractionNotAllowed => CKR_FUNCTION_CANCELED,
+        ErrorKind::ItemNotFound | ErrorKind::InvalidItemRef => CKR_KEY_HANDLE_INVALID,
+        ErrorKind::KeySizeNotAllowed => CKR_KEY_SIZE_RANGE,
+        ErrorKind::DataTooLarge => CKR_DATA_LEN_RANGE,
+        ErrorKind::Param | ErrorKind::Decode => CKR_DATA_INVALID,
+        ErrorKind::ReadOnly => CKR_TOKEN_WRITE_PROTECTED,
+        ErrorKind::Unimplemented => CKR_FUNCTION_NOT_SUPPORTED,
+        _ => CKR_FUNCTION_FAILED,
+    }
+}
+
+/// Encode a `CK_BBOOL` attribute value
+fn bool_bytes(value: bool) -> Vec<u8> {
+    vec![if value { CK_TRUE } else { CK_FALSE }]
+}
+
+/// Encode a `CK_ULONG` attribute value
+fn ulong_bytes(value: CK_ULONG) -> Vec<u8> {
+    value.to_ne_bytes().to_vec()
+}
+
+/// Decode a `CK_BBOOL` attribute value passed by an application
+fn bool_value(value: &[u8]) -> Result<bool, CK_RV> {
+    match value {
+        [value] => Ok(*value != CK_FALSE),
+        _ => Err(CKR_ATTRIBUTE_VALUE_INVALID),
+    }
+}
+
+/// Decode a `CK_ULONG` attribute value passed by an application
+fn ulong_value(value: &[u8]) -> Result<CK_ULONG, CK_RV> {
+    value
+        .try_into()
+        .map(CK_ULONG::from_ne_bytes)
+        .map_err(|_| CKR_ATTRIBUTE_VALUE_INVALID)
+}
+
+/// Encode a string as a fixed-length, space-padded field of a PKCS#11
+/// structure, truncating it if necessary
+fn padded<const N: usize>(s: &str) -> [u8; N] {
+    let mut field = [b' '; N];
+    let len = s.len().min(N);
+    field[..len].copy_from_slice(&s.as_bytes()[..len]);
+    field
+}
+
+/// Version of this crate
+fn library_version() -> CK_VERSION {
+    CK_VERSION {
+        major: env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap_or(0),
+        minor: env!("CARGO_PKG_VERSION_MINOR").parse().unwrap_or(0),
+    }
+}
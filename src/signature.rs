@@ -3,7 +3,48 @@
 //! This type doesn't map directly to any type in the Keychain Services API,
 //! but instead provides a newtype for signatures this binding produces.
 
-use crate::key::KeyAlgorithm;
+use crate::{
+    error::{Error, ErrorKind},
+    key::{EcCurve, KeyAlgorithm},
+    software::{self, PublicKey, SignatureScheme},
+};
+
+/// Verify a signature over the given message using a public key in Keychain
+/// Services' external representation (i.e. `04 || X || Y` for EC keys or
+/// PKCS#1 for RSA keys), without needing the Security Framework or a
+/// keychain.
+///
+/// Supports every signature variant of `KeyAlgorithm`, interpreting the
+/// message as either a precomputed digest or data to be hashed as the
+/// algorithm specifies. Returns an error with a kind of
+/// `ErrorKind::VerifyFailed` if the signature is invalid.
+pub fn verify(
+    public_key: &[u8],
+    alg: KeyAlgorithm,
+    message: &[u8],
+    signature: &Signature,
+) -> Result<(), Error> {
+    if signature.algorithm() != alg {
+        return Err(Error::new(
+            ErrorKind::Param,
+            &format!(
+                "signature algorithm mismatch: expected {:?}, got {:?}",
+                alg,
+                signature.algorithm()
+            ),
+        ));
+    }
+
+    if software::signature_algorithm(alg).is_none() {
+        return Err(not_a_signature_algorithm(alg));
+    }
+
+    PublicKey::from_external_representation(alg.descriptor().key_type(), public_key)?.verify(
+        alg,
+        message,
+        signature.as_bytes(),
+    )
+}
 
 /// Cryptographic signatures
 #[derive(Clone, Debug)]
@@ -19,6 +60,46 @@ impl Signature {
         Self { alg, bytes }
     }
 
+    /// Create a `Signature` from its encoded bytes (e.g. one produced
+    /// elsewhere) for the given signature algorithm
+    pub fn from_bytes(alg: KeyAlgorithm, bytes: &[u8]) -> Result<Self, Error> {
+        match software::signature_algorithm(alg) {
+            Some(_) => Ok(Self::new(alg, bytes.into())),
+            None => Err(not_a_signature_algorithm(alg)),
+        }
+    }
+
+    /// Parse an ASN.1 DER encoded ECDSA signature (as produced by the
+    /// `ECDSASignature*X962*` algorithms) on the given curve, converting it
+    /// to the encoding used by `alg`.
+    ///
+    /// Non-canonical DER encodings and out-of-range `r`/`s` values are
+    /// rejected with `ErrorKind::Decode`.
+    pub fn from_der(alg: KeyAlgorithm, curve: EcCurve, der: &[u8]) -> Result<Self, Error> {
+        let fixed = der_to_fixed(curve, der)?;
+
+        let bytes = match ecdsa_scheme(alg)? {
+            SignatureScheme::EcdsaDer => der.into(),
+            _ => fixed,
+        };
+
+        Ok(Self::new(alg, bytes))
+    }
+
+    /// Parse a fixed-width `r || s` ECDSA signature (as produced by the
+    /// `ECDSASignatureRFC4754` algorithm) on the given curve, converting it
+    /// to the encoding used by `alg`.
+    pub fn from_fixed(alg: KeyAlgorithm, curve: EcCurve, bytes: &[u8]) -> Result<Self, Error> {
+        let der = fixed_to_der(curve, bytes)?;
+
+        let bytes = match ecdsa_scheme(alg)? {
+            SignatureScheme::EcdsaDer => der,
+            _ => bytes.into(),
+        };
+
+        Ok(Self::new(alg, bytes))
+    }
+
     /// Get the algorithm which produced this signature
     pub fn algorithm(&self) -> KeyAlgorithm {
         self.alg
@@ -33,6 +114,42 @@ impl Signature {
     pub fn into_vec(self) -> Vec<u8> {
         self.bytes
     }
+
+    /// Encode this ECDSA signature as ASN.1 DER (i.e. the X9.62 format)
+    pub fn to_der(&self, curve: EcCurve) -> Result<Vec<u8>, Error> {
+        match ecdsa_scheme(self.alg)? {
+            SignatureScheme::EcdsaDer => fixed_to_der(curve, &der_to_fixed(curve, &self.bytes)?),
+            _ => fixed_to_der(curve, &self.bytes),
+        }
+    }
+
+    /// Encode this ECDSA signature as fixed-width `r || s` (i.e. the
+    /// RFC 4754 format, also used by JOSE/WebAuthn), where each of `r` and
+    /// `s` is the size of a field element on the given curve.
+    pub fn to_fixed(&self, curve: EcCurve) -> Result<Vec<u8>, Error> {
+        match ecdsa_scheme(self.alg)? {
+            SignatureScheme::EcdsaDer => der_to_fixed(curve, &self.bytes),
+            _ => fixed_to_der(curve, &self.bytes).and_then(|der| der_to_fixed(curve, &der)),
+        }
+    }
+
+    /// Is the `s` component of this ECDSA signature "low", i.e. no more than
+    /// half the order of the curve?
+    pub fn is_low_s(&self, curve: EcCurve) -> Result<bool, Error> {
+        Ok(self.normalize_s(curve)?.bytes == self.bytes)
+    }
+
+    /// Normalize this ECDSA signature to use a "low" `s` value, i.e. replace
+    /// `s` with `n - s` if it's more than half the curve order `n`, as
+    /// required by e.g. Bitcoin (BIP 62).
+    pub fn normalize_s(&self, curve: EcCurve) -> Result<Self, Error> {
+        let fixed = normalize_s(curve, &self.to_fixed(curve)?)?;
+
+        match ecdsa_scheme(self.alg)? {
+            SignatureScheme::EcdsaDer => Self::from_fixed(self.alg, curve, &fixed),
+            _ => Ok(Self::new(self.alg, fixed)),
+        }
+    }
 }
 
 impl AsRef<[u8]> for Signature {
@@ -46,3 +163,75 @@ impl From<Signature> for Vec<u8> {
         sig.into_vec()
     }
 }
+
+/// Evaluate an expression with `$sig` bound to the ECDSA signature type for
+/// the given curve
+macro_rules! with_ecdsa_signature {
+    ($curve:expr, $sig:ident => $body:expr) => {
+        match $curve {
+            EcCurve::P256 => {
+                type $sig = p256::ecdsa::Signature;
+                $body
+            }
+            EcCurve::P384 => {
+                type $sig = p384::ecdsa::Signature;
+                $body
+            }
+            EcCurve::P521 => {
+                type $sig = p521::ecdsa::Signature;
+                $body
+            }
+        }
+    };
+}
+
+/// Get the signature scheme for an ECDSA algorithm
+fn ecdsa_scheme(alg: KeyAlgorithm) -> Result<SignatureScheme, Error> {
+    match software::signature_algorithm(alg) {
+        Some(scheme @ SignatureScheme::EcdsaDer) | Some(scheme @ SignatureScheme::EcdsaFixed) => {
+            Ok(scheme)
+        }
+        _ => Err(Error::new(
+            ErrorKind::Param,
+            &format!("{:?} is not an ECDSA signature algorithm", alg),
+        )),
+    }
+}
+
+/// Convert an ASN.1 DER signature into fixed-width `r || s`
+fn der_to_fixed(curve: EcCurve, der: &[u8]) -> Result<Vec<u8>, Error> {
+    with_ecdsa_signature!(curve, Sig => Sig::from_der(der)
+        .map(|sig| sig.to_bytes().to_vec())
+        .map_err(|_| invalid_signature(curve, "ASN.1 DER")))
+}
+
+/// Convert a fixed-width `r || s` signature into ASN.1 DER
+fn fixed_to_der(curve: EcCurve, bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    with_ecdsa_signature!(curve, Sig => Sig::from_slice(bytes)
+        .map(|sig| sig.to_der().as_bytes().to_vec())
+        .map_err(|_| invalid_signature(curve, "fixed-width")))
+}
+
+/// Normalize the `s` value of a fixed-width signature
+fn normalize_s(curve: EcCurve, bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    with_ecdsa_signature!(curve, Sig => {
+        let sig = Sig::from_slice(bytes).map_err(|_| invalid_signature(curve, "fixed-width"))?;
+        Ok(sig.normalize_s().unwrap_or(sig).to_bytes().to_vec())
+    })
+}
+
+/// Error for malformed signatures
+fn invalid_signature(curve: EcCurve, encoding: &str) -> Error {
+    Error::new(
+        ErrorKind::Decode,
+        &format!("invalid {} ECDSA signature for {:?}", encoding, curve),
+    )
+}
+
+/// Error for algorithms which don't produce signatures
+fn not_a_signature_algorithm(alg: KeyAlgorithm) -> Error {
+    Error::new(
+        ErrorKind::Param,
+        &format!("{:?} is not a signature algorithm", alg),
+    )
+}
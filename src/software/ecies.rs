@@ -0,0 +1,106 @@
+//! Elliptic Curve Integrated Encryption Scheme (ECIES) compatible with the
+//! `kSecKeyAlgorithmECIESEncryption*` algorithms in Keychain Services.
+//!
+//! Ciphertexts consist of the sender's ephemeral public key (as an
+//! uncompressed `04 || X || Y` point), followed by the AES-GCM encrypted
+//! message and a 16-byte authentication tag. The AES key is derived from the
+//! ECDH shared secret using the ANSI X9.63 KDF, with the ephemeral public key
+//! as the KDF's shared info. AES-128 is used with keys of 256 bits or
+//! smaller, and AES-256 with larger keys.
+//!
+//! The 16-byte AES-GCM IV is all-zero for the original variants, whereas the
+//! "variable IV" variants derive it from the KDF output following the key.
+//!
+//! This is the construction documented for these algorithms in `SecKey.h`.
+
+use super::{
+    aes_gcm_decrypt, aes_gcm_encrypt, DigestAlgorithm, PrivateKey, PublicKey, GCM_TAG_SIZE,
+};
+use crate::{
+    attr::AttrKeyType,
+    error::{Error, ErrorKind},
+};
+use zeroize::Zeroizing;
+
+/// Size of the AES-GCM IV used by Keychain Services' ECIES
+const IV_SIZE: usize = 16;
+
+/// Encrypt the given plaintext to an elliptic curve public key
+pub(crate) fn encrypt(
+    public_key: &PublicKey,
+    kdf: DigestAlgorithm,
+    variable_iv: bool,
+    plaintext: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let ephemeral_key =
+        PrivateKey::generate(AttrKeyType::EcSecPrimeRandom, public_key.key_size_in_bits())?;
+    let shared_secret = ephemeral_key.diffie_hellman(public_key)?;
+
+    let mut ciphertext = ephemeral_key.public_key().to_external_representation();
+    let (key, iv) = derive_key_and_iv(public_key, kdf, variable_iv, &shared_secret, &ciphertext);
+
+    ciphertext.extend_from_slice(&aes_gcm_encrypt(&key, iv, &[], plaintext)?);
+    Ok(ciphertext)
+}
+
+/// Decrypt the given ciphertext using an elliptic curve private key, given
+/// its public key and a function which computes the ECDH shared secret with
+/// another public key (so the private key needn't be held in software)
+pub(crate) fn decrypt<F>(
+    public_key: &PublicKey,
+    diffie_hellman: F,
+    kdf: DigestAlgorithm,
+    variable_iv: bool,
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, Error>
+where
+    F: FnOnce(&PublicKey) -> Result<Zeroizing<Vec<u8>>, Error>,
+{
+    let point_size = public_key.to_external_representation().len();
+
+    if ciphertext.len() < point_size + GCM_TAG_SIZE {
+        return Err(Error::new(ErrorKind::Param, "invalid ECIES ciphertext"));
+    }
+
+    let (ephemeral_point, sealed) = ciphertext.split_at(point_size);
+    let ephemeral_key =
+        PublicKey::from_external_representation(AttrKeyType::EcSecPrimeRandom, ephemeral_point)
+            .map_err(|_| Error::new(ErrorKind::Param, "invalid ECIES ephemeral public key"))?;
+    let shared_secret = diffie_hellman(&ephemeral_key)?;
+    let (key, iv) = derive_key_and_iv(
+        public_key,
+        kdf,
+        variable_iv,
+        &shared_secret,
+        ephemeral_point,
+    );
+
+    aes_gcm_decrypt(&key, iv, &[], sealed)
+}
+
+/// Derive the AES key and IV from the ECDH shared secret
+fn derive_key_and_iv(
+    public_key: &PublicKey,
+    kdf: DigestAlgorithm,
+    variable_iv: bool,
+    shared_secret: &[u8],
+    ephemeral_point: &[u8],
+) -> (Zeroizing<Vec<u8>>, [u8; IV_SIZE]) {
+    let key_size = if public_key.key_size_in_bits() <= 256 {
+        16
+    } else {
+        32
+    };
+
+    let mut iv = [0u8; IV_SIZE];
+
+    if !variable_iv {
+        let key = kdf.x963_kdf(shared_secret, ephemeral_point, key_size);
+        return (key, iv);
+    }
+
+    let mut key = kdf.x963_kdf(shared_secret, ephemeral_point, key_size + IV_SIZE);
+    iv.copy_from_slice(&key[key_size..]);
+    key.truncate(key_size);
+    (key, iv)
+}
@@ -0,0 +1,749 @@
+//! Software implementations of the asymmetric key types Keychain Services
+//! supports (i.e. ECDSA over the NIST P-curves and RSA).
+
+use super::{
+    aes_gcm_decrypt, aes_gcm_encrypt, ecies, encryption_algorithm, prehash, signature_algorithm,
+    DigestAlgorithm, EncryptionScheme, SignatureScheme, GCM_TAG_SIZE,
+};
+use crate::{
+    attr::{AttrKeyClass, AttrKeyType},
+    error::{Error, ErrorKind},
+    key::{AlgorithmFamily, KeyAlgorithm, KeyOperation},
+};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use rand_core::{OsRng, RngCore};
+use rsa::{
+    hazmat::{rsa_decrypt_and_check, rsa_encrypt},
+    pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey, EncodeRsaPrivateKey, EncodeRsaPublicKey},
+    traits::PublicKeyParts,
+    BigUint, Oaep, Pkcs1v15Encrypt, Pkcs1v15Sign, Pss, RsaPrivateKey, RsaPublicKey,
+};
+use sha1::{Digest, Sha1};
+use sha2::{Sha224, Sha256, Sha384, Sha512};
+use std::fmt::{self, Debug};
+use zeroize::Zeroizing;
+
+/// Smallest RSA modulus size we will generate or import
+const MIN_RSA_KEY_SIZE: usize = 1024;
+
+/// Largest RSA modulus size we will generate or import
+const MAX_RSA_KEY_SIZE: usize = 8192;
+
+/// Sign a digest with an elliptic curve secret key
+macro_rules! ecdsa_sign {
+    ($curve:ident, $secret_key:expr, $prehash:expr, $scheme:expr) => {{
+        use $curve::ecdsa::signature::hazmat::PrehashSigner;
+        let signing_key = $curve::ecdsa::SigningKey::from_bytes(&$secret_key.to_bytes())
+            .map_err(|e| Error::new(ErrorKind::Param, &e))?;
+        let signature: $curve::ecdsa::Signature = signing_key
+            .sign_prehash(&$prehash)
+            .map_err(|e| Error::new(ErrorKind::Param, &e))?;
+
+        Ok(match $scheme {
+            SignatureScheme::EcdsaFixed => signature.to_bytes().to_vec(),
+            _ => signature.to_der().as_bytes().to_vec(),
+        })
+    }};
+}
+
+/// Verify a signature over a digest with an elliptic curve public key
+macro_rules! ecdsa_verify {
+    ($curve:ident, $public_key:expr, $prehash:expr, $scheme:expr, $signature:expr) => {{
+        use $curve::ecdsa::signature::hazmat::PrehashVerifier;
+        let verifying_key = $curve::ecdsa::VerifyingKey::from_affine(*$public_key.as_affine())
+            .map_err(|e| Error::new(ErrorKind::Param, &e))?;
+        let signature = match $scheme {
+            SignatureScheme::EcdsaFixed => $curve::ecdsa::Signature::from_slice($signature),
+            _ => $curve::ecdsa::Signature::from_der($signature),
+        }
+        .map_err(|e| Error::new(ErrorKind::VerifyFailed, &e))?;
+
+        verifying_key
+            .verify_prehash(&$prehash, &signature)
+            .map_err(|e| Error::new(ErrorKind::VerifyFailed, &e))
+    }};
+}
+
+/// Software keys, i.e. either a public or private key
+#[derive(Clone, Debug)]
+pub(crate) enum SoftwareKey {
+    /// Public key
+    Public(PublicKey),
+
+    /// Private key
+    Private(PrivateKey),
+}
+
+impl SoftwareKey {
+    /// Restore a key from Keychain Services' external representation
+    pub(crate) fn from_external_representation(
+        key_class: AttrKeyClass,
+        key_type: AttrKeyType,
+        bytes: &[u8],
+    ) -> Result<Self, Error> {
+        match key_class {
+            AttrKeyClass::Public => Ok(SoftwareKey::Public(
+                PublicKey::from_external_representation(key_type, bytes)?,
+            )),
+            AttrKeyClass::Private => Ok(SoftwareKey::Private(
+                PrivateKey::from_external_representation(key_type, bytes)?,
+            )),
+            AttrKeyClass::Symmetric => Err(Error::new(
+                ErrorKind::Param,
+                "symmetric keys are unsupported",
+            )),
+        }
+    }
+
+    /// Serialize this key as Keychain Services' external representation
+    pub(crate) fn to_external_representation(&self) -> Zeroizing<Vec<u8>> {
+        match self {
+            SoftwareKey::Public(key) => Zeroizing::new(key.to_external_representation()),
+            SoftwareKey::Private(key) => key.to_external_representation(),
+        }
+    }
+
+    /// Get the class of this key
+    pub(crate) fn key_class(&self) -> AttrKeyClass {
+        match self {
+            SoftwareKey::Public(_) => AttrKeyClass::Public,
+            SoftwareKey::Private(_) => AttrKeyClass::Private,
+        }
+    }
+
+    /// Get the public key (or public component of a private key)
+    pub(crate) fn public_key(&self) -> PublicKey {
+        match self {
+            SoftwareKey::Public(key) => key.clone(),
+            SoftwareKey::Private(key) => key.public_key(),
+        }
+    }
+
+    /// Get the private key, or an error if this is a public key
+    pub(crate) fn private_key(&self) -> Result<&PrivateKey, Error> {
+        match self {
+            SoftwareKey::Private(key) => Ok(key),
+            SoftwareKey::Public(_) => Err(Error::new(ErrorKind::Param, "not a private key")),
+        }
+    }
+
+    /// Can this key perform the given operation using the given algorithm?
+    pub(crate) fn is_supported(&self, operation: KeyOperation, alg: KeyAlgorithm) -> bool {
+        is_supported(
+            self.key_class(),
+            self.public_key().key_type(),
+            operation,
+            alg,
+        )
+    }
+}
+
+/// Can keys of the given class and type perform the given operation using
+/// the given algorithm?
+pub(crate) fn is_supported(
+    key_class: AttrKeyClass,
+    key_type: AttrKeyType,
+    operation: KeyOperation,
+    alg: KeyAlgorithm,
+) -> bool {
+    let usable = matches!(
+        (key_class, operation),
+        (AttrKeyClass::Private, KeyOperation::Sign)
+            | (AttrKeyClass::Private, KeyOperation::Decrypt)
+            | (AttrKeyClass::Private, KeyOperation::KeyExchange)
+            | (AttrKeyClass::Public, KeyOperation::Verify)
+            | (AttrKeyClass::Public, KeyOperation::Encrypt)
+    );
+
+    usable && alg.is_compatible(operation, key_type)
+}
+
+/// Software private keys
+#[derive(Clone)]
+pub(crate) enum PrivateKey {
+    /// NIST P-256 secret scalar
+    P256(p256::SecretKey),
+
+    /// NIST P-384 secret scalar
+    P384(p384::SecretKey),
+
+    /// NIST P-521 secret scalar
+    P521(p521::SecretKey),
+
+    /// RSA private key
+    Rsa(Box<RsaPrivateKey>),
+}
+
+impl PrivateKey {
+    /// Generate a random private key of the given type and size
+    pub(crate) fn generate(key_type: AttrKeyType, key_size: usize) -> Result<Self, Error> {
+        match (key_type, key_size) {
+            (AttrKeyType::EcSecPrimeRandom, 256) => {
+                Ok(PrivateKey::P256(p256::SecretKey::random(&mut OsRng)))
+            }
+            (AttrKeyType::EcSecPrimeRandom, 384) => {
+                Ok(PrivateKey::P384(p384::SecretKey::random(&mut OsRng)))
+            }
+            (AttrKeyType::EcSecPrimeRandom, 521) => {
+                Ok(PrivateKey::P521(p521::SecretKey::random(&mut OsRng)))
+            }
+            (AttrKeyType::Rsa, MIN_RSA_KEY_SIZE..=MAX_RSA_KEY_SIZE) if key_size % 8 == 0 => {
+                RsaPrivateKey::new(&mut OsRng, key_size)
+                    .map(|sk| PrivateKey::Rsa(Box::new(sk)))
+                    .map_err(|e| Error::new(ErrorKind::Param, &e))
+            }
+            (AttrKeyType::Aes, _) => Err(Error::new(
+                ErrorKind::Param,
+                "symmetric keys are unsupported",
+            )),
+            _ => Err(Error::new(
+                ErrorKind::KeySizeNotAllowed,
+                &format!("unsupported {:?} key size: {}", key_type, key_size),
+            )),
+        }
+    }
+
+    /// Restore a private key from Keychain Services' external representation
+    pub(crate) fn from_external_representation(
+        key_type: AttrKeyType,
+        bytes: &[u8],
+    ) -> Result<Self, Error> {
+        let key = match key_type {
+            AttrKeyType::EcSecPrimeRandom => {
+                let (point, scalar) = match bytes.len() {
+                    97 => bytes.split_at(65),
+                    145 => bytes.split_at(97),
+                    199 => bytes.split_at(133),
+                    _ => return Err(invalid_key_data("EC private key")),
+                };
+
+                let key = match scalar.len() {
+                    32 => p256::SecretKey::from_slice(scalar).map(PrivateKey::P256),
+                    48 => p384::SecretKey::from_slice(scalar).map(PrivateKey::P384),
+                    _ => p521::SecretKey::from_slice(scalar).map(PrivateKey::P521),
+                }
+                .map_err(|_| invalid_key_data("EC private key"))?;
+
+                // Ensure the public key matches the secret scalar
+                if key.public_key().to_external_representation() != point {
+                    return Err(invalid_key_data("EC private key"));
+                }
+
+                key
+            }
+            AttrKeyType::Rsa => RsaPrivateKey::from_pkcs1_der(bytes)
+                .map(|sk| PrivateKey::Rsa(Box::new(sk)))
+                .map_err(|_| invalid_key_data("RSA private key"))?,
+            AttrKeyType::Aes => {
+                return Err(Error::new(
+                    ErrorKind::Param,
+                    "symmetric keys are unsupported",
+                ))
+            }
+        };
+
+        key.public_key().check_size()?;
+        Ok(key)
+    }
+
+    /// Serialize this key as Keychain Services' external representation,
+    /// i.e. `04 || X || Y || K` for EC keys or PKCS#1 for RSA keys.
+    pub(crate) fn to_external_representation(&self) -> Zeroizing<Vec<u8>> {
+        let mut bytes = Zeroizing::new(self.public_key().to_external_representation());
+
+        match self {
+            PrivateKey::P256(sk) => bytes.extend_from_slice(&sk.to_bytes()),
+            PrivateKey::P384(sk) => bytes.extend_from_slice(&sk.to_bytes()),
+            PrivateKey::P521(sk) => bytes.extend_from_slice(&sk.to_bytes()),
+            PrivateKey::Rsa(sk) => {
+                let der = sk
+                    .to_pkcs1_der()
+                    .expect("RSA private key serialization failed");
+                return Zeroizing::new(der.as_bytes().to_vec());
+            }
+        }
+
+        bytes
+    }
+
+    /// Get the public key which corresponds to this private key
+    pub(crate) fn public_key(&self) -> PublicKey {
+        match self {
+            PrivateKey::P256(sk) => PublicKey::P256(sk.public_key()),
+            PrivateKey::P384(sk) => PublicKey::P384(sk.public_key()),
+            PrivateKey::P521(sk) => PublicKey::P521(sk.public_key()),
+            PrivateKey::Rsa(sk) => PublicKey::Rsa(sk.to_public_key()),
+        }
+    }
+
+    /// Sign the given data using the given algorithm
+    pub(crate) fn sign(&self, alg: KeyAlgorithm, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let scheme = signature_algorithm(alg).ok_or_else(|| unsupported(alg))?;
+        let prehash = prehash(alg, data)?;
+
+        match (self, scheme) {
+            (PrivateKey::P256(sk), SignatureScheme::EcdsaFixed)
+            | (PrivateKey::P256(sk), SignatureScheme::EcdsaDer) => {
+                ecdsa_sign!(p256, sk, pad_prehash(&prehash, 32), scheme)
+            }
+            (PrivateKey::P384(sk), SignatureScheme::EcdsaFixed)
+            | (PrivateKey::P384(sk), SignatureScheme::EcdsaDer) => {
+                ecdsa_sign!(p384, sk, pad_prehash(&prehash, 48), scheme)
+            }
+            (PrivateKey::P521(sk), SignatureScheme::EcdsaFixed)
+            | (PrivateKey::P521(sk), SignatureScheme::EcdsaDer) => {
+                ecdsa_sign!(p521, sk, pad_prehash(&prehash, 66), scheme)
+            }
+            (PrivateKey::Rsa(sk), SignatureScheme::RsaRaw) => {
+                let input = BigUint::from_bytes_be(&prehash);
+
+                if &input >= sk.n() {
+                    return Err(Error::new(ErrorKind::Param, "input too large for RSA key"));
+                }
+
+                let output = rsa_decrypt_and_check(&**sk, Some(&mut OsRng), &input)
+                    .map_err(|e| Error::new(ErrorKind::Param, &e))?;
+
+                Ok(left_pad(&output.to_bytes_be(), sk.size()))
+            }
+            (PrivateKey::Rsa(sk), SignatureScheme::RsaPkcs1v15) => sk
+                .sign(pkcs1v15_scheme(alg), &prehash)
+                .map_err(|e| Error::new(ErrorKind::Param, &e)),
+            (PrivateKey::Rsa(sk), SignatureScheme::RsaPss) => sk
+                .sign_with_rng(&mut OsRng, pss_scheme(alg), &prehash)
+                .map_err(|e| Error::new(ErrorKind::Param, &e)),
+            _ => Err(unsupported(alg)),
+        }
+    }
+
+    /// Decrypt the given ciphertext using the given algorithm
+    pub(crate) fn decrypt(&self, alg: KeyAlgorithm, ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        let scheme = encryption_algorithm(alg).ok_or_else(|| unsupported(alg))?;
+
+        let sk = match (self, scheme) {
+            (PrivateKey::Rsa(sk), _) => sk,
+            (_, EncryptionScheme::Ecies { kdf, variable_iv }) => {
+                return ecies::decrypt(
+                    &self.public_key(),
+                    |ephemeral_key| self.diffie_hellman(ephemeral_key),
+                    kdf,
+                    variable_iv,
+                    ciphertext,
+                )
+            }
+            _ => return Err(unsupported(alg)),
+        };
+
+        match scheme {
+            EncryptionScheme::RsaRaw => {
+                let input = BigUint::from_bytes_be(ciphertext);
+
+                if ciphertext.len() != sk.size() || &input >= sk.n() {
+                    return Err(Error::new(
+                        ErrorKind::DecryptionFailed,
+                        "invalid RSA ciphertext",
+                    ));
+                }
+
+                let output = rsa_decrypt_and_check(&**sk, Some(&mut OsRng), &input)
+                    .map_err(|e| Error::new(ErrorKind::DecryptionFailed, &e))?;
+
+                Ok(left_pad(&output.to_bytes_be(), sk.size()))
+            }
+            EncryptionScheme::RsaPkcs1 => sk
+                .decrypt_blinded(&mut OsRng, Pkcs1v15Encrypt, ciphertext)
+                .map_err(|e| Error::new(ErrorKind::DecryptionFailed, &e)),
+            EncryptionScheme::RsaOaep(digest_alg) => sk
+                .decrypt_blinded(&mut OsRng, oaep_scheme(digest_alg), ciphertext)
+                .map_err(|e| Error::new(ErrorKind::DecryptionFailed, &e)),
+            EncryptionScheme::RsaOaepAesGcm(digest_alg) => {
+                if ciphertext.len() < sk.size() + GCM_TAG_SIZE {
+                    return Err(Error::new(
+                        ErrorKind::DecryptionFailed,
+                        "invalid RSA ciphertext",
+                    ));
+                }
+
+                let (wrapped_key, sealed) = ciphertext.split_at(sk.size());
+                let session_key = sk
+                    .decrypt_blinded(&mut OsRng, oaep_scheme(digest_alg), wrapped_key)
+                    .map(Zeroizing::new)
+                    .map_err(|e| Error::new(ErrorKind::DecryptionFailed, &e))?;
+
+                let aad = self.public_key().to_external_representation();
+                aes_gcm_decrypt(&session_key, [0u8; 16], &aad, sealed)
+            }
+            EncryptionScheme::Ecies { .. } => Err(unsupported(alg)),
+        }
+    }
+    /// Perform an ECDH key exchange with the given public key using one of
+    /// the `ECDHKeyExchange*` algorithms, deriving `requested_size` bytes
+    /// with the X9.63 KDF for algorithms which use it (or returning the raw
+    /// shared secret for those which don't)
+    pub(crate) fn key_exchange(
+        &self,
+        alg: KeyAlgorithm,
+        public_key: &PublicKey,
+        requested_size: usize,
+        shared_info: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let descriptor = alg.descriptor();
+
+        if descriptor.family != AlgorithmFamily::Ecdh {
+            return Err(unsupported(alg));
+        }
+
+        let shared_secret = self.diffie_hellman(public_key)?;
+
+        match descriptor.digest {
+            Some(_) if requested_size == 0 => Err(Error::new(
+                ErrorKind::Param,
+                &format!("{} requires a requested size", alg),
+            )),
+            Some(kdf) => Ok(kdf.x963_kdf(&shared_secret, shared_info, requested_size)),
+            None => Ok(shared_secret),
+        }
+    }
+
+    /// Compute the raw ECDH shared secret (i.e. the x-coordinate of the
+    /// shared point) with the given public key, which must be on the same
+    /// curve. The NIST P-curves have a cofactor of 1, so standard and
+    /// cofactor ECDH are equivalent.
+    pub(crate) fn diffie_hellman(
+        &self,
+        public_key: &PublicKey,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let shared_secret = match (self, public_key) {
+            (PrivateKey::P256(sk), PublicKey::P256(pk)) => {
+                p256::ecdh::diffie_hellman(sk.to_nonzero_scalar(), pk.as_affine())
+                    .raw_secret_bytes()
+                    .to_vec()
+            }
+            (PrivateKey::P384(sk), PublicKey::P384(pk)) => {
+                p384::ecdh::diffie_hellman(sk.to_nonzero_scalar(), pk.as_affine())
+                    .raw_secret_bytes()
+                    .to_vec()
+            }
+            (PrivateKey::P521(sk), PublicKey::P521(pk)) => {
+                p521::ecdh::diffie_hellman(sk.to_nonzero_scalar(), pk.as_affine())
+                    .raw_secret_bytes()
+                    .to_vec()
+            }
+            (PrivateKey::Rsa(_), _) => {
+                return Err(Error::new(
+                    ErrorKind::Param,
+                    "key exchange not supported by RSA keys",
+                ))
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::Param,
+                    "key exchange requires keys on the same curve",
+                ))
+            }
+        };
+
+        Ok(Zeroizing::new(shared_secret))
+    }
+}
+
+impl Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let curve = match self {
+            PrivateKey::P256(_) => "P256",
+            PrivateKey::P384(_) => "P384",
+            PrivateKey::P521(_) => "P521",
+            PrivateKey::Rsa(_) => "Rsa",
+        };
+
+        write!(f, "PrivateKey::{}(...)", curve)
+    }
+}
+
+/// Software public keys
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum PublicKey {
+    /// NIST P-256 public point
+    P256(p256::PublicKey),
+
+    /// NIST P-384 public point
+    P384(p384::PublicKey),
+
+    /// NIST P-521 public point
+    P521(p521::PublicKey),
+
+    /// RSA public key
+    Rsa(RsaPublicKey),
+}
+
+impl PublicKey {
+    /// Restore a public key from Keychain Services' external representation
+    pub(crate) fn from_external_representation(
+        key_type: AttrKeyType,
+        bytes: &[u8],
+    ) -> Result<Self, Error> {
+        let key = match key_type {
+            AttrKeyType::EcSecPrimeRandom => match bytes.len() {
+                65 => p256::PublicKey::from_sec1_bytes(bytes).map(PublicKey::P256),
+                97 => p384::PublicKey::from_sec1_bytes(bytes).map(PublicKey::P384),
+                133 => p521::PublicKey::from_sec1_bytes(bytes).map(PublicKey::P521),
+                _ => return Err(invalid_key_data("EC public key")),
+            }
+            .map_err(|_| invalid_key_data("EC public key"))?,
+            AttrKeyType::Rsa => RsaPublicKey::from_pkcs1_der(bytes)
+                .map(PublicKey::Rsa)
+                .map_err(|_| invalid_key_data("RSA public key"))?,
+            AttrKeyType::Aes => {
+                return Err(Error::new(
+                    ErrorKind::Param,
+                    "symmetric keys are unsupported",
+                ))
+            }
+        };
+
+        key.check_size()?;
+        Ok(key)
+    }
+
+    /// Serialize this key as Keychain Services' external representation,
+    /// i.e. `04 || X || Y` for EC keys or PKCS#1 for RSA keys.
+    pub(crate) fn to_external_representation(&self) -> Vec<u8> {
+        match self {
+            PublicKey::P256(pk) => pk.to_encoded_point(false).as_bytes().to_vec(),
+            PublicKey::P384(pk) => pk.to_encoded_point(false).as_bytes().to_vec(),
+            PublicKey::P521(pk) => pk.to_encoded_point(false).as_bytes().to_vec(),
+            PublicKey::Rsa(pk) => pk
+                .to_pkcs1_der()
+                .expect("RSA public key serialization failed")
+                .into_vec(),
+        }
+    }
+
+    /// Get the type of this key
+    pub(crate) fn key_type(&self) -> AttrKeyType {
+        match self {
+            PublicKey::Rsa(_) => AttrKeyType::Rsa,
+            _ => AttrKeyType::EcSecPrimeRandom,
+        }
+    }
+
+    /// Get the size of this key in bits
+    pub(crate) fn key_size_in_bits(&self) -> usize {
+        match self {
+            PublicKey::P256(_) => 256,
+            PublicKey::P384(_) => 384,
+            PublicKey::P521(_) => 521,
+            PublicKey::Rsa(pk) => pk.n().bits(),
+        }
+    }
+
+    /// Compute the `kSecAttrApplicationLabel` Keychain Services assigns to
+    /// this key, i.e. the SHA-1 hash of the public key (the `04 || X || Y`
+    /// point for EC keys or the modulus for RSA keys)
+    pub(crate) fn application_label(&self) -> Vec<u8> {
+        match self {
+            PublicKey::Rsa(pk) => Sha1::digest(pk.n().to_bytes_be()).to_vec(),
+            _ => Sha1::digest(self.to_external_representation()).to_vec(),
+        }
+    }
+
+    /// Verify a signature over the given data, returning an error with a
+    /// kind of `ErrorKind::VerifyFailed` if the signature is invalid
+    pub(crate) fn verify(
+        &self,
+        alg: KeyAlgorithm,
+        data: &[u8],
+        signature: &[u8],
+    ) -> Result<(), Error> {
+        let scheme = signature_algorithm(alg).ok_or_else(|| unsupported(alg))?;
+        let prehash = prehash(alg, data)?;
+
+        match (self, scheme) {
+            (PublicKey::P256(pk), SignatureScheme::EcdsaFixed)
+            | (PublicKey::P256(pk), SignatureScheme::EcdsaDer) => {
+                ecdsa_verify!(p256, pk, pad_prehash(&prehash, 32), scheme, signature)
+            }
+            (PublicKey::P384(pk), SignatureScheme::EcdsaFixed)
+            | (PublicKey::P384(pk), SignatureScheme::EcdsaDer) => {
+                ecdsa_verify!(p384, pk, pad_prehash(&prehash, 48), scheme, signature)
+            }
+            (PublicKey::P521(pk), SignatureScheme::EcdsaFixed)
+            | (PublicKey::P521(pk), SignatureScheme::EcdsaDer) => {
+                ecdsa_verify!(p521, pk, pad_prehash(&prehash, 66), scheme, signature)
+            }
+            (PublicKey::Rsa(pk), SignatureScheme::RsaRaw) => {
+                let input = BigUint::from_bytes_be(signature);
+
+                if signature.len() != pk.size() || &input >= pk.n() {
+                    return Err(Error::new(ErrorKind::VerifyFailed, "invalid signature"));
+                }
+
+                let output =
+                    rsa_encrypt(pk, &input).map_err(|e| Error::new(ErrorKind::VerifyFailed, &e))?;
+
+                if output == BigUint::from_bytes_be(&prehash) {
+                    Ok(())
+                } else {
+                    Err(Error::new(ErrorKind::VerifyFailed, "invalid signature"))
+                }
+            }
+            (PublicKey::Rsa(pk), SignatureScheme::RsaPkcs1v15) => pk
+                .verify(pkcs1v15_scheme(alg), &prehash, signature)
+                .map_err(|e| Error::new(ErrorKind::VerifyFailed, &e)),
+            (PublicKey::Rsa(pk), SignatureScheme::RsaPss) => pk
+                .verify(pss_scheme(alg), &prehash, signature)
+                .map_err(|e| Error::new(ErrorKind::VerifyFailed, &e)),
+            _ => Err(unsupported(alg)),
+        }
+    }
+
+    /// Encrypt the given plaintext using the given algorithm
+    pub(crate) fn encrypt(&self, alg: KeyAlgorithm, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        let scheme = encryption_algorithm(alg).ok_or_else(|| unsupported(alg))?;
+
+        let pk = match (self, scheme) {
+            (PublicKey::Rsa(pk), _) => pk,
+            (_, EncryptionScheme::Ecies { kdf, variable_iv }) => {
+                return ecies::encrypt(self, kdf, variable_iv, plaintext)
+            }
+            _ => return Err(unsupported(alg)),
+        };
+
+        match scheme {
+            EncryptionScheme::RsaRaw => {
+                let input = BigUint::from_bytes_be(plaintext);
+
+                if plaintext.len() != pk.size() || &input >= pk.n() {
+                    return Err(Error::new(ErrorKind::Param, "invalid RSA plaintext"));
+                }
+
+                let output =
+                    rsa_encrypt(pk, &input).map_err(|e| Error::new(ErrorKind::Param, &e))?;
+                Ok(left_pad(&output.to_bytes_be(), pk.size()))
+            }
+            EncryptionScheme::RsaPkcs1 => pk
+                .encrypt(&mut OsRng, Pkcs1v15Encrypt, plaintext)
+                .map_err(|e| Error::new(ErrorKind::Param, &e)),
+            EncryptionScheme::RsaOaep(digest_alg) => pk
+                .encrypt(&mut OsRng, oaep_scheme(digest_alg), plaintext)
+                .map_err(|e| Error::new(ErrorKind::Param, &e)),
+            EncryptionScheme::RsaOaepAesGcm(digest_alg) => {
+                // A random AES session key is wrapped using RSAES-OAEP, and
+                // the plaintext encrypted under it using an all-zero IV and
+                // the public key's PKCS#1 encoding as additional data.
+                // AES-256 is used with moduli of 4096 bits or more, and
+                // AES-128 with smaller ones.
+                let mut session_key =
+                    Zeroizing::new(vec![0u8; if pk.size() >= 512 { 32 } else { 16 }]);
+                OsRng.fill_bytes(&mut session_key);
+
+                let mut ciphertext = pk
+                    .encrypt(&mut OsRng, oaep_scheme(digest_alg), &session_key)
+                    .map_err(|e| Error::new(ErrorKind::Param, &e))?;
+                ciphertext.extend_from_slice(&aes_gcm_encrypt(
+                    &session_key,
+                    [0u8; 16],
+                    &self.to_external_representation(),
+                    plaintext,
+                )?);
+                Ok(ciphertext)
+            }
+            EncryptionScheme::Ecies { .. } => Err(unsupported(alg)),
+        }
+    }
+
+    /// Ensure the size of this key is one we support
+    pub(crate) fn check_size(&self) -> Result<(), Error> {
+        match self {
+            PublicKey::Rsa(pk)
+                if pk.size() * 8 < MIN_RSA_KEY_SIZE || pk.size() * 8 > MAX_RSA_KEY_SIZE =>
+            {
+                Err(Error::new(
+                    ErrorKind::KeySizeNotAllowed,
+                    &format!("unsupported RSA key size: {}", pk.n().bits()),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Get the PKCS#1 v1.5 signature padding for the given algorithm
+fn pkcs1v15_scheme(alg: KeyAlgorithm) -> Pkcs1v15Sign {
+    match alg {
+        KeyAlgorithm::RSASignatureDigestPKCS1v15SHA1
+        | KeyAlgorithm::RSASignatureMessagePKCS1v15SHA1 => Pkcs1v15Sign::new::<Sha1>(),
+        KeyAlgorithm::RSASignatureDigestPKCS1v15SHA224
+        | KeyAlgorithm::RSASignatureMessagePKCS1v15SHA224 => Pkcs1v15Sign::new::<Sha224>(),
+        KeyAlgorithm::RSASignatureDigestPKCS1v15SHA256
+        | KeyAlgorithm::RSASignatureMessagePKCS1v15SHA256 => Pkcs1v15Sign::new::<Sha256>(),
+        KeyAlgorithm::RSASignatureDigestPKCS1v15SHA384
+        | KeyAlgorithm::RSASignatureMessagePKCS1v15SHA384 => Pkcs1v15Sign::new::<Sha384>(),
+        KeyAlgorithm::RSASignatureDigestPKCS1v15SHA512
+        | KeyAlgorithm::RSASignatureMessagePKCS1v15SHA512 => Pkcs1v15Sign::new::<Sha512>(),
+        _ => Pkcs1v15Sign::new_unprefixed(),
+    }
+}
+
+/// Get the PSS signature padding for the given algorithm
+fn pss_scheme(alg: KeyAlgorithm) -> Pss {
+    match alg {
+        KeyAlgorithm::RSASignatureDigestPSSSHA1 | KeyAlgorithm::RSASignatureMessagePSSSHA1 => {
+            Pss::new::<Sha1>()
+        }
+        KeyAlgorithm::RSASignatureDigestPSSSHA224 | KeyAlgorithm::RSASignatureMessagePSSSHA224 => {
+            Pss::new::<Sha224>()
+        }
+        KeyAlgorithm::RSASignatureDigestPSSSHA384 | KeyAlgorithm::RSASignatureMessagePSSSHA384 => {
+            Pss::new::<Sha384>()
+        }
+        KeyAlgorithm::RSASignatureDigestPSSSHA512 | KeyAlgorithm::RSASignatureMessagePSSSHA512 => {
+            Pss::new::<Sha512>()
+        }
+        _ => Pss::new::<Sha256>(),
+    }
+}
+
+/// Get the OAEP encryption padding using the given digest for both the
+/// label hash and MGF1
+fn oaep_scheme(digest_alg: DigestAlgorithm) -> Oaep {
+    match digest_alg {
+        DigestAlgorithm::Sha1 => Oaep::new::<Sha1>(),
+        DigestAlgorithm::Sha224 => Oaep::new::<Sha224>(),
+        DigestAlgorithm::Sha256 => Oaep::new::<Sha256>(),
+        DigestAlgorithm::Sha384 => Oaep::new::<Sha384>(),
+        DigestAlgorithm::Sha512 => Oaep::new::<Sha512>(),
+    }
+}
+
+/// Left pad a digest with zeroes to the size of a curve's field elements.
+///
+/// ECDSA interprets digests shorter than the field size as integers, which
+/// is equivalent to zero-padding them on the left.
+fn pad_prehash(prehash: &[u8], field_size: usize) -> Vec<u8> {
+    left_pad(prehash, field_size)
+}
+
+/// Left pad the given big endian integer with zeroes to the given length
+fn left_pad(bytes: &[u8], len: usize) -> Vec<u8> {
+    if bytes.len() >= len {
+        return bytes.into();
+    }
+
+    let mut result = vec![0u8; len - bytes.len()];
+    result.extend_from_slice(bytes);
+    result
+}
+
+/// Error for key data which couldn't be parsed
+fn invalid_key_data(description: &str) -> Error {
+    Error::new(ErrorKind::Param, &format!("invalid {} data", description))
+}
+
+/// Error for algorithms which can't be used with a particular key
+fn unsupported(alg: KeyAlgorithm) -> Error {
+    Error::new(
+        ErrorKind::Param,
+        &format!("algorithm not supported by key: {:?}", alg),
+    )
+}
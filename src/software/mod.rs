@@ -0,0 +1,250 @@
+//! Pure Rust implementations of the cryptographic operations Keychain
+//! Services performs with keys, used by backends which don't have access
+//! to the Security Framework (e.g. `backend::MemoryBackend`).
+
+pub(crate) mod ecies;
+mod key;
+
+pub(crate) use self::key::{is_supported, PrivateKey, PublicKey, SoftwareKey};
+pub(crate) use crate::key::DigestAlgorithm;
+use crate::{
+    error::{Error, ErrorKind},
+    key::{AlgorithmFamily, AlgorithmPadding, KeyAlgorithm, SignatureInput},
+};
+use aes::{Aes128, Aes256};
+use aes_gcm::{
+    aead::{consts::U16, Aead, Payload},
+    AesGcm, KeyInit,
+};
+use sha1::Sha1;
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+use std::io::{self, Read, Write};
+use zeroize::Zeroizing;
+
+/// Size of the AES-GCM authentication tags Keychain Services appends to
+/// ciphertexts
+pub(crate) const GCM_TAG_SIZE: usize = 16;
+
+/// AES-GCM with a 128-bit key and 16-byte IV
+type Aes128Gcm16 = AesGcm<Aes128, U16>;
+
+/// AES-GCM with a 256-bit key and 16-byte IV
+type Aes256Gcm16 = AesGcm<Aes256, U16>;
+
+impl DigestAlgorithm {
+    /// Compute the digest of the given message
+    pub(crate) fn digest(self, message: &[u8]) -> Vec<u8> {
+        match self {
+            DigestAlgorithm::Sha1 => Sha1::digest(message).to_vec(),
+            DigestAlgorithm::Sha224 => Sha224::digest(message).to_vec(),
+            DigestAlgorithm::Sha256 => Sha256::digest(message).to_vec(),
+            DigestAlgorithm::Sha384 => Sha384::digest(message).to_vec(),
+            DigestAlgorithm::Sha512 => Sha512::digest(message).to_vec(),
+        }
+    }
+
+    /// Compute the digest of all of the data read from the given reader
+    pub(crate) fn digest_reader<R: Read>(self, reader: R) -> io::Result<Vec<u8>> {
+        match self {
+            DigestAlgorithm::Sha1 => digest_reader::<Sha1, R>(reader),
+            DigestAlgorithm::Sha224 => digest_reader::<Sha224, R>(reader),
+            DigestAlgorithm::Sha256 => digest_reader::<Sha256, R>(reader),
+            DigestAlgorithm::Sha384 => digest_reader::<Sha384, R>(reader),
+            DigestAlgorithm::Sha512 => digest_reader::<Sha512, R>(reader),
+        }
+    }
+
+    /// Derive `len` bytes of key material from the shared secret `z` using
+    /// the ANSI X9.63 KDF (SEC 1 section 3.6.1) with this digest function
+    pub(crate) fn x963_kdf(self, z: &[u8], shared_info: &[u8], len: usize) -> Zeroizing<Vec<u8>> {
+        let mut output = Zeroizing::new(Vec::with_capacity(len + self.output_size()));
+        let mut counter = 1u32;
+
+        while output.len() < len {
+            let mut input = Zeroizing::new(z.to_vec());
+            input.extend_from_slice(&counter.to_be_bytes());
+            input.extend_from_slice(shared_info);
+            output.extend_from_slice(&self.digest(&input));
+            counter += 1;
+        }
+
+        output.truncate(len);
+        output
+    }
+}
+
+/// Signature schemes supported by `KeyAlgorithm`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum SignatureScheme {
+    /// ECDSA with the signature encoded as `r || s` (RFC 4754)
+    EcdsaFixed,
+
+    /// ECDSA with an ASN.1 DER encoded signature (X9.62)
+    EcdsaDer,
+
+    /// Raw RSA private key operation with no padding
+    RsaRaw,
+
+    /// RSASSA-PKCS1-v1_5
+    RsaPkcs1v15,
+
+    /// RSASSA-PSS with a salt the length of the digest
+    RsaPss,
+}
+
+/// Compute the digest to be signed with the given signature algorithm
+pub(crate) fn prehash(alg: KeyAlgorithm, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let descriptor = alg.descriptor();
+
+    match (descriptor.input, descriptor.digest) {
+        (Some(SignatureInput::Message), Some(digest_alg)) => Ok(digest_alg.digest(data)),
+        (Some(SignatureInput::Digest), Some(digest_alg))
+            if digest_alg.output_size() != data.len() =>
+        {
+            Err(Error::new(
+                ErrorKind::Param,
+                &format!(
+                    "expected {}-byte {:?} digest (got {} bytes)",
+                    digest_alg.output_size(),
+                    digest_alg,
+                    data.len()
+                ),
+            ))
+        }
+        _ => Ok(data.into()),
+    }
+}
+
+/// Get the scheme used by a signature `KeyAlgorithm`
+pub(crate) fn signature_algorithm(alg: KeyAlgorithm) -> Option<SignatureScheme> {
+    let descriptor = alg.descriptor();
+    descriptor.input?;
+
+    Some(match (descriptor.family, descriptor.padding?) {
+        (AlgorithmFamily::Ecdsa, AlgorithmPadding::Rfc4754) => SignatureScheme::EcdsaFixed,
+        (AlgorithmFamily::Ecdsa, AlgorithmPadding::X962) => SignatureScheme::EcdsaDer,
+        (AlgorithmFamily::Rsa, AlgorithmPadding::Raw) => SignatureScheme::RsaRaw,
+        (AlgorithmFamily::Rsa, AlgorithmPadding::Pkcs1v15) => SignatureScheme::RsaPkcs1v15,
+        (AlgorithmFamily::Rsa, AlgorithmPadding::Pss) => SignatureScheme::RsaPss,
+        _ => return None,
+    })
+}
+
+/// Encryption schemes supported by `KeyAlgorithm`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum EncryptionScheme {
+    /// Raw RSA public key operation with no padding
+    RsaRaw,
+
+    /// RSAES-PKCS1-v1_5
+    RsaPkcs1,
+
+    /// RSAES-OAEP using the given digest for both the label hash and MGF1
+    RsaOaep(DigestAlgorithm),
+
+    /// RSAES-OAEP wrapped session key with AES-GCM encrypted payload
+    RsaOaepAesGcm(DigestAlgorithm),
+
+    /// Elliptic Curve Integrated Encryption Scheme, deriving an AES-GCM key
+    /// (and optionally IV) using the X9.63 KDF with the given digest
+    Ecies {
+        /// Digest used by the X9.63 KDF
+        kdf: DigestAlgorithm,
+
+        /// Is the AES-GCM IV derived by the KDF (as opposed to all-zero)?
+        variable_iv: bool,
+    },
+}
+
+/// Get the scheme used by an encryption `KeyAlgorithm`
+pub(crate) fn encryption_algorithm(alg: KeyAlgorithm) -> Option<EncryptionScheme> {
+    let descriptor = alg.descriptor();
+
+    if descriptor.input.is_some() {
+        return None;
+    }
+
+    // The NIST P-curves have a cofactor of 1, so the standard and cofactor
+    // ECIES variants are equivalent
+    Some(
+        match (descriptor.family, descriptor.padding, descriptor.digest) {
+            (AlgorithmFamily::Rsa, Some(AlgorithmPadding::Raw), _) => EncryptionScheme::RsaRaw,
+            (AlgorithmFamily::Rsa, Some(AlgorithmPadding::Pkcs1v15), _) => {
+                EncryptionScheme::RsaPkcs1
+            }
+            (AlgorithmFamily::Rsa, Some(AlgorithmPadding::Oaep), Some(digest_alg)) => {
+                EncryptionScheme::RsaOaep(digest_alg)
+            }
+            (AlgorithmFamily::Rsa, Some(AlgorithmPadding::OaepAesGcm), Some(digest_alg)) => {
+                EncryptionScheme::RsaOaepAesGcm(digest_alg)
+            }
+            (AlgorithmFamily::Ecies, _, Some(kdf)) => EncryptionScheme::Ecies {
+                kdf,
+                variable_iv: descriptor.variable_iv,
+            },
+            _ => return None,
+        },
+    )
+}
+
+/// Incrementally hash the data read from the given reader
+fn digest_reader<D: Digest + Write, R: Read>(mut reader: R) -> io::Result<Vec<u8>> {
+    let mut hasher = D::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Encrypt the given plaintext with a 128-bit or 256-bit AES key in GCM mode
+/// using a 16-byte IV, as the hybrid `KeyAlgorithm`s do, returning the
+/// ciphertext with the authentication tag appended
+pub(crate) fn aes_gcm_encrypt(
+    key: &[u8],
+    iv: [u8; 16],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let payload = Payload {
+        msg: plaintext,
+        aad,
+    };
+
+    match key.len() {
+        16 => Aes128Gcm16::new_from_slice(key)
+            .expect("invalid AES-128 key")
+            .encrypt(&iv.into(), payload),
+        32 => Aes256Gcm16::new_from_slice(key)
+            .expect("invalid AES-256 key")
+            .encrypt(&iv.into(), payload),
+        _ => return Err(Error::new(ErrorKind::Param, "invalid AES key size")),
+    }
+    .map_err(|_| Error::new(ErrorKind::Param, "AES-GCM encryption failed"))
+}
+
+/// Decrypt and authenticate a ciphertext produced by `aes_gcm_encrypt`
+pub(crate) fn aes_gcm_decrypt(
+    key: &[u8],
+    iv: [u8; 16],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let payload = Payload {
+        msg: ciphertext,
+        aad,
+    };
+
+    match key.len() {
+        16 => Aes128Gcm16::new_from_slice(key)
+            .expect("invalid AES-128 key")
+            .decrypt(&iv.into(), payload),
+        32 => Aes256Gcm16::new_from_slice(key)
+            .expect("invalid AES-256 key")
+            .decrypt(&iv.into(), payload),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::DecryptionFailed,
+                "invalid AES key size",
+            ))
+        }
+    }
+    .map_err(|_| Error::new(ErrorKind::DecryptionFailed, "AES-GCM decryption failed"))
+}
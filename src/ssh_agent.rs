@@ -0,0 +1,385 @@
+//! SSH agent which signs with keys stored in a keychain, e.g. Secure Enclave
+//! keys, so they can be used with `ssh` via `SSH_AUTH_SOCK` or the
+//! `IdentityAgent` option.
+//!
+//! Implements the subset of the SSH agent protocol needed to list and sign
+//! with keys. Requests to add, remove, or lock keys are refused. See:
+//! <https://datatracker.ietf.org/doc/html/draft-miller-ssh-agent>
+
+use crate::{
+    attr::AttrKeyClass,
+    encoding::openssh,
+    error::{Error, ErrorKind},
+    key::{EcCurve, Key, KeyAlgorithm},
+    keychain::{item::Query, Keychain},
+    software::PublicKey,
+};
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Read, Write},
+    os::unix::{
+        fs::{DirBuilderExt, PermissionsExt},
+        net::UnixListener,
+    },
+    path::Path,
+    process, thread,
+    time::Duration,
+};
+
+/// Generic failure response
+const SSH_AGENT_FAILURE: u8 = 5;
+
+/// Request for the list of keys the agent holds
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+
+/// Response to `SSH_AGENTC_REQUEST_IDENTITIES`
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+
+/// Request to sign data with a key
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+
+/// Response to `SSH_AGENTC_SIGN_REQUEST`
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+/// Sign request flag for `rsa-sha2-256` signatures
+const SSH_AGENT_RSA_SHA2_256: u32 = 2;
+
+/// Sign request flag for `rsa-sha2-512` signatures
+const SSH_AGENT_RSA_SHA2_512: u32 = 4;
+
+/// Maximum size of an agent protocol message (same limit as OpenSSH)
+const MAX_MESSAGE_SIZE: usize = 256 * 1024;
+
+/// Delay before accepting connections again after failing to accept one
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// SSH agent serving the keys in a keychain which match a `Query`.
+///
+/// Keys are looked up on every request, so keys added to or removed from
+/// the keychain are reflected immediately. Works with any `KeychainBackend`.
+#[derive(Clone, Debug)]
+pub struct SshAgent {
+    keychain: Keychain,
+    query: Query,
+}
+
+impl SshAgent {
+    /// Create an agent for the private keys in the given keychain which
+    /// match the given query
+    pub fn new(keychain: Keychain, query: Query) -> Self {
+        Self {
+            keychain,
+            query: query.key_class(AttrKeyClass::Private),
+        }
+    }
+
+    /// Bind a Unix socket at the given path (which must not already exist)
+    /// and serve agent requests on it forever. The socket is only accessible
+    /// to the current user.
+    pub fn listen<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let file_name = path.file_name().ok_or_else(|| {
+            Error::new(ErrorKind::Param, "SSH agent socket path has no file name")
+        })?;
+
+        // Bind the socket in a private directory and restrict its
+        // permissions before linking it into place, so other users can't
+        // connect to it in the meantime
+        let mut dir_name = OsString::from(".");
+        dir_name.push(file_name);
+        dir_name.push(format!(".{}", process::id()));
+        let dir = path.with_file_name(dir_name);
+
+        // A directory left behind by an earlier process with the same PID
+        // would otherwise prevent the agent from starting
+        match fs::remove_dir_all(&dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => (),
+        }
+
+        fs::DirBuilder::new().mode(0o700).create(&dir)?;
+
+        let temp_path = dir.join("agent.sock");
+        let result = UnixListener::bind(&temp_path).and_then(|listener| {
+            fs::set_permissions(&temp_path, fs::Permissions::from_mode(0o600))?;
+            fs::hard_link(&temp_path, path)?;
+            Ok(listener)
+        });
+
+        let _ = fs::remove_file(&temp_path);
+        fs::remove_dir(&dir)?;
+        self.serve(result?)
+    }
+
+    /// Serve agent requests on the given listener forever, handling each
+    /// connection on its own thread. Transient failures to accept a
+    /// connection (e.g. because the process has too many open files) are
+    /// retried after a short delay, and any other failure is returned.
+    pub fn serve(&self, listener: UnixListener) -> Result<(), Error> {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let agent = self.clone();
+                    thread::spawn(move || agent.serve_connection(stream));
+                }
+                Err(ref e) if is_transient(e) => thread::sleep(ACCEPT_RETRY_DELAY),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Serve agent requests on a single connection until the client
+    /// disconnects.
+    pub fn serve_connection<S: Read + Write>(&self, mut stream: S) -> Result<(), Error> {
+        loop {
+            let mut length = [0u8; 4];
+
+            match stream.read_exact(&mut length) {
+                Ok(()) => (),
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+
+            let length = u32::from_be_bytes(length) as usize;
+
+            if length > MAX_MESSAGE_SIZE {
+                return Err(Error::new(
+                    ErrorKind::DataTooLarge,
+                    &format!("SSH agent message too large ({} bytes)", length),
+                ));
+            }
+
+            let mut request = vec![0u8; length];
+            stream.read_exact(&mut request)?;
+
+            let response = self.handle_request(&request);
+            stream.write_all(&(response.len() as u32).to_be_bytes())?;
+            stream.write_all(&response)?;
+        }
+    }
+
+    /// Handle a single agent protocol message (without its length prefix),
+    /// returning the response message. Unsupported or malformed requests
+    /// receive an `SSH_AGENT_FAILURE` response.
+    pub fn handle_request(&self, request: &[u8]) -> Vec<u8> {
+        let mut reader = Reader(request);
+
+        let result = match reader.read_u8() {
+            Ok(SSH_AGENTC_REQUEST_IDENTITIES) => self.identities_answer(),
+            Ok(SSH_AGENTC_SIGN_REQUEST) => self.sign_response(&mut reader),
+            _ => Err(Error::new(
+                ErrorKind::Unimplemented,
+                "unsupported SSH agent request",
+            )),
+        };
+
+        result.unwrap_or_else(|_| vec![SSH_AGENT_FAILURE])
+    }
+
+    /// Respond to `SSH_AGENTC_REQUEST_IDENTITIES`
+    fn identities_answer(&self) -> Result<Vec<u8>, Error> {
+        let identities = self.identities()?;
+        let mut response = vec![SSH_AGENT_IDENTITIES_ANSWER];
+        write_u32(&mut response, identities.len() as u32);
+
+        for identity in &identities {
+            write_string(&mut response, &identity.blob);
+            write_string(&mut response, identity.comment.as_bytes());
+        }
+
+        Ok(response)
+    }
+
+    /// Respond to `SSH_AGENTC_SIGN_REQUEST`
+    fn sign_response(&self, reader: &mut Reader<'_>) -> Result<Vec<u8>, Error> {
+        let blob = reader.read_string()?;
+        let data = reader.read_string()?;
+        let flags = reader.read_u32()?;
+
+        let identity = self
+            .identities()?
+            .into_iter()
+            .find(|identity| identity.blob == blob)
+            .ok_or_else(|| Error::new(ErrorKind::ItemNotFound, "no such SSH agent identity"))?;
+
+        let (format, signature) = identity.sign(data, flags)?;
+
+        let mut signature_blob = vec![];
+        write_string(&mut signature_blob, format.as_bytes());
+        write_string(&mut signature_blob, &signature);
+
+        let mut response = vec![SSH_AGENT_SIGN_RESPONSE];
+        write_string(&mut response, &signature_blob);
+        Ok(response)
+    }
+
+    /// Find the keys this agent serves. Keys of unsupported types are skipped.
+    fn identities(&self) -> Result<Vec<Identity>, Error> {
+        Ok(self
+            .keychain
+            .find_keys(self.query.clone())?
+            .into_iter()
+            .filter_map(|key| Identity::new(key).ok())
+            .collect())
+    }
+}
+
+/// Key served by the agent
+struct Identity {
+    /// Private key
+    key: Key,
+
+    /// Corresponding public key
+    public_key: PublicKey,
+
+    /// SSH wire format public key
+    blob: Vec<u8>,
+
+    /// Comment (i.e. `AttrLabel`) for this key
+    comment: String,
+}
+
+impl Identity {
+    /// Load the public key for the given private key
+    fn new(key: Key) -> Result<Self, Error> {
+        let public = key.public_key()?;
+        let public_key = public.software_key()?.public_key();
+        let blob = openssh::public_key_blob(&public_key)?;
+        let comment = key
+            .label()
+            .or_else(|| public.label())
+            .map(|label| label.as_str().to_owned())
+            .unwrap_or_default();
+
+        Ok(Self {
+            key,
+            public_key,
+            blob,
+            comment,
+        })
+    }
+
+    /// Sign the given data, returning the SSH signature format name and the
+    /// encoded signature
+    fn sign(&self, data: &[u8], flags: u32) -> Result<(&'static str, Vec<u8>), Error> {
+        let (format, alg, curve) = match self.public_key {
+            PublicKey::P256(_) => (
+                "ecdsa-sha2-nistp256",
+                KeyAlgorithm::ECDSASignatureMessageX962SHA256,
+                EcCurve::P256,
+            ),
+            PublicKey::P384(_) => (
+                "ecdsa-sha2-nistp384",
+                KeyAlgorithm::ECDSASignatureMessageX962SHA384,
+                EcCurve::P384,
+            ),
+            PublicKey::P521(_) => (
+                "ecdsa-sha2-nistp521",
+                KeyAlgorithm::ECDSASignatureMessageX962SHA512,
+                EcCurve::P521,
+            ),
+            PublicKey::Rsa(_) => {
+                let (format, alg) = if flags & SSH_AGENT_RSA_SHA2_256 != 0 {
+                    (
+                        "rsa-sha2-256",
+                        KeyAlgorithm::RSASignatureMessagePKCS1v15SHA256,
+                    )
+                } else if flags & SSH_AGENT_RSA_SHA2_512 != 0 {
+                    (
+                        "rsa-sha2-512",
+                        KeyAlgorithm::RSASignatureMessagePKCS1v15SHA512,
+                    )
+                } else {
+                    return Err(Error::new(
+                        ErrorKind::Unimplemented,
+                        "SHA-1 ssh-rsa signatures are not supported",
+                    ));
+                };
+
+                return Ok((format, self.key.sign(alg, data)?.into_vec()));
+            }
+        };
+
+        // SSH encodes ECDSA signatures as a pair of mpints (RFC 5656 3.1.2)
+        let fixed = self.key.sign(alg, data)?.to_fixed(curve)?;
+        let (r, s) = fixed.split_at(fixed.len() / 2);
+
+        let mut signature = vec![];
+        write_mpint(&mut signature, r);
+        write_mpint(&mut signature, s);
+        Ok((format, signature))
+    }
+}
+
+/// Reader for SSH wire format data (RFC 4251 section 5)
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// Read the given number of bytes
+    fn read(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.0.len() {
+            return Err(Error::new(ErrorKind::Decode, "truncated SSH agent message"));
+        }
+
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    /// Read a `byte`
+    fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read(1)?[0])
+    }
+
+    /// Read a `uint32`
+    fn read_u32(&mut self) -> Result<u32, Error> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.read(4)?);
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    /// Read a length-prefixed `string`
+    fn read_string(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.read_u32()? as usize;
+        self.read(len)
+    }
+}
+
+/// Write a `uint32`
+fn write_u32(out: &mut Vec<u8>, n: u32) {
+    out.extend_from_slice(&n.to_be_bytes());
+}
+
+/// Write a length-prefixed `string`
+fn write_string(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_u32(out, bytes.len() as u32);
+    out.extend_from_slice(bytes);
+}
+
+/// Write a big endian unsigned integer as an `mpint`
+fn write_mpint(out: &mut Vec<u8>, bytes: &[u8]) {
+    let bytes = &bytes[bytes.iter().take_while(|&&b| b == 0).count()..];
+
+    if bytes.first().map(|&b| b & 0x80 != 0).unwrap_or(false) {
+        write_u32(out, bytes.len() as u32 + 1);
+        out.push(0);
+        out.extend_from_slice(bytes);
+    } else {
+        write_string(out, bytes);
+    }
+}
+
+/// Is a failure to accept a connection likely to resolve itself, e.g. once
+/// other connections are closed?
+fn is_transient(error: &io::Error) -> bool {
+    match error.kind() {
+        io::ErrorKind::ConnectionAborted | io::ErrorKind::Interrupted => true,
+        _ => matches!(
+            error.raw_os_error(),
+            Some(libc::EMFILE) | Some(libc::ENFILE) | Some(libc::ENOBUFS) | Some(libc::ENOMEM)
+        ),
+    }
+}